@@ -0,0 +1,232 @@
+//! Send/expect automation on a running session.
+//!
+//! An `ExpectSession` gets its own copy of the received text, so scripts and
+//! tests can drive the device while the `SerialMonitorView` keeps showing it.
+
+use std::io::{Error, ErrorKind, Result};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use regex::bytes::Regex;
+
+use crate::serial_tty::encoding::Encoding;
+use crate::serial_tty::event_loop::{SerialEventLoopSender, SerialMsg};
+use crate::serial_tty::newline::TxNewline;
+use crate::serial_tty::subscription::SubscriptionReceiver;
+
+/// Received text kept for matching, older text is dropped beyond it.
+pub(crate) const MAX_BUFFER_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectMatch {
+    /// The whole match followed by the capture groups, `None` for groups
+    /// which didn't participate in the match.
+    pub groups: Vec<Option<String>>,
+    /// Text received between the previous match and this one.
+    pub before: String,
+}
+
+impl ExpectMatch {
+    #[inline]
+    pub fn group(&self, index: usize) -> Option<&str> {
+        self.groups.get(index)?.as_deref()
+    }
+}
+
+/// Handle for send/expect scripts, created by
+/// `SerialMonitorBackend::expect_session`.
+///
+/// It only sees the text received after it was created, with escape
+/// sequences and carriage returns removed. The handle can be moved to
+/// another thread, and stops working once the backend is dropped.
+pub struct ExpectSession {
+    sender: SerialEventLoopSender,
+    received: SubscriptionReceiver,
+    buffer: Vec<u8>,
+    encoding: Encoding,
+    tx_newline: TxNewline,
+}
+
+impl ExpectSession {
+    pub(crate) fn new(
+        sender: SerialEventLoopSender,
+        received: SubscriptionReceiver,
+        encoding: Encoding,
+        tx_newline: TxNewline,
+    ) -> Self {
+        Self {
+            sender,
+            received,
+            buffer: vec![],
+            encoding,
+            tx_newline,
+        }
+    }
+
+    /// Write bytes to the device, as they are.
+    pub fn send(&self, bytes: &[u8]) -> Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        self.send_msg(SerialMsg::Input(bytes.to_vec().into()))
+    }
+
+    /// Write a line in the encoding of the session, terminated by its TX
+    /// line ending.
+    pub fn send_line(&self, line: &str) -> Result<()> {
+        let mut bytes = self.encoding.encode(line.as_bytes());
+        bytes.extend_from_slice(self.tx_newline.as_bytes());
+        self.send(&bytes)
+    }
+
+    /// Drive the DTR modem line, often wired to the reset of the board.
+    pub fn set_dtr(&self, level: bool) -> Result<()> {
+        self.send_msg(SerialMsg::Dtr(level))
+    }
+
+    /// Drive the RTS modem line.
+    pub fn set_rts(&self, level: bool) -> Result<()> {
+        self.send_msg(SerialMsg::Rts(level))
+    }
+
+    /// Wait until the received text matches `pattern`.
+    ///
+    /// The text up to the end of the match is consumed, so the next call
+    /// only looks at what follows it. Fails with `ErrorKind::TimedOut` when
+    /// nothing matched within `timeout`.
+    pub fn expect(
+        &mut self,
+        pattern: &str,
+        timeout: Duration,
+    ) -> Result<ExpectMatch> {
+        let regex = Regex::new(pattern)
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(captures) = regex.captures(&self.buffer) {
+                let found = captures.get(0).unwrap();
+                let expect_match = ExpectMatch {
+                    groups: captures
+                        .iter()
+                        .map(|group| {
+                            group.map(|group| {
+                                String::from_utf8_lossy(group.as_bytes())
+                                    .into_owned()
+                            })
+                        })
+                        .collect(),
+                    before: String::from_utf8_lossy(
+                        &self.buffer[..found.start()],
+                    )
+                    .into_owned(),
+                };
+                self.buffer.drain(..found.end());
+                return Ok(expect_match);
+            }
+
+            let now = Instant::now();
+            if now >= deadline || !self.receive(deadline - now)? {
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    format!("Timed out waiting for {:?}", pattern),
+                ));
+            }
+        }
+    }
+
+    /// Wait until nothing was received for `quiet`.
+    pub fn wait_quiet(&mut self, quiet: Duration) -> Result<()> {
+        while self.receive(quiet)? {}
+        Ok(())
+    }
+
+    /// Text received since the last match.
+    pub fn buffer(&self) -> String {
+        String::from_utf8_lossy(&self.buffer).into_owned()
+    }
+
+    /// Forget the text received so far.
+    pub fn clear(&mut self) {
+        while self.received.try_recv().is_ok() {}
+        self.buffer.clear();
+    }
+
+    fn send_msg(&self, msg: SerialMsg) -> Result<()> {
+        self.sender
+            .send(msg)
+            .map_err(|err| Error::new(ErrorKind::BrokenPipe, err.to_string()))
+    }
+
+    /// Wait for the next received text, returns `false` on timeout.
+    pub(crate) fn receive(&mut self, timeout: Duration) -> Result<bool> {
+        match self.received.recv_timeout(timeout) {
+            Ok(bytes) => {
+                self.buffer.extend_from_slice(&bytes);
+                if self.buffer.len() > MAX_BUFFER_SIZE {
+                    let overflow = self.buffer.len() - MAX_BUFFER_SIZE;
+                    self.buffer.drain(..overflow);
+                }
+                Ok(true)
+            },
+            Err(RecvTimeoutError::Timeout) => Ok(false),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "The serial session was closed",
+            )),
+        }
+    }
+}
+
+/// Session without event loop, returning the messages sent to the event
+/// loop and the sender of the received text.
+#[cfg(test)]
+pub(crate) fn test_session() -> (
+    ExpectSession,
+    std::sync::mpsc::Receiver<SerialMsg>,
+    crate::serial_tty::subscription::SubscriptionSender,
+) {
+    use crate::serial_tty::event_loop::tests::fake_sender;
+    use crate::serial_tty::subscription::subscription;
+
+    let (sender, input_receiver) = fake_sender();
+    let (received, receiver) = subscription(MAX_BUFFER_SIZE);
+    let session =
+        ExpectSession::new(sender, receiver, Encoding::Utf8, TxNewline::CrLf);
+    (session, input_receiver, received)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expect_consumes_up_to_the_match() {
+        let (mut session, input_receiver, received) = test_session();
+
+        assert!(received.send(b"U-Boot 2024.01\nDRAM:  512 "));
+        assert!(received.send(b"MiB\n=> "));
+        let found = session
+            .expect(r"DRAM:\s+(\d+) (?:(G)|MiB)", Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(found.group(1), Some("512"));
+        assert_eq!(found.group(2), None);
+        assert_eq!(found.before, "U-Boot 2024.01\n");
+        assert!(session.expect("=> ", Duration::ZERO).is_ok());
+
+        let timeout = session.expect("=> ", Duration::from_millis(10));
+        assert_eq!(timeout.unwrap_err().kind(), ErrorKind::TimedOut);
+        session.wait_quiet(Duration::from_millis(10)).unwrap();
+
+        session.send_line("boot").unwrap();
+        match input_receiver.try_recv() {
+            Ok(SerialMsg::Input(bytes)) => assert_eq!(&*bytes, b"boot\r\n"),
+            _ => panic!("no input was sent"),
+        }
+
+        drop(received);
+        let closed = session.expect("=> ", Duration::from_secs(1));
+        assert_eq!(closed.unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+}
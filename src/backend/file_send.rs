@@ -0,0 +1,333 @@
+//! Raw file send, the file is written to the device verbatim.
+//!
+//! The file is streamed from disk on a worker thread. Every chunk is queued
+//! once the previous one was handed to the TTY, so flow control of the port
+//! holds the file back instead of the whole file piling up in memory.
+
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::serial_tty::event_loop::{SerialEventLoopSender, SerialMsg};
+
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Progress is reported at most this often.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileSendOptions {
+    /// Bytes read from the file and queued at once.
+    pub chunk_size: usize,
+    /// Pause after every byte, for devices without flow control which
+    /// can't keep up.
+    pub char_delay: Duration,
+    /// Pause after every line feed, for devices which process a line at a
+    /// time.
+    pub line_delay: Duration,
+}
+
+impl Default for FileSendOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 4096,
+            char_delay: Duration::ZERO,
+            line_delay: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSendProgress {
+    pub file: String,
+    pub sent: u64,
+    pub total: u64,
+    /// Average throughput since the start.
+    pub bytes_per_second: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileSendEvent {
+    Progress(FileSendProgress),
+    Finished,
+    /// The send was cancelled or failed.
+    Failed(String),
+}
+
+/// A raw file send running on its worker thread.
+pub(crate) struct FileSend {
+    cancel: Arc<AtomicBool>,
+    events: Receiver<FileSendEvent>,
+    thread: JoinHandle<()>,
+}
+
+impl FileSend {
+    pub(crate) fn start(
+        path: &Path,
+        options: FileSendOptions,
+        sender: SerialEventLoopSender,
+        app_context: egui::Context,
+    ) -> Result<Self> {
+        let file = File::open(path)?;
+        let total = file.metadata()?.len();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (events, received) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name(format!("file_send_{}", name))
+            .spawn({
+                let cancel = cancel.clone();
+                move || {
+                    let mut progress = FileSendProgress {
+                        file: name,
+                        sent: 0,
+                        total,
+                        bytes_per_second: 0,
+                    };
+                    let report = |event| {
+                        let _ = events.send(event);
+                        app_context.request_repaint();
+                    };
+                    let result = stream(
+                        file,
+                        options,
+                        &sender,
+                        &cancel,
+                        &mut progress,
+                        report,
+                    );
+                    report(match result {
+                        Ok(()) => FileSendEvent::Finished,
+                        Err(err) => {
+                            if err.kind() != ErrorKind::Interrupted {
+                                log::error!(
+                                    "Sending {:?} failed: {}",
+                                    progress.file,
+                                    err
+                                );
+                            }
+                            FileSendEvent::Failed(err.to_string())
+                        },
+                    });
+                }
+            })?;
+
+        Ok(Self {
+            cancel,
+            events: received,
+            thread,
+        })
+    }
+
+    #[inline]
+    pub(crate) fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    /// Stop before the next chunk, what was queued is still written.
+    pub(crate) fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn events(&self) -> Vec<FileSendEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+impl Drop for FileSend {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Write `reader` to the device, paced by `options`.
+fn stream(
+    mut reader: impl Read,
+    options: FileSendOptions,
+    sender: &SerialEventLoopSender,
+    cancel: &AtomicBool,
+    progress: &mut FileSendProgress,
+    report: impl Fn(FileSendEvent),
+) -> Result<()> {
+    let start = Instant::now();
+    let mut reported = start;
+    let mut buf = vec![0; options.chunk_size.max(1)];
+    loop {
+        let read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+
+        let chunk = &buf[..read];
+        let pieces: Box<dyn Iterator<Item = &[u8]>> =
+            if !options.char_delay.is_zero() {
+                Box::new(chunk.chunks(1))
+            } else if !options.line_delay.is_zero() {
+                Box::new(chunk.split_inclusive(|&byte| byte == b'\n'))
+            } else {
+                Box::new(std::iter::once(chunk))
+            };
+        for piece in pieces {
+            write(piece, sender, cancel)?;
+
+            progress.sent += piece.len() as u64;
+            let elapsed = start.elapsed().as_secs_f64();
+            if elapsed > 0.0 {
+                progress.bytes_per_second =
+                    (progress.sent as f64 / elapsed) as u64;
+            }
+            if reported.elapsed() >= PROGRESS_INTERVAL {
+                reported = Instant::now();
+                report(FileSendEvent::Progress(progress.clone()));
+            }
+
+            let mut delay = options.char_delay;
+            if piece.ends_with(b"\n") {
+                delay += options.line_delay;
+            }
+            sleep(delay, cancel)?;
+        }
+    }
+
+    report(FileSendEvent::Progress(progress.clone()));
+    Ok(())
+}
+
+/// Queue `bytes` and wait until they were handed to the TTY.
+fn write(
+    bytes: &[u8],
+    sender: &SerialEventLoopSender,
+    cancel: &AtomicBool,
+) -> Result<()> {
+    check_cancel(cancel)?;
+    let (flush, flushed) = mpsc::channel();
+    sender
+        .send(SerialMsg::Input(bytes.to_vec().into()))
+        .and_then(|()| sender.send(SerialMsg::Flush(flush)))
+        .map_err(|err| Error::new(ErrorKind::BrokenPipe, err.to_string()))?;
+
+    loop {
+        match flushed.recv_timeout(CANCEL_POLL_INTERVAL) {
+            Ok(()) => return Ok(()),
+            Err(RecvTimeoutError::Timeout) => check_cancel(cancel)?,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(Error::new(
+                    ErrorKind::BrokenPipe,
+                    "The serial port was closed",
+                ))
+            },
+        }
+    }
+}
+
+fn sleep(delay: Duration, cancel: &AtomicBool) -> Result<()> {
+    let end = Instant::now() + delay;
+    loop {
+        check_cancel(cancel)?;
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(());
+        }
+        std::thread::sleep(left.min(CANCEL_POLL_INTERVAL));
+    }
+}
+
+#[inline]
+fn check_cancel(cancel: &AtomicBool) -> Result<()> {
+    if cancel.load(Ordering::Relaxed) {
+        return Err(Error::new(ErrorKind::Interrupted, "Cancelled"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial_tty::event_loop::tests::fake_event_loop;
+    use crate::test_utils::temp_path;
+
+    fn wait_end(send: &FileSend) -> Vec<FileSendEvent> {
+        let mut events = vec![];
+        while !matches!(
+            events.last(),
+            Some(FileSendEvent::Finished | FileSendEvent::Failed(_))
+        ) {
+            events.push(send.events.recv().unwrap());
+        }
+        events
+    }
+
+    #[test]
+    fn lines_are_paced() {
+        let path = temp_path("file-send.txt");
+        std::fs::write(&path, b"reset\nboot\nrun").unwrap();
+        let (written, pieces) = mpsc::channel();
+        let sender = fake_event_loop(move |msg| match msg {
+            SerialMsg::Input(bytes) => written.send(bytes.to_vec()).unwrap(),
+            SerialMsg::Flush(flush) => flush.send(()).unwrap(),
+            _ => {},
+        });
+        let options = FileSendOptions {
+            line_delay: Duration::from_millis(20),
+            ..FileSendOptions::default()
+        };
+
+        let start = Instant::now();
+        let send =
+            FileSend::start(&path, options, sender, egui::Context::default())
+                .unwrap();
+        let events = wait_end(&send);
+
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(
+            pieces.try_iter().collect::<Vec<_>>(),
+            [&b"reset\n"[..], b"boot\n", b"run"]
+        );
+        assert_eq!(events.last(), Some(&FileSendEvent::Finished));
+        assert!(matches!(
+            &events[events.len() - 2],
+            FileSendEvent::Progress(progress)
+                if progress.sent == 14 && progress.total == 14
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn cancel_while_flow_control_holds_back() {
+        let path = temp_path("file-send.bin");
+        std::fs::write(&path, vec![0x55; 10_000]).unwrap();
+        // The flushes are never answered, as if the port was stopped.
+        let mut held = vec![];
+        let sender = fake_event_loop(move |msg| {
+            if let SerialMsg::Flush(flush) = msg {
+                held.push(flush);
+            }
+        });
+
+        let send = FileSend::start(
+            &path,
+            FileSendOptions::default(),
+            sender,
+            egui::Context::default(),
+        )
+        .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(send.is_running());
+        send.cancel();
+
+        assert_eq!(
+            wait_end(&send),
+            [FileSendEvent::Failed("Cancelled".into())]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use crate::serial_tty::tap::{TrafficDirection, TrafficLog};
+
+pub const DEFAULT_BYTES_PER_ROW: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayMode {
+    /// Bytes are interpreted by the terminal emulator.
+    #[default]
+    Text,
+    /// Bytes are shown as classic hex + ASCII dump.
+    Hex,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexDumpSettings {
+    pub bytes_per_row: usize,
+    /// Amount of raw traffic bytes kept for the hex view.
+    pub capacity: usize,
+}
+
+impl Default for HexDumpSettings {
+    fn default() -> Self {
+        Self {
+            bytes_per_row: DEFAULT_BYTES_PER_ROW,
+            capacity: crate::serial_tty::tap::DEFAULT_TRAFFIC_CAPACITY,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexDumpRow {
+    pub offset: u64,
+    /// `None` for the leading bytes which were already evicted.
+    pub bytes: Vec<Option<(u8, TrafficDirection)>>,
+}
+
+impl HexDumpRow {
+    /// Printable representation of a byte for the ASCII column.
+    #[inline]
+    pub fn ascii(byte: u8) -> char {
+        if byte.is_ascii_graphic() || byte == b' ' {
+            byte as char
+        } else {
+            '.'
+        }
+    }
+}
+
+/// Amount of rows needed to show the whole traffic log.
+pub fn total_rows(log: &TrafficLog, bytes_per_row: usize) -> usize {
+    let first = aligned_start(log, bytes_per_row);
+    log.end_offset()
+        .saturating_sub(first)
+        .div_ceil(bytes_per_row as u64) as usize
+}
+
+/// Build `rows` rows ending `scroll` rows above the newest one.
+pub fn rows(
+    log: &TrafficLog,
+    bytes_per_row: usize,
+    rows: usize,
+    scroll: usize,
+) -> Vec<HexDumpRow> {
+    let total = total_rows(log, bytes_per_row);
+    let last = total.saturating_sub(scroll);
+    let first = last.saturating_sub(rows);
+    let base = aligned_start(log, bytes_per_row);
+    let row_offset = |row: usize| base + (row * bytes_per_row) as u64;
+
+    let mut result: Vec<HexDumpRow> = (first..last)
+        .map(|row| HexDumpRow {
+            offset: row_offset(row),
+            bytes: Vec::with_capacity(bytes_per_row),
+        })
+        .collect();
+
+    for (offset, byte, direction) in
+        log.range(row_offset(first), row_offset(last))
+    {
+        let row =
+            &mut result[((offset - base) as usize / bytes_per_row) - first];
+        let column = (offset - row.offset) as usize;
+        if row.bytes.len() < column {
+            row.bytes.resize(column, None);
+        }
+        row.bytes.push(Some((byte, direction)));
+    }
+
+    result
+}
+
+#[inline]
+fn aligned_start(log: &TrafficLog, bytes_per_row: usize) -> u64 {
+    let start = log.start_offset();
+    start - start % bytes_per_row as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use TrafficDirection::{Rx, Tx};
+
+    #[test]
+    fn rows_start_at_evicted_bytes() {
+        let mut log = TrafficLog::new(4);
+        log.push(Rx, b"012");
+        log.push(Tx, b"345");
+        assert_eq!(log.start_offset(), 2);
+        assert_eq!(total_rows(&log, 4), 2);
+
+        assert_eq!(
+            rows(&log, 4, 10, 0),
+            [
+                HexDumpRow {
+                    offset: 0,
+                    bytes: vec![None, None, Some((b'2', Rx)), Some((b'3', Tx))],
+                },
+                HexDumpRow {
+                    offset: 4,
+                    bytes: vec![Some((b'4', Tx)), Some((b'5', Tx))],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rows_scrolled_up() {
+        let mut log = TrafficLog::new(1024);
+        log.push(Rx, b"0123456789");
+        assert_eq!(total_rows(&log, 4), 3);
+
+        let scrolled = rows(&log, 4, 2, 1);
+        let offsets: Vec<u64> = scrolled.iter().map(|row| row.offset).collect();
+        assert_eq!(offsets, [0, 4]);
+        assert!(rows(&log, 4, 2, 3).is_empty());
+        assert!(rows(&log, 4, 2, 10).is_empty());
+    }
+}
@@ -1,13 +1,47 @@
+pub mod expect;
+pub mod file_send;
+pub mod hex_dump;
+pub mod modbus;
+pub mod search;
+pub mod settings;
+
+use crate::highlight::{HighlightRule, HighlightSpan, Highlighter};
+use crate::line_input::InputMode;
+#[cfg(feature = "scripting")]
+use crate::script::{Script, ScriptHost, ScriptNotification};
+use crate::serial_tty::archive::{
+    row_text, ArchiveReader, ArchivedLine, ScrollbackArchive,
+};
+use crate::serial_tty::control_tokens::ControlTokenStyle;
+use crate::serial_tty::defmt::{DefmtDecoder, DefmtRecord, DefmtTable};
+use crate::serial_tty::encoding::Encoding;
 use crate::serial_tty::event_loop::{
     SerialEventLoop, SerialMsg, SerialNotifier,
 };
+use crate::serial_tty::framing::{DecodedFrame, FrameCheck, FrameLog, Framer};
+use crate::serial_tty::line_stamps::{LineStamps, TimestampFormat};
+use crate::serial_tty::newline::{RxNewline, TxNewline};
+use crate::serial_tty::nmea::GnssState;
+use crate::serial_tty::plot::{self, PlotPattern, PlotSeries, Plotter};
+use crate::serial_tty::schema::{DecodedPacket, PacketSchema, SchemaLogFormat};
+use crate::serial_tty::subscription::subscription;
+use crate::serial_tty::tap::SerialTap;
+use crate::serial_tty::triggers::{Trigger, TriggerEvent, TriggerSet};
+use crate::transfer::kermit::{KermitReceiver, KermitSender};
+use crate::transfer::xmodem::{XmodemReceiver, XmodemSender};
+use crate::transfer::zmodem::{ZmodemReceiver, ZmodemSender};
+use crate::transfer::{
+    SendFile, Transfer, TransferEvent, TransferJob, TransferProtocol,
+};
 use crate::types::Size;
-use crate::{serial_tty, SerialTtyOptions};
+use crate::{serial_tty, SerialTty, SerialTtyOptions};
 use alacritty_terminal::event::{
     Event, EventListener, Notify, OnResize, WindowSize,
 };
 use alacritty_terminal::grid::{Dimensions, Scroll};
-use alacritty_terminal::index::{Column, Direction, Line, Point, Side};
+use alacritty_terminal::index::{
+    Boundary, Column, Direction, Line, Point, Side,
+};
 use alacritty_terminal::selection::{
     Selection, SelectionRange, SelectionType as AlacrittySelectionType,
 };
@@ -16,14 +50,27 @@ use alacritty_terminal::term::search::{Match, RegexIter, RegexSearch};
 use alacritty_terminal::term::{
     self, cell::Cell, test::TermSize, viewport_to_point, Term, TermMode,
 };
+use alacritty_terminal::vte::ansi::{Color, NamedColor};
 use alacritty_terminal::Grid;
 use egui::Modifiers;
+use expect::ExpectSession;
+use file_send::{FileSend, FileSendEvent, FileSendOptions};
+use hex_dump::{DisplayMode, HexDumpRow, HexDumpSettings};
+use log::error;
+use modbus::{ModbusMaster, ModbusTable};
+use search::{
+    PendingSearch, Search, SearchKind, SearchMatch, SearchStatus, SearchStep,
+};
+use settings::BackendSettings;
 use std::borrow::Cow;
 use std::cmp::min;
-use std::io::Result;
-use std::ops::{Index, RangeInclusive};
-use std::sync::mpsc::Sender;
+use std::io::{Error, ErrorKind, Result};
+use std::ops::{Index, Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{mpsc, Arc};
+use std::time::SystemTime;
 
 pub type TerminalMode = TermMode;
 pub type TtyEvent = Event;
@@ -37,7 +84,11 @@ pub enum BackendCommand {
     SelectStart(SelectionType, f32, f32),
     SelectUpdate(f32, f32),
     ProcessLink(LinkAction, Point),
+    /// Run the script with the given name, does nothing without the
+    /// `scripting` feature.
+    RunScript(String),
     MouseReport(MouseButton, Modifiers, Point, bool),
+    ToggleLocalEcho,
 }
 
 #[derive(Debug, Clone)]
@@ -133,6 +184,12 @@ impl From<TerminalSize> for WindowSize {
     }
 }
 
+/// Archived lines shown from `top`, as read last.
+struct ArchiveCache {
+    top: u64,
+    rows: Vec<ArchivedLine>,
+}
+
 pub struct SerialMonitorBackend {
     pub id: u64,
     pub url_regex: RegexSearch,
@@ -140,6 +197,37 @@ pub struct SerialMonitorBackend {
     size: TerminalSize,
     notifier: SerialNotifier,
     last_content: RenderableContent,
+    tap: SerialTap,
+    display_mode: DisplayMode,
+    hex_dump_settings: HexDumpSettings,
+    hex_dump_scroll: usize,
+    control_tokens: Option<ControlTokenStyle>,
+    line_stamps: Arc<FairMutex<LineStamps>>,
+    rx_newline: RxNewline,
+    tx_newline: TxNewline,
+    local_echo: bool,
+    local_echo_color: Option<Color>,
+    input_mode: InputMode,
+    encoding: Encoding,
+    settings: BackendSettings,
+    /// Archived line at the top of the viewport, while scrolled past the
+    /// grid history.
+    archive_top: Option<u64>,
+    archive_cache: Option<ArchiveCache>,
+    search: Option<Search>,
+    highlighter: Highlighter,
+    triggers: Vec<Trigger>,
+    trigger_events: Option<Receiver<TriggerEvent>>,
+    transfer_sender: Sender<TransferEvent>,
+    transfer_events: Receiver<TransferEvent>,
+    /// Whether the last started file transfer is still running.
+    transfer_active: Arc<AtomicBool>,
+    zmodem_detection: bool,
+    file_send: Option<FileSend>,
+    modbus_table: ModbusTable,
+    app_context: egui::Context,
+    #[cfg(feature = "scripting")]
+    scripts: ScriptHost,
 }
 
 impl SerialMonitorBackend {
@@ -149,14 +237,51 @@ impl SerialMonitorBackend {
         tty_event_proxy_sender: Sender<(u64, TtyEvent)>,
         serial_settings: SerialTtyOptions,
     ) -> Result<Self> {
-        let config = term::Config::default();
-        let terminal_size = TerminalSize::default();
+        Self::with_settings(
+            id,
+            app_context,
+            tty_event_proxy_sender,
+            serial_settings,
+            BackendSettings::default(),
+        )
+    }
+
+    /// Like [`Self::new`], with the scrollback size and other terminal
+    /// settings.
+    pub fn with_settings(
+        id: u64,
+        app_context: egui::Context,
+        tty_event_proxy_sender: Sender<(u64, TtyEvent)>,
+        serial_settings: SerialTtyOptions,
+        settings: BackendSettings,
+    ) -> Result<Self> {
+        let tty = serial_tty::new(
+            &serial_settings,
+            TerminalSize::default().into(),
+            id,
+        )?;
+        Self::with_tty(id, app_context, tty_event_proxy_sender, tty, settings)
+    }
 
-        let tty = serial_tty::new(&serial_settings, terminal_size.into(), id)?;
+    pub(crate) fn with_tty(
+        id: u64,
+        app_context: egui::Context,
+        tty_event_proxy_sender: Sender<(u64, TtyEvent)>,
+        tty: SerialTty,
+        settings: BackendSettings,
+    ) -> Result<Self> {
+        let config = term::Config::from(&settings);
+        let mut line_stamps = LineStamps::new(config.scrolling_history);
+        if let Some(path) = &settings.scrollback_archive {
+            line_stamps.set_archive(Some(ScrollbackArchive::create(path)?));
+        }
+        let line_stamps = Arc::new(FairMutex::new(line_stamps));
+        let terminal_size = TerminalSize::default();
 
         let (event_sender, event_receiver) = mpsc::channel();
         let event_proxy = EventProxy(event_sender);
         let mut term = Term::new(config, &terminal_size, event_proxy.clone());
+        let hex_dump_settings = HexDumpSettings::default();
         let initial_content = RenderableContent {
             grid: term.grid().clone(),
             selectable_range: None,
@@ -164,13 +289,30 @@ impl SerialMonitorBackend {
             terminal_size,
             cursor: term.grid_mut().cursor_cell().clone(),
             hovered_hyperlink: None,
+            display_mode: DisplayMode::default(),
+            hex_dump: vec![],
+            hex_dump_bytes_per_row: hex_dump_settings.bytes_per_row,
+            line_stamps: vec![],
+            ..RenderableContent::default()
         };
         let term = Arc::new(FairMutex::new(term));
-        let serial_event_loop =
-            SerialEventLoop::new(term.clone(), event_proxy, tty, false, false)?;
+        let (transfer_sender, transfer_events) = mpsc::channel();
+        let tap = SerialTap::new(hex_dump_settings.capacity);
+        let serial_event_loop = SerialEventLoop::new(
+            term.clone(),
+            event_proxy,
+            tty,
+            tap.clone(),
+            line_stamps.clone(),
+            false,
+            false,
+        )?;
         let notifier = SerialNotifier(serial_event_loop.channel());
         let url_regex = RegexSearch::new(r#"(ipfs:|ipns:|magnet:|mailto:|gemini://|gopher://|https://|http://|news:|file://|git://|ssh:|ftp://)[^\u{0000}-\u{001F}\u{007F}-\u{009F}<>"\s{-}\^⟨⟩`]+"#).unwrap();
+        #[cfg(feature = "scripting")]
+        let scripts = ScriptHost::new(app_context.clone());
         let _tty_event_loop_thread = serial_event_loop.spawn();
+        let repaint_context = app_context.clone();
         let _tty_event_subscription = std::thread::Builder::new()
                 .name(format!("tty_event_subscription_{}", id))
                 .spawn(move || loop {
@@ -194,6 +336,34 @@ impl SerialMonitorBackend {
             size: terminal_size,
             notifier,
             last_content: initial_content,
+            tap,
+            display_mode: DisplayMode::default(),
+            hex_dump_settings,
+            hex_dump_scroll: 0,
+            control_tokens: None,
+            line_stamps,
+            rx_newline: RxNewline::default(),
+            tx_newline: TxNewline::default(),
+            local_echo: false,
+            local_echo_color: Some(Color::Named(NamedColor::Cyan)),
+            input_mode: InputMode::default(),
+            encoding: Encoding::default(),
+            settings,
+            archive_top: None,
+            archive_cache: None,
+            search: None,
+            highlighter: Highlighter::default(),
+            triggers: vec![],
+            trigger_events: None,
+            transfer_sender,
+            transfer_events,
+            transfer_active: Arc::new(AtomicBool::new(false)),
+            zmodem_detection: false,
+            file_send: None,
+            modbus_table: ModbusTable::default(),
+            app_context: repaint_context,
+            #[cfg(feature = "scripting")]
+            scripts,
         })
     }
 
@@ -202,8 +372,17 @@ impl SerialMonitorBackend {
         let mut term = term.lock();
         match cmd {
             BackendCommand::Write(input) => {
-                self.write(input);
+                let input = self.tx_newline.translate(&input);
+                if self.local_echo {
+                    let _ = self.notifier.0.send(SerialMsg::Echo(
+                        input.clone(),
+                        self.local_echo_color,
+                    ));
+                }
+                self.write(self.encoding.encode(&input));
                 term.scroll_display(Scroll::Bottom);
+                self.hex_dump_scroll = 0;
+                self.archive_top = None;
             },
             BackendCommand::Scroll(delta) => {
                 self.scroll(&mut term, delta);
@@ -223,6 +402,17 @@ impl SerialMonitorBackend {
             BackendCommand::MouseReport(button, modifiers, point, pressed) => {
                 self.process_mouse_report(button, modifiers, point, pressed);
             },
+            BackendCommand::ToggleLocalEcho => {
+                self.local_echo = !self.local_echo;
+            },
+            BackendCommand::RunScript(name) => {
+                #[cfg(feature = "scripting")]
+                if let Err(err) = self.run_script(&name) {
+                    error!("Error running script {:?}: {}", name, err);
+                }
+                #[cfg(not(feature = "scripting"))]
+                let _ = name;
+            },
         };
     }
 
@@ -258,7 +448,89 @@ impl SerialMonitorBackend {
         result
     }
 
+    /// Selected text with the RX timestamp of each line as prefix.
+    pub fn selectable_content_with_timestamps(
+        &self,
+        format: TimestampFormat,
+    ) -> String {
+        let content = self.last_content();
+        let Some(range) = content.selectable_range else {
+            return String::new();
+        };
+
+        let line_stamps = self.line_stamps.lock();
+        let mut lines = vec![];
+        for line in range.start.line.0..=range.end.line.0 {
+            let line = Line(line);
+            let start = if line == range.start.line || range.is_block {
+                range.start.column
+            } else {
+                Column(0)
+            };
+            let end = if line == range.end.line || range.is_block {
+                range.end.column
+            } else {
+                content.grid.last_column()
+            };
+
+            lines.push(format_stamped_line(
+                &line_stamps,
+                format,
+                line,
+                row_text(&content.grid, line, start, end),
+            ));
+        }
+
+        lines.join("\n")
+    }
+
+    /// Whole scrollback and screen as text, optionally with RX timestamps.
+    pub fn export_text(&self, format: Option<TimestampFormat>) -> String {
+        let term = self.term.lock();
+        let grid = term.grid();
+        let line_stamps = self.line_stamps.lock();
+        let mut lines = vec![];
+        for line in grid.topmost_line().0..=grid.bottommost_line().0 {
+            let line = Line(line);
+            let text = row_text(grid, line, Column(0), grid.last_column());
+            lines.push(match format {
+                Some(format) => {
+                    format_stamped_line(&line_stamps, format, line, text)
+                },
+                None => text,
+            });
+        }
+
+        while lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
+
+        lines.join("\n")
+    }
+
+    fn visible_line_stamps(
+        &self,
+        terminal: &Term<EventProxy>,
+    ) -> Vec<LineStamp> {
+        if terminal.mode().contains(TermMode::ALT_SCREEN) {
+            return vec![];
+        }
+
+        let line_stamps = self.line_stamps.lock();
+        let top = -(terminal.grid().display_offset() as i32);
+        let mut previous = line_stamps.previous(Line(top));
+        (top..top + terminal.screen_lines() as i32)
+            .map(|line| {
+                let stamp = line_stamps.get(Line(line));
+                let line_stamp = LineStamp { stamp, previous };
+                previous = stamp.or(previous);
+                line_stamp
+            })
+            .collect()
+    }
+
     pub fn sync(&mut self) -> &RenderableContent {
+        self.poll_search();
         let term = self.term.clone();
         let mut terminal = term.lock();
         let selectable_range = match &terminal.selection {
@@ -272,9 +544,896 @@ impl SerialMonitorBackend {
         self.last_content.cursor = cursor.clone();
         self.last_content.terminal_mode = *terminal.mode();
         self.last_content.terminal_size = self.size;
+        self.last_content.line_stamps = self.visible_line_stamps(&terminal);
+        self.last_content.display_mode = self.display_mode;
+        self.last_content.hex_dump_bytes_per_row =
+            self.hex_dump_settings.bytes_per_row;
+        self.last_content.archive_rows = match self.archive_top {
+            Some(top) if self.display_mode == DisplayMode::Text => {
+                self.archive_rows(&terminal, top)
+            },
+            _ => vec![],
+        };
+        self.last_content.bookmarked_rows = self.visible_bookmarks(&terminal);
+        self.update_search_matches(&terminal);
+        self.last_content.highlights = self.visible_highlights(&terminal);
+        self.last_content.hex_dump = match self.display_mode {
+            DisplayMode::Text => vec![],
+            DisplayMode::Hex => hex_dump::rows(
+                &self.tap.log().lock(),
+                self.hex_dump_settings.bytes_per_row,
+                self.size.num_lines as usize,
+                self.hex_dump_scroll,
+            ),
+        };
         self.last_content()
     }
 
+    pub fn display_mode(&self) -> DisplayMode {
+        self.display_mode
+    }
+
+    /// Switch between terminal and hex dump rendering.
+    ///
+    /// The terminal state keeps being updated in both modes.
+    pub fn set_display_mode(&mut self, display_mode: DisplayMode) {
+        self.display_mode = display_mode;
+        self.hex_dump_scroll = 0;
+    }
+
+    pub fn hex_dump_settings(&self) -> &HexDumpSettings {
+        &self.hex_dump_settings
+    }
+
+    pub fn set_hex_dump_settings(&mut self, settings: HexDumpSettings) {
+        self.tap.log().lock().set_capacity(settings.capacity);
+        self.hex_dump_settings = HexDumpSettings {
+            bytes_per_row: settings.bytes_per_row.max(1),
+            ..settings
+        };
+        self.hex_dump_scroll = 0;
+    }
+
+    pub fn control_tokens(&self) -> Option<ControlTokenStyle> {
+        self.control_tokens
+    }
+
+    /// Show control bytes and invalid UTF-8 which the terminal would drop
+    /// as inline tokens. `None` restores the default behaviour.
+    pub fn set_control_tokens(&mut self, style: Option<ControlTokenStyle>) {
+        self.control_tokens = style;
+        let _ = self.notifier.0.send(SerialMsg::ControlTokens(style));
+    }
+
+    pub fn rx_newline(&self) -> RxNewline {
+        self.rx_newline
+    }
+
+    /// Force newline handling of received bytes regardless of the terminal
+    /// mode requested by the device.
+    pub fn set_rx_newline(&mut self, policy: RxNewline) {
+        self.rx_newline = policy;
+        let _ = self.notifier.0.send(SerialMsg::RxNewline(policy));
+    }
+
+    pub fn tx_newline(&self) -> TxNewline {
+        self.tx_newline
+    }
+
+    /// Line ending used for Enter and pasted text.
+    ///
+    /// Applies to [`BackendCommand::Write`], while [`Self::write`] keeps
+    /// sending the bytes untouched.
+    pub fn set_tx_newline(&mut self, newline: TxNewline) {
+        self.tx_newline = newline;
+    }
+
+    pub fn local_echo(&self) -> bool {
+        self.local_echo
+    }
+
+    /// Show typed and pasted bytes in the terminal for devices which don't
+    /// echo them back.
+    pub fn set_local_echo(&mut self, enabled: bool) {
+        self.local_echo = enabled;
+    }
+
+    /// Color of the echoed bytes, `None` keeps the current terminal color.
+    pub fn set_local_echo_color(&mut self, color: Option<Color>) {
+        self.local_echo_color = color;
+    }
+
+    pub fn input_mode(&self) -> InputMode {
+        self.input_mode
+    }
+
+    /// Switch between raw keystroke passthrough and the line editor.
+    pub fn set_input_mode(&mut self, input_mode: InputMode) {
+        self.input_mode = input_mode;
+    }
+
+    pub fn settings(&self) -> &BackendSettings {
+        &self.settings
+    }
+
+    /// Apply terminal settings to the running session.
+    ///
+    /// Shrinking the scrollback drops the oldest lines of the history, or
+    /// moves them to the scrollback archive when there is one.
+    pub fn set_settings(&mut self, settings: BackendSettings) -> Result<()> {
+        let mut terminal = self.term.lock();
+        let mut line_stamps = self.line_stamps.lock();
+        if settings.scrollback_archive != self.settings.scrollback_archive {
+            let archive = match &settings.scrollback_archive {
+                Some(path) => Some(ScrollbackArchive::create(path)?),
+                None => None,
+            };
+            line_stamps.set_archive(archive);
+            self.archive_top = None;
+            self.archive_cache = None;
+        }
+
+        line_stamps.archive_history(&terminal, settings.scrolling_history);
+        terminal.set_options(term::Config::from(&settings));
+        line_stamps.set_max_history(settings.scrolling_history);
+        self.settings = settings;
+        Ok(())
+    }
+
+    /// Rules colorizing the matching text of the grid.
+    ///
+    /// Cheap when the rules didn't change, so it can be called every frame.
+    pub fn set_highlight_rules(&mut self, rules: &[HighlightRule]) {
+        self.highlighter.set_rules(rules);
+    }
+
+    fn visible_highlights(
+        &mut self,
+        terminal: &Term<EventProxy>,
+    ) -> Vec<Vec<HighlightSpan>> {
+        if self.highlighter.is_empty() {
+            return vec![];
+        }
+
+        let grid = terminal.grid();
+        let display_offset = grid.display_offset() as i32;
+        (0..grid.screen_lines() as i32)
+            .map(|row| {
+                let line = Line(row - display_offset);
+                let mut text = String::new();
+                let mut columns = vec![];
+                for (column, cell) in grid[line].into_iter().enumerate() {
+                    if !cell.flags.contains(term::cell::Flags::WIDE_CHAR_SPACER)
+                    {
+                        text.push(cell.c);
+                        columns.push(column);
+                    }
+                }
+
+                self.highlighter.row_spans(&text, &columns, grid.columns())
+            })
+            .collect()
+    }
+
+    /// Look for `pattern` in the scrollback archive, history and screen,
+    /// focusing the match closest to the bottom.
+    ///
+    /// The archive is scanned on a worker thread, the match is focused once
+    /// `search_status` stops being `Searching`. Fails on an invalid regex.
+    pub fn search(
+        &mut self,
+        pattern: &str,
+        kind: SearchKind,
+        case_sensitive: bool,
+    ) -> Result<SearchStatus> {
+        if pattern.is_empty() {
+            self.clear_search();
+            return Ok(SearchStatus::NotFound);
+        }
+
+        self.search = Some(Search::new(pattern, kind, case_sensitive)?);
+        Ok(self.search_prev())
+    }
+
+    /// Focus the next match towards the bottom, wrapping around.
+    pub fn search_next(&mut self) -> SearchStatus {
+        self.step_search(Direction::Right)
+    }
+
+    /// Focus the previous match towards older lines, wrapping around.
+    pub fn search_prev(&mut self) -> SearchStatus {
+        self.step_search(Direction::Left)
+    }
+
+    pub fn clear_search(&mut self) {
+        self.search = None;
+    }
+
+    pub fn search_status(&self) -> SearchStatus {
+        match &self.search {
+            Some(search) if search.pending.is_some() => SearchStatus::Searching,
+            Some(Search {
+                current: Some(_), ..
+            }) => SearchStatus::Found,
+            _ => SearchStatus::NotFound,
+        }
+    }
+
+    fn step_search(&mut self, direction: Direction) -> SearchStatus {
+        let Some(mut search) = self.search.take() else {
+            return SearchStatus::NotFound;
+        };
+
+        // A step while the archive is still scanned starts over from the
+        // same match.
+        search.pending = None;
+        let archive = self.archive_reader().unwrap_or_else(|err| {
+            error!("Error reading scrollback archive: {}", err);
+            None
+        });
+        let archive_len = archive.as_ref().map(ArchiveReader::len);
+        // Only the grid is searched with the terminal locked.
+        let steps = search_steps(
+            &self.term.lock(),
+            &mut search,
+            direction,
+            archive_len,
+        );
+        match (steps.first(), archive) {
+            (Some(SearchStep::Archive { .. }), Some(archive)) => {
+                search.pending = Some(PendingSearch::spawn(
+                    archive,
+                    search.line_regex.clone(),
+                    steps,
+                    self.app_context.clone(),
+                ));
+            },
+            (Some(SearchStep::Found(found)), _) => {
+                let found = found.clone();
+                self.focus_search_match(&mut search, Some(found));
+            },
+            _ => self.focus_search_match(&mut search, None),
+        }
+
+        self.search = Some(search);
+        self.search_status()
+    }
+
+    /// Focus the match of an archive scan which is done.
+    fn poll_search(&mut self) {
+        let Some(mut search) = self.search.take() else {
+            return;
+        };
+
+        if let Some(found) =
+            search.pending.as_ref().and_then(PendingSearch::try_found)
+        {
+            search.pending = None;
+            self.focus_search_match(&mut search, found);
+        }
+        self.search = Some(search);
+    }
+
+    fn focus_search_match(
+        &mut self,
+        search: &mut Search,
+        found: Option<SearchMatch>,
+    ) {
+        match &found {
+            Some(SearchMatch::Grid(grid_match)) => {
+                self.archive_top = None;
+                self.term.lock().scroll_to_point(*grid_match.start());
+            },
+            Some(SearchMatch::Archive(line)) => {
+                let center = self.size.num_lines as u64 / 2;
+                self.term.lock().scroll_display(Scroll::Top);
+                self.archive_top = Some(line.saturating_sub(center));
+            },
+            None => {},
+        }
+        search.current = found;
+    }
+
+    fn update_search_matches(&mut self, terminal: &Term<EventProxy>) {
+        let content = &mut self.last_content;
+        content.search_matches.clear();
+        content.focused_search_match = None;
+        content.archive_search_matches.clear();
+        content.focused_archive_row = None;
+        let Some(search) = &mut self.search else {
+            return;
+        };
+
+        match &search.current {
+            Some(SearchMatch::Grid(current)) => {
+                content.focused_search_match = Some(current.clone())
+            },
+            Some(SearchMatch::Archive(line)) => {
+                content.focused_archive_row = self
+                    .archive_top
+                    .and_then(|top| line.checked_sub(top))
+                    .map(|row| row as usize)
+            },
+            None => {},
+        }
+
+        if content.archive_rows.is_empty() {
+            content.search_matches =
+                visible_regex_match_iter(terminal, &mut search.grid_regex)
+                    .collect();
+            return;
+        }
+
+        for (row, line) in content.archive_rows.iter().enumerate() {
+            for found in search.line_regex.find_iter(&line.text) {
+                // Columns are counted in chars, like the cells of the row.
+                let start = line.text[..found.start()].chars().count();
+                let end = start + found.as_str().chars().count();
+                content.archive_search_matches.push((row, start..end));
+            }
+        }
+    }
+
+    /// Amount of lines in the scrollback archive.
+    pub fn archived_lines(&self) -> u64 {
+        self.line_stamps
+            .lock()
+            .archive()
+            .map_or(0, |archive| archive.len())
+    }
+
+    /// Reader of the scrollback archive, so the file is read without
+    /// holding the line stamps the event loop needs.
+    fn archive_reader(&self) -> Result<Option<ArchiveReader>> {
+        self.line_stamps
+            .lock()
+            .archive()
+            .map(ScrollbackArchive::reader)
+            .transpose()
+    }
+
+    /// Index of the first archived line matching `regex`, looking from line
+    /// `from` in the given direction.
+    pub fn find_in_archive(
+        &self,
+        regex: &regex::Regex,
+        from: u64,
+        backwards: bool,
+    ) -> Result<Option<u64>> {
+        match self.archive_reader()? {
+            Some(mut archive) => archive.find(regex, from, backwards),
+            None => Ok(None),
+        }
+    }
+
+    /// Scroll the viewport to show an archived line at its top.
+    pub fn scroll_to_archived_line(&mut self, line: u64) {
+        let archived_lines = self.archived_lines();
+        if line >= archived_lines {
+            return;
+        }
+
+        let mut terminal = self.term.lock();
+        terminal.scroll_display(Scroll::Top);
+        self.archive_top = Some(line);
+        self.display_mode = DisplayMode::Text;
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Character encoding used on the wire.
+    ///
+    /// Received bytes are decoded before the terminal parser, typed and
+    /// pasted text is encoded before it's sent.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+        let _ = self.notifier.0.send(SerialMsg::Encoding(encoding));
+    }
+
+    pub fn triggers(&self) -> &[Trigger] {
+        &self.triggers
+    }
+
+    /// Actions to run when a received line matches a pattern.
+    ///
+    /// The triggers are evaluated by the event loop as the bytes arrive, a
+    /// line without newline is matched too, so prompts can be answered.
+    pub fn set_triggers(&mut self, triggers: &[Trigger]) -> Result<()> {
+        let set = if triggers.is_empty() {
+            self.trigger_events = None;
+            None
+        } else {
+            let (sender, receiver) = mpsc::channel();
+            let set = TriggerSet::new(triggers, sender)?;
+            self.trigger_events = Some(receiver);
+            Some(set)
+        };
+
+        self.triggers = triggers.to_vec();
+        let _ = self.notifier.0.send(SerialMsg::Triggers(set));
+        Ok(())
+    }
+
+    /// Events of the `TriggerAction::Event` triggers since the last call.
+    pub fn trigger_events(&self) -> Vec<TriggerEvent> {
+        self.trigger_events
+            .as_ref()
+            .map(|events| events.try_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Handle for send/expect scripts, which may run on another thread
+    /// while the session is shown by the view.
+    ///
+    /// Sent lines use the current encoding and TX line ending.
+    pub fn expect_session(&self) -> ExpectSession {
+        let (sender, receiver) = subscription(expect::MAX_BUFFER_SIZE);
+        let _ = self.notifier.0.send(SerialMsg::Subscribe(sender));
+        ExpectSession::new(
+            self.notifier.0.clone(),
+            receiver,
+            self.encoding,
+            self.tx_newline,
+        )
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn scripts(&self) -> &[Script] {
+        self.scripts.scripts()
+    }
+
+    /// Scripts which can be run by name, for example from a
+    /// `BindingAction::RunScript` key binding.
+    #[cfg(feature = "scripting")]
+    pub fn set_scripts(&mut self, scripts: &[Script]) {
+        self.scripts.set_scripts(scripts);
+    }
+
+    /// Start a script on a worker thread, its errors are reported by
+    /// `script_notifications`.
+    #[cfg(feature = "scripting")]
+    pub fn run_script(&mut self, name: &str) -> Result<()> {
+        let script = self.scripts.script(name).cloned().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No script named {:?}", name),
+            )
+        })?;
+        let session = self.expect_session();
+        self.scripts.run(script, session)
+    }
+
+    /// Names of the scripts which are still running.
+    #[cfg(feature = "scripting")]
+    pub fn running_scripts(&self) -> Vec<String> {
+        self.scripts.running()
+    }
+
+    /// Stop the running scripts.
+    #[cfg(feature = "scripting")]
+    pub fn cancel_scripts(&mut self) {
+        self.scripts.cancel();
+    }
+
+    /// Notifications and errors of the scripts since the last call.
+    #[cfg(feature = "scripting")]
+    pub fn script_notifications(&self) -> Vec<ScriptNotification> {
+        self.scripts.notifications()
+    }
+
+    /// Drive the DTR modem line.
+    pub fn set_dtr(&mut self, level: bool) {
+        let _ = self.notifier.0.send(SerialMsg::Dtr(level));
+    }
+
+    /// Drive the RTS modem line.
+    pub fn set_rts(&mut self, level: bool) {
+        let _ = self.notifier.0.send(SerialMsg::Rts(level));
+    }
+
+    /// Send files with XMODEM, XMODEM-1K, or YMODEM, ZMODEM and Kermit for
+    /// several files. ZMODEM starts `rz` on the remote when it isn't waiting
+    /// yet.
+    ///
+    /// The transfer takes over the stream until it is done, typed input is
+    /// dropped meanwhile. Its progress is reported by `transfer_events`.
+    pub fn send_files(
+        &mut self,
+        protocol: TransferProtocol,
+        paths: &[PathBuf],
+    ) -> Result<()> {
+        let is_batch = matches!(
+            protocol,
+            TransferProtocol::Ymodem
+                | TransferProtocol::Zmodem
+                | TransferProtocol::Kermit
+        );
+        if !is_batch && paths.len() != 1 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "XMODEM sends a single file",
+            ));
+        }
+
+        let files = paths
+            .iter()
+            .map(|path| SendFile::read(path))
+            .collect::<Result<_>>()?;
+        match protocol {
+            TransferProtocol::Zmodem => {
+                self.start_transfer(Box::new(ZmodemSender::new(files)))
+            },
+            TransferProtocol::Kermit => {
+                self.start_transfer(Box::new(KermitSender::new(files)))
+            },
+            _ => self
+                .start_transfer(Box::new(XmodemSender::new(protocol, files))),
+        }
+    }
+
+    /// Receive files with XMODEM, YMODEM, ZMODEM or Kermit.
+    ///
+    /// `destination` is the file to write for XMODEM, and the directory of
+    /// the received files for the other protocols.
+    pub fn receive_files(
+        &mut self,
+        protocol: TransferProtocol,
+        destination: impl Into<PathBuf>,
+    ) -> Result<()> {
+        let destination = destination.into();
+        let is_batch = matches!(
+            protocol,
+            TransferProtocol::Ymodem
+                | TransferProtocol::Zmodem
+                | TransferProtocol::Kermit
+        );
+        if is_batch && !destination.is_dir() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{} is not a directory", destination.display()),
+            ));
+        }
+
+        match protocol {
+            TransferProtocol::Zmodem => {
+                self.start_transfer(Box::new(ZmodemReceiver::new(destination)))
+            },
+            TransferProtocol::Kermit => {
+                self.start_transfer(Box::new(KermitReceiver::new(destination)))
+            },
+            _ => self.start_transfer(Box::new(XmodemReceiver::new(
+                protocol,
+                destination,
+            ))),
+        }
+    }
+
+    fn start_transfer(&mut self, transfer: Box<dyn Transfer>) -> Result<()> {
+        let job = TransferJob::new(transfer, self.transfer_sender.clone());
+        self.transfer_active = job.active.clone();
+        self.notifier
+            .0
+            .send(SerialMsg::Transfer(job))
+            .map_err(|err| Error::new(ErrorKind::BrokenPipe, err.to_string()))
+    }
+
+    /// Cancel the running file transfer, telling the peer.
+    pub fn cancel_transfer(&mut self) {
+        let _ = self.notifier.0.send(SerialMsg::CancelTransfer);
+    }
+
+    #[inline]
+    pub fn is_transferring(&self) -> bool {
+        self.transfer_active.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn zmodem_detection(&self) -> bool {
+        self.zmodem_detection
+    }
+
+    /// Watch the received bytes for a remote `sz` or `rz`, disabled by
+    /// default. A detected one is reported as
+    /// `TransferEvent::ZmodemRequest`, it has to be accepted or cancelled
+    /// before the terminal gets the bytes again.
+    pub fn set_zmodem_detection(&mut self, enabled: bool) {
+        self.zmodem_detection = enabled;
+        let events = enabled.then(|| self.transfer_sender.clone());
+        let _ = self.notifier.0.send(SerialMsg::ZmodemDetection(events));
+    }
+
+    /// Progress and results of the file transfers since the last call.
+    pub fn transfer_events(&self) -> Vec<TransferEvent> {
+        self.transfer_events.try_iter().collect()
+    }
+
+    /// Write a file to the device as it is, streamed from disk without
+    /// newline translation or local echo.
+    ///
+    /// Each chunk waits until the previous one was written, so flow control
+    /// holds the file back. Progress is reported by `file_send_events`.
+    pub fn send_raw_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        options: FileSendOptions,
+    ) -> Result<()> {
+        if self.is_sending_file() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "A file is already being sent",
+            ));
+        }
+        if self.is_transferring() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "A file transfer holds the stream",
+            ));
+        }
+
+        self.file_send = Some(FileSend::start(
+            path.as_ref(),
+            options,
+            self.notifier.0.clone(),
+            self.app_context.clone(),
+        )?);
+        Ok(())
+    }
+
+    /// Stop the raw file send, the chunk being written still goes out.
+    pub fn cancel_file_send(&mut self) {
+        if let Some(file_send) = &self.file_send {
+            file_send.cancel();
+        }
+    }
+
+    pub fn is_sending_file(&self) -> bool {
+        self.file_send
+            .as_ref()
+            .is_some_and(|file_send| file_send.is_running())
+    }
+
+    /// Events of the raw file send since the last call.
+    pub fn file_send_events(&self) -> Vec<FileSendEvent> {
+        self.file_send
+            .as_ref()
+            .map(|file_send| file_send.events())
+            .unwrap_or_default()
+    }
+
+    pub fn logging_paused(&self) -> bool {
+        self.tap.log().lock().is_paused()
+    }
+
+    /// Stop recording the traffic log, the hex dump keeps its content.
+    pub fn set_logging_paused(&mut self, paused: bool) {
+        self.tap.log().lock().set_paused(paused);
+    }
+
+    /// Absolute indexes of the bookmarked lines, oldest first.
+    pub fn bookmarks(&self) -> Vec<u64> {
+        self.line_stamps
+            .lock()
+            .bookmarks()
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Bookmark the line under the cursor.
+    pub fn add_bookmark(&mut self) {
+        let line = self.term.lock().grid().cursor.point.line;
+        self.line_stamps.lock().bookmark(line);
+    }
+
+    pub fn clear_bookmarks(&mut self) {
+        self.line_stamps.lock().clear_bookmarks();
+    }
+
+    /// Scroll the viewport to show a bookmarked line.
+    pub fn scroll_to_bookmark(&mut self, bookmark: u64) {
+        let line = self.line_stamps.lock().line(bookmark);
+        let mut terminal = self.term.lock();
+        if line < terminal.grid().topmost_line() {
+            return;
+        }
+
+        self.archive_top = None;
+        self.display_mode = DisplayMode::Text;
+        terminal.scroll_to_point(Point::new(line, Column(0)));
+    }
+
+    /// Split the received bytes into frames with `framer`, checking the end
+    /// of each frame with `check`. `None` stops framing, the frames received
+    /// so far are kept.
+    pub fn set_framer(
+        &mut self,
+        framer: Option<Box<dyn Framer>>,
+        check: FrameCheck,
+    ) {
+        self.tap.frames().lock().set_framer(framer, check);
+    }
+
+    pub fn is_framing(&self) -> bool {
+        self.tap.frames().lock().is_framing()
+    }
+
+    /// Frames received since the one numbered `index`, included, as long
+    /// as they are still kept.
+    pub fn frames_since(&self, index: u64) -> Vec<DecodedFrame> {
+        self.tap.frames().lock().since(index).cloned().collect()
+    }
+
+    pub fn clear_frames(&mut self) {
+        self.tap.frames().lock().clear();
+    }
+
+    #[inline]
+    pub(crate) fn frame_log(&self) -> &Arc<FairMutex<FrameLog>> {
+        self.tap.frames()
+    }
+
+    /// Decode the received frames which pass their check with `schema`,
+    /// `None` stops decoding and logging.
+    pub fn set_packet_schema(&mut self, schema: Option<PacketSchema>) {
+        self.tap.frames().lock().set_schema(schema);
+    }
+
+    /// Load a schema file and decode the received frames with it.
+    pub fn load_packet_schema(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let schema = PacketSchema::load(path)?;
+        self.set_packet_schema(Some(schema));
+        Ok(())
+    }
+
+    /// Last packet of every layout of the schema, in the order of the
+    /// schema.
+    pub fn decoded_packets(&self) -> Vec<DecodedPacket> {
+        self.tap.frames().lock().latest_packets()
+    }
+
+    /// Log every packet decoded from now on to `path`, replacing it.
+    pub fn start_packet_log(
+        &mut self,
+        path: impl AsRef<Path>,
+        format: SchemaLogFormat,
+    ) -> Result<()> {
+        let mut frames = self.tap.frames().lock();
+        let decoder = frames.schema_decoder().ok_or_else(|| {
+            Error::new(ErrorKind::InvalidInput, "No packet schema is set")
+        })?;
+        decoder.start_log(path.as_ref(), format)
+    }
+
+    pub fn stop_packet_log(&mut self) {
+        if let Some(decoder) = self.tap.frames().lock().schema_decoder() {
+            decoder.stop_log();
+        }
+    }
+
+    /// Modbus RTU master writing to this port, its requests and the values
+    /// it reads or writes are kept in `modbus_table`.
+    ///
+    /// Pair it with `set_framer(Some(Box::new(ModbusFramer::new(..))), ..)`
+    /// to see the traffic in the `FrameListView`.
+    pub fn modbus_master(&self) -> ModbusMaster {
+        ModbusMaster::new(
+            self.notifier.0.clone(),
+            self.tap.clone(),
+            self.modbus_table.clone(),
+        )
+    }
+
+    #[inline]
+    pub fn modbus_table(&self) -> &ModbusTable {
+        &self.modbus_table
+    }
+
+    /// Read NMEA 0183 sentences from the received bytes into `gnss_state`,
+    /// and underline the sentences whose checksum is wrong in red.
+    pub fn set_nmea_decoding(&mut self, enabled: bool) {
+        self.tap.nmea().lock().set_enabled(enabled);
+        self.highlighter.set_nmea_checksums(enabled);
+    }
+
+    pub fn is_decoding_nmea(&self) -> bool {
+        self.tap.nmea().lock().is_enabled()
+    }
+
+    /// Fix, position and satellites reported by the NMEA sentences so far.
+    pub fn gnss_state(&self) -> GnssState {
+        self.tap.nmea().lock().state()
+    }
+
+    pub fn clear_gnss_state(&mut self) {
+        self.tap.nmea().lock().clear();
+    }
+
+    /// Decode the received bytes as `defmt` frames with the format strings
+    /// of `table`, the terminal shows the decoded lines instead of the raw
+    /// bytes. None goes back to the raw bytes, the records are kept.
+    pub fn set_defmt_table(&mut self, table: Option<DefmtTable>) {
+        self.tap.defmt().lock().set_table(table);
+    }
+
+    /// Load the format strings of a firmware ELF file and decode the
+    /// received bytes with them.
+    pub fn load_defmt_elf(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let table = DefmtTable::load(path)?;
+        self.set_defmt_table(Some(table));
+        Ok(())
+    }
+
+    pub fn is_decoding_defmt(&self) -> bool {
+        self.tap.defmt().lock().is_enabled()
+    }
+
+    /// Copy of the decoded log messages from `index` on, oldest first.
+    pub fn defmt_records_since(&self, index: u64) -> Vec<DefmtRecord> {
+        self.tap.defmt().lock().records_since(index)
+    }
+
+    /// Log messages kept, `DEFAULT_DEFMT_CAPACITY` by default.
+    pub fn set_defmt_capacity(&mut self, capacity: usize) {
+        self.tap.defmt().lock().set_capacity(capacity);
+    }
+
+    pub fn clear_defmt_log(&mut self) {
+        self.tap.defmt().lock().clear();
+    }
+
+    #[inline]
+    pub(crate) fn defmt_decoder(&self) -> &Arc<FairMutex<DefmtDecoder>> {
+        self.tap.defmt()
+    }
+
+    /// Plot the numbers of the received lines found by `patterns`, none
+    /// stops plotting. Fails on an invalid regex.
+    pub fn set_plot_patterns(
+        &mut self,
+        patterns: &[PlotPattern],
+    ) -> Result<()> {
+        self.tap.plot().lock().set_patterns(patterns)
+    }
+
+    pub fn is_plotting(&self) -> bool {
+        self.tap.plot().lock().is_plotting()
+    }
+
+    /// Samples kept per series, `DEFAULT_PLOT_CAPACITY` by default.
+    pub fn set_plot_capacity(&mut self, capacity: usize) {
+        self.tap.plot().lock().set_capacity(capacity);
+    }
+
+    /// Copy of the plotted series, in the order they first appeared.
+    pub fn plot_series(&self) -> Vec<PlotSeries> {
+        self.tap.plot().lock().series().to_vec()
+    }
+
+    pub fn clear_plot(&mut self) {
+        self.tap.plot().lock().clear();
+    }
+
+    /// Write the plotted series to a CSV file, a row per received line
+    /// with the time in seconds since the Unix epoch.
+    pub fn export_plot_csv(&self, path: impl AsRef<Path>) -> Result<()> {
+        let series = self.plot_series();
+        let file = std::fs::File::create(path)?;
+        plot::write_csv(&series, std::io::BufWriter::new(file))
+    }
+
+    #[inline]
+    pub(crate) fn plotter(&self) -> &Arc<FairMutex<Plotter>> {
+        self.tap.plot()
+    }
+
+    /// Forget the raw traffic shown by the hex dump.
+    pub fn clear_hex_dump(&mut self) {
+        self.tap.log().lock().clear();
+        self.hex_dump_scroll = 0;
+    }
+
     pub fn last_content(&self) -> &RenderableContent {
         &self.last_content
     }
@@ -473,10 +1632,25 @@ impl SerialMonitorBackend {
             };
 
             self.notifier.on_resize(self.size.into());
+            let mut line_stamps = self.line_stamps.lock();
+            let history_size = terminal.grid().history_size();
+            // Keep the lines the reflow pushes out of the history, so they
+            // can be archived before being dropped.
+            let is_archiving = line_stamps.archive().is_some();
+            if is_archiving {
+                terminal.set_options(term::Config {
+                    scrolling_history: usize::MAX / 2,
+                    ..term::Config::from(&self.settings)
+                });
+            }
             terminal.resize(TermSize::new(
                 self.size.num_cols as usize,
                 self.size.num_lines as usize,
             ));
+            line_stamps.history_changed(terminal, history_size);
+            if is_archiving {
+                terminal.set_options(term::Config::from(&self.settings));
+            }
         }
     }
 
@@ -485,8 +1659,21 @@ impl SerialMonitorBackend {
     }
 
     fn scroll(&mut self, terminal: &mut Term<EventProxy>, delta_value: i32) {
+        if self.display_mode == DisplayMode::Hex {
+            let total_rows = hex_dump::total_rows(
+                &self.tap.log().lock(),
+                self.hex_dump_settings.bytes_per_row,
+            );
+            let max_scroll =
+                total_rows.saturating_sub(self.size.num_lines as usize);
+            self.hex_dump_scroll = self
+                .hex_dump_scroll
+                .saturating_add_signed(delta_value as isize)
+                .min(max_scroll);
+            return;
+        }
+
         if delta_value != 0 {
-            let scroll = Scroll::Delta(delta_value);
             if terminal
                 .mode()
                 .contains(TermMode::ALTERNATE_SCROLL | TermMode::ALT_SCREEN)
@@ -502,9 +1689,96 @@ impl SerialMonitorBackend {
 
                 self.notifier.notify(content);
             } else {
-                terminal.grid_mut().scroll_display(scroll);
+                self.scroll_with_archive(terminal, delta_value);
+            }
+        }
+    }
+
+    /// Viewport rows of the bookmarked lines.
+    fn visible_bookmarks(&self, terminal: &Term<EventProxy>) -> Vec<usize> {
+        let display_offset = terminal.grid().display_offset() as i32;
+        let screen_lines = terminal.screen_lines() as i32;
+        let line_stamps = self.line_stamps.lock();
+        line_stamps
+            .bookmarks()
+            .iter()
+            .map(|&bookmark| line_stamps.line(bookmark).0 + display_offset)
+            .filter(|row| (0..screen_lines).contains(row))
+            .map(|row| row as usize)
+            .collect()
+    }
+
+    /// Scroll the grid history, continuing into the scrollback archive once
+    /// its top is reached.
+    fn scroll_with_archive(
+        &mut self,
+        terminal: &mut Term<EventProxy>,
+        delta_value: i32,
+    ) {
+        let archived_lines = self.archived_lines();
+        let history_size = terminal.grid().history_size() as u64;
+        let display_offset = terminal.grid().display_offset() as u64;
+        let offset = display_offset
+            + self.archive_top.map_or(0, |top| archived_lines - top);
+        let offset = offset
+            .saturating_add_signed(delta_value as i64)
+            .min(history_size + archived_lines);
+
+        let grid_offset = offset.min(history_size);
+        terminal.grid_mut().scroll_display(Scroll::Delta(
+            grid_offset as i32 - display_offset as i32,
+        ));
+        self.archive_top = (offset > history_size)
+            .then(|| archived_lines - (offset - history_size));
+    }
+
+    /// Rows of the viewport while it shows the scrollback archive, followed
+    /// by the top of the grid history.
+    fn archive_rows(
+        &mut self,
+        terminal: &Term<EventProxy>,
+        top: u64,
+    ) -> Vec<ArchivedLine> {
+        let screen_lines = self.size.num_lines as usize;
+        // Archived lines never change, so they are read again only when
+        // scrolled or when more lines could show up below the cached ones.
+        let is_cached = self.archive_cache.as_ref().is_some_and(|cache| {
+            cache.top == top
+                && (cache.rows.len() >= screen_lines
+                    || top + cache.rows.len() as u64 == self.archived_lines())
+        });
+        if !is_cached {
+            let rows = match self.archive_reader() {
+                Ok(Some(mut archive)) => archive.lines(top, screen_lines),
+                Ok(None) => return vec![],
+                Err(err) => Err(err),
+            };
+            match rows {
+                Ok(rows) => {
+                    self.archive_cache = Some(ArchiveCache { top, rows })
+                },
+                Err(err) => {
+                    error!("Error reading scrollback archive: {}", err);
+                    return vec![];
+                },
             }
         }
+
+        let cache = self.archive_cache.as_ref().expect("cached above");
+        let mut rows: Vec<ArchivedLine> =
+            cache.rows.iter().take(screen_lines).cloned().collect();
+        let line_stamps = self.line_stamps.lock();
+        let grid = terminal.grid();
+        let mut line = grid.topmost_line();
+        while rows.len() < screen_lines && line <= grid.bottommost_line() {
+            rows.push(ArchivedLine {
+                stamp: line_stamps.get(line),
+                text: row_text(grid, line, Column(0), grid.last_column()),
+            });
+            line += 1;
+        }
+
+        rows
     }
 
     /// Based on alacritty/src/display/hint.rs > regex_match_at
@@ -521,6 +1795,89 @@ impl SerialMonitorBackend {
     }
 }
 
+fn format_stamped_line(
+    line_stamps: &LineStamps,
+    format: TimestampFormat,
+    line: Line,
+    text: String,
+) -> String {
+    let stamp = match line_stamps.get(line) {
+        Some(stamp) => format.format(stamp, line_stamps.previous(line)),
+        None => String::new(),
+    };
+
+    format!("{:width$} {}", stamp, text, width = format.width())
+}
+
+/// Places to look for the next match from the focused one, with the
+/// archive placed above the grid history. The grid is searched right away,
+/// the archive steps are left to a worker.
+fn search_steps(
+    terminal: &Term<EventProxy>,
+    search: &mut Search,
+    direction: Direction,
+    archive_len: Option<u64>,
+) -> Vec<SearchStep> {
+    let archive = |from: u64, backwards: bool| {
+        archive_len.map(|_| SearchStep::Archive { from, backwards })
+    };
+    let found = |found: Option<Match>| {
+        found.map(|found| SearchStep::Found(SearchMatch::Grid(found)))
+    };
+
+    let grid = terminal.grid();
+    let bottom = Point::new(grid.bottommost_line(), grid.last_column());
+    let top = Point::new(grid.topmost_line(), Column(0));
+    let mut find_in_grid = |origin: Point| {
+        terminal.search_next(
+            &mut search.grid_regex,
+            origin,
+            direction,
+            Side::Left,
+            None,
+        )
+    };
+
+    let steps = match (direction, search.current.clone()) {
+        (Direction::Left, None) => {
+            vec![found(find_in_grid(bottom)), archive(u64::MAX, true)]
+        },
+        (Direction::Left, Some(SearchMatch::Grid(current))) => {
+            let origin = current.start().sub(grid, Boundary::None, 1);
+            match find_in_grid(origin) {
+                Some(next) if next.start() < current.start() => {
+                    vec![found(Some(next))]
+                },
+                wrapped => vec![archive(u64::MAX, true), found(wrapped)],
+            }
+        },
+        (Direction::Left, Some(SearchMatch::Archive(line))) => vec![
+            line.checked_sub(1).and_then(|line| archive(line, true)),
+            found(find_in_grid(bottom)),
+            archive(u64::MAX, true),
+        ],
+        (Direction::Right, Some(SearchMatch::Grid(current))) => {
+            let origin = current.start().add(grid, Boundary::None, 1);
+            match find_in_grid(origin) {
+                Some(next) if next.start() > current.start() => {
+                    vec![found(Some(next))]
+                },
+                wrapped => vec![archive(0, false), found(wrapped)],
+            }
+        },
+        (Direction::Right, Some(SearchMatch::Archive(line))) => vec![
+            archive(line + 1, false)
+                .filter(|_| archive_len.is_some_and(|len| line + 1 < len)),
+            found(find_in_grid(top)),
+            archive(0, false),
+        ],
+        (Direction::Right, None) => {
+            vec![archive(0, false), found(find_in_grid(top))]
+        },
+    };
+    steps.into_iter().flatten().collect()
+}
+
 /// Copied from alacritty/src/display/hint.rs:
 /// Iterate over all visible regex matches.
 fn visible_regex_match_iter<'a>(
@@ -540,6 +1897,14 @@ fn visible_regex_match_iter<'a>(
         .take_while(move |rm| rm.start().line <= viewport_end)
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineStamp {
+    /// Arrival time of the first byte of the line.
+    pub stamp: Option<SystemTime>,
+    /// Latest stamp of the lines above, used by delta timestamps.
+    pub previous: Option<SystemTime>,
+}
+
 pub struct RenderableContent {
     pub grid: Grid<Cell>,
     pub hovered_hyperlink: Option<RangeInclusive<Point>>,
@@ -547,6 +1912,24 @@ pub struct RenderableContent {
     pub cursor: Cell,
     pub terminal_mode: TermMode,
     pub terminal_size: TerminalSize,
+    pub display_mode: DisplayMode,
+    pub hex_dump: Vec<HexDumpRow>,
+    pub hex_dump_bytes_per_row: usize,
+    /// RX timestamps of the visible lines, from top to bottom.
+    pub line_stamps: Vec<LineStamp>,
+    /// Plain text rows shown instead of the grid while the viewport is
+    /// scrolled into the scrollback archive.
+    pub archive_rows: Vec<ArchivedLine>,
+    /// Visible matches of the search.
+    pub search_matches: Vec<RangeInclusive<Point>>,
+    pub focused_search_match: Option<RangeInclusive<Point>>,
+    /// Row and column range of the search matches in `archive_rows`.
+    pub archive_search_matches: Vec<(usize, Range<usize>)>,
+    pub focused_archive_row: Option<usize>,
+    /// Spans of the highlight rules, for every visible row.
+    pub highlights: Vec<Vec<HighlightSpan>>,
+    /// Visible rows of the bookmarked lines.
+    pub bookmarked_rows: Vec<usize>,
 }
 
 impl Default for RenderableContent {
@@ -558,6 +1941,17 @@ impl Default for RenderableContent {
             cursor: Cell::default(),
             terminal_mode: TermMode::empty(),
             terminal_size: TerminalSize::default(),
+            display_mode: DisplayMode::default(),
+            hex_dump: vec![],
+            hex_dump_bytes_per_row: hex_dump::DEFAULT_BYTES_PER_ROW,
+            line_stamps: vec![],
+            archive_rows: vec![],
+            search_matches: vec![],
+            focused_search_match: None,
+            archive_search_matches: vec![],
+            focused_archive_row: None,
+            highlights: vec![],
+            bookmarked_rows: vec![],
         }
     }
 }
@@ -576,3 +1970,170 @@ impl EventListener for EventProxy {
         let _ = self.0.send(event.clone());
     }
 }
+
+#[cfg(all(test, unix))]
+pub(crate) mod tests {
+    use super::*;
+    use crate::serial_tty::event_loop::tests::tty_pair;
+    use crate::test_utils::temp_path;
+    use mio_serial::SerialStream;
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+
+    pub(crate) type TestBackend = (
+        SerialMonitorBackend,
+        SerialStream,
+        Receiver<(u64, TtyEvent)>,
+    );
+
+    /// Backend on one end of a PTY pair, the other end plays the device.
+    pub(crate) fn backend(settings: BackendSettings) -> TestBackend {
+        let (tty, remote) = tty_pair();
+        let (events, receiver) = mpsc::channel();
+        let backend = SerialMonitorBackend::with_tty(
+            0,
+            egui::Context::default(),
+            events,
+            tty,
+            settings,
+        )
+        .unwrap();
+        (backend, remote, receiver)
+    }
+
+    /// Read what the backend wrote to the device, until it is quiet.
+    pub(crate) fn read_remote(remote: &mut SerialStream) -> Vec<u8> {
+        let mut written = vec![];
+        let mut buf = [0; 256];
+        let mut quiet_since = Instant::now();
+        while quiet_since.elapsed() < Duration::from_millis(100) {
+            match remote.read(&mut buf) {
+                Ok(got) if got > 0 => {
+                    written.extend_from_slice(&buf[..got]);
+                    quiet_since = Instant::now();
+                },
+                _ => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        written
+    }
+
+    /// Wait until `done` holds for the terminal, for up to 5 seconds.
+    pub(crate) fn wait_terminal(
+        backend: &SerialMonitorBackend,
+        done: impl Fn(&Term<EventProxy>) -> bool,
+    ) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if done(&backend.term.lock()) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    #[test]
+    fn settings_limit_the_scrollback() {
+        let settings = BackendSettings {
+            scrolling_history: 5,
+            ..BackendSettings::default()
+        };
+        let (backend, mut remote, _events) = backend(settings.clone());
+        assert_eq!(backend.settings(), &settings);
+
+        let lines: Vec<u8> = (0..100)
+            .flat_map(|line| format!("line {}\r\n", line).into_bytes())
+            .collect();
+        crate::serial_tty::event_loop::tests::write_remote(&mut remote, &lines);
+        assert!(wait_terminal(&backend, |terminal| {
+            terminal.grid().history_size() == 5
+        }));
+    }
+
+    #[test]
+    fn lines_pushed_out_by_a_resize_are_archived() {
+        let path = temp_path("resize-archive");
+        let settings = BackendSettings {
+            scrolling_history: 5,
+            scrollback_archive: Some(path.clone()),
+            ..BackendSettings::default()
+        };
+        let (mut backend, mut remote, _events) = backend(settings);
+        let lines: Vec<u8> = (0..100)
+            .flat_map(|line| format!("line {}\r\n", line).into_bytes())
+            .collect();
+        crate::serial_tty::event_loop::tests::write_remote(&mut remote, &lines);
+        assert!(wait_terminal(&backend, |terminal| {
+            let grid = terminal.grid();
+            let line = grid.cursor.point.line - 1;
+            grid.history_size() == 5
+                && row_text(grid, line, Column(0), grid.last_column())
+                    == "line 99"
+        }));
+        assert_eq!(backend.archived_lines(), 46);
+
+        backend.process_command(BackendCommand::Resize(
+            Size::new(80.0, 10.0),
+            Size::new(1.0, 1.0),
+        ));
+        assert_eq!(backend.term.lock().grid().history_size(), 5);
+        let mut archive = backend.archive_reader().unwrap().unwrap();
+        let archived = archive.lines(0, 1000).unwrap();
+        assert_eq!(archived.len(), 86);
+        assert!(archived
+            .iter()
+            .enumerate()
+            .all(|(i, line)| line.text == format!("line {}", i)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn archive_search_runs_in_the_background() {
+        let path = temp_path("search-archive");
+        let settings = BackendSettings {
+            scrolling_history: 5,
+            scrollback_archive: Some(path.clone()),
+            ..BackendSettings::default()
+        };
+        let (mut backend, mut remote, _events) = backend(settings);
+        let lines: Vec<u8> = (0..100)
+            .flat_map(|line| format!("line {}\r\n", line).into_bytes())
+            .collect();
+        crate::serial_tty::event_loop::tests::write_remote(&mut remote, &lines);
+        assert!(wait_terminal(&backend, |_| backend.archived_lines() == 46));
+
+        let status = backend.search("line 3$", SearchKind::Regex, false);
+        assert_eq!(status.unwrap(), SearchStatus::Searching);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backend.search_status() == SearchStatus::Searching
+            && Instant::now() < deadline
+        {
+            backend.sync();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(backend.search_status(), SearchStatus::Found);
+        assert!(matches!(
+            backend.search.as_ref().unwrap().current,
+            Some(SearchMatch::Archive(3))
+        ));
+
+        // Lines of the grid are found without a scan.
+        let status = backend.search("line 99", SearchKind::Plain, false);
+        assert_eq!(status.unwrap(), SearchStatus::Found);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn default_writes_are_unchanged() {
+        let (mut backend, mut remote, _events) =
+            backend(BackendSettings::default());
+        let input = b"ls\ncat\r\n\x0a".to_vec();
+        backend.process_command(BackendCommand::Write(input.clone()));
+        assert_eq!(read_remote(&mut remote), input);
+
+        backend.set_tx_newline(TxNewline::CrLf);
+        backend.process_command(BackendCommand::Write(b"a\n".to_vec()));
+        assert_eq!(read_remote(&mut remote), b"a\r\n");
+    }
+}
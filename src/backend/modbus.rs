@@ -0,0 +1,1002 @@
+//! Modbus RTU on a running session.
+//!
+//! `ModbusFramer` splits the received bytes into RTU frames for the frame
+//! list, `ModbusFrame` decodes them, and `ModbusMaster` polls slaves from
+//! any thread, keeping the values they answer in a `ModbusTable`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use alacritty_terminal::sync::FairMutex;
+use mio_serial::{DataBits, Parity, StopBits};
+
+use crate::serial_tty::event_loop::{SerialEventLoopSender, SerialMsg};
+use crate::serial_tty::framing::{
+    crc16_modbus, crc16_modbus_update, FrameError, Framer,
+};
+use crate::serial_tty::tap::SerialTap;
+use crate::SerialTtyOptions;
+
+/// Longest RTU frame, address, PDU and CRC.
+const MAX_ADU_LEN: usize = 256;
+/// Shortest RTU frame, address, function and CRC.
+const MIN_ADU_LEN: usize = 4;
+const EXCEPTION: u8 = 0x80;
+
+pub const READ_COILS: u8 = 0x01;
+pub const READ_DISCRETE_INPUTS: u8 = 0x02;
+pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+pub const READ_INPUT_REGISTERS: u8 = 0x04;
+pub const WRITE_SINGLE_COIL: u8 = 0x05;
+pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+pub const WRITE_MULTIPLE_COILS: u8 = 0x0f;
+pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+/// Silence ending an RTU frame, 3.5 characters at the port settings, and
+/// 1.75 ms above 19200 baud as the specification recommends.
+pub fn silent_interval(options: &SerialTtyOptions) -> Duration {
+    if options.baud_rate == 0 || options.baud_rate > 19_200 {
+        return Duration::from_micros(1750);
+    }
+
+    let data_bits = match options.data_bits {
+        DataBits::Five => 5,
+        DataBits::Six => 6,
+        DataBits::Seven => 7,
+        DataBits::Eight => 8,
+    };
+    let parity_bits = match options.parity {
+        Parity::None => 0,
+        Parity::Odd | Parity::Even => 1,
+    };
+    let stop_bits = match options.stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    };
+    let char_bits = 1 + data_bits + parity_bits + stop_bits;
+    Duration::from_secs_f64(3.5 * char_bits as f64 / options.baud_rate as f64)
+}
+
+/// Splits RTU frames, on the silent interval or as soon as the bytes so far
+/// end with their CRC.
+///
+/// Reads are timed when the event loop gets them, so adapters which hold
+/// bytes back can split a frame in two, the CRC check then shows it.
+pub struct ModbusFramer {
+    silent_interval: Duration,
+    buffer: Vec<u8>,
+    crc: u16,
+    last: Option<Instant>,
+}
+
+impl ModbusFramer {
+    pub fn new(options: &SerialTtyOptions) -> Self {
+        Self::with_silent_interval(silent_interval(options))
+    }
+
+    pub fn with_silent_interval(silent_interval: Duration) -> Self {
+        Self {
+            silent_interval,
+            buffer: vec![],
+            crc: 0xffff,
+            last: None,
+        }
+    }
+
+    fn flush(
+        &mut self,
+        frames: &mut Vec<std::result::Result<Vec<u8>, FrameError>>,
+    ) {
+        self.crc = 0xffff;
+        if !self.buffer.is_empty() {
+            frames.push(Ok(std::mem::take(&mut self.buffer)));
+        }
+    }
+}
+
+impl Framer for ModbusFramer {
+    fn push(
+        &mut self,
+        bytes: &[u8],
+        frames: &mut Vec<std::result::Result<Vec<u8>, FrameError>>,
+    ) {
+        let now = Instant::now();
+        if self
+            .last
+            .is_some_and(|last| now - last >= self.silent_interval)
+        {
+            self.flush(frames);
+        }
+        self.last = Some(now);
+
+        for &byte in bytes {
+            self.buffer.push(byte);
+            self.crc = crc16_modbus_update(self.crc, byte);
+            // The CRC of a frame followed by its CRC is zero.
+            if self.crc == 0 && self.buffer.len() >= MIN_ADU_LEN {
+                self.flush(frames);
+            } else if self.buffer.len() > MAX_ADU_LEN {
+                frames.push(Err(FrameError::TooLong));
+                self.buffer.clear();
+                self.crc = 0xffff;
+            }
+        }
+    }
+}
+
+/// Protocol data unit of a request or a response.
+///
+/// Requests and responses of the read functions are told apart by their
+/// length, a read of bits starting at 0x300 to 0x3FF may be mistaken for
+/// a response of three bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModbusPdu {
+    ReadRequest {
+        function: u8,
+        start: u16,
+        count: u16,
+    },
+    /// Coils or discrete inputs, eight per byte, the first in the lowest
+    /// bit.
+    ReadBitsResponse {
+        function: u8,
+        bits: Vec<u8>,
+    },
+    ReadRegistersResponse {
+        function: u8,
+        values: Vec<u16>,
+    },
+    /// Request and response of the single writes are the same.
+    WriteSingle {
+        function: u8,
+        address: u16,
+        value: u16,
+    },
+    WriteMultipleRequest {
+        function: u8,
+        start: u16,
+        count: u16,
+        data: Vec<u8>,
+    },
+    WriteMultipleResponse {
+        function: u8,
+        start: u16,
+        count: u16,
+    },
+    Exception(ModbusException),
+    Other {
+        function: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// A decoded RTU frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModbusFrame {
+    pub slave: u8,
+    pub pdu: ModbusPdu,
+}
+
+impl ModbusFrame {
+    /// Decode a frame, with its CRC.
+    pub fn decode(adu: &[u8]) -> Result<Self> {
+        if adu.len() < MIN_ADU_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, "Frame too short"));
+        }
+        if crc16_modbus(adu) != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Bad CRC"));
+        }
+
+        let slave = adu[0];
+        let function = adu[1];
+        let data = &adu[2..adu.len() - 2];
+        let word =
+            |index: usize| u16::from_be_bytes([data[index], data[index + 1]]);
+        let is_response = data
+            .first()
+            .is_some_and(|&len| len as usize + 1 == data.len());
+        let pdu = match function {
+            _ if function & EXCEPTION != 0 && data.len() == 1 => {
+                ModbusPdu::Exception(ModbusException {
+                    function: function & !EXCEPTION,
+                    code: data[0],
+                })
+            },
+            READ_COILS | READ_DISCRETE_INPUTS if is_response => {
+                ModbusPdu::ReadBitsResponse {
+                    function,
+                    bits: data[1..].to_vec(),
+                }
+            },
+            READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS
+                if is_response && data.len() % 2 == 1 =>
+            {
+                ModbusPdu::ReadRegistersResponse {
+                    function,
+                    values: (1..data.len()).step_by(2).map(word).collect(),
+                }
+            },
+            READ_COILS..=READ_INPUT_REGISTERS if data.len() == 4 => {
+                ModbusPdu::ReadRequest {
+                    function,
+                    start: word(0),
+                    count: word(2),
+                }
+            },
+            WRITE_SINGLE_COIL | WRITE_SINGLE_REGISTER if data.len() == 4 => {
+                ModbusPdu::WriteSingle {
+                    function,
+                    address: word(0),
+                    value: word(2),
+                }
+            },
+            WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS
+                if data.len() == 4 =>
+            {
+                ModbusPdu::WriteMultipleResponse {
+                    function,
+                    start: word(0),
+                    count: word(2),
+                }
+            },
+            WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS
+                if data.len() >= 5 && data[4] as usize + 5 == data.len() =>
+            {
+                ModbusPdu::WriteMultipleRequest {
+                    function,
+                    start: word(0),
+                    count: word(2),
+                    data: data[5..].to_vec(),
+                }
+            },
+            _ => ModbusPdu::Other {
+                function,
+                data: data.to_vec(),
+            },
+        };
+        Ok(Self { slave, pdu })
+    }
+
+    /// One line summary of a frame, for `FrameListView::set_describe`.
+    pub fn describe(adu: &[u8]) -> Option<String> {
+        Self::decode(adu).ok().map(|frame| frame.to_string())
+    }
+}
+
+fn function_name(function: u8) -> &'static str {
+    match function {
+        READ_COILS => "read coils",
+        READ_DISCRETE_INPUTS => "read discrete inputs",
+        READ_HOLDING_REGISTERS => "read holding registers",
+        READ_INPUT_REGISTERS => "read input registers",
+        WRITE_SINGLE_COIL => "write coil",
+        WRITE_SINGLE_REGISTER => "write register",
+        WRITE_MULTIPLE_COILS => "write coils",
+        WRITE_MULTIPLE_REGISTERS => "write registers",
+        _ => "function",
+    }
+}
+
+impl fmt::Display for ModbusFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "slave {}: ", self.slave)?;
+        match &self.pdu {
+            ModbusPdu::ReadRequest {
+                function,
+                start,
+                count,
+            } => {
+                write!(f, "{} {} at {}", function_name(*function), count, start)
+            },
+            ModbusPdu::ReadBitsResponse { function, bits } => write!(
+                f,
+                "{} response, {} bytes",
+                function_name(*function),
+                bits.len()
+            ),
+            ModbusPdu::ReadRegistersResponse { function, values } => {
+                write!(f, "{} response {:?}", function_name(*function), values)
+            },
+            ModbusPdu::WriteSingle {
+                function,
+                address,
+                value,
+            } => write!(
+                f,
+                "{} {} = {}",
+                function_name(*function),
+                address,
+                value
+            ),
+            ModbusPdu::WriteMultipleRequest {
+                function,
+                start,
+                count,
+                ..
+            }
+            | ModbusPdu::WriteMultipleResponse {
+                function,
+                start,
+                count,
+            } => {
+                write!(f, "{} {} at {}", function_name(*function), count, start)
+            },
+            ModbusPdu::Exception(exception) => exception.fmt(f),
+            ModbusPdu::Other { function, data } => {
+                write!(f, "function {:#04x}, {} bytes", function, data.len())
+            },
+        }
+    }
+}
+
+/// Error answered by a slave, returned by `ModbusMaster` inside an
+/// `io::Error` of kind `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModbusException {
+    pub function: u8,
+    pub code: u8,
+}
+
+impl fmt::Display for ModbusException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.code {
+            0x01 => "illegal function",
+            0x02 => "illegal data address",
+            0x03 => "illegal data value",
+            0x04 => "slave device failure",
+            0x05 => "acknowledge",
+            0x06 => "slave device busy",
+            0x08 => "memory parity error",
+            0x0a => "gateway path unavailable",
+            0x0b => "gateway target failed to respond",
+            _ => "exception",
+        };
+        write!(
+            f,
+            "{} failed: {} ({:#04x})",
+            function_name(self.function),
+            reason,
+            self.code
+        )
+    }
+}
+
+impl std::error::Error for ModbusException {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ModbusTableKind {
+    Coil,
+    DiscreteInput,
+    InputRegister,
+    HoldingRegister,
+}
+
+impl fmt::Display for ModbusTableKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ModbusTableKind::Coil => "coil",
+            ModbusTableKind::DiscreteInput => "discrete input",
+            ModbusTableKind::InputRegister => "input register",
+            ModbusTableKind::HoldingRegister => "holding register",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModbusEntry {
+    pub slave: u8,
+    pub kind: ModbusTableKind,
+    pub address: u16,
+    /// 0 or 1 for coils and discrete inputs.
+    pub value: u16,
+    /// When the value was last read or written.
+    pub time: SystemTime,
+}
+
+type Entries = BTreeMap<(u8, ModbusTableKind, u16), ModbusEntry>;
+
+/// Values last read from or written to the slaves, shared by the masters of
+/// a backend and shown by `ModbusTableView`.
+#[derive(Clone)]
+pub struct ModbusTable {
+    entries: Arc<FairMutex<Entries>>,
+}
+
+impl Default for ModbusTable {
+    fn default() -> Self {
+        Self {
+            entries: Arc::new(FairMutex::new(BTreeMap::new())),
+        }
+    }
+}
+
+impl ModbusTable {
+    /// Entries sorted by slave, table and address.
+    pub fn entries(&self) -> Vec<ModbusEntry> {
+        self.entries.lock().values().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().clear();
+    }
+
+    fn update(
+        &self,
+        slave: u8,
+        kind: ModbusTableKind,
+        start: u16,
+        values: impl IntoIterator<Item = u16>,
+    ) {
+        let time = SystemTime::now();
+        let mut entries = self.entries.lock();
+        for (address, value) in (start..=u16::MAX).zip(values) {
+            entries.insert(
+                (slave, kind, address),
+                ModbusEntry {
+                    slave,
+                    kind,
+                    address,
+                    value,
+                    time,
+                },
+            );
+        }
+    }
+}
+
+/// Modbus RTU master, created by `SerialMonitorBackend::modbus_master`.
+///
+/// Requests block until the slave answers or the timeout passes. The handle
+/// can be moved to another thread, and stops working once the backend is
+/// dropped. Responses from the slaves are also shown by the terminal.
+pub struct ModbusMaster {
+    sender: SerialEventLoopSender,
+    /// Subscribed to only while waiting for a response.
+    tap: SerialTap,
+    table: ModbusTable,
+    timeout: Duration,
+}
+
+impl ModbusMaster {
+    pub(crate) fn new(
+        sender: SerialEventLoopSender,
+        tap: SerialTap,
+        table: ModbusTable,
+    ) -> Self {
+        Self {
+            sender,
+            tap,
+            table,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// How long to wait for a response, one second by default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn read_coils(
+        &mut self,
+        slave: u8,
+        start: u16,
+        count: u16,
+    ) -> Result<Vec<bool>> {
+        self.read_bits(slave, READ_COILS, start, count)
+    }
+
+    pub fn read_discrete_inputs(
+        &mut self,
+        slave: u8,
+        start: u16,
+        count: u16,
+    ) -> Result<Vec<bool>> {
+        self.read_bits(slave, READ_DISCRETE_INPUTS, start, count)
+    }
+
+    pub fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        start: u16,
+        count: u16,
+    ) -> Result<Vec<u16>> {
+        self.read_registers(slave, READ_HOLDING_REGISTERS, start, count)
+    }
+
+    pub fn read_input_registers(
+        &mut self,
+        slave: u8,
+        start: u16,
+        count: u16,
+    ) -> Result<Vec<u16>> {
+        self.read_registers(slave, READ_INPUT_REGISTERS, start, count)
+    }
+
+    pub fn write_single_coil(
+        &mut self,
+        slave: u8,
+        address: u16,
+        value: bool,
+    ) -> Result<()> {
+        let value = if value { 0xff00 } else { 0 };
+        let mut pdu = vec![WRITE_SINGLE_COIL];
+        pdu.extend(address.to_be_bytes());
+        pdu.extend(u16::to_be_bytes(value));
+        self.transact(slave, &pdu, 8)?;
+        self.table.update(
+            slave,
+            ModbusTableKind::Coil,
+            address,
+            [(value != 0) as u16],
+        );
+        Ok(())
+    }
+
+    pub fn write_single_register(
+        &mut self,
+        slave: u8,
+        address: u16,
+        value: u16,
+    ) -> Result<()> {
+        let mut pdu = vec![WRITE_SINGLE_REGISTER];
+        pdu.extend(address.to_be_bytes());
+        pdu.extend(value.to_be_bytes());
+        self.transact(slave, &pdu, 8)?;
+        self.table.update(
+            slave,
+            ModbusTableKind::HoldingRegister,
+            address,
+            [value],
+        );
+        Ok(())
+    }
+
+    pub fn write_multiple_coils(
+        &mut self,
+        slave: u8,
+        start: u16,
+        values: &[bool],
+    ) -> Result<()> {
+        let mut bits = vec![0u8; values.len().div_ceil(8)];
+        for (i, _) in values.iter().enumerate().filter(|(_, &value)| value) {
+            bits[i / 8] |= 1 << (i % 8);
+        }
+        self.write_multiple(
+            slave,
+            WRITE_MULTIPLE_COILS,
+            start,
+            values.len(),
+            &bits,
+        )?;
+        self.table.update(
+            slave,
+            ModbusTableKind::Coil,
+            start,
+            values.iter().map(|&value| value as u16),
+        );
+        Ok(())
+    }
+
+    pub fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        start: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        let data: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect();
+        self.write_multiple(
+            slave,
+            WRITE_MULTIPLE_REGISTERS,
+            start,
+            values.len(),
+            &data,
+        )?;
+        self.table.update(
+            slave,
+            ModbusTableKind::HoldingRegister,
+            start,
+            values.iter().copied(),
+        );
+        Ok(())
+    }
+
+    fn read_bits(
+        &mut self,
+        slave: u8,
+        function: u8,
+        start: u16,
+        count: u16,
+    ) -> Result<Vec<bool>> {
+        check_count(count, 2000)?;
+        let byte_count = (count as usize).div_ceil(8);
+        let response = self.transact(
+            slave,
+            &read_request(function, start, count),
+            5 + byte_count,
+        )?;
+        if response[2] as usize != byte_count {
+            return Err(unexpected_response());
+        }
+
+        let bits: Vec<bool> = (0..count as usize)
+            .map(|i| response[3 + i / 8] & (1 << (i % 8)) != 0)
+            .collect();
+        let kind = if function == READ_COILS {
+            ModbusTableKind::Coil
+        } else {
+            ModbusTableKind::DiscreteInput
+        };
+        self.table.update(
+            slave,
+            kind,
+            start,
+            bits.iter().map(|&bit| bit as u16),
+        );
+        Ok(bits)
+    }
+
+    fn read_registers(
+        &mut self,
+        slave: u8,
+        function: u8,
+        start: u16,
+        count: u16,
+    ) -> Result<Vec<u16>> {
+        check_count(count, 125)?;
+        let byte_count = 2 * count as usize;
+        let response = self.transact(
+            slave,
+            &read_request(function, start, count),
+            5 + byte_count,
+        )?;
+        if response[2] as usize != byte_count {
+            return Err(unexpected_response());
+        }
+
+        let values: Vec<u16> = response[3..3 + byte_count]
+            .chunks(2)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]))
+            .collect();
+        let kind = if function == READ_HOLDING_REGISTERS {
+            ModbusTableKind::HoldingRegister
+        } else {
+            ModbusTableKind::InputRegister
+        };
+        self.table
+            .update(slave, kind, start, values.iter().copied());
+        Ok(values)
+    }
+
+    fn write_multiple(
+        &mut self,
+        slave: u8,
+        function: u8,
+        start: u16,
+        count: usize,
+        data: &[u8],
+    ) -> Result<()> {
+        let max = if function == WRITE_MULTIPLE_COILS {
+            1968
+        } else {
+            123
+        };
+        check_count(count.min(u16::MAX as usize) as u16, max)?;
+
+        let mut pdu = vec![function];
+        pdu.extend(start.to_be_bytes());
+        pdu.extend((count as u16).to_be_bytes());
+        pdu.push(data.len() as u8);
+        pdu.extend_from_slice(data);
+        self.transact(slave, &pdu, 8).map(|_| ())
+    }
+
+    /// Send a request and wait for its response of `len` bytes, returns
+    /// the response with its CRC. Broadcasts to slave 0 return right away.
+    fn transact(
+        &mut self,
+        slave: u8,
+        pdu: &[u8],
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        // Only what is received from now on can be the response, the
+        // subscription ends with the transaction.
+        let received = self.tap.subscribe();
+        let mut request = vec![slave];
+        request.extend_from_slice(pdu);
+        request.extend(crc16_modbus(&request).to_le_bytes());
+        self.sender
+            .send(SerialMsg::Input(request.clone().into()))
+            .map_err(|err| {
+                Error::new(ErrorKind::BrokenPipe, err.to_string())
+            })?;
+        if slave == 0 {
+            return Ok(vec![]);
+        }
+
+        let function = pdu[0];
+        let deadline = Instant::now() + self.timeout;
+        let mut buffer: Vec<u8> = vec![];
+        let mut echo = true;
+        loop {
+            // RS-485 adapters may echo the request. A response which is the
+            // same as the request, like the one of a single write, must then
+            // come on top of the echo.
+            if echo && buffer.len() >= request.len() {
+                echo = false;
+                if buffer.starts_with(&request) {
+                    buffer.drain(..request.len());
+                }
+            }
+
+            if buffer.len() >= 5 && buffer[1] == function | EXCEPTION {
+                let frame = ModbusFrame::decode(&buffer[..5])?;
+                if let ModbusPdu::Exception(exception) = frame.pdu {
+                    return Err(Error::other(exception));
+                }
+            }
+            if buffer.len() >= len {
+                buffer.truncate(len);
+                if crc16_modbus(&buffer) != 0 {
+                    return Err(Error::new(ErrorKind::InvalidData, "Bad CRC"));
+                }
+                if buffer[0] != slave || buffer[1] != function {
+                    return Err(unexpected_response());
+                }
+                return Ok(buffer);
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+            match received.recv_timeout(left) {
+                Ok(bytes) => buffer.extend_from_slice(&bytes),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(Error::new(
+                        ErrorKind::TimedOut,
+                        format!("No response from slave {}", slave),
+                    ))
+                },
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Error::new(
+                        ErrorKind::BrokenPipe,
+                        "The serial port was closed",
+                    ))
+                },
+            }
+        }
+    }
+}
+
+fn read_request(function: u8, start: u16, count: u16) -> Vec<u8> {
+    let mut pdu = vec![function];
+    pdu.extend(start.to_be_bytes());
+    pdu.extend(count.to_be_bytes());
+    pdu
+}
+
+fn check_count(count: u16, max: u16) -> Result<()> {
+    if count == 0 || count > max {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Between 1 and {} values can be transferred at once", max),
+        ));
+    }
+    Ok(())
+}
+
+fn unexpected_response() -> Error {
+    Error::new(ErrorKind::InvalidData, "Unexpected response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial_tty::event_loop::tests::fake_event_loop;
+    use crate::serial_tty::tap::TrafficDirection;
+
+    fn adu(bytes: &[u8]) -> Vec<u8> {
+        let mut adu = bytes.to_vec();
+        adu.extend(crc16_modbus(bytes).to_le_bytes());
+        adu
+    }
+
+    #[test]
+    fn silent_interval_follows_the_port_settings() {
+        let mut options = SerialTtyOptions {
+            baud_rate: 9600,
+            ..SerialTtyOptions::default()
+        };
+        assert_eq!(silent_interval(&options).as_micros(), 3645);
+        options.parity = Parity::Even;
+        assert_eq!(silent_interval(&options).as_micros(), 4010);
+        options.baud_rate = 115_200;
+        assert_eq!(silent_interval(&options), Duration::from_micros(1750));
+    }
+
+    #[test]
+    fn framer_splits_on_crc_and_silence() {
+        let request = adu(&[0x11, 0x03, 0x00, 0x6b, 0x00, 0x03]);
+        let response = adu(&[0x11, 0x03, 0x06, 0x02, 0x2b, 0, 0, 0, 0x64]);
+        let mut framer =
+            ModbusFramer::with_silent_interval(Duration::from_millis(20));
+        let mut frames = vec![];
+
+        let mut bytes = request.clone();
+        bytes.extend_from_slice(&response[..4]);
+        framer.push(&bytes, &mut frames);
+        framer.push(&response[4..], &mut frames);
+        assert_eq!(frames, [Ok(request), Ok(response)]);
+
+        // A truncated frame ends with the silence after it.
+        frames.clear();
+        framer.push(&[0x11, 0x03, 0x06], &mut frames);
+        assert!(frames.is_empty());
+        std::thread::sleep(Duration::from_millis(30));
+        framer.push(&adu(&[0x01, 0x05, 0, 1, 0xff, 0]), &mut frames);
+        assert_eq!(
+            frames,
+            [
+                Ok(vec![0x11, 0x03, 0x06]),
+                Ok(adu(&[0x01, 0x05, 0, 1, 0xff, 0]))
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_requests_responses_and_exceptions() {
+        let frame =
+            ModbusFrame::decode(&adu(&[0x11, 0x03, 0x00, 0x6b, 0x00, 0x03]))
+                .unwrap();
+        assert_eq!(
+            frame.pdu,
+            ModbusPdu::ReadRequest {
+                function: READ_HOLDING_REGISTERS,
+                start: 0x6b,
+                count: 3
+            }
+        );
+        assert_eq!(
+            frame.to_string(),
+            "slave 17: read holding registers 3 at 107"
+        );
+
+        let frame =
+            ModbusFrame::decode(&adu(&[0x11, 0x03, 0x04, 0x02, 0x2b, 0, 0x64]))
+                .unwrap();
+        assert_eq!(
+            frame.pdu,
+            ModbusPdu::ReadRegistersResponse {
+                function: READ_HOLDING_REGISTERS,
+                values: vec![0x022b, 0x64]
+            }
+        );
+
+        let frame = ModbusFrame::decode(&adu(&[
+            0x01, 0x10, 0, 1, 0, 2, 4, 0, 0x0a, 1, 2,
+        ]))
+        .unwrap();
+        assert!(matches!(
+            frame.pdu,
+            ModbusPdu::WriteMultipleRequest { count: 2, ref data, .. }
+                if data == &[0, 0x0a, 1, 2]
+        ));
+
+        let frame = ModbusFrame::decode(&adu(&[0x0a, 0x81, 0x02])).unwrap();
+        assert_eq!(
+            frame.to_string(),
+            "slave 10: read coils failed: illegal data address (0x02)"
+        );
+
+        let mut bad = adu(&[0x0a, 0x81, 0x02]);
+        bad[2] = 0x03;
+        assert_eq!(
+            ModbusFrame::decode(&bad).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    /// Master talking to a slave which echoes the requests, like a two
+    /// wire RS-485 adapter, and holds registers 0 to 9 valued ten times
+    /// their address.
+    fn master() -> ModbusMaster {
+        let tap = SerialTap::new(1024);
+        let received = tap.clone();
+        let sender = fake_event_loop(move |msg| {
+            let SerialMsg::Input(request) = msg else {
+                return;
+            };
+            received.record(TrafficDirection::Rx, &request);
+            let start = u16::from_be_bytes([request[2], request[3]]);
+            let count = u16::from_be_bytes([request[4], request[5]]);
+            let response = match request[1] {
+                READ_HOLDING_REGISTERS if start + count > 10 => {
+                    adu(&[request[0], request[1] | 0x80, 2])
+                },
+                READ_HOLDING_REGISTERS => {
+                    let mut pdu = vec![request[0], request[1], 2 * count as u8];
+                    for address in start..start + count {
+                        pdu.extend((address * 10).to_be_bytes());
+                    }
+                    adu(&pdu)
+                },
+                _ => request[..request.len()].to_vec(),
+            };
+            // The response comes in two reads.
+            let (first, second) = response.split_at(3);
+            received.record(TrafficDirection::Rx, first);
+            received.record(TrafficDirection::Rx, second);
+        });
+        let mut master = ModbusMaster::new(sender, tap, ModbusTable::default());
+        master.set_timeout(Duration::from_millis(200));
+        master
+    }
+
+    #[test]
+    fn echo_alone_is_not_a_response() {
+        let tap = SerialTap::new(1024);
+        let received = tap.clone();
+        let sender = fake_event_loop(move |msg| {
+            if let SerialMsg::Input(request) = msg {
+                received.record(TrafficDirection::Rx, &request);
+            }
+        });
+        let mut master = ModbusMaster::new(sender, tap, ModbusTable::default());
+        master.set_timeout(Duration::from_millis(50));
+        assert_eq!(
+            master
+                .write_single_register(1, 9, 0x1234)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::TimedOut
+        );
+        assert_eq!(
+            master.write_single_coil(1, 2, true).unwrap_err().kind(),
+            ErrorKind::TimedOut
+        );
+    }
+
+    #[test]
+    fn master_reads_and_writes_registers() {
+        let mut master = master();
+        assert_eq!(
+            master.read_holding_registers(1, 2, 3).unwrap(),
+            [20, 30, 40]
+        );
+        master.write_single_register(1, 9, 0x1234).unwrap();
+
+        let entries = master.table.entries();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.address, entry.value))
+                .collect::<Vec<_>>(),
+            [(2, 20), (3, 30), (4, 40), (9, 0x1234)]
+        );
+        assert!(entries
+            .iter()
+            .all(|entry| entry.kind == ModbusTableKind::HoldingRegister));
+    }
+
+    #[test]
+    fn master_reports_exceptions() {
+        let mut master = master();
+        let err = master.read_holding_registers(1, 8, 4).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Other);
+        let exception = err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<ModbusException>())
+            .unwrap();
+        assert_eq!(exception.code, 2);
+        assert!(master.table.entries().is_empty());
+
+        assert_eq!(
+            master.read_holding_registers(1, 0, 0).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::io::{Error, ErrorKind, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+
+use alacritty_terminal::term::search::{Match, RegexSearch};
+use log::error;
+
+use crate::serial_tty::archive::ArchiveReader;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchKind {
+    /// The pattern is matched literally.
+    #[default]
+    Plain,
+    Regex,
+}
+
+/// Outcome of the latest search step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchStatus {
+    /// The scrollback archive is still being scanned.
+    Searching,
+    Found,
+    NotFound,
+}
+
+/// Position of the focused match.
+#[derive(Debug, Clone)]
+pub(crate) enum SearchMatch {
+    Grid(Match),
+    /// Line of the scrollback archive.
+    Archive(u64),
+}
+
+pub(crate) struct Search {
+    /// Matcher of the terminal grid, which also finds wrapped matches.
+    pub(crate) grid_regex: RegexSearch,
+    /// Matcher of the plain text lines of the scrollback archive.
+    pub(crate) line_regex: regex::Regex,
+    pub(crate) current: Option<SearchMatch>,
+    /// Archive scan of the latest step, `current` is replaced once it's done.
+    pub(crate) pending: Option<PendingSearch>,
+}
+
+impl Search {
+    pub(crate) fn new(
+        pattern: &str,
+        kind: SearchKind,
+        case_sensitive: bool,
+    ) -> Result<Self> {
+        let pattern = match kind {
+            SearchKind::Plain => regex::escape(pattern),
+            SearchKind::Regex => pattern.to_owned(),
+        };
+        let pattern = format!(
+            "(?{}){}",
+            if case_sensitive { "-i" } else { "i" },
+            pattern
+        );
+        let invalid = |err: String| Error::new(ErrorKind::InvalidInput, err);
+
+        Ok(Self {
+            grid_regex: RegexSearch::new(&pattern)
+                .map_err(|err| invalid(err.to_string()))?,
+            line_regex: regex::Regex::new(&pattern)
+                .map_err(|err| invalid(err.to_string()))?,
+            current: None,
+            pending: None,
+        })
+    }
+}
+
+/// Place to look for the next match, the first one which has it wins.
+#[derive(Debug, Clone)]
+pub(crate) enum SearchStep {
+    /// Match found in the grid while the terminal was locked.
+    Found(SearchMatch),
+    Archive {
+        from: u64,
+        backwards: bool,
+    },
+}
+
+/// Scan of the scrollback archive on a worker thread, which is cancelled
+/// when dropped.
+pub(crate) struct PendingSearch {
+    found: Receiver<Option<SearchMatch>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl PendingSearch {
+    pub(crate) fn spawn(
+        mut archive: ArchiveReader,
+        regex: regex::Regex,
+        steps: Vec<SearchStep>,
+        app_context: egui::Context,
+    ) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (sender, found) = mpsc::channel();
+        let worker_cancel = cancel.clone();
+        let spawned = std::thread::Builder::new()
+            .name("archive_search".to_owned())
+            .spawn(move || {
+                let found = steps.into_iter().find_map(|step| match step {
+                    SearchStep::Found(found) => Some(found),
+                    SearchStep::Archive { from, backwards } => archive
+                        .find_until_cancelled(
+                            &regex,
+                            from,
+                            backwards,
+                            &worker_cancel,
+                        )
+                        .unwrap_or_else(|err| {
+                            if err.kind() != ErrorKind::Interrupted {
+                                error!(
+                                    "Error reading scrollback archive: {}",
+                                    err
+                                );
+                            }
+                            None
+                        })
+                        .map(SearchMatch::Archive),
+                });
+                if !worker_cancel.load(Ordering::Relaxed) {
+                    let _ = sender.send(found);
+                    app_context.request_repaint();
+                }
+            });
+        if let Err(err) = spawned {
+            error!("Error starting the archive search: {}", err);
+        }
+
+        Self { found, cancel }
+    }
+
+    /// The match, once the scan is done.
+    pub(crate) fn try_found(&self) -> Option<Option<SearchMatch>> {
+        match self.found.try_recv() {
+            Ok(found) => Some(found),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(None),
+        }
+    }
+}
+
+impl Drop for PendingSearch {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_and_case_sensitive_patterns() {
+        let search = Search::new("a.b", SearchKind::Plain, false).unwrap();
+        assert!(search.line_regex.is_match("xA.By"));
+        assert!(!search.line_regex.is_match("axb"));
+
+        let search = Search::new("a.b", SearchKind::Regex, true).unwrap();
+        assert!(search.line_regex.is_match("axb"));
+        assert!(!search.line_regex.is_match("AXB"));
+
+        assert!(Search::new("(", SearchKind::Regex, false).is_err());
+    }
+}
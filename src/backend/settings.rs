@@ -1,16 +1,48 @@
-const DEFAULT_SHELL: &str = "/bin/bash";
+use std::path::PathBuf;
 
-#[derive(Debug, Clone)]
+use alacritty_terminal::term::{self, SEMANTIC_ESCAPE_CHARS};
+
+pub use alacritty_terminal::term::Osc52;
+
+/// Lines kept in the scrollback history by default.
+pub const DEFAULT_SCROLLING_HISTORY: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BackendSettings {
-    pub shell: String,
-    pub args: Vec<String>,
+    /// Max amount of lines kept above the screen.
+    pub scrolling_history: usize,
+    /// Characters which terminate a semantic (double click) selection.
+    pub semantic_escape_chars: String,
+    /// Which OSC 52 clipboard requests of the device are honoured.
+    pub osc52: Osc52,
+    /// Whether the device may enable the kitty keyboard protocol.
+    pub kitty_keyboard: bool,
+    /// File receiving the lines which fall off the scrollback history.
+    ///
+    /// Unlimited, the file is truncated when the archive is opened.
+    pub scrollback_archive: Option<PathBuf>,
 }
 
 impl Default for BackendSettings {
     fn default() -> Self {
         Self {
-            shell: DEFAULT_SHELL.to_string(),
-            args: vec![],
+            scrolling_history: DEFAULT_SCROLLING_HISTORY,
+            semantic_escape_chars: SEMANTIC_ESCAPE_CHARS.to_string(),
+            osc52: Osc52::default(),
+            kitty_keyboard: false,
+            scrollback_archive: None,
+        }
+    }
+}
+
+impl From<&BackendSettings> for term::Config {
+    fn from(settings: &BackendSettings) -> Self {
+        Self {
+            scrolling_history: settings.scrolling_history,
+            semantic_escape_chars: settings.semantic_escape_chars.clone(),
+            osc52: settings.osc52,
+            kitty_keyboard: settings.kitty_keyboard,
+            ..Default::default()
         }
     }
 }
@@ -8,6 +8,13 @@ pub enum BindingAction {
     Char(char),
     Esc(String),
     LinkOpen,
+    ToggleLocalEcho,
+    /// Open the search bar.
+    Search,
+    /// Run the script with the given name, see
+    /// `SerialMonitorBackend::set_scripts`. Does nothing without the
+    /// `scripting` feature.
+    RunScript(String),
     Ignore,
 }
 
@@ -325,6 +332,7 @@ fn platform_keyboard_bindings() -> Vec<(Binding<InputKind>, BindingAction)> {
         KeyboardBinding;
         C, Modifiers::MAC_CMD; BindingAction::Copy;
         V, Modifiers::MAC_CMD; BindingAction::Paste;
+        F, Modifiers::MAC_CMD; BindingAction::Search;
     )
 }
 
@@ -334,6 +342,7 @@ fn platform_keyboard_bindings() -> Vec<(Binding<InputKind>, BindingAction)> {
         KeyboardBinding;
         C, Modifiers::SHIFT | Modifiers::COMMAND; BindingAction::Copy;
         V, Modifiers::SHIFT | Modifiers::COMMAND; BindingAction::Paste;
+        F, Modifiers::SHIFT | Modifiers::CTRL; BindingAction::Search;
     )
 }
 
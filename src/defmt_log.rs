@@ -0,0 +1,126 @@
+use egui::{Color32, Response, RichText, ScrollArea, TextStyle, Widget};
+
+use crate::backend::SerialMonitorBackend;
+use crate::serial_tty::defmt::{DefmtLevel, DefmtRecord};
+use crate::serial_tty::line_stamps::TimestampFormat;
+
+/// Log messages decoded by `SerialMonitorBackend::load_defmt_elf`, meant to
+/// sit next to the `SerialMonitorView` of the same backend.
+///
+/// Every row shows the message number, its arrival time, the timestamp of
+/// the firmware, the level and the message. The list follows new messages
+/// while scrolled to the bottom.
+pub struct DefmtLogView<'a> {
+    backend: &'a SerialMonitorBackend,
+    timestamp_format: TimestampFormat,
+    min_level: Option<DefmtLevel>,
+}
+
+impl<'a> DefmtLogView<'a> {
+    pub fn new(backend: &'a SerialMonitorBackend) -> Self {
+        Self {
+            backend,
+            timestamp_format: TimestampFormat::Absolute,
+            min_level: None,
+        }
+    }
+
+    #[inline]
+    pub fn set_timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.timestamp_format = format;
+        self
+    }
+
+    /// Hide the messages below `level`, `println!` messages are always
+    /// shown.
+    #[inline]
+    pub fn set_min_level(mut self, level: Option<DefmtLevel>) -> Self {
+        self.min_level = level;
+        self
+    }
+
+    fn row(
+        &self,
+        ui: &mut egui::Ui,
+        record: &DefmtRecord,
+        previous: Option<std::time::SystemTime>,
+    ) {
+        let time = self.timestamp_format.format(record.time, previous);
+        ui.label(
+            RichText::new(format!("{:>6} {}", record.index, time))
+                .monospace()
+                .weak(),
+        );
+        if let Some(timestamp) = &record.timestamp {
+            ui.label(RichText::new(timestamp).monospace());
+        }
+        if let Some(level) = record.level {
+            ui.label(
+                RichText::new(format!("{:<5}", level.as_str()))
+                    .monospace()
+                    .color(level_color(ui, level)),
+            );
+        }
+
+        let message = RichText::new(&record.message).monospace();
+        ui.label(if record.malformed {
+            message.weak().italics()
+        } else {
+            message
+        });
+    }
+}
+
+impl Widget for DefmtLogView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> Response {
+        // Only the shown records are copied, and the lock is released before
+        // drawing them, so the event loop isn't kept waiting.
+        let decoder = self.backend.defmt_decoder().clone();
+        let shown: Vec<u64> = decoder
+            .lock()
+            .records()
+            .iter()
+            .filter(|record| match (self.min_level, record.level) {
+                (Some(min_level), Some(level)) => level >= min_level,
+                _ => true,
+            })
+            .map(|record| record.index)
+            .collect();
+        let row_height = ui
+            .text_style_height(&TextStyle::Monospace)
+            .max(ui.spacing().interact_size.y);
+
+        ui.push_id(("egui_term::defmt_log", self.backend.id), |ui| {
+            ScrollArea::vertical()
+                .auto_shrink(false)
+                .stick_to_bottom(true)
+                .show_rows(ui, row_height, shown.len(), |ui, rows| {
+                    let Some(&first) = shown.get(rows.start) else {
+                        return;
+                    };
+                    // With the record above, as base of the delta time.
+                    let records = decoder.lock().records_at(
+                        &shown[rows.start.saturating_sub(1)..rows.end],
+                    );
+                    let mut previous = None;
+                    for record in &records {
+                        if record.index >= first {
+                            ui.horizontal(|ui| self.row(ui, record, previous));
+                        }
+                        previous = Some(record.time);
+                    }
+                });
+        })
+        .response
+    }
+}
+
+fn level_color(ui: &egui::Ui, level: DefmtLevel) -> Color32 {
+    match level {
+        DefmtLevel::Trace => ui.visuals().weak_text_color(),
+        DefmtLevel::Debug => ui.visuals().text_color(),
+        DefmtLevel::Info => Color32::LIGHT_GREEN,
+        DefmtLevel::Warn => Color32::YELLOW,
+        DefmtLevel::Error => Color32::LIGHT_RED,
+    }
+}
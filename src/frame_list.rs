@@ -0,0 +1,156 @@
+use egui::{Color32, Response, RichText, ScrollArea, TextStyle, Widget};
+
+use crate::backend::SerialMonitorBackend;
+use crate::serial_tty::framing::{DecodedFrame, FrameCheckResult};
+use crate::serial_tty::line_stamps::TimestampFormat;
+
+type Describe = fn(&[u8]) -> Option<String>;
+
+/// List of the frames decoded from the received bytes, meant to sit next to
+/// the `SerialMonitorView` of the same backend.
+///
+/// Every row shows the frame number, its arrival time, its length, its
+/// bytes in hex and the result of its check. The list follows new frames
+/// while scrolled to the bottom.
+pub struct FrameListView<'a> {
+    backend: &'a SerialMonitorBackend,
+    timestamp_format: TimestampFormat,
+    max_bytes: usize,
+    describe: Option<Describe>,
+}
+
+impl<'a> FrameListView<'a> {
+    pub fn new(backend: &'a SerialMonitorBackend) -> Self {
+        Self {
+            backend,
+            timestamp_format: TimestampFormat::Absolute,
+            max_bytes: 32,
+            describe: None,
+        }
+    }
+
+    #[inline]
+    pub fn set_timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.timestamp_format = format;
+        self
+    }
+
+    /// Bytes shown per frame, longer frames are cut with an ellipsis.
+    #[inline]
+    pub fn set_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Summary shown after the check of every frame, such as
+    /// `ModbusFrame::describe`.
+    #[inline]
+    pub fn set_describe(mut self, describe: Describe) -> Self {
+        self.describe = Some(describe);
+        self
+    }
+
+    fn row(
+        &self,
+        ui: &mut egui::Ui,
+        frame: &DecodedFrame,
+        previous: Option<std::time::SystemTime>,
+    ) {
+        let time = self.timestamp_format.format(frame.time, previous);
+        ui.label(
+            RichText::new(format!(
+                "{:>6} {} {:>5}",
+                frame.index,
+                time,
+                frame.bytes.len()
+            ))
+            .monospace()
+            .weak(),
+        );
+
+        let mut hex = frame
+            .bytes
+            .iter()
+            .take(self.max_bytes)
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if frame.bytes.len() > self.max_bytes {
+            hex.push_str(" …");
+        }
+        ui.label(RichText::new(hex).monospace());
+
+        let status = match (frame.error, frame.check) {
+            (Some(error), _) => Some(error.to_string()),
+            (None, FrameCheckResult::Unchecked) => None,
+            (None, FrameCheckResult::Valid) => {
+                ui.label(RichText::new("ok").color(Color32::LIGHT_GREEN));
+                None
+            },
+            (None, FrameCheckResult::Invalid { received, computed }) => Some(
+                format!("bad check {:X}, expected {:X}", received, computed),
+            ),
+            (None, FrameCheckResult::Missing) => Some("no check".to_owned()),
+        };
+        if let Some(status) = status {
+            ui.label(RichText::new(status).color(Color32::LIGHT_RED));
+        }
+        if let Some(summary) = self
+            .describe
+            .filter(|_| frame.error.is_none())
+            .and_then(|describe| describe(&frame.bytes))
+        {
+            ui.label(summary);
+        }
+    }
+}
+
+impl Widget for FrameListView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> Response {
+        // Only the shown frames are copied, and the lock is released before
+        // drawing them, so the event loop isn't kept waiting.
+        let log = self.backend.frame_log().clone();
+        let (count, first) = {
+            let log = log.lock();
+            let frames = log.frames();
+            (frames.len(), frames.front().map_or(0, |frame| frame.index))
+        };
+        let row_height = ui
+            .text_style_height(&TextStyle::Monospace)
+            .max(ui.spacing().interact_size.y);
+
+        ui.push_id(("egui_term::frame_list", self.backend.id), |ui| {
+            ScrollArea::vertical()
+                .auto_shrink(false)
+                .stick_to_bottom(true)
+                .show_rows(ui, row_height, count, |ui, rows| {
+                    // With the frame above, as base of the delta time.
+                    let start = first + rows.start.saturating_sub(1) as u64;
+                    let end = first + rows.end as u64;
+                    let frames: Vec<DecodedFrame> = {
+                        let log = log.lock();
+                        let frames = log.frames();
+                        let front =
+                            frames.front().map_or(0, |frame| frame.index);
+                        let skip = (start.saturating_sub(front) as usize)
+                            .min(frames.len());
+                        frames
+                            .range(skip..)
+                            .take_while(|frame| frame.index < end)
+                            .cloned()
+                            .collect()
+                    };
+
+                    let shown = first + rows.start as u64;
+                    let mut previous = None;
+                    for frame in &frames {
+                        if frame.index >= shown {
+                            ui.horizontal(|ui| self.row(ui, frame, previous));
+                        }
+                        previous = Some(frame.time);
+                    }
+                });
+        })
+        .response
+    }
+}
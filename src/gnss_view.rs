@@ -0,0 +1,163 @@
+use egui::{
+    Color32, Grid, ProgressBar, Response, RichText, ScrollArea, Widget,
+};
+
+use crate::backend::SerialMonitorBackend;
+use crate::serial_tty::nmea::{FixQuality, GnssState};
+
+/// Signal to noise ratio filling the bar of a satellite.
+const MAX_SNR: f32 = 50.0;
+
+/// Fix, position and satellites decoded from the NMEA sentences, meant to
+/// sit next to the `SerialMonitorView` of the same backend.
+///
+/// Shows nothing useful until `SerialMonitorBackend::set_nmea_decoding` is
+/// enabled.
+pub struct GnssStatusView<'a> {
+    backend: &'a SerialMonitorBackend,
+}
+
+impl<'a> GnssStatusView<'a> {
+    pub fn new(backend: &'a SerialMonitorBackend) -> Self {
+        Self { backend }
+    }
+
+    fn status(ui: &mut egui::Ui, state: &GnssState) {
+        let optional = |value: Option<f64>, unit: &str, digits: usize| {
+            value.map_or("-".to_owned(), |value| {
+                format!("{:.*}{}", digits, value, unit)
+            })
+        };
+
+        let fix = format!("{}, {}", state.quality, state.mode);
+        let color = if state.quality == FixQuality::Invalid {
+            Color32::LIGHT_RED
+        } else {
+            Color32::LIGHT_GREEN
+        };
+        ui.label("Fix");
+        ui.label(RichText::new(fix).color(color));
+        ui.end_row();
+
+        ui.label("UTC");
+        ui.label(match (state.date, state.time) {
+            (Some(date), Some(time)) => format!("{} {}", date, time),
+            (None, Some(time)) => time.to_string(),
+            _ => "-".to_owned(),
+        });
+        ui.end_row();
+
+        ui.label("Position");
+        ui.label(
+            RichText::new(match (state.latitude, state.longitude) {
+                (Some(latitude), Some(longitude)) => {
+                    format!("{:.6}, {:.6}", latitude, longitude)
+                },
+                _ => "-".to_owned(),
+            })
+            .monospace(),
+        );
+        ui.end_row();
+
+        ui.label("Altitude");
+        ui.label(optional(state.altitude, " m", 1));
+        ui.end_row();
+
+        ui.label("Speed");
+        ui.label(optional(state.speed_knots, " kn", 1));
+        ui.end_row();
+
+        ui.label("Course");
+        ui.label(optional(state.course, "°", 1));
+        ui.end_row();
+
+        ui.label("DOP");
+        ui.label(format!(
+            "P {} H {} V {}",
+            optional(state.pdop, "", 1),
+            optional(state.hdop, "", 1),
+            optional(state.vdop, "", 1)
+        ));
+        ui.end_row();
+
+        ui.label("Satellites");
+        ui.label(format!(
+            "{} used, {} in view",
+            state
+                .satellites_used
+                .map_or("-".to_owned(), |used| used.to_string()),
+            state.satellites.len()
+        ));
+        ui.end_row();
+
+        ui.label("Sentences");
+        let mut sentences = RichText::new(format!(
+            "{} ok, {} bad checksums",
+            state.sentences, state.bad_checksums
+        ));
+        if state.bad_checksums > 0 {
+            sentences = sentences.color(Color32::LIGHT_RED);
+        }
+        ui.label(sentences);
+        ui.end_row();
+    }
+
+    fn satellites(ui: &mut egui::Ui, state: &GnssState) {
+        for header in ["System", "PRN", "Elevation", "Azimuth", "SNR"] {
+            ui.label(RichText::new(header).strong());
+        }
+        ui.end_row();
+
+        for satellite in &state.satellites {
+            let text = |text: String| {
+                let text = RichText::new(text).monospace();
+                if satellite.used {
+                    text.strong()
+                } else {
+                    text.weak()
+                }
+            };
+            let angle = |angle: Option<String>| {
+                text(
+                    angle.map_or("-".to_owned(), |angle| format!("{}°", angle)),
+                )
+            };
+            ui.label(text(satellite.system.clone()));
+            ui.label(text(satellite.prn.to_string()));
+            ui.label(angle(satellite.elevation.map(|angle| angle.to_string())));
+            ui.label(angle(satellite.azimuth.map(|angle| angle.to_string())));
+            let snr = satellite.snr.unwrap_or_default();
+            ui.add(
+                ProgressBar::new(snr as f32 / MAX_SNR)
+                    .desired_width(80.0)
+                    .text(format!("{} dB", snr))
+                    .fill(if satellite.used {
+                        Color32::DARK_GREEN
+                    } else {
+                        Color32::DARK_GRAY
+                    }),
+            );
+            ui.end_row();
+        }
+    }
+}
+
+impl Widget for GnssStatusView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> Response {
+        let state = self.backend.gnss_state();
+
+        ui.push_id(("egui_term::gnss", self.backend.id), |ui| {
+            ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                Grid::new("gnss_status")
+                    .num_columns(2)
+                    .show(ui, |ui| Self::status(ui, &state));
+                ui.separator();
+                Grid::new("gnss_satellites")
+                    .striped(true)
+                    .num_columns(5)
+                    .show(ui, |ui| Self::satellites(ui, &state));
+            });
+        })
+        .response
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use egui::Color32;
+use regex::Regex;
+
+use crate::serial_tty::nmea::bad_checksums;
+use crate::theme::hex_to_color;
+
+/// Distinct rows kept in the match cache before it's cleared.
+const MAX_CACHED_ROWS: usize = 4096;
+
+/// Style of the NMEA sentences whose checksum is wrong.
+const BAD_CHECKSUM_STYLE: HighlightStyle = HighlightStyle {
+    fg: Some(Color32::from_rgb(0xff, 0x55, 0x55)),
+    bg: None,
+    bold: false,
+    underline: true,
+};
+
+/// Colors and attributes applied to the text matching `pattern`, on top of
+/// the ANSI colors of the device.
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub struct HighlightRule {
+    pub pattern: String,
+    /// `#rrggbb`, like the colors of `ColorPalette`.
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    pub bold: bool,
+    pub underline: bool,
+    /// Highlight the whole row instead of the matched text.
+    pub whole_line: bool,
+}
+
+impl HighlightRule {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn set_fg(mut self, fg: impl Into<String>) -> Self {
+        self.fg = Some(fg.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_bg(mut self, bg: impl Into<String>) -> Self {
+        self.bg = Some(bg.into());
+        self
+    }
+
+    #[must_use]
+    pub fn set_bold(mut self, bold: bool) -> Self {
+        self.bold = bold;
+        self
+    }
+
+    #[must_use]
+    pub fn set_underline(mut self, underline: bool) -> Self {
+        self.underline = underline;
+        self
+    }
+
+    #[must_use]
+    pub fn set_whole_line(mut self, whole_line: bool) -> Self {
+        self.whole_line = whole_line;
+        self
+    }
+
+    /// Errors, panics and failed asserts in red, warnings in yellow.
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            HighlightRule::new(r"\b(ERROR|[Ee]rror|panic(ked)?|assert\w*)\b")
+                .set_fg("#ff5555")
+                .set_bold(true),
+            HighlightRule::new(r"\b(WARN(ING)?|[Ww]arning)\b")
+                .set_fg("#f4bf75")
+                .set_bold(true),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HighlightStyle {
+    pub fg: Option<Color32>,
+    pub bg: Option<Color32>,
+    pub bold: bool,
+    pub underline: bool,
+}
+
+/// Highlighted columns of a row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighlightSpan {
+    pub columns: Range<usize>,
+    pub style: HighlightStyle,
+}
+
+struct CompiledRule {
+    regex: Regex,
+    style: HighlightStyle,
+    whole_line: bool,
+}
+
+/// Matches the rules against grid rows.
+///
+/// Results are cached by row content, so only rows which changed since the
+/// last frame are matched again.
+#[derive(Default)]
+pub(crate) struct Highlighter {
+    rules: Vec<HighlightRule>,
+    compiled: Vec<CompiledRule>,
+    nmea_checksums: bool,
+    cache: HashMap<u64, Vec<HighlightSpan>>,
+}
+
+impl Highlighter {
+    /// Replace the rules, unless they are the same as the current ones.
+    ///
+    /// Rules with an invalid regex or color are skipped.
+    pub(crate) fn set_rules(&mut self, rules: &[HighlightRule]) {
+        if self.rules == rules {
+            return;
+        }
+
+        self.rules = rules.to_vec();
+        self.cache.clear();
+        self.compiled = rules
+            .iter()
+            .filter_map(|rule| {
+                let color = |hex: &Option<String>| match hex {
+                    Some(hex) => hex_to_color(hex).map(Some),
+                    None => Ok(None),
+                };
+                let compiled = Regex::new(&rule.pattern)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|regex| {
+                        Ok(CompiledRule {
+                            regex,
+                            style: HighlightStyle {
+                                fg: color(&rule.fg)?,
+                                bg: color(&rule.bg)?,
+                                bold: rule.bold,
+                                underline: rule.underline,
+                            },
+                            whole_line: rule.whole_line,
+                        })
+                    });
+
+                compiled
+                    .inspect_err(|err| {
+                        log::error!(
+                            "Invalid highlight rule {:?}: {}",
+                            rule.pattern,
+                            err
+                        )
+                    })
+                    .ok()
+            })
+            .collect();
+    }
+
+    /// Also mark the NMEA sentences whose checksum is wrong, after the
+    /// rules.
+    pub(crate) fn set_nmea_checksums(&mut self, enabled: bool) {
+        if self.nmea_checksums != enabled {
+            self.nmea_checksums = enabled;
+            self.cache.clear();
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.compiled.is_empty() && !self.nmea_checksums
+    }
+
+    /// Spans of a row, given its text and the grid column of every char.
+    ///
+    /// Earlier rules take precedence where spans overlap.
+    pub(crate) fn row_spans(
+        &mut self,
+        text: &str,
+        columns: &[usize],
+        width: usize,
+    ) -> Vec<HighlightSpan> {
+        let mut hasher = DefaultHasher::new();
+        (text, columns, width).hash(&mut hasher);
+        let key = hasher.finish();
+        if let Some(spans) = self.cache.get(&key) {
+            return spans.clone();
+        }
+
+        let column = |byte: usize| {
+            let index = text[..byte].chars().count();
+            columns.get(index).copied().unwrap_or(width)
+        };
+        let mut spans = vec![];
+        for rule in &self.compiled {
+            for found in rule.regex.find_iter(text) {
+                let columns = if rule.whole_line {
+                    0..width
+                } else {
+                    column(found.start())..column(found.end())
+                };
+                spans.push(HighlightSpan {
+                    columns,
+                    style: rule.style,
+                });
+                if rule.whole_line {
+                    break;
+                }
+            }
+        }
+        if self.nmea_checksums {
+            spans.extend(bad_checksums(text).into_iter().map(|range| {
+                HighlightSpan {
+                    columns: column(range.start)..column(range.end),
+                    style: BAD_CHECKSUM_STYLE,
+                }
+            }));
+        }
+
+        if self.cache.len() >= MAX_CACHED_ROWS {
+            self.cache.clear();
+        }
+        self.cache.insert(key, spans.clone());
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_use_grid_columns() {
+        let mut highlighter = Highlighter::default();
+        highlighter.set_rules(&[
+            HighlightRule::new("ERROR").set_fg("#ff0000"),
+            HighlightRule::new("boot")
+                .set_bg("#0000ff")
+                .set_whole_line(true),
+            HighlightRule::new("(").set_fg("#ff0000"),
+        ]);
+        assert_eq!(highlighter.compiled.len(), 2);
+
+        // A wide char takes the columns 0 and 1.
+        let text = "漢 ERROR boot";
+        let mut columns = vec![0];
+        columns.extend(2..text.chars().count() + 1);
+        let spans = highlighter.row_spans(text, &columns, 20);
+        assert_eq!(spans[0].columns, 3..8);
+        assert_eq!(spans[0].style.fg, Some(Color32::from_rgb(255, 0, 0)));
+        assert_eq!(spans[1].columns, 0..20);
+        assert_eq!(highlighter.row_spans(text, &columns, 20), spans);
+    }
+
+    #[test]
+    fn nmea_bad_checksums_are_marked() {
+        let mut highlighter = Highlighter::default();
+        assert!(highlighter.is_empty());
+        highlighter.set_nmea_checksums(true);
+        assert!(!highlighter.is_empty());
+
+        let text = "$GPZDA*48 $GPZDA*00 ";
+        let columns: Vec<usize> = (0..text.len()).collect();
+        let spans = highlighter.row_spans(text, &columns, 40);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].columns, 10..19);
+        assert_eq!(spans[0].style, BAD_CHECKSUM_STYLE);
+    }
+}
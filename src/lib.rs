@@ -1,21 +1,83 @@
 mod backend;
 mod bindings;
+mod defmt_log;
 mod font;
+mod frame_list;
+mod gnss_view;
+mod highlight;
+mod line_input;
+mod modbus_table;
+mod packet_view;
+mod plot_view;
+#[cfg(feature = "scripting")]
+mod script;
+mod search_bar;
 mod serial_tty;
 mod simple_manager;
+#[cfg(test)]
+mod test_utils;
 mod theme;
+mod transfer;
 mod types;
 mod view;
 
-// pub use backend::settings::BackendSettings;
+pub use backend::expect::{ExpectMatch, ExpectSession};
+pub use backend::file_send::{
+    FileSendEvent, FileSendOptions, FileSendProgress,
+};
+pub use backend::hex_dump::{DisplayMode, HexDumpRow, HexDumpSettings};
+pub use backend::modbus::{
+    silent_interval, ModbusEntry, ModbusException, ModbusFrame, ModbusFramer,
+    ModbusMaster, ModbusPdu, ModbusTable, ModbusTableKind,
+};
+pub use backend::search::{SearchKind, SearchStatus};
+pub use backend::settings::{BackendSettings, Osc52};
 pub use backend::{
-    BackendCommand, SerialMonitorBackend, TerminalMode, TtyEvent,
+    BackendCommand, LineStamp, SerialMonitorBackend, TerminalMode, TtyEvent,
 };
 pub use bindings::{Binding, BindingAction, InputKind, KeyboardBinding};
+pub use defmt_log::DefmtLogView;
 pub use font::{FontSettings, TerminalFont};
+pub use frame_list::FrameListView;
+pub use gnss_view::GnssStatusView;
+pub use highlight::{HighlightRule, HighlightSpan, HighlightStyle};
+pub use line_input::InputMode;
+pub use modbus_table::ModbusTableView;
+pub use packet_view::PacketFieldsView;
+pub use plot_view::PlotView;
+#[cfg(feature = "scripting")]
+pub use script::{Script, ScriptNotification};
 pub use theme::{ColorPalette, TerminalTheme};
+pub use transfer::{
+    TransferEvent, TransferProgress, TransferProtocol, ZmodemRequest,
+};
 pub use view::SerialMonitorView;
 
 // Modify for Serial Monitor
+pub use serial_tty::archive::ArchivedLine;
+pub use serial_tty::control_tokens::ControlTokenStyle;
+pub use serial_tty::defmt::{
+    DefmtEncoding, DefmtLevel, DefmtRecord, DefmtTable, DEFAULT_DEFMT_CAPACITY,
+};
+pub use serial_tty::encoding::Encoding;
+pub use serial_tty::framing::{
+    DecodedFrame, FrameCheck, FrameCheckResult, FrameError, Framer, Framing,
+    LengthPrefix,
+};
+pub use serial_tty::line_stamps::TimestampFormat;
+pub use serial_tty::newline::{RxNewline, TxNewline};
+pub use serial_tty::nmea::{
+    nmea_checksum, FixMode, FixQuality, GnssState, NmeaError, NmeaSentence,
+    Satellite,
+};
+pub use serial_tty::plot::{
+    PlotPattern, PlotSample, PlotSeries, DEFAULT_PLOT_CAPACITY,
+};
+pub use serial_tty::schema::{
+    DecodedField, DecodedPacket, FieldType, PacketLayout, PacketSchema,
+    SchemaField, SchemaLogFormat, SchemaTag,
+};
+pub use serial_tty::tap::TrafficDirection;
+pub use serial_tty::triggers::{Trigger, TriggerAction, TriggerEvent};
 pub use serial_tty::{SerialTty, SerialTtyOptions};
 pub use simple_manager::SimpleSerialMonitorManager;
@@ -0,0 +1,183 @@
+use egui::{Key, Response, TextEdit};
+
+use crate::backend::{BackendCommand, SerialMonitorBackend};
+
+/// Max amount of sent lines kept for the history.
+const MAX_HISTORY: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputMode {
+    /// Every keystroke is sent to the device right away.
+    #[default]
+    Raw,
+    /// Input is edited locally and sent on Enter.
+    Line,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct LineInputState {
+    text: String,
+    history: Vec<String>,
+    /// Position while browsing the history, `None` when editing a new line.
+    history_index: Option<usize>,
+    /// Line being edited before browsing the history.
+    draft: String,
+    /// Whether the editor had the keyboard focus on the last frame.
+    has_focus: bool,
+}
+
+impl LineInputState {
+    pub(crate) fn has_focus(&self) -> bool {
+        self.has_focus
+    }
+
+    /// Append `text` to the line, without its line breaks.
+    pub(crate) fn insert(&mut self, text: &str) {
+        self.text
+            .extend(text.chars().filter(|c| !matches!(c, '\r' | '\n')));
+        self.history_index = None;
+    }
+
+    fn submit(&mut self) -> String {
+        let line = std::mem::take(&mut self.text);
+        if !line.is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+
+        self.history_index = None;
+        self.draft.clear();
+        line
+    }
+
+    fn history_up(&mut self) {
+        let index = match self.history_index {
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.text.clone();
+                self.history.len() - 1
+            },
+            Some(index) => index.saturating_sub(1),
+        };
+
+        self.history_index = Some(index);
+        self.text = self.history[index].clone();
+    }
+
+    fn history_down(&mut self) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+
+        if index + 1 < self.history.len() {
+            self.history_index = Some(index + 1);
+            self.text = self.history[index + 1].clone();
+        } else {
+            self.history_index = None;
+            self.text = std::mem::take(&mut self.draft);
+        }
+    }
+}
+
+/// Single line editor which sends its content with the TX newline on Enter.
+pub(crate) fn show_line_input(
+    ui: &mut egui::Ui,
+    backend: &mut SerialMonitorBackend,
+    state: &mut LineInputState,
+    has_focus: bool,
+) -> Response {
+    let response = ui.add(
+        TextEdit::singleline(&mut state.text)
+            .id_salt(("egui_term::line_input", backend.id))
+            .code_editor()
+            .desired_width(f32::INFINITY)
+            .hint_text("Line mode, Enter to send"),
+    );
+
+    if response.has_focus() {
+        let (up, down) = ui.input(|i| {
+            (i.key_pressed(Key::ArrowUp), i.key_pressed(Key::ArrowDown))
+        });
+        if up {
+            state.history_up();
+        } else if down {
+            state.history_down();
+        }
+    }
+
+    if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+        let mut line = state.submit().into_bytes();
+        // Translated to the configured TX newline by the backend.
+        line.push(b'\r');
+        backend.process_command(BackendCommand::Write(line));
+    }
+
+    if has_focus {
+        response.request_focus();
+    }
+    state.has_focus = response.has_focus();
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with_history(lines: &[&str]) -> LineInputState {
+        let mut state = LineInputState::default();
+        for line in lines {
+            state.insert(line);
+            state.submit();
+        }
+        state
+    }
+
+    #[test]
+    fn submit_records_history() {
+        let mut state = state_with_history(&["one", "two"]);
+        state.insert("two");
+        assert_eq!(state.submit(), "two");
+        assert_eq!(state.submit(), "");
+        assert_eq!(state.history, ["one", "two"]);
+
+        let mut state = LineInputState::default();
+        for i in 0..MAX_HISTORY + 5 {
+            state.insert(&i.to_string());
+            state.submit();
+        }
+        assert_eq!(state.history.len(), MAX_HISTORY);
+        assert_eq!(state.history[0], "5");
+    }
+
+    #[test]
+    fn insert_drops_line_breaks() {
+        let mut state = LineInputState::default();
+        state.insert("AT\r\n");
+        state.insert("+GMR");
+        assert_eq!(state.text, "AT+GMR");
+    }
+
+    #[test]
+    fn history_browsing_restores_the_draft() {
+        let mut state = state_with_history(&["one", "two"]);
+        state.insert("dra");
+        state.history_up();
+        assert_eq!(state.text, "two");
+        state.history_up();
+        state.history_up();
+        assert_eq!(state.text, "one");
+        state.history_down();
+        assert_eq!(state.text, "two");
+        state.history_down();
+        assert_eq!(state.text, "dra");
+        state.history_down();
+        assert_eq!(state.text, "dra");
+
+        let mut state = LineInputState::default();
+        state.history_up();
+        assert_eq!(state.text, "");
+    }
+}
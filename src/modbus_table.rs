@@ -0,0 +1,85 @@
+use egui::{Grid, Response, RichText, ScrollArea, Widget};
+
+use crate::backend::modbus::ModbusTableKind;
+use crate::backend::SerialMonitorBackend;
+use crate::serial_tty::line_stamps::TimestampFormat;
+
+/// Table of the values read from or written to the Modbus slaves by the
+/// masters of a backend, one row per slave and address.
+pub struct ModbusTableView<'a> {
+    backend: &'a SerialMonitorBackend,
+    timestamp_format: TimestampFormat,
+}
+
+impl<'a> ModbusTableView<'a> {
+    pub fn new(backend: &'a SerialMonitorBackend) -> Self {
+        Self {
+            backend,
+            timestamp_format: TimestampFormat::Absolute,
+        }
+    }
+
+    #[inline]
+    pub fn set_timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.timestamp_format = format;
+        self
+    }
+}
+
+impl Widget for ModbusTableView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> Response {
+        let entries = self.backend.modbus_table().entries();
+
+        ui.push_id(("egui_term::modbus_table", self.backend.id), |ui| {
+            ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                Grid::new("modbus_table").striped(true).num_columns(6).show(
+                    ui,
+                    |ui| {
+                        for header in [
+                            "Slave", "Table", "Address", "Value", "Hex",
+                            "Updated",
+                        ] {
+                            ui.label(RichText::new(header).strong());
+                        }
+                        ui.end_row();
+
+                        for entry in &entries {
+                            let is_bit = matches!(
+                                entry.kind,
+                                ModbusTableKind::Coil
+                                    | ModbusTableKind::DiscreteInput
+                            );
+                            ui.label(entry.slave.to_string());
+                            ui.label(entry.kind.to_string());
+                            ui.label(
+                                RichText::new(entry.address.to_string())
+                                    .monospace(),
+                            );
+                            let (value, hex) = if is_bit {
+                                let on =
+                                    if entry.value != 0 { "on" } else { "off" };
+                                (on.to_owned(), String::new())
+                            } else {
+                                (
+                                    entry.value.to_string(),
+                                    format!("{:04X}", entry.value),
+                                )
+                            };
+                            ui.label(RichText::new(value).monospace());
+                            ui.label(RichText::new(hex).monospace().weak());
+                            ui.label(
+                                RichText::new(
+                                    self.timestamp_format
+                                        .format(entry.time, None),
+                                )
+                                .weak(),
+                            );
+                            ui.end_row();
+                        }
+                    },
+                );
+            });
+        })
+        .response
+    }
+}
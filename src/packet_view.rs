@@ -0,0 +1,76 @@
+use egui::{CollapsingHeader, Grid, Response, RichText, ScrollArea, Widget};
+
+use crate::backend::SerialMonitorBackend;
+use crate::serial_tty::line_stamps::TimestampFormat;
+
+/// Fields of the last packet of every layout of the packet schema, updated
+/// as frames arrive. Meant to sit next to the `FrameListView` of the same
+/// backend.
+pub struct PacketFieldsView<'a> {
+    backend: &'a SerialMonitorBackend,
+    timestamp_format: TimestampFormat,
+}
+
+impl<'a> PacketFieldsView<'a> {
+    pub fn new(backend: &'a SerialMonitorBackend) -> Self {
+        Self {
+            backend,
+            timestamp_format: TimestampFormat::Absolute,
+        }
+    }
+
+    #[inline]
+    pub fn set_timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.timestamp_format = format;
+        self
+    }
+}
+
+impl Widget for PacketFieldsView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> Response {
+        let packets = self.backend.decoded_packets();
+
+        ui.push_id(("egui_term::packet_fields", self.backend.id), |ui| {
+            ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                for packet in &packets {
+                    let title = format!(
+                        "{}  #{}  {}",
+                        packet.packet,
+                        packet.frame,
+                        self.timestamp_format.format(packet.time, None)
+                    );
+                    CollapsingHeader::new(title)
+                        .id_salt(&packet.packet)
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            Grid::new(&packet.packet).striped(true).show(
+                                ui,
+                                |ui| {
+                                    for field in &packet.fields {
+                                        ui.label(&field.name);
+                                        ui.label(
+                                            RichText::new(format_value(
+                                                field.value,
+                                            ))
+                                            .monospace(),
+                                        );
+                                        ui.label(
+                                            RichText::new(&field.unit).weak(),
+                                        );
+                                        ui.end_row();
+                                    }
+                                },
+                            );
+                        });
+                }
+            });
+        })
+        .response
+    }
+}
+
+/// Up to 6 decimals, without the rounding noise of the scaling.
+fn format_value(value: f64) -> String {
+    let text = format!("{:.6}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
@@ -0,0 +1,269 @@
+use std::time::{Duration, SystemTime};
+
+use egui::{
+    pos2, vec2, Align2, Color32, Id, Pos2, Rect, Response, Sense, Shape,
+    Stroke, TextStyle, Widget,
+};
+
+use crate::backend::SerialMonitorBackend;
+use crate::serial_tty::line_stamps::TimestampFormat;
+
+const COLORS: [Color32; 8] = [
+    Color32::from_rgb(0x4e, 0x9a, 0xf1),
+    Color32::from_rgb(0xf4, 0xbf, 0x75),
+    Color32::from_rgb(0x90, 0xa9, 0x59),
+    Color32::from_rgb(0xff, 0x55, 0x55),
+    Color32::from_rgb(0xaa, 0x75, 0x9f),
+    Color32::from_rgb(0x75, 0xb5, 0xaa),
+    Color32::from_rgb(0xd2, 0x8b, 0x71),
+    Color32::from_rgb(0xd0, 0xd0, 0xd0),
+];
+const MIN_SPAN: f64 = 0.1;
+const MAX_SPAN: f64 = 24.0 * 3600.0;
+const REPAINT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Points of a series inside the view.
+struct VisibleSeries {
+    name: String,
+    last: Option<f64>,
+    /// Seconds since the Unix epoch and value.
+    points: Vec<[f64; 2]>,
+}
+
+/// View of one plot, kept in the egui memory between frames.
+#[derive(Debug, Clone, Copy)]
+struct PlotViewState {
+    /// Right edge of the frozen view, in seconds since the Unix epoch,
+    /// `None` while following the new samples.
+    frozen_at: Option<f64>,
+    /// Seconds shown.
+    span: f64,
+}
+
+impl Default for PlotViewState {
+    fn default() -> Self {
+        Self {
+            frozen_at: None,
+            span: 30.0,
+        }
+    }
+}
+
+/// Live graph of the series plotted by `SerialMonitorBackend::
+/// set_plot_patterns`, meant to sit next to the `SerialMonitorView` of the
+/// same backend.
+///
+/// Samples are placed by the time their line was received, on the same
+/// clock as the line timestamps of the terminal. The wheel zooms the time
+/// axis, dragging pans it and pauses the view, the samples received
+/// meanwhile are still recorded.
+pub struct PlotView<'a> {
+    backend: &'a SerialMonitorBackend,
+    timestamp_format: TimestampFormat,
+}
+
+impl<'a> PlotView<'a> {
+    pub fn new(backend: &'a SerialMonitorBackend) -> Self {
+        Self {
+            backend,
+            timestamp_format: TimestampFormat::Absolute,
+        }
+    }
+
+    /// Format of the time under the pointer.
+    #[inline]
+    pub fn set_timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.timestamp_format = format;
+        self
+    }
+
+    fn visible_series(
+        &self,
+        left: f64,
+        right: f64,
+        width: f32,
+    ) -> Vec<VisibleSeries> {
+        let plotter = self.backend.plotter().lock();
+        plotter
+            .series()
+            .iter()
+            .map(|series| {
+                let samples = &series.samples;
+                // One sample past each edge, so lines reach the borders.
+                let start = samples
+                    .partition_point(|sample| seconds(sample.time) < left)
+                    .saturating_sub(1);
+                let end = (samples
+                    .partition_point(|sample| seconds(sample.time) <= right)
+                    + 1)
+                .min(samples.len());
+                let count = end.saturating_sub(start);
+                let step = (count / (2 * width.max(1.0) as usize)).max(1);
+                let points = samples
+                    .range(start..end)
+                    .step_by(step)
+                    .map(|sample| [seconds(sample.time), sample.value])
+                    .collect();
+                VisibleSeries {
+                    name: series.name.clone(),
+                    last: samples.back().map(|sample| sample.value),
+                    points,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Widget for PlotView<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> Response {
+        let id = Id::new(("egui_term::plot", self.backend.id));
+        let mut state: PlotViewState =
+            ui.data(|data| data.get_temp(id)).unwrap_or_default();
+        let now = seconds(SystemTime::now());
+
+        ui.horizontal(|ui| {
+            let pause = if state.frozen_at.is_some() {
+                "Resume"
+            } else {
+                "Pause"
+            };
+            if ui.button(pause).clicked() {
+                state.frozen_at = match state.frozen_at {
+                    Some(_) => None,
+                    None => Some(now),
+                };
+            }
+            if ui.button("+").on_hover_text("Zoom in").clicked() {
+                state.span /= 2.0;
+            }
+            if ui.button("-").on_hover_text("Zoom out").clicked() {
+                state.span *= 2.0;
+            }
+            ui.label(format!("{:.1} s", state.span));
+        });
+
+        let size = vec2(ui.available_width(), ui.available_height().max(100.0));
+        let (rect, response) =
+            ui.allocate_exact_size(size, Sense::click_and_drag());
+        if response.hovered() {
+            let scroll = ui.input(|input| input.smooth_scroll_delta.y);
+            state.span *= f64::from(-scroll / 200.0).exp();
+        }
+        state.span = state.span.clamp(MIN_SPAN, MAX_SPAN);
+        if response.dragged() {
+            let right = state.frozen_at.unwrap_or(now);
+            let delta = response.drag_delta().x / rect.width();
+            state.frozen_at =
+                Some((right - f64::from(delta) * state.span).min(now));
+        }
+
+        let right = state.frozen_at.unwrap_or(now);
+        let left = right - state.span;
+        let series = self.visible_series(left, right, rect.width());
+        paint(ui, rect, left, right, &series);
+
+        if let Some(pointer) = response.hover_pos() {
+            let time = left
+                + f64::from((pointer.x - rect.left()) / rect.width())
+                    * state.span;
+            let time =
+                SystemTime::UNIX_EPOCH + Duration::from_secs_f64(time.max(0.0));
+            let painter = ui.painter_at(rect);
+            painter.vline(
+                pointer.x,
+                rect.y_range(),
+                ui.visuals().widgets.noninteractive.bg_stroke,
+            );
+            painter.text(
+                pos2(pointer.x + 4.0, rect.bottom() - 4.0),
+                Align2::LEFT_BOTTOM,
+                self.timestamp_format.format(time, None),
+                TextStyle::Small.resolve(ui.style()),
+                ui.visuals().text_color(),
+            );
+        }
+
+        ui.data_mut(|data| data.insert_temp(id, state));
+        if state.frozen_at.is_none() && !series.is_empty() {
+            ui.ctx().request_repaint_after(REPAINT_INTERVAL);
+        }
+        response
+    }
+}
+
+fn paint(
+    ui: &egui::Ui,
+    rect: Rect,
+    left: f64,
+    right: f64,
+    series: &[VisibleSeries],
+) {
+    let painter = ui.painter_at(rect);
+    let visuals = ui.visuals();
+    let font = TextStyle::Small.resolve(ui.style());
+    painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+
+    let (mut min, mut max) = series
+        .iter()
+        .flat_map(|series| series.points.iter().map(|point| point[1]))
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        });
+    if !min.is_finite() {
+        (min, max) = (0.0, 1.0);
+    } else if max == min {
+        (min, max) = (min - 1.0, max + 1.0);
+    }
+    let margin = (max - min) * 0.05;
+    let (min, max) = (min - margin, max + margin);
+
+    let to_screen = |[time, value]: [f64; 2]| -> Pos2 {
+        let x = (time - left) / (right - left);
+        let y = (value - min) / (max - min);
+        pos2(
+            rect.left() + x as f32 * rect.width(),
+            rect.bottom() - y as f32 * rect.height(),
+        )
+    };
+
+    for fraction in [0.0, 0.5, 1.0] {
+        let value = min + (max - min) * fraction;
+        let y = to_screen([left, value]).y;
+        painter.hline(
+            rect.x_range(),
+            y,
+            Stroke::new(1.0, visuals.faint_bg_color),
+        );
+        painter.text(
+            pos2(rect.right() - 4.0, y),
+            Align2::RIGHT_CENTER,
+            format!("{:.3}", value),
+            font.clone(),
+            visuals.weak_text_color(),
+        );
+    }
+
+    let mut legend = rect.left_top() + vec2(6.0, 4.0);
+    for (index, series) in series.iter().enumerate() {
+        let color = COLORS[index % COLORS.len()];
+        let points: Vec<Pos2> =
+            series.points.iter().copied().map(to_screen).collect();
+        painter.add(Shape::line(points, Stroke::new(1.5, color)));
+
+        let label = match series.last {
+            Some(last) => format!("{} {}", series.name, last),
+            None => series.name.clone(),
+        };
+        let galley = painter.layout_no_wrap(label, font.clone(), color);
+        let height = galley.size().y;
+        painter.galley(legend, galley, color);
+        legend.y += height;
+    }
+}
+
+#[inline]
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
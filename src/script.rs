@@ -0,0 +1,360 @@
+//! Rhai scripts attached to a session, available with the `scripting`
+//! feature.
+//!
+//! Every run gets its own engine on a worker thread, talking to the device
+//! through an `ExpectSession`. The functions available to scripts are:
+//!
+//! - `send(text)`, `send(blob)` and `send_line(text)`
+//! - `expect(pattern, timeout_ms)`, returning the match and its groups
+//! - `wait_quiet(ms)` and `sleep(ms)`
+//! - `set_dtr(level)` and `set_rts(level)`
+//! - `notify(message)`, reported by `SerialMonitorBackend::script_notifications`
+
+use std::cell::RefCell;
+use std::io::ErrorKind;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use rhai::{Array, Blob, Dynamic, Engine, EvalAltResult, INT};
+
+use crate::backend::expect::ExpectSession;
+
+/// Longest time a blocking script function waits before checking whether
+/// the run was cancelled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Script {
+    pub name: String,
+    pub source: String,
+}
+
+impl Script {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            source: source.into(),
+        }
+    }
+}
+
+/// Message of a script for the user, or the error which stopped it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptNotification {
+    pub script: String,
+    pub message: String,
+    pub is_error: bool,
+}
+
+/// Reports the notifications of one run.
+#[derive(Clone)]
+struct Notifier {
+    script: String,
+    notifications: Sender<ScriptNotification>,
+    app_context: egui::Context,
+}
+
+impl Notifier {
+    fn notify(&self, message: String, is_error: bool) {
+        let _ = self.notifications.send(ScriptNotification {
+            script: self.script.clone(),
+            message,
+            is_error,
+        });
+        self.app_context.request_repaint();
+    }
+}
+
+struct ScriptRun {
+    name: String,
+    cancel: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// Scripts of a session and their runs.
+pub(crate) struct ScriptHost {
+    scripts: Vec<Script>,
+    runs: Vec<ScriptRun>,
+    notifications: Sender<ScriptNotification>,
+    received: Receiver<ScriptNotification>,
+    app_context: egui::Context,
+}
+
+impl ScriptHost {
+    pub(crate) fn new(app_context: egui::Context) -> Self {
+        let (notifications, received) = mpsc::channel();
+        Self {
+            scripts: vec![],
+            runs: vec![],
+            notifications,
+            received,
+            app_context,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn scripts(&self) -> &[Script] {
+        &self.scripts
+    }
+
+    pub(crate) fn set_scripts(&mut self, scripts: &[Script]) {
+        self.scripts = scripts.to_vec();
+    }
+
+    pub(crate) fn script(&self, name: &str) -> Option<&Script> {
+        self.scripts.iter().find(|script| script.name == name)
+    }
+
+    /// Start `script` on a worker thread.
+    pub(crate) fn run(
+        &mut self,
+        script: Script,
+        session: ExpectSession,
+    ) -> std::io::Result<()> {
+        self.runs.retain(|run| !run.thread.is_finished());
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let name = script.name.clone();
+        let notifier = Notifier {
+            script: script.name.clone(),
+            notifications: self.notifications.clone(),
+            app_context: self.app_context.clone(),
+        };
+        let thread = std::thread::Builder::new()
+            .name(format!("script_{}", script.name))
+            .spawn({
+                let cancel = cancel.clone();
+                move || {
+                    let result = run_script(
+                        &script,
+                        session,
+                        cancel.clone(),
+                        notifier.clone(),
+                    );
+                    if let (Err(err), false) =
+                        (result, cancel.load(Ordering::Relaxed))
+                    {
+                        log::error!("Script {:?} failed: {}", script.name, err);
+                        notifier.notify(err.to_string(), true);
+                    }
+                }
+            })?;
+
+        self.runs.push(ScriptRun {
+            name,
+            cancel,
+            thread,
+        });
+        Ok(())
+    }
+
+    /// Names of the scripts which are still running.
+    pub(crate) fn running(&self) -> Vec<String> {
+        self.runs
+            .iter()
+            .filter(|run| !run.thread.is_finished())
+            .map(|run| run.name.clone())
+            .collect()
+    }
+
+    /// Stop all runs at their next statement or blocking call.
+    pub(crate) fn cancel(&mut self) {
+        for run in &self.runs {
+            run.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn notifications(&self) -> Vec<ScriptNotification> {
+        self.received.try_iter().collect()
+    }
+}
+
+impl Drop for ScriptHost {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+fn run_script(
+    script: &Script,
+    session: ExpectSession,
+    cancel: Arc<AtomicBool>,
+    notifier: Notifier,
+) -> ScriptResult<()> {
+    let mut engine = Engine::new();
+    let session = Rc::new(RefCell::new(session));
+    let is_cancelled = {
+        let cancel = cancel.clone();
+        move || -> ScriptResult<()> {
+            if cancel.load(Ordering::Relaxed) {
+                Err("Cancelled".into())
+            } else {
+                Ok(())
+            }
+        }
+    };
+
+    engine.on_progress({
+        let cancel = cancel.clone();
+        move |_| {
+            cancel
+                .load(Ordering::Relaxed)
+                .then(|| Dynamic::from("Cancelled".to_string()))
+        }
+    });
+    engine.on_print(|text| log::info!("{}", text));
+
+    let s = session.clone();
+    engine.register_fn("send", move |text: &str| -> ScriptResult<()> {
+        to_script(s.borrow().send(text.as_bytes()))
+    });
+    let s = session.clone();
+    engine.register_fn("send", move |bytes: Blob| -> ScriptResult<()> {
+        to_script(s.borrow().send(&bytes))
+    });
+    let s = session.clone();
+    engine.register_fn("send_line", move |line: &str| -> ScriptResult<()> {
+        to_script(s.borrow().send_line(line))
+    });
+    let s = session.clone();
+    engine.register_fn("set_dtr", move |level: bool| -> ScriptResult<()> {
+        to_script(s.borrow().set_dtr(level))
+    });
+    let s = session.clone();
+    engine.register_fn("set_rts", move |level: bool| -> ScriptResult<()> {
+        to_script(s.borrow().set_rts(level))
+    });
+    engine.register_fn("notify", move |message: &str| {
+        notifier.notify(message.to_string(), false)
+    });
+
+    let s = session.clone();
+    let check = is_cancelled.clone();
+    engine.register_fn(
+        "expect",
+        move |pattern: &str, timeout: INT| -> ScriptResult<Array> {
+            let deadline = Instant::now() + millis(timeout);
+            loop {
+                check()?;
+                let slice = deadline
+                    .saturating_duration_since(Instant::now())
+                    .min(CANCEL_POLL_INTERVAL);
+                match s.borrow_mut().expect(pattern, slice) {
+                    Ok(found) => {
+                        return Ok(found
+                            .groups
+                            .into_iter()
+                            .map(|group| {
+                                group.map_or(Dynamic::UNIT, Into::into)
+                            })
+                            .collect())
+                    },
+                    Err(err)
+                        if err.kind() == ErrorKind::TimedOut
+                            && Instant::now() < deadline => {},
+                    Err(err) => return Err(err.to_string().into()),
+                }
+            }
+        },
+    );
+
+    let s = session;
+    let check = is_cancelled.clone();
+    engine.register_fn("wait_quiet", move |quiet: INT| -> ScriptResult<()> {
+        let quiet = millis(quiet);
+        let mut last = Instant::now();
+        loop {
+            check()?;
+            let left = quiet.saturating_sub(last.elapsed());
+            if left.is_zero() {
+                return Ok(());
+            }
+            if to_script(
+                s.borrow_mut().receive(left.min(CANCEL_POLL_INTERVAL)),
+            )? {
+                last = Instant::now();
+            }
+        }
+    });
+
+    let check = is_cancelled;
+    engine.register_fn("sleep", move |duration: INT| -> ScriptResult<()> {
+        let deadline = Instant::now() + millis(duration);
+        loop {
+            check()?;
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            std::thread::sleep(left.min(CANCEL_POLL_INTERVAL));
+        }
+    });
+
+    let ast = engine.compile(&script.source)?;
+    engine.run_ast(&ast)
+}
+
+#[inline]
+fn to_script<T>(result: std::io::Result<T>) -> ScriptResult<T> {
+    result.map_err(|err| err.to_string().into())
+}
+
+#[inline]
+fn millis(millis: INT) -> Duration {
+    Duration::from_millis(millis.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::expect::test_session;
+    use crate::serial_tty::event_loop::SerialMsg;
+
+    #[test]
+    fn script_answers_prompt_and_notifies() {
+        let (session, input_receiver, received) = test_session();
+        let mut host = ScriptHost::new(egui::Context::default());
+        host.set_scripts(&[Script::new(
+            "login",
+            r#"
+                let found = expect("login: (\\w+)", 1000);
+                send_line("root");
+                set_dtr(false);
+                notify("logged into " + found[1]);
+            "#,
+        )]);
+
+        assert!(received.send(b"\nlogin: board1"));
+        let script = host.script("login").cloned().unwrap();
+        host.run(script, session).unwrap();
+
+        let notification = host.received.recv().unwrap();
+        assert_eq!(notification.message, "logged into board1");
+        assert!(!notification.is_error);
+        let sent: Vec<_> = input_receiver.try_iter().collect();
+        assert!(
+            matches!(&sent[0], SerialMsg::Input(bytes) if **bytes == *b"root\r\n")
+        );
+        assert!(matches!(sent[1], SerialMsg::Dtr(false)));
+    }
+
+    #[test]
+    fn cancel_stops_blocking_calls() {
+        let (session, _input_receiver, _received) = test_session();
+        let mut host = ScriptHost::new(egui::Context::default());
+        let script = Script::new("wait", "expect(\"never\", 60000);");
+        host.run(script, session).unwrap();
+        assert_eq!(host.running(), ["wait"]);
+
+        host.cancel();
+        let run = host.runs.pop().unwrap();
+        run.thread.join().unwrap();
+        assert!(host.notifications().is_empty());
+    }
+}
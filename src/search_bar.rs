@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use egui::{Align2, Color32, Id, Key, Rect, TextEdit};
+
+use crate::backend::search::{SearchKind, SearchStatus};
+use crate::backend::SerialMonitorBackend;
+
+/// Seconds without typing before the search runs.
+const SEARCH_DELAY: f64 = 0.25;
+
+#[derive(Clone, Default, Debug)]
+pub struct SearchBarState {
+    is_open: bool,
+    text: String,
+    is_regex: bool,
+    case_sensitive: bool,
+    /// Pattern is not a valid regex.
+    is_invalid: bool,
+    /// Time of the last change which isn't searched yet, searching waits
+    /// for the typing to pause.
+    changed_at: Option<f64>,
+    needs_focus: bool,
+}
+
+impl SearchBarState {
+    #[inline]
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    pub(crate) fn open(&mut self) {
+        self.is_open = true;
+        self.needs_focus = true;
+    }
+
+    fn close(&mut self, backend: &mut SerialMonitorBackend) {
+        self.is_open = false;
+        backend.clear_search();
+    }
+
+    fn search(&mut self, backend: &mut SerialMonitorBackend) {
+        let kind = if self.is_regex {
+            SearchKind::Regex
+        } else {
+            SearchKind::Plain
+        };
+        self.changed_at = None;
+        self.is_invalid = backend
+            .search(&self.text, kind, self.case_sensitive)
+            .is_err();
+    }
+
+    /// Whether the field shows that nothing matched.
+    fn is_failed(&self, backend: &SerialMonitorBackend) -> bool {
+        self.is_invalid
+            || (!self.text.is_empty()
+                && self.changed_at.is_none()
+                && backend.search_status() == SearchStatus::NotFound)
+    }
+}
+
+/// Search field with previous, next and close buttons, overlaid on the
+/// top right corner of the terminal.
+pub(crate) fn show_search_bar(
+    ui: &mut egui::Ui,
+    id: Id,
+    rect: Rect,
+    backend: &mut SerialMonitorBackend,
+    state: &mut SearchBarState,
+) {
+    egui::Area::new(id.with("search_bar"))
+        .order(egui::Order::Foreground)
+        .pivot(Align2::RIGHT_TOP)
+        .fixed_pos(rect.right_top())
+        .show(ui.ctx(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.horizontal(|ui| search_bar_ui(ui, backend, state));
+            });
+        });
+}
+
+fn search_bar_ui(
+    ui: &mut egui::Ui,
+    backend: &mut SerialMonitorBackend,
+    state: &mut SearchBarState,
+) {
+    let text_color = state.is_failed(backend).then_some(Color32::LIGHT_RED);
+    let response = ui.add(
+        TextEdit::singleline(&mut state.text)
+            .id_salt(("egui_term::search_bar", backend.id))
+            .desired_width(180.0)
+            .text_color_opt(text_color)
+            .hint_text("Search"),
+    );
+
+    let mut changed = response.changed();
+    changed |= ui
+        .toggle_value(&mut state.is_regex, ".*")
+        .on_hover_text("Regex")
+        .changed();
+    changed |= ui
+        .toggle_value(&mut state.case_sensitive, "Aa")
+        .on_hover_text("Match case")
+        .changed();
+    let now = ui.input(|i| i.time);
+    if changed {
+        state.changed_at = Some(now);
+    }
+    if let Some(changed_at) = state.changed_at {
+        let wait = SEARCH_DELAY - (now - changed_at);
+        if wait <= 0.0 {
+            state.search(backend);
+        } else {
+            ui.ctx()
+                .request_repaint_after(Duration::from_secs_f64(wait));
+        }
+    }
+
+    let (enter, shift, escape) = ui.input(|i| {
+        (
+            i.key_pressed(Key::Enter),
+            i.modifiers.shift,
+            i.key_pressed(Key::Escape),
+        )
+    });
+
+    // Enter goes up to older lines, as the search starts from the bottom.
+    let on_enter = response.lost_focus() && enter;
+    if on_enter {
+        state.needs_focus = true;
+    }
+    let up = ui.button("▲").on_hover_text("Previous").clicked();
+    let down = ui.button("▼").on_hover_text("Next").clicked();
+    if (up || down || on_enter) && state.changed_at.is_some() {
+        // The first step searches what was typed, from the bottom.
+        state.search(backend);
+    } else if down || (on_enter && shift) {
+        backend.search_next();
+    } else if up || on_enter {
+        backend.search_prev();
+    }
+
+    if ui.button("✖").on_hover_text("Close").clicked()
+        || (escape && (response.has_focus() || response.lost_focus()))
+    {
+        state.close(backend);
+        return;
+    }
+
+    if std::mem::take(&mut state.needs_focus) {
+        response.request_focus();
+    }
+}
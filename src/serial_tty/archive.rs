@@ -0,0 +1,325 @@
+//! On-disk store of the lines evicted from the scrollback history.
+//!
+//! Every line is appended as `<unix millis>\t<text>\n`, the millis are empty
+//! for lines without an RX timestamp. Only the offset of every
+//! `INDEX_STRIDE`th line is kept in memory, so memory use stays bounded no
+//! matter how long the session runs.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line};
+use alacritty_terminal::term::cell::{Cell, Flags};
+use alacritty_terminal::Grid;
+use regex::Regex;
+
+/// Lines between two indexed offsets.
+const INDEX_STRIDE: u64 = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivedLine {
+    pub stamp: Option<SystemTime>,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub struct ScrollbackArchive {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// Byte offset of line `i * INDEX_STRIDE`.
+    index: Vec<u64>,
+    len: u64,
+    size: u64,
+}
+
+impl ScrollbackArchive {
+    /// Create the archive file, truncating a previous one.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)?;
+
+        Ok(Self {
+            path: path.as_ref().to_owned(),
+            writer: BufWriter::new(file),
+            index: vec![],
+            len: 0,
+            size: 0,
+        })
+    }
+
+    /// Amount of archived lines.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn push(
+        &mut self,
+        stamp: Option<SystemTime>,
+        text: &str,
+    ) -> io::Result<()> {
+        // First line of a block.
+        if self.len == self.index.len() as u64 * INDEX_STRIDE {
+            self.index.push(self.size);
+        }
+
+        let millis = stamp
+            .and_then(|stamp| stamp.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_millis().to_string())
+            .unwrap_or_default();
+        let record = format!("{}\t{}\n", millis, text.replace('\n', " "));
+        self.writer.write_all(record.as_bytes())?;
+        self.size += record.len() as u64;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Reader of the lines archived so far, which doesn't borrow the
+    /// archive, so the lines can be read while more are pushed.
+    pub fn reader(&mut self) -> io::Result<ArchiveReader> {
+        self.writer.flush()?;
+        Ok(ArchiveReader {
+            reader: BufReader::new(File::open(&self.path)?),
+            index: self.index.clone(),
+            len: self.len,
+        })
+    }
+}
+
+/// The lines of a `ScrollbackArchive` when the reader was made.
+#[derive(Debug)]
+pub struct ArchiveReader {
+    reader: BufReader<File>,
+    index: Vec<u64>,
+    len: u64,
+}
+
+impl ArchiveReader {
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Up to `count` lines starting at line `start`.
+    pub fn lines(
+        &mut self,
+        start: u64,
+        count: usize,
+    ) -> io::Result<Vec<ArchivedLine>> {
+        let mut lines = Vec::with_capacity(count);
+        if start >= self.len {
+            return Ok(lines);
+        }
+
+        self.seek(start)?;
+        let end = self.len.min(start + count as u64);
+        for _ in start..end {
+            lines.push(self.read_line()?);
+        }
+
+        Ok(lines)
+    }
+
+    /// Index of the first line matching `regex`, looking from line `from` in
+    /// the given direction.
+    pub fn find(
+        &mut self,
+        regex: &Regex,
+        from: u64,
+        backwards: bool,
+    ) -> io::Result<Option<u64>> {
+        self.find_until_cancelled(
+            regex,
+            from,
+            backwards,
+            &AtomicBool::new(false),
+        )
+    }
+
+    /// Like `find`, failing with `ErrorKind::Interrupted` once `cancel` is
+    /// set, so a long scan can be abandoned from another thread.
+    pub fn find_until_cancelled(
+        &mut self,
+        regex: &Regex,
+        from: u64,
+        backwards: bool,
+        cancel: &AtomicBool,
+    ) -> io::Result<Option<u64>> {
+        let check_cancel = || {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
+            Ok(())
+        };
+        if self.is_empty() {
+            return Ok(None);
+        }
+
+        let from = from.min(self.len - 1);
+        if !backwards {
+            self.seek(from)?;
+            for line in from..self.len {
+                check_cancel()?;
+                if regex.is_match(&self.read_line()?.text) {
+                    return Ok(Some(line));
+                }
+            }
+            return Ok(None);
+        }
+
+        // Blocks are read forward, so scan them from the last one.
+        let mut block_end = from + 1;
+        while block_end > 0 {
+            check_cancel()?;
+            let block_start = (block_end - 1) / INDEX_STRIDE * INDEX_STRIDE;
+            let lines =
+                self.lines(block_start, (block_end - block_start) as usize)?;
+            if let Some(offset) =
+                lines.iter().rposition(|line| regex.is_match(&line.text))
+            {
+                return Ok(Some(block_start + offset as u64));
+            }
+            block_end = block_start;
+        }
+
+        Ok(None)
+    }
+
+    /// Position the reader at the start of `line`.
+    fn seek(&mut self, line: u64) -> io::Result<()> {
+        let block = line / INDEX_STRIDE;
+        self.reader
+            .seek(SeekFrom::Start(self.index[block as usize]))?;
+        for _ in block * INDEX_STRIDE..line {
+            self.reader.skip_until(b'\n')?;
+        }
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> io::Result<ArchivedLine> {
+        let mut record = String::new();
+        self.reader.read_line(&mut record)?;
+        let record = record.strip_suffix('\n').unwrap_or(&record);
+        let (millis, text) = record.split_once('\t').unwrap_or(("", record));
+        Ok(ArchivedLine {
+            stamp: millis
+                .parse()
+                .ok()
+                .map(|millis| UNIX_EPOCH + Duration::from_millis(millis)),
+            text: text.to_string(),
+        })
+    }
+}
+
+/// Text of the cells `start..=end` of a grid line, without trailing spaces.
+pub(crate) fn row_text(
+    grid: &Grid<Cell>,
+    line: Line,
+    start: Column,
+    end: Column,
+) -> String {
+    let row = &grid[line];
+    let mut text = String::new();
+    for column in start.0..=end.0.min(grid.last_column().0) {
+        let cell = &row[Column(column)];
+        if !cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+            text.push(cell.c);
+        }
+    }
+
+    text.trim_end().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_path;
+
+    fn archive(name: &str, len: u64) -> (ScrollbackArchive, PathBuf) {
+        let path = temp_path(name);
+        let mut archive = ScrollbackArchive::create(&path).unwrap();
+        for line in 0..len {
+            let stamp = UNIX_EPOCH + Duration::from_millis(line);
+            archive
+                .push(Some(stamp), &format!("line {}", line))
+                .unwrap();
+        }
+        (archive, path)
+    }
+
+    #[test]
+    fn read_across_index_blocks() {
+        let (mut archive, path) = archive("read", 3000);
+        let lines = archive.reader().unwrap().lines(1020, 10).unwrap();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0].text, "line 1020");
+        assert_eq!(lines[9].text, "line 1029");
+        assert_eq!(
+            lines[9].stamp,
+            Some(UNIX_EPOCH + Duration::from_millis(1029))
+        );
+        assert_eq!(archive.reader().unwrap().lines(2998, 10).unwrap().len(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reader_keeps_its_lines() {
+        let (mut archive, path) = archive("reader", 10);
+        let mut reader = archive.reader().unwrap();
+        archive.push(None, "line 10").unwrap();
+        assert_eq!(reader.len(), 10);
+        assert_eq!(reader.lines(8, 5).unwrap().len(), 2);
+        assert_eq!(archive.reader().unwrap().lines(8, 5).unwrap().len(), 3);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn find_in_both_directions() {
+        let (mut archive, path) = archive("find", 3000);
+        archive.push(None, "tab\tinside").unwrap();
+        let regex = Regex::new(r"^line 1\d{3}$").unwrap();
+        assert_eq!(
+            archive.reader().unwrap().find(&regex, 0, false).unwrap(),
+            Some(1000)
+        );
+        assert_eq!(
+            archive.reader().unwrap().find(&regex, 2500, true).unwrap(),
+            Some(1999)
+        );
+        assert_eq!(
+            archive.reader().unwrap().find(&regex, 999, true).unwrap(),
+            None
+        );
+
+        let regex = Regex::new("tab\tinside").unwrap();
+        assert_eq!(
+            archive.reader().unwrap().find(&regex, 0, false).unwrap(),
+            Some(3000)
+        );
+        assert_eq!(
+            archive.reader().unwrap().lines(3000, 1).unwrap()[0].stamp,
+            None
+        );
+
+        let cancel = AtomicBool::new(true);
+        let err = archive
+            .reader()
+            .unwrap()
+            .find_until_cancelled(&regex, 0, false, &cancel)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,264 @@
+//! Inline rendering of bytes which the VTE parser would silently drop.
+//!
+//! Control bytes without terminal meaning and invalid UTF-8 are replaced with
+//! printable tokens before they reach the parser. Tokens are wrapped in an
+//! OSC 8 hyperlink with a private URI, so the view can style those cells
+//! without touching the SGR state set by the device.
+
+use std::fmt::Write;
+
+use crate::serial_tty::escape::{Escape, TextFilter};
+
+/// Hyperlink URI of cells holding a control byte token.
+pub const CONTROL_TOKEN_URI: &str = "egui-serial-term:control";
+
+/// Hyperlink URI of cells holding an invalid UTF-8 byte token.
+pub const INVALID_TOKEN_URI: &str = "egui-serial-term:invalid";
+
+const MNEMONICS: [&str; 32] = [
+    "NUL", "SOH", "STX", "ETX", "EOT", "ENQ", "ACK", "BEL", "BS", "HT", "LF",
+    "VT", "FF", "CR", "SO", "SI", "DLE", "DC1", "DC2", "DC3", "DC4", "NAK",
+    "SYN", "ETB", "CAN", "EM", "SUB", "ESC", "FS", "GS", "RS", "US",
+];
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub enum ControlTokenStyle {
+    /// `<0x1F>`
+    Hex,
+    /// `<US>`
+    Mnemonic,
+    /// `^_`
+    Caret,
+}
+
+impl ControlTokenStyle {
+    fn control_token(self, byte: u8) -> String {
+        match (self, byte) {
+            (ControlTokenStyle::Hex, _) => format!("<0x{:02X}>", byte),
+            (ControlTokenStyle::Mnemonic, 0x7f) => "<DEL>".to_owned(),
+            (ControlTokenStyle::Mnemonic, _) => {
+                format!("<{}>", MNEMONICS[byte as usize])
+            },
+            (ControlTokenStyle::Caret, _) => {
+                format!("^{}", (byte ^ 0x40) as char)
+            },
+        }
+    }
+}
+
+/// Longest OSC string kept to follow the hyperlinks opened by the device.
+const MAX_OSC_LENGTH: usize = 2048;
+
+#[derive(Debug, Clone)]
+pub struct ControlTokenFilter {
+    style: ControlTokenStyle,
+    escape: TextFilter,
+    utf8: Vec<u8>,
+    utf8_len: usize,
+    /// OSC string being received, `None` for other strings.
+    osc: Option<Vec<u8>>,
+    /// OSC 8 string of the hyperlink opened by the device.
+    link: Option<Vec<u8>>,
+}
+
+impl ControlTokenFilter {
+    pub fn new(style: ControlTokenStyle) -> Self {
+        Self {
+            style,
+            escape: TextFilter::default(),
+            utf8: Vec::with_capacity(4),
+            utf8_len: 0,
+            osc: None,
+            link: None,
+        }
+    }
+
+    pub fn filter(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        for &byte in input {
+            self.advance(byte, output);
+        }
+    }
+
+    fn advance(&mut self, byte: u8, output: &mut Vec<u8>) {
+        if let Some(&first) = self.utf8.first() {
+            let continuation = match (first, self.utf8.len()) {
+                // Overlong forms, surrogates and code points above U+10FFFF.
+                (0xe0, 1) => 0xa0..=0xbf,
+                (0xed, 1) => 0x80..=0x9f,
+                (0xf0, 1) => 0x90..=0xbf,
+                (0xf4, 1) => 0x80..=0x8f,
+                _ => 0x80..=0xbf,
+            };
+            if continuation.contains(&byte) {
+                self.utf8.push(byte);
+                if self.utf8.len() == self.utf8_len {
+                    output.append(&mut self.utf8);
+                }
+                return;
+            }
+
+            // Truncated sequence, the current byte starts something new.
+            self.flush_invalid(output);
+        }
+
+        if self.escape.escape() == Escape::Ground && byte != 0x1b {
+            self.ground(byte, output);
+            return;
+        }
+
+        let before = self.escape.escape();
+        self.escape.is_text(byte);
+        match (before, self.escape.escape()) {
+            (Escape::Escape, Escape::String) => {
+                self.osc = (byte == b']').then(Vec::new);
+            },
+            (Escape::String, Escape::String) => {
+                if let Some(osc) = &mut self.osc {
+                    if osc.len() < MAX_OSC_LENGTH {
+                        osc.push(byte);
+                    }
+                }
+            },
+            (Escape::String | Escape::StringEscape, Escape::Ground) => {
+                if let Some(osc) = self.osc.take() {
+                    if byte == 0x07 || byte == b'\\' {
+                        self.osc_end(osc);
+                    }
+                }
+            },
+            _ => {},
+        }
+        output.push(byte);
+    }
+
+    /// Follow the hyperlinks opened and closed by the device.
+    fn osc_end(&mut self, osc: Vec<u8>) {
+        let Some(link) = osc.strip_prefix(b"8;") else {
+            return;
+        };
+        self.link = match link.iter().position(|&byte| byte == b';') {
+            Some(uri) if uri + 1 < link.len() => Some(osc),
+            _ => None,
+        };
+    }
+
+    fn ground(&mut self, byte: u8, output: &mut Vec<u8>) {
+        match byte {
+            // Controls handled by the terminal.
+            0x07..=0x0f | 0x20..=0x7e => output.push(byte),
+            0x00..=0x1f | 0x7f => {
+                let token = self.style.control_token(byte);
+                self.push_token(output, CONTROL_TOKEN_URI, &token);
+            },
+            0xc2..=0xdf => self.start_utf8(byte, 2),
+            0xe0..=0xef => self.start_utf8(byte, 3),
+            0xf0..=0xf4 => self.start_utf8(byte, 4),
+            _ => self.push_token(
+                output,
+                INVALID_TOKEN_URI,
+                &format!("<0x{:02X}>", byte),
+            ),
+        }
+    }
+
+    #[inline]
+    fn start_utf8(&mut self, byte: u8, len: usize) {
+        self.utf8.push(byte);
+        self.utf8_len = len;
+    }
+
+    fn flush_invalid(&mut self, output: &mut Vec<u8>) {
+        let mut token = String::new();
+        for byte in self.utf8.drain(..) {
+            let _ = write!(token, "<0x{:02X}>", byte);
+        }
+        self.push_token(output, INVALID_TOKEN_URI, &token);
+    }
+
+    /// Write `token` in a hyperlink to `uri`, then restore the hyperlink of
+    /// the device rather than closing it.
+    fn push_token(&self, output: &mut Vec<u8>, uri: &str, token: &str) {
+        output.extend_from_slice(b"\x1b]8;;");
+        output.extend_from_slice(uri.as_bytes());
+        output.extend_from_slice(b"\x1b\\");
+        output.extend_from_slice(token.as_bytes());
+        output.extend_from_slice(b"\x1b]");
+        match &self.link {
+            Some(link) => output.extend_from_slice(link),
+            None => output.extend_from_slice(b"8;;"),
+        }
+        output.extend_from_slice(b"\x1b\\");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(style: ControlTokenStyle, input: &[u8]) -> String {
+        let mut output = vec![];
+        ControlTokenFilter::new(style).filter(input, &mut output);
+        String::from_utf8(output)
+            .unwrap()
+            .replace(&format!("\x1b]8;;{}\x1b\\", CONTROL_TOKEN_URI), "{")
+            .replace(&format!("\x1b]8;;{}\x1b\\", INVALID_TOKEN_URI), "{!")
+            .replace("\x1b]8;;\x1b\\", "}")
+    }
+
+    #[test]
+    fn control_bytes_become_tokens() {
+        assert_eq!(filter(ControlTokenStyle::Hex, b"a\x00b"), "a{<0x00>}b");
+        assert_eq!(
+            filter(ControlTokenStyle::Mnemonic, b"\x1f\x7f"),
+            "{<US>}{<DEL>}"
+        );
+        assert_eq!(filter(ControlTokenStyle::Caret, b"\x01\r\n"), "{^A}\r\n");
+    }
+
+    #[test]
+    fn escape_sequences_pass_through() {
+        let input = b"\x1b[31mred\x1b]0;title\x07\x1b]2;x\x1b\\";
+        assert_eq!(
+            filter(ControlTokenStyle::Hex, input),
+            String::from_utf8(input.to_vec()).unwrap()
+        );
+    }
+
+    #[test]
+    fn links_of_the_device_are_restored() {
+        let link = "\x1b]8;id=1;http://a\x1b\\";
+        assert_eq!(
+            filter(
+                ControlTokenStyle::Hex,
+                b"\x1b]8;id=1;http://a\x07x\x00y\x1b]8;;\x1b\\\x00"
+            ),
+            format!("\x1b]8;id=1;http://a\x07x{{<0x00>{}y}}{{<0x00>}}", link)
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        assert_eq!(filter(ControlTokenStyle::Hex, "é".as_bytes()), "é");
+        assert_eq!(
+            filter(ControlTokenStyle::Hex, b"\xc3a\xff"),
+            "{!<0xC3>}a{!<0xFF>}"
+        );
+        assert_eq!(
+            filter(
+                ControlTokenStyle::Hex,
+                "\u{800}\u{d7ff}\u{10ffff}".as_bytes()
+            ),
+            "\u{800}\u{d7ff}\u{10ffff}"
+        );
+        for (input, tokens) in [
+            (&b"\xe0\x80\x80"[..], "{!<0xE0>}{!<0x80>}{!<0x80>}"),
+            (b"\xf0\x80\x80\x80", "{!<0xF0>}{!<0x80>}{!<0x80>}{!<0x80>}"),
+            (b"\xed\xa0\x80", "{!<0xED>}{!<0xA0>}{!<0x80>}"),
+            (b"\xf4\x90\x80\x80", "{!<0xF4>}{!<0x90>}{!<0x80>}{!<0x80>}"),
+        ] {
+            assert_eq!(filter(ControlTokenStyle::Hex, input), tokens);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! Just enough of ELF to list the symbols of a firmware.
+
+use std::io::{Error, ErrorKind, Result};
+
+const SHT_SYMTAB: u32 = 2;
+
+/// A symbol with the name of the section it's defined in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Symbol {
+    pub(crate) name: String,
+    pub(crate) value: u64,
+    pub(crate) section: Option<String>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, offset: u64) -> Result<[u8; N]> {
+        usize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.data.get(offset..offset.checked_add(N)?))
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(truncated)
+    }
+
+    fn u8(&self, offset: u64) -> Result<u8> {
+        Ok(self.bytes::<1>(offset)?[0])
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u64(&self, offset: u64) -> Result<u64> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    /// Address or offset sized word.
+    fn word(&self, offset: u64) -> Result<u64> {
+        if self.is_64 {
+            self.u64(offset)
+        } else {
+            self.u32(offset).map(u64::from)
+        }
+    }
+
+    /// NUL terminated string of a string table.
+    fn string(&self, table: &Section, offset: u32) -> Result<String> {
+        if offset as u64 >= table.size {
+            return Err(truncated());
+        }
+        let start = table
+            .offset
+            .checked_add(offset as u64)
+            .and_then(|start| usize::try_from(start).ok())
+            .ok_or_else(truncated)?;
+        let bytes = self.data.get(start..).ok_or_else(truncated)?;
+        let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(0);
+        Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+}
+
+struct Section {
+    name: u32,
+    kind: u32,
+    offset: u64,
+    size: u64,
+    link: u32,
+    entry_size: u64,
+}
+
+fn truncated() -> Error {
+    Error::new(ErrorKind::InvalidData, "Truncated ELF file")
+}
+
+/// Symbols of all the symbol tables of an ELF file.
+pub(crate) fn symbols(data: &[u8]) -> Result<Vec<Symbol>> {
+    if !data.starts_with(b"\x7fELF") {
+        return Err(Error::new(ErrorKind::InvalidData, "Not an ELF file"));
+    }
+    let reader = Reader {
+        data,
+        is_64: data.get(4) == Some(&2),
+        big_endian: data.get(5) == Some(&2),
+    };
+
+    let (section_offset, entry_size, count, names) = if reader.is_64 {
+        (
+            reader.u64(0x28)?,
+            reader.u16(0x3a)?,
+            reader.u16(0x3c)?,
+            reader.u16(0x3e)?,
+        )
+    } else {
+        (
+            reader.u32(0x20)? as u64,
+            reader.u16(0x2e)?,
+            reader.u16(0x30)?,
+            reader.u16(0x32)?,
+        )
+    };
+    let sections = (0..count as u64)
+        .map(|index| {
+            let header = section_offset + index * entry_size as u64;
+            let (offset, size, link, entry_size) = if reader.is_64 {
+                (0x18, 0x20, 0x28, 0x38)
+            } else {
+                (0x10, 0x14, 0x18, 0x24)
+            };
+            Ok(Section {
+                name: reader.u32(header)?,
+                kind: reader.u32(header + 4)?,
+                offset: reader.word(header + offset)?,
+                size: reader.word(header + size)?,
+                link: reader.u32(header + link)?,
+                entry_size: reader.word(header + entry_size)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let section_names = sections.get(names as usize).ok_or_else(truncated)?;
+    let section_name = |index: u16| {
+        sections
+            .get(index as usize)
+            .and_then(|section| reader.string(section_names, section.name).ok())
+            .filter(|name| !name.is_empty())
+    };
+
+    let mut symbols = vec![];
+    for table in sections.iter().filter(|section| section.kind == SHT_SYMTAB) {
+        let strings =
+            sections.get(table.link as usize).ok_or_else(truncated)?;
+        let entry_size = match table.entry_size {
+            0 if reader.is_64 => 24,
+            0 => 16,
+            size => size,
+        };
+        for index in 1..table.size / entry_size {
+            let entry = table.offset + index * entry_size;
+            let (name, value, section) = if reader.is_64 {
+                (
+                    reader.u32(entry)?,
+                    reader.u64(entry + 8)?,
+                    reader.u16(entry + 6)?,
+                )
+            } else {
+                (
+                    reader.u32(entry)?,
+                    reader.u32(entry + 4)? as u64,
+                    reader.u16(entry + 14)?,
+                )
+            };
+            // Skip the symbols of the sections and files themselves.
+            let kind = reader.u8(entry + if reader.is_64 { 4 } else { 12 })?;
+            if kind & 0xf >= 3 {
+                continue;
+            }
+            symbols.push(Symbol {
+                name: reader.string(strings, name)?,
+                value,
+                section: section_name(section),
+            });
+        }
+    }
+    Ok(symbols)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Little endian ELF32 with a section per name and a symbol table of
+    /// `(name, value, section index)`, sections are numbered from 1.
+    pub(crate) fn elf32(
+        section_names: &[&str],
+        symbols: &[(&str, u32, u16)],
+    ) -> Vec<u8> {
+        let mut strings = vec![0u8];
+        let mut symtab = vec![0u8; 16];
+        for (name, value, section) in symbols {
+            symtab.extend((strings.len() as u32).to_le_bytes());
+            symtab.extend(value.to_le_bytes());
+            symtab.extend(1u32.to_le_bytes());
+            // Global object.
+            symtab.extend([0x11, 0]);
+            symtab.extend(section.to_le_bytes());
+            strings.extend(name.as_bytes());
+            strings.push(0);
+        }
+        let mut section_strings = vec![0u8];
+        let mut name_offsets = vec![];
+        for name in
+            section_names
+                .iter()
+                .chain(&[".symtab", ".strtab", ".shstrtab"])
+        {
+            name_offsets.push(section_strings.len() as u32);
+            section_strings.extend(name.as_bytes());
+            section_strings.push(0);
+        }
+
+        let mut elf = vec![0u8; 0x34];
+        elf[..6].copy_from_slice(b"\x7fELF\x01\x01");
+        let symtab_offset = elf.len() as u32;
+        elf.extend(&symtab);
+        let strings_offset = elf.len() as u32;
+        elf.extend(&strings);
+        let names_offset = elf.len() as u32;
+        elf.extend(&section_strings);
+
+        let count = section_names.len() as u16 + 4;
+        let header_offset = elf.len() as u32;
+        elf[0x20..0x24].copy_from_slice(&header_offset.to_le_bytes());
+        elf[0x2e..0x30].copy_from_slice(&40u16.to_le_bytes());
+        elf[0x30..0x32].copy_from_slice(&count.to_le_bytes());
+        elf[0x32..0x34].copy_from_slice(&(count - 1).to_le_bytes());
+
+        let mut header =
+            |name: u32, kind: u32, offset: u32, size: u32, link: u32| {
+                let mut section = [0u8; 40];
+                section[0..4].copy_from_slice(&name.to_le_bytes());
+                section[4..8].copy_from_slice(&kind.to_le_bytes());
+                section[0x10..0x14].copy_from_slice(&offset.to_le_bytes());
+                section[0x14..0x18].copy_from_slice(&size.to_le_bytes());
+                section[0x18..0x1c].copy_from_slice(&link.to_le_bytes());
+                elf.extend(section);
+            };
+        header(0, 0, 0, 0, 0);
+        for offset in &name_offsets[..section_names.len()] {
+            // NOLOAD, like the `.defmt` section.
+            header(*offset, 8, 0, 0, 0);
+        }
+        let mut names = name_offsets[section_names.len()..].iter().copied();
+        let strtab = count as u32 - 2;
+        header(
+            names.next().unwrap(),
+            SHT_SYMTAB,
+            symtab_offset,
+            symtab.len() as u32,
+            strtab,
+        );
+        header(
+            names.next().unwrap(),
+            3,
+            strings_offset,
+            strings.len() as u32,
+            0,
+        );
+        header(
+            names.next().unwrap(),
+            3,
+            names_offset,
+            section_strings.len() as u32,
+            0,
+        );
+        elf
+    }
+
+    #[test]
+    fn symbols_with_their_section() {
+        let elf =
+            elf32(&[".text", ".defmt"], &[("main", 0x100, 1), ("{}", 3, 2)]);
+        assert_eq!(
+            symbols(&elf).unwrap(),
+            [
+                Symbol {
+                    name: "main".into(),
+                    value: 0x100,
+                    section: Some(".text".into())
+                },
+                Symbol {
+                    name: "{}".into(),
+                    value: 3,
+                    section: Some(".defmt".into())
+                },
+            ]
+        );
+        assert_eq!(
+            symbols(&elf[..elf.len() - 8]).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        assert!(symbols(b"MZ").is_err());
+    }
+}
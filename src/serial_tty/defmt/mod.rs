@@ -0,0 +1,446 @@
+//! Log messages of `defmt` firmwares.
+//!
+//! `defmt` sends the index of an interned format string and the binary
+//! arguments instead of text. With the format strings of the firmware ELF
+//! file the received frames are turned back into leveled and timestamped
+//! lines, kept in a log and rendered into the terminal in place of the
+//! binary stream.
+
+mod elf;
+mod table;
+
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use table::{DecodeError, Message};
+pub use table::{DefmtEncoding, DefmtLevel, DefmtTable};
+
+/// Default amount of records kept.
+pub const DEFAULT_DEFMT_CAPACITY: usize = 10_000;
+
+/// Longest frame buffered, longer ones are dropped as malformed.
+const MAX_FRAME_LENGTH: usize = 0x1_0000;
+
+/// A decoded log message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefmtRecord {
+    /// Position in the session, counting from `0`.
+    pub index: u64,
+    /// When the frame was received.
+    pub time: SystemTime,
+    /// Timestamp sent by the firmware, if it defines one.
+    pub timestamp: Option<String>,
+    /// `None` for `println!` messages and frames that failed to decode.
+    pub level: Option<DefmtLevel>,
+    pub message: String,
+    /// The frame didn't match the format strings, `message` describes it.
+    pub malformed: bool,
+}
+
+impl DefmtRecord {
+    /// `<timestamp> <LEVEL> <message>`, with the level colored.
+    fn render(&self, text: &mut Vec<u8>) {
+        if let Some(timestamp) = &self.timestamp {
+            text.extend_from_slice(timestamp.as_bytes());
+            text.push(b' ');
+        }
+        if let Some(level) = self.level {
+            let color = match level {
+                DefmtLevel::Trace => "2",
+                DefmtLevel::Debug => "0",
+                DefmtLevel::Info => "32",
+                DefmtLevel::Warn => "33",
+                DefmtLevel::Error => "31",
+            };
+            let level = format!("\x1b[{}m{:<5}\x1b[0m ", color, level.as_str());
+            text.extend_from_slice(level.as_bytes());
+        }
+        if self.malformed {
+            text.extend_from_slice(b"\x1b[2m");
+        }
+        text.extend_from_slice(self.message.replace('\n', "\r\n").as_bytes());
+        if self.malformed {
+            text.extend_from_slice(b"\x1b[0m");
+        }
+        text.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Decode rzCOBS `frame`, without its `0x00` terminator.
+///
+/// rzCOBS is decoded back to front: a byte with the high bit clear tells
+/// which of the 7 bytes before it are zeros, a higher one is a run of
+/// non-zero bytes followed by a zero. The last group is padded, so the
+/// result may end with up to 7 extra zeros.
+pub(crate) fn rzcobs_decode(frame: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(frame.len() * 2);
+    let mut bytes = frame.iter().rev();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            0x00 => return None,
+            0x01..=0x7f => {
+                for bit in 0..7 {
+                    if byte & (1 << (6 - bit)) == 0 {
+                        decoded.push(*bytes.next()?);
+                    } else {
+                        decoded.push(0);
+                    }
+                }
+            },
+            0x80..=0xfe => {
+                decoded.push(0);
+                for _ in 0..(byte & 0x7f) + 7 {
+                    decoded.push(*bytes.next()?);
+                }
+            },
+            0xff => {
+                for _ in 0..134 {
+                    decoded.push(*bytes.next()?);
+                }
+            },
+        }
+    }
+
+    decoded.reverse();
+    Some(decoded)
+}
+
+/// Turns the received bytes into records with the table of the firmware,
+/// while it has one.
+#[derive(Debug)]
+pub(crate) struct DefmtDecoder {
+    table: Option<DefmtTable>,
+    buffer: Vec<u8>,
+    records: VecDeque<DefmtRecord>,
+    next_index: u64,
+    capacity: usize,
+    /// Lines not handed to the terminal yet.
+    rendered: Vec<u8>,
+}
+
+impl Default for DefmtDecoder {
+    fn default() -> Self {
+        Self {
+            table: None,
+            buffer: vec![],
+            records: VecDeque::new(),
+            next_index: 0,
+            capacity: DEFAULT_DEFMT_CAPACITY,
+            rendered: vec![],
+        }
+    }
+}
+
+impl DefmtDecoder {
+    /// Replace the table, none stops decoding. The records are kept.
+    pub(crate) fn set_table(&mut self, table: Option<DefmtTable>) {
+        self.table = table;
+        self.buffer.clear();
+        self.rendered.clear();
+    }
+
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.table.is_some()
+    }
+
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        let Some(table) = &self.table else {
+            return;
+        };
+
+        let mut messages = vec![];
+        match table.encoding() {
+            DefmtEncoding::Rzcobs => {
+                for &byte in bytes {
+                    if byte != 0 {
+                        if self.buffer.len() < MAX_FRAME_LENGTH {
+                            self.buffer.push(byte);
+                        }
+                        continue;
+                    }
+                    if self.buffer.is_empty() {
+                        continue;
+                    }
+                    let frame = std::mem::take(&mut self.buffer);
+                    let message = rzcobs_decode(&frame)
+                        .filter(|_| frame.len() < MAX_FRAME_LENGTH)
+                        .ok_or(DecodeError::Malformed)
+                        .and_then(|decoded| {
+                            let (message, length) = table
+                                .decode(&decoded)
+                                .map_err(|_| DecodeError::Malformed)?;
+                            // Anything but padding means the table doesn't
+                            // match.
+                            decoded[length..]
+                                .iter()
+                                .all(|&byte| byte == 0)
+                                .then_some(message)
+                                .ok_or(DecodeError::Malformed)
+                        });
+                    messages.push(message.map_err(|_| frame.len()));
+                }
+            },
+            DefmtEncoding::Raw => {
+                self.buffer.extend_from_slice(bytes);
+                let mut start = 0;
+                let mut skipped = 0;
+                while start < self.buffer.len() {
+                    match table.decode(&self.buffer[start..]) {
+                        Ok((message, length)) => {
+                            if skipped > 0 {
+                                messages.push(Err(skipped));
+                                skipped = 0;
+                            }
+                            messages.push(Ok(message));
+                            start += length;
+                        },
+                        Err(DecodeError::Incomplete)
+                            if self.buffer.len() - start < MAX_FRAME_LENGTH =>
+                        {
+                            break
+                        },
+                        Err(_) => {
+                            // Resynchronize a byte further.
+                            skipped += 1;
+                            start += 1;
+                        },
+                    }
+                }
+                if skipped > 0 {
+                    messages.push(Err(skipped));
+                }
+                self.buffer.drain(..start);
+            },
+        }
+
+        let time = SystemTime::now();
+        for message in messages {
+            let record = match message {
+                Ok(Message {
+                    timestamp,
+                    level,
+                    text,
+                }) => DefmtRecord {
+                    index: self.next_index,
+                    time,
+                    timestamp,
+                    level,
+                    message: text,
+                    malformed: false,
+                },
+                Err(length) => DefmtRecord {
+                    index: self.next_index,
+                    time,
+                    timestamp: None,
+                    level: None,
+                    message: format!(
+                        "<malformed defmt frame, {} bytes>",
+                        length
+                    ),
+                    malformed: true,
+                },
+            };
+            self.next_index += 1;
+            record.render(&mut self.rendered);
+            if self.records.len() >= self.capacity {
+                self.records.pop_front();
+            }
+            self.records.push_back(record);
+        }
+    }
+
+    /// Take the lines rendered since the last call, `None` while not
+    /// decoding.
+    pub(crate) fn take_rendered(&mut self) -> Option<Vec<u8>> {
+        self.table.as_ref()?;
+        Some(std::mem::take(&mut self.rendered))
+    }
+
+    /// Records kept, the oldest are dropped first.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        let excess = self.records.len().saturating_sub(self.capacity);
+        self.records.drain(..excess);
+    }
+
+    #[inline]
+    pub(crate) fn records(&self) -> &VecDeque<DefmtRecord> {
+        &self.records
+    }
+
+    /// Records from `index` on.
+    pub(crate) fn records_since(&self, index: u64) -> Vec<DefmtRecord> {
+        let start = self.records.partition_point(|record| record.index < index);
+        self.records.range(start..).cloned().collect()
+    }
+
+    /// Copies of the records with the given ascending indexes, the dropped
+    /// ones are skipped.
+    pub(crate) fn records_at(&self, indexes: &[u64]) -> Vec<DefmtRecord> {
+        let (Some(&first), Some(&last)) = (indexes.first(), indexes.last())
+        else {
+            return vec![];
+        };
+
+        let start = self.records.partition_point(|record| record.index < first);
+        self.records
+            .range(start..)
+            .take_while(|record| record.index <= last)
+            .filter(|record| indexes.binary_search(&record.index).is_ok())
+            .cloned()
+            .collect()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::elf::tests::elf32;
+    use super::table::tests::{symbol, table};
+    use super::*;
+
+    /// rzCOBS encode `data` and end the frame.
+    fn rzcobs_encode(data: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![];
+        let mut run = 0;
+        let mut zeros = 0u8;
+        for &byte in data {
+            if run < 7 {
+                if byte == 0 {
+                    zeros |= 1 << run;
+                } else {
+                    encoded.push(byte);
+                }
+                run += 1;
+                if run == 7 && zeros != 0 {
+                    encoded.push(zeros);
+                    run = 0;
+                    zeros = 0;
+                }
+            } else if byte == 0 {
+                encoded.push((run - 7) | 0x80);
+                run = 0;
+                zeros = 0;
+            } else {
+                encoded.push(byte);
+                run += 1;
+                if run == 134 {
+                    encoded.push(0xff);
+                    run = 0;
+                    zeros = 0;
+                }
+            }
+        }
+        if run < 7 {
+            encoded.push((zeros | (0x7f << run)) & 0x7f);
+        } else {
+            encoded.push((run - 7) | 0x80);
+        }
+        encoded.push(0);
+        encoded
+    }
+
+    #[test]
+    fn rzcobs_round_trip() {
+        let long: Vec<u8> = (1..=200).collect();
+        for data in [
+            &[][..],
+            &[0],
+            &[1, 2, 3],
+            &[0, 0, 1, 0, 0, 0, 0, 0, 2],
+            &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 0, 11],
+            &long,
+        ] {
+            let encoded = rzcobs_encode(data);
+            assert!(!encoded[..encoded.len() - 1].contains(&0));
+            let decoded = rzcobs_decode(&encoded[..encoded.len() - 1]).unwrap();
+            assert!(decoded.starts_with(data));
+            assert!(decoded.len() - data.len() <= 7);
+            assert!(decoded[data.len()..].iter().all(|&byte| byte == 0));
+        }
+        assert_eq!(rzcobs_decode(&[1, 0, 2]), None);
+    }
+
+    #[test]
+    fn frames_become_records() {
+        let table = table(&[
+            (1, "info", "temp={=u8}"),
+            (2, "error", "line\nbreak"),
+            (3, "println", "{=str}"),
+        ]);
+        let mut decoder = DefmtDecoder::default();
+        decoder.push(&rzcobs_encode(&[1, 0, 20]));
+        assert_eq!(decoder.take_rendered(), None);
+
+        decoder.set_table(Some(table));
+        let mut bytes = rzcobs_encode(&[1, 0, 20]);
+        bytes.extend(rzcobs_encode(&[9, 9]));
+        bytes.extend(rzcobs_encode(&[2, 0]));
+        let split = bytes.len() - 2;
+        decoder.push(&bytes[..split]);
+        decoder.push(&bytes[split..]);
+
+        let records = decoder.records_since(0);
+        let messages: Vec<(Option<DefmtLevel>, &str, bool)> = records
+            .iter()
+            .map(|r| (r.level, r.message.as_str(), r.malformed))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (Some(DefmtLevel::Info), "temp=20", false),
+                (None, "<malformed defmt frame, 3 bytes>", true),
+                (Some(DefmtLevel::Error), "line\nbreak", false),
+            ]
+        );
+        assert_eq!(decoder.records_since(2).len(), 1);
+
+        let rendered = String::from_utf8(decoder.take_rendered().unwrap());
+        assert_eq!(
+            rendered.unwrap(),
+            "\x1b[32mINFO \x1b[0m temp=20\r\n\
+             \x1b[2m<malformed defmt frame, 3 bytes>\x1b[0m\r\n\
+             \x1b[31mERROR\x1b[0m line\r\nbreak\r\n"
+        );
+        assert_eq!(decoder.take_rendered(), Some(vec![]));
+
+        let indexes = |records: Vec<DefmtRecord>| -> Vec<u64> {
+            records.iter().map(|record| record.index).collect()
+        };
+        assert_eq!(indexes(decoder.records_at(&[0, 2, 5])), [0, 2]);
+
+        decoder.set_capacity(1);
+        assert_eq!(decoder.records().len(), 1);
+        assert_eq!(indexes(decoder.records_at(&[0, 2])), [2]);
+        assert!(decoder.records_at(&[]).is_empty());
+    }
+
+    #[test]
+    fn raw_frames_resynchronize() {
+        let name = symbol("warn", "{=u16:#x}");
+        let elf = elf32(
+            &[".defmt"],
+            &[(&name, 1, 1), ("_defmt_encoding_ = raw", 0, 0)],
+        );
+        let table = DefmtTable::from_elf(&elf).unwrap();
+        let mut decoder = DefmtDecoder::default();
+        decoder.set_table(Some(table));
+
+        decoder.push(&[1, 0, 0x34]);
+        assert!(decoder.records().is_empty());
+        decoder.push(&[0x12, 7, 1, 0, 0xff, 0]);
+        let messages: Vec<&str> = decoder
+            .records()
+            .iter()
+            .map(|record| record.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            ["0x1234", "<malformed defmt frame, 1 bytes>", "0xff"]
+        );
+    }
+}
@@ -0,0 +1,800 @@
+//! Format strings of a firmware and the decoding of their arguments.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use super::elf;
+
+/// Deepest nesting of `Format` values decoded.
+const MAX_DEPTH: usize = 32;
+
+/// How the firmware frames the log messages on the wire.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DefmtEncoding {
+    /// Frames compressed with rzCOBS and ended by a `0x00`, the default of
+    /// `defmt-rtt` and most UART transports.
+    #[default]
+    Rzcobs,
+    /// Frames back to back, without delimiters.
+    Raw,
+}
+
+/// Level of a log message, `println!` messages have none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DefmtLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl DefmtLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DefmtLevel::Trace => "TRACE",
+            DefmtLevel::Debug => "DEBUG",
+            DefmtLevel::Info => "INFO",
+            DefmtLevel::Warn => "WARN",
+            DefmtLevel::Error => "ERROR",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tag {
+    Level(DefmtLevel),
+    Println,
+    Timestamp,
+    Derived,
+    /// Interned strings, `write!` and manual `Format` impls.
+    Other,
+}
+
+impl Tag {
+    fn new(tag: &str) -> Self {
+        match tag {
+            "defmt_trace" => Tag::Level(DefmtLevel::Trace),
+            "defmt_debug" => Tag::Level(DefmtLevel::Debug),
+            "defmt_info" => Tag::Level(DefmtLevel::Info),
+            "defmt_warn" => Tag::Level(DefmtLevel::Warn),
+            "defmt_error" => Tag::Level(DefmtLevel::Error),
+            "defmt_println" => Tag::Println,
+            "defmt_timestamp" => Tag::Timestamp,
+            "defmt_derived" => Tag::Derived,
+            _ => Tag::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    tag: Tag,
+    format: String,
+}
+
+/// Why a frame could not be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DecodeError {
+    /// More bytes are needed.
+    Incomplete,
+    /// The bytes don't match the table.
+    Malformed,
+}
+
+type Decoded<T> = std::result::Result<T, DecodeError>;
+
+/// A decoded log message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Message {
+    pub(crate) timestamp: Option<String>,
+    pub(crate) level: Option<DefmtLevel>,
+    pub(crate) text: String,
+}
+
+/// The interned format strings of a firmware, read from the `.defmt`
+/// section of its ELF file.
+///
+/// Only the symbol table is read: the locations of the messages live in
+/// the DWARF info and are not shown.
+#[derive(Debug, Clone)]
+pub struct DefmtTable {
+    entries: HashMap<u16, Entry>,
+    timestamp: Option<u16>,
+    encoding: DefmtEncoding,
+}
+
+impl DefmtTable {
+    /// Read the table of an ELF file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_elf(&std::fs::read(path)?)
+    }
+
+    /// Read the table of the bytes of an ELF file, built with `defmt` 0.3.
+    pub fn from_elf(data: &[u8]) -> Result<Self> {
+        let mut table = Self {
+            entries: HashMap::new(),
+            timestamp: None,
+            encoding: DefmtEncoding::default(),
+        };
+        for symbol in elf::symbols(data)? {
+            match symbol.name.strip_prefix("_defmt_encoding_ = ") {
+                Some("raw") => table.encoding = DefmtEncoding::Raw,
+                Some("rzcobs") => table.encoding = DefmtEncoding::Rzcobs,
+                Some(encoding) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Unknown defmt encoding {}", encoding),
+                    ))
+                },
+                None => {},
+            }
+            if symbol.section.as_deref() != Some(".defmt") {
+                continue;
+            }
+            let (Some(fields), Ok(index)) =
+                (json_object(&symbol.name), u16::try_from(symbol.value))
+            else {
+                continue;
+            };
+            let field = |name: &str| {
+                fields
+                    .iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.clone())
+            };
+            let (Some(tag), Some(format)) = (field("tag"), field("data"))
+            else {
+                continue;
+            };
+            let tag = Tag::new(&tag);
+            if tag == Tag::Timestamp {
+                table.timestamp = Some(index);
+            }
+            table.entries.insert(index, Entry { tag, format });
+        }
+
+        if table.entries.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "No defmt symbols in the ELF file",
+            ));
+        }
+        Ok(table)
+    }
+
+    #[inline]
+    pub fn encoding(&self) -> DefmtEncoding {
+        self.encoding
+    }
+
+    /// Amount of format strings.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Decode the message at the start of `data`, with the amount of bytes
+    /// it spans.
+    pub(crate) fn decode(&self, data: &[u8]) -> Decoded<(Message, usize)> {
+        let mut cursor = Cursor { data, position: 0 };
+        let entry = self.entry(cursor.u16()?)?;
+        let level = match entry.tag {
+            Tag::Level(level) => Some(level),
+            Tag::Println => None,
+            _ => return Err(DecodeError::Malformed),
+        };
+        let timestamp = match self.timestamp {
+            Some(index) => {
+                let format = &self.entry(index)?.format;
+                Some(self.format(format, &mut cursor, 0)?)
+            },
+            None => None,
+        };
+        let text = self.format(&entry.format, &mut cursor, 0)?;
+        let message = Message {
+            timestamp,
+            level,
+            text,
+        };
+        Ok((message, cursor.position))
+    }
+
+    fn entry(&self, index: u16) -> Decoded<&Entry> {
+        self.entries.get(&index).ok_or(DecodeError::Malformed)
+    }
+
+    /// A `Format` value: its index and arguments.
+    fn nested(&self, cursor: &mut Cursor, depth: usize) -> Decoded<String> {
+        let entry = self.entry(cursor.u16()?)?;
+        if entry.tag != Tag::Derived || !entry.format.contains('|') {
+            return self.format(&entry.format, cursor, depth + 1);
+        }
+
+        // Enums list a format per variant, after their discriminant.
+        let variants: Vec<&str> = entry.format.split('|').collect();
+        let discriminant = if variants.len() < 256 {
+            cursor.uint(1)? as usize
+        } else {
+            cursor.u16()? as usize
+        };
+        let variant =
+            variants.get(discriminant).ok_or(DecodeError::Malformed)?;
+        self.format(variant, cursor, depth + 1)
+    }
+
+    /// Decode the arguments of `format` and fill them in.
+    fn format(
+        &self,
+        format: &str,
+        cursor: &mut Cursor,
+        depth: usize,
+    ) -> Decoded<String> {
+        if depth > MAX_DEPTH {
+            return Err(DecodeError::Malformed);
+        }
+        let segments = parse_format(format);
+        let mut params: Vec<&Param> = segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Param(param) => Some(param),
+                Segment::Literal(_) => None,
+            })
+            .collect();
+        params.sort_by_key(|param| param.index);
+
+        // Arguments are encoded once each, in the order of their index.
+        let mut values = HashMap::new();
+        for (position, param) in params.iter().enumerate() {
+            if values.contains_key(&param.index) {
+                continue;
+            }
+            let value = match bit_range(&param.ty) {
+                Some(_) => {
+                    let ranges = params[position..]
+                        .iter()
+                        .take_while(|other| other.index == param.index)
+                        .filter_map(|other| bit_range(&other.ty));
+                    let (start, end) =
+                        ranges.fold((u32::MAX, 0), |(start, end), range| {
+                            (start.min(range.0), end.max(range.1))
+                        });
+                    let low = start / 8;
+                    let high = (end.max(1) - 1) / 8;
+                    Value::Uint(
+                        cursor.uint((high - low + 1) as usize)? << (low * 8),
+                    )
+                },
+                None => self.value(&param.ty, cursor, depth)?,
+            };
+            values.insert(param.index, value);
+        }
+
+        let mut text = String::new();
+        for segment in &segments {
+            match segment {
+                Segment::Literal(literal) => text.push_str(literal),
+                Segment::Param(param) => {
+                    let value = &values[&param.index];
+                    let value = match (bit_range(&param.ty), value) {
+                        (Some((start, end)), Value::Uint(bits)) => {
+                            let width = end.saturating_sub(start).min(127);
+                            Value::Uint((bits >> start) & ((1 << width) - 1))
+                        },
+                        _ => value.clone(),
+                    };
+                    render(&value, &Hint::new(&param.hint), &mut text);
+                },
+            }
+        }
+        Ok(text)
+    }
+
+    fn value(
+        &self,
+        ty: &str,
+        cursor: &mut Cursor,
+        depth: usize,
+    ) -> Decoded<Value> {
+        let signed = |cursor: &mut Cursor, size: usize| -> Decoded<Value> {
+            let bits = size as u32 * 8;
+            let value = cursor.uint(size)?;
+            // Sign extend from the width of the type.
+            let value = ((value << (128 - bits)) as i128) >> (128 - bits);
+            Ok(Value::Int(value, bits))
+        };
+        Ok(match ty {
+            "bool" => Value::Bool(cursor.uint(1)? != 0),
+            "u8" => Value::Uint(cursor.uint(1)?),
+            "u16" => Value::Uint(cursor.uint(2)?),
+            "u32" | "usize" => Value::Uint(cursor.uint(4)?),
+            "u64" => Value::Uint(cursor.uint(8)?),
+            "u128" => Value::Uint(cursor.uint(16)?),
+            "i8" => signed(cursor, 1)?,
+            "i16" => signed(cursor, 2)?,
+            "i32" | "isize" => signed(cursor, 4)?,
+            "i64" => signed(cursor, 8)?,
+            "i128" => signed(cursor, 16)?,
+            "f32" => Value::F32(f32::from_bits(cursor.uint(4)? as u32)),
+            "f64" => Value::F64(f64::from_bits(cursor.uint(8)? as u64)),
+            "char" => Value::Char(
+                char::from_u32(cursor.uint(4)? as u32)
+                    .ok_or(DecodeError::Malformed)?,
+            ),
+            "str" | "__internal_Display" | "__internal_Debug" => {
+                let length = cursor.uint(4)? as usize;
+                let bytes = cursor.take(length)?;
+                Value::Str(String::from_utf8_lossy(bytes).into_owned())
+            },
+            "istr" => Value::Str(self.entry(cursor.u16()?)?.format.clone()),
+            "[u8]" => {
+                let length = cursor.uint(4)? as usize;
+                Value::Bytes(cursor.take(length)?.to_vec())
+            },
+            "?" => Value::Formatted(self.nested(cursor, depth)?),
+            "[?]" => {
+                let length = cursor.uint(4)? as usize;
+                let mut elements = Vec::new();
+                for _ in 0..length {
+                    elements.push(self.nested(cursor, depth)?);
+                }
+                Value::Formatted(format!("[{}]", elements.join(", ")))
+            },
+            "__internal_FormatSequence" => {
+                let mut text = String::new();
+                loop {
+                    let index = cursor.u16()?;
+                    if index == 0 {
+                        break;
+                    }
+                    let format = &self.entry(index)?.format;
+                    text.push_str(&self.format(format, cursor, depth + 1)?);
+                }
+                Value::Formatted(text)
+            },
+            _ => {
+                let length = ty
+                    .strip_prefix("[u8;")
+                    .and_then(|length| length.strip_suffix(']'))
+                    .and_then(|length| length.trim().parse().ok())
+                    .ok_or(DecodeError::Malformed)?;
+                Value::Bytes(cursor.take(length)?.to_vec())
+            },
+        })
+    }
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, length: usize) -> Decoded<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(length)
+            .ok_or(DecodeError::Malformed)?;
+        let bytes = self
+            .data
+            .get(self.position..end)
+            .ok_or(DecodeError::Incomplete)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Little endian integer of `size` bytes.
+    fn uint(&mut self, size: usize) -> Decoded<u128> {
+        if size > 16 {
+            return Err(DecodeError::Malformed);
+        }
+        let bytes = self.take(size)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |value, &byte| (value << 8) | byte as u128))
+    }
+
+    fn u16(&mut self) -> Decoded<u16> {
+        Ok(self.uint(2)? as u16)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Uint(u128),
+    /// Value and width in bits.
+    Int(i128, u32),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+    /// A `Format` value, already filled in.
+    Formatted(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Param {
+    index: usize,
+    ty: String,
+    hint: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(Param),
+}
+
+/// Split a format string in literals and `{[index][=type][:hint]}`
+/// parameters, a parameter without type is a `Format` value.
+fn parse_format(format: &str) -> Vec<Segment> {
+    let mut segments = vec![];
+    let mut literal = String::new();
+    let mut next_index = 0;
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            },
+            '{' => {
+                let spec: String =
+                    chars.by_ref().take_while(|&c| c != '}').collect();
+                let (spec, hint) = spec.split_once(':').unwrap_or((&spec, ""));
+                let (index, ty) = spec.split_once('=').unwrap_or((spec, "?"));
+                let index = match index.parse() {
+                    Ok(index) => index,
+                    Err(_) => {
+                        next_index += 1;
+                        next_index - 1
+                    },
+                };
+                if !literal.is_empty() {
+                    segments
+                        .push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Param(Param {
+                    index,
+                    ty: ty.to_owned(),
+                    hint: hint.to_owned(),
+                }));
+            },
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    segments
+}
+
+/// The bits of a bitfield type, `0..4`.
+fn bit_range(ty: &str) -> Option<(u32, u32)> {
+    let (start, end) = ty.split_once("..")?;
+    let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+    (start < end && end <= 128).then_some((start, end))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum HintKind {
+    #[default]
+    Display,
+    Debug,
+    LowerHex,
+    UpperHex,
+    Binary,
+    Octal,
+    Ascii,
+    /// Seconds from an amount of `1 / scale` seconds.
+    Seconds(u128),
+    /// `hh:mm:ss` from an amount of `1 / scale` seconds.
+    Time(u128),
+}
+
+/// Display hint of a parameter, like `#04x`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Hint {
+    alternate: bool,
+    width: usize,
+    kind: HintKind,
+}
+
+impl Hint {
+    fn new(hint: &str) -> Self {
+        let (alternate, hint) = match hint.strip_prefix('#') {
+            Some(hint) => (true, hint),
+            None => (false, hint),
+        };
+        let digits = hint.find(|c: char| !c.is_ascii_digit()).unwrap_or(0);
+        let (width, hint) = hint.split_at(digits);
+        let kind = match hint {
+            "?" => HintKind::Debug,
+            "x" => HintKind::LowerHex,
+            "X" => HintKind::UpperHex,
+            "b" => HintKind::Binary,
+            "o" => HintKind::Octal,
+            "a" => HintKind::Ascii,
+            "us" => HintKind::Seconds(1_000_000),
+            "ms" => HintKind::Seconds(1_000),
+            "tus" => HintKind::Time(1_000_000),
+            "tms" => HintKind::Time(1_000),
+            "ts" => HintKind::Time(1),
+            _ => HintKind::Display,
+        };
+        Self {
+            alternate,
+            width: width.parse().unwrap_or(0),
+            kind,
+        }
+    }
+}
+
+fn render(value: &Value, hint: &Hint, text: &mut String) {
+    match value {
+        Value::Uint(value) => integer(*value, None, hint, text),
+        Value::Int(value, bits) => integer(
+            *value as u128 & (u128::MAX >> (128 - bits)),
+            Some(*value),
+            hint,
+            text,
+        ),
+        Value::F32(value) if hint.kind == HintKind::Debug => {
+            let _ = write!(text, "{:?}", value);
+        },
+        Value::F32(value) => {
+            let _ = write!(text, "{}", value);
+        },
+        Value::F64(value) if hint.kind == HintKind::Debug => {
+            let _ = write!(text, "{:?}", value);
+        },
+        Value::F64(value) => {
+            let _ = write!(text, "{}", value);
+        },
+        Value::Bool(value) => {
+            let _ = write!(text, "{}", value);
+        },
+        Value::Char(value) if hint.kind == HintKind::Debug => {
+            let _ = write!(text, "{:?}", value);
+        },
+        Value::Char(value) => text.push(*value),
+        Value::Str(value) if hint.kind == HintKind::Debug => {
+            let _ = write!(text, "{:?}", value);
+        },
+        Value::Str(value) | Value::Formatted(value) => text.push_str(value),
+        Value::Bytes(bytes) if hint.kind == HintKind::Ascii => {
+            let _ = write!(text, "b\"{}\"", bytes.escape_ascii());
+        },
+        Value::Bytes(bytes) => {
+            text.push('[');
+            for (position, &byte) in bytes.iter().enumerate() {
+                if position > 0 {
+                    text.push_str(", ");
+                }
+                integer(byte as u128, None, hint, text);
+            }
+            text.push(']');
+        },
+    }
+}
+
+/// An integer, by its bits and its signed value for decimal.
+fn integer(bits: u128, signed: Option<i128>, hint: &Hint, text: &mut String) {
+    let width = hint.width;
+    let _ = match (hint.kind, hint.alternate) {
+        (HintKind::LowerHex, false) => write!(text, "{:0width$x}", bits),
+        (HintKind::LowerHex, true) => write!(text, "{:#0width$x}", bits),
+        (HintKind::UpperHex, false) => write!(text, "{:0width$X}", bits),
+        (HintKind::UpperHex, true) => write!(text, "{:#0width$X}", bits),
+        (HintKind::Binary, false) => write!(text, "{:0width$b}", bits),
+        (HintKind::Binary, true) => write!(text, "{:#0width$b}", bits),
+        (HintKind::Octal, false) => write!(text, "{:0width$o}", bits),
+        (HintKind::Octal, true) => write!(text, "{:#0width$o}", bits),
+        (HintKind::Ascii, _) if bits < 0x80 => {
+            write!(text, "{}", (bits as u8).escape_ascii())
+        },
+        (HintKind::Seconds(scale), _) => {
+            let digits = scale.ilog10() as usize;
+            write!(text, "{}.{:0digits$}", bits / scale, bits % scale)
+        },
+        (HintKind::Time(scale), _) => {
+            let seconds = bits / scale;
+            let _ = write!(
+                text,
+                "{:02}:{:02}:{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            );
+            let digits = scale.ilog10() as usize;
+            match digits {
+                0 => Ok(()),
+                _ => write!(text, ".{:0digits$}", bits % scale),
+            }
+        },
+        _ => match signed {
+            Some(value) => write!(text, "{:0width$}", value),
+            None => write!(text, "{:0width$}", bits),
+        },
+    };
+}
+
+/// A flat JSON object of strings, like the names of the `.defmt` symbols.
+fn json_object(text: &str) -> Option<Vec<(String, String)>> {
+    let mut chars = text.trim().chars().peekable();
+    let mut fields = vec![];
+    let skip_whitespace = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    };
+    (chars.next()? == '{').then_some(())?;
+    skip_whitespace(&mut chars);
+    if chars.next_if_eq(&'}').is_some() {
+        return Some(fields);
+    }
+    loop {
+        skip_whitespace(&mut chars);
+        let key = json_string(&mut chars)?;
+        skip_whitespace(&mut chars);
+        (chars.next()? == ':').then_some(())?;
+        skip_whitespace(&mut chars);
+        let value = json_string(&mut chars)?;
+        fields.push((key, value));
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => {},
+            '}' => return Some(fields),
+            _ => return None,
+        }
+    }
+}
+
+fn json_string(chars: &mut impl Iterator<Item = char>) -> Option<String> {
+    (chars.next()? == '"').then_some(())?;
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => string.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'u' => {
+                    let code: String = chars.take(4).collect();
+                    let code = u32::from_str_radix(&code, 16).ok()?;
+                    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                },
+                c => c,
+            }),
+            c => string.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::serial_tty::defmt::elf::tests::elf32;
+
+    /// Symbol name of a `.defmt` entry.
+    pub(crate) fn symbol(tag: &str, format: &str) -> String {
+        format!(
+            r#"{{"package":"app","tag":"defmt_{}","data":"{}","disambiguator":"1","crate_name":"app"}}"#,
+            tag,
+            format.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    }
+
+    /// Table of `(index, tag, format)` entries.
+    pub(crate) fn table(entries: &[(u16, &str, &str)]) -> DefmtTable {
+        let names: Vec<String> = entries
+            .iter()
+            .map(|(_, tag, format)| symbol(tag, format))
+            .collect();
+        let symbols: Vec<(&str, u32, u16)> = names
+            .iter()
+            .zip(entries)
+            .map(|(name, (index, ..))| (name.as_str(), *index as u32, 2))
+            .collect();
+        DefmtTable::from_elf(&elf32(&[".text", ".defmt"], &symbols)).unwrap()
+    }
+
+    fn decode(table: &DefmtTable, bytes: &[u8]) -> Decoded<Message> {
+        let (message, length) = table.decode(bytes)?;
+        assert_eq!(length, bytes.len());
+        Ok(message)
+    }
+
+    #[test]
+    fn arguments_and_hints() {
+        let table = table(&[
+            (1, "info", "x={=u8:#04x} y={=i16} {=str:?} {=bool}"),
+            (2, "warn", "{1=u8} {0=u8} {0=u8:b} {{}} {2=[u8]:a}"),
+            (3, "error", "{} and {=[u8; 2]:x} at {=u32:us}s"),
+            (4, "derived", "None|Some({=u8})"),
+            (5, "debug", "flags {0=0..4:b} {0=4..8}"),
+            (6, "timestamp", "{=u32:ms}"),
+        ]);
+
+        let mut bytes = vec![1, 0, 0xe8, 0x03, 0, 0, 0x0a, 0xfe, 0xff];
+        bytes.extend([3, 0, 0, 0, b'a', b'"', b'b', 1]);
+        let message = decode(&table, &bytes).unwrap();
+        assert_eq!(message.timestamp.as_deref(), Some("1.000"));
+        assert_eq!(message.level, Some(DefmtLevel::Info));
+        assert_eq!(message.text, r#"x=0x0a y=-2 "a\"b" true"#);
+
+        let bytes = [2, 0, 0, 0, 0, 0, 5, 7, 2, 0, 0, 0, b'h', 0];
+        assert_eq!(
+            decode(&table, &bytes).unwrap().text,
+            r#"7 5 101 {} b"h\x00""#
+        );
+
+        let bytes = [
+            3, 0, 0, 0, 0, 0, 4, 0, 1, 42, 0x0f, 0xab, 0x40, 0x42, 0x0f, 0,
+        ];
+        assert_eq!(
+            decode(&table, &bytes).unwrap().text,
+            "Some(42) and [f, ab] at 1.000000s"
+        );
+
+        let bytes = [5, 0, 0, 0, 0, 0, 0x3a];
+        assert_eq!(decode(&table, &bytes).unwrap().text, "flags 1010 3");
+
+        assert_eq!(decode(&table, &bytes[..4]), Err(DecodeError::Incomplete));
+        assert_eq!(decode(&table, &[9, 0]), Err(DecodeError::Malformed));
+        // Only log messages start a frame.
+        assert_eq!(
+            decode(&table, &[4, 0, 0, 0, 0, 0]),
+            Err(DecodeError::Malformed)
+        );
+    }
+
+    #[test]
+    fn table_from_elf() {
+        let table = table(&[(1, "println", "Hello")]);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.encoding(), DefmtEncoding::Rzcobs);
+        assert_eq!(
+            decode(&table, &[1, 0]).unwrap(),
+            Message {
+                timestamp: None,
+                level: None,
+                text: "Hello".into(),
+            }
+        );
+
+        let elf = elf32(
+            &[".defmt"],
+            &[
+                (&symbol("info", "a"), 1, 1),
+                ("_defmt_encoding_ = raw", 0, 0),
+            ],
+        );
+        assert_eq!(
+            DefmtTable::from_elf(&elf).unwrap().encoding(),
+            DefmtEncoding::Raw
+        );
+
+        // Symbols of other sections are not format strings.
+        let elf = elf32(&[".defmt", ".text"], &[(&symbol("info", "a"), 1, 2)]);
+        assert_eq!(
+            DefmtTable::from_elf(&elf).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Transcoding between the wire encoding and the UTF-8 terminal parser.
+
+use std::fmt::{self, Display, Formatter};
+
+/// Upper half of code page 437, the lower half is ASCII.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä',
+    'Å', 'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥',
+    '₧', 'ƒ', 'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼',
+    '¡', '«', '»', '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗',
+    '╝', '╜', '╛', '┐', '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩',
+    '╦', '╠', '═', '╬', '╧', '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘',
+    '┌', '█', '▄', '▌', '▐', '▀', 'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ',
+    'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈',
+    '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// ISO 8859-1
+    Latin1,
+    /// IBM PC code page 437, with box drawing characters.
+    Cp437,
+    ShiftJis,
+    EucKr,
+    Gb18030,
+}
+
+impl Encoding {
+    fn encoding_rs(self) -> Option<&'static encoding_rs::Encoding> {
+        match self {
+            Encoding::ShiftJis => Some(encoding_rs::SHIFT_JIS),
+            Encoding::EucKr => Some(encoding_rs::EUC_KR),
+            Encoding::Gb18030 => Some(encoding_rs::GB18030),
+            Encoding::Utf8 | Encoding::Latin1 | Encoding::Cp437 => None,
+        }
+    }
+
+    /// Encode UTF-8 bytes going to the device.
+    ///
+    /// Input which isn't valid UTF-8 is sent as it is, same for characters
+    /// missing in the target encoding which are replaced by `?`.
+    pub fn encode(self, input: &[u8]) -> Vec<u8> {
+        let Ok(text) = std::str::from_utf8(input) else {
+            return input.to_vec();
+        };
+
+        match self {
+            Encoding::Utf8 => input.to_vec(),
+            Encoding::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c as u32).unwrap_or(b'?'))
+                .collect(),
+            Encoding::Cp437 => text
+                .chars()
+                .map(|c| match c {
+                    '\0'..='\x7f' => c as u8,
+                    _ => CP437_HIGH
+                        .iter()
+                        .position(|high| *high == c)
+                        .map_or(b'?', |index| 0x80 + index as u8),
+                })
+                .collect(),
+            _ => {
+                let encoding = self.encoding_rs().unwrap();
+                let mut encoder = encoding.new_encoder();
+                let mut output = Vec::with_capacity(
+                    encoder
+                        .max_buffer_length_from_utf8_if_no_unmappables(
+                            text.len(),
+                        )
+                        .unwrap_or(text.len() * 4),
+                );
+                let mut text = text;
+                loop {
+                    let (result, read) = encoder
+                        .encode_from_utf8_to_vec_without_replacement(
+                            text,
+                            &mut output,
+                            true,
+                        );
+                    text = &text[read..];
+                    match result {
+                        encoding_rs::EncoderResult::InputEmpty => break,
+                        encoding_rs::EncoderResult::OutputFull => {
+                            output.reserve(text.len() * 4 + 16)
+                        },
+                        encoding_rs::EncoderResult::Unmappable(_) => {
+                            output.push(b'?')
+                        },
+                    }
+                }
+                output
+            },
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Utf8 => write!(f, "UTF-8"),
+            Encoding::Latin1 => write!(f, "Latin-1"),
+            Encoding::Cp437 => write!(f, "CP437"),
+            Encoding::ShiftJis => write!(f, "Shift-JIS"),
+            Encoding::EucKr => write!(f, "EUC-KR"),
+            Encoding::Gb18030 => write!(f, "GB18030"),
+        }
+    }
+}
+
+/// Streaming decoder of the received bytes into UTF-8.
+pub enum RxDecoder {
+    Latin1,
+    Cp437,
+    EncodingRs(encoding_rs::Decoder),
+}
+
+impl RxDecoder {
+    /// `None` when the bytes can be passed to the parser as they are.
+    pub fn new(encoding: Encoding) -> Option<Self> {
+        match encoding {
+            Encoding::Utf8 => None,
+            Encoding::Latin1 => Some(RxDecoder::Latin1),
+            Encoding::Cp437 => Some(RxDecoder::Cp437),
+            _ => Some(RxDecoder::EncodingRs(
+                encoding
+                    .encoding_rs()
+                    .unwrap()
+                    .new_decoder_without_bom_handling(),
+            )),
+        }
+    }
+
+    pub fn decode(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        let mut buf = [0; 4];
+        match self {
+            RxDecoder::Latin1 => {
+                for &byte in input {
+                    let c = byte as char;
+                    output
+                        .extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            },
+            RxDecoder::Cp437 => {
+                for &byte in input {
+                    let c = match byte {
+                        0x00..=0x7f => byte as char,
+                        _ => CP437_HIGH[byte as usize - 0x80],
+                    };
+                    output
+                        .extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            },
+            RxDecoder::EncodingRs(decoder) => {
+                let start = output.len();
+                let len = decoder
+                    .max_utf8_buffer_length(input.len())
+                    .unwrap_or(input.len() * 4);
+                output.resize(start + len, 0);
+                let (_, _, written, _) =
+                    decoder.decode_to_utf8(input, &mut output[start..], false);
+                output.truncate(start + written);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(encoding: Encoding, chunks: &[&[u8]]) -> String {
+        let mut decoder = RxDecoder::new(encoding).unwrap();
+        let mut output = vec![];
+        for chunk in chunks {
+            decoder.decode(chunk, &mut output);
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn single_byte_round_trip() {
+        assert_eq!(decode(Encoding::Cp437, &[b"\xc9\xcd\xbb\r\n"]), "╔═╗\r\n");
+        assert_eq!(Encoding::Cp437.encode("╔═╗".as_bytes()), b"\xc9\xcd\xbb");
+        assert_eq!(decode(Encoding::Latin1, &[b"caf\xe9"]), "café");
+        assert_eq!(Encoding::Latin1.encode("café€".as_bytes()), b"caf\xe9?");
+    }
+
+    #[test]
+    fn multi_byte_split_between_reads() {
+        let encoded = Encoding::ShiftJis.encode("\x1b[1mテスト".as_bytes());
+        assert_eq!(&encoded[..4], b"\x1b[1m");
+        let (first, second) = encoded.split_at(5);
+        assert_eq!(
+            decode(Encoding::ShiftJis, &[first, second]),
+            "\x1b[1mテスト"
+        );
+    }
+
+    #[test]
+    fn unmappable_and_invalid_input() {
+        assert_eq!(Encoding::EucKr.encode("a\u{1F600}b".as_bytes()), b"a?b");
+        assert_eq!(Encoding::Gb18030.encode(b"\xff\x03"), b"\xff\x03");
+    }
+}
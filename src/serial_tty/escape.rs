@@ -0,0 +1,79 @@
+//! Tracking of the escape sequences in the received bytes.
+//!
+//! Shared by the consumers which only care about the printable text, and by
+//! the control token filter which must pass the sequences through untouched.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Escape {
+    #[default]
+    Ground,
+    Escape,
+    Csi,
+    /// OSC, DCS and the other strings terminated by ST.
+    String,
+    /// ESC inside of a string, possibly starting ST.
+    StringEscape,
+}
+
+/// Drops the escape sequences from the received bytes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TextFilter {
+    escape: Escape,
+}
+
+impl TextFilter {
+    #[inline]
+    pub(crate) fn escape(&self) -> Escape {
+        self.escape
+    }
+
+    /// Whether `byte` is text or a control, rather than a part of an escape
+    /// sequence.
+    pub(crate) fn is_text(&mut self, byte: u8) -> bool {
+        let (escape, is_text) = match (self.escape, byte) {
+            (_, 0x18 | 0x1a) => (Escape::Ground, false),
+            (Escape::String, 0x07) => (Escape::Ground, false),
+            (Escape::String | Escape::StringEscape, 0x1b) => {
+                (Escape::StringEscape, false)
+            },
+            (Escape::StringEscape, b'\\') => (Escape::Ground, false),
+            (Escape::String | Escape::StringEscape, _) => {
+                (Escape::String, false)
+            },
+            (_, 0x1b) => (Escape::Escape, false),
+            (Escape::Escape, b'[') => (Escape::Csi, false),
+            (Escape::Escape, b']' | b'P' | b'X' | b'^' | b'_') => {
+                (Escape::String, false)
+            },
+            (Escape::Escape, 0x20..=0x2f) => (Escape::Escape, false),
+            (Escape::Escape, _) => (Escape::Ground, false),
+            (Escape::Csi, 0x40..=0x7e) => (Escape::Ground, false),
+            (Escape::Csi, _) => (Escape::Csi, false),
+            (Escape::Ground, _) => (Escape::Ground, true),
+        };
+
+        self.escape = escape;
+        is_text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(input: &[u8]) -> Vec<u8> {
+        let mut filter = TextFilter::default();
+        input
+            .iter()
+            .copied()
+            .filter(|&byte| filter.is_text(byte))
+            .collect()
+    }
+
+    #[test]
+    fn escape_sequences_are_dropped() {
+        assert_eq!(text(b"a\x1b[1;31mb\x1b(Bc\r\n"), b"abc\r\n");
+        assert_eq!(text(b"a\x1b]0;title\x07b\x1bPq\x1b\x1b\\c"), b"abc");
+        assert_eq!(text(b"a\x1b[12\x18b"), b"ab");
+    }
+}
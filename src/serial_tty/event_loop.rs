@@ -9,19 +9,36 @@ use std::io::{self, ErrorKind, Read, Write};
 use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 use std::sync::Arc;
 use std::thread::JoinHandle;
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 
 use log::error;
 use mio::{Registry, Waker};
+use mio_serial::SerialPort;
 use polling::Event as PollingEvent;
 
 use alacritty_terminal::event::{self, Event, OnResize, WindowSize};
 use alacritty_terminal::sync::FairMutex;
 use alacritty_terminal::term::Term;
 use alacritty_terminal::thread;
-use alacritty_terminal::vte::ansi;
-
+use alacritty_terminal::vte::ansi::{self, Color};
+
+use crate::serial_tty::control_tokens::{
+    ControlTokenFilter, ControlTokenStyle,
+};
+use crate::serial_tty::encoding::{Encoding, RxDecoder};
+use crate::serial_tty::escape::TextFilter;
+use crate::serial_tty::line_stamps::LineStamps;
+use crate::serial_tty::newline::{RxNewline, RxNewlineFilter, TxNewline};
+use crate::serial_tty::subscription::SubscriptionSender;
+use crate::serial_tty::tap::{SerialTap, TrafficDirection};
+use crate::serial_tty::triggers::{
+    FiredTrigger, TriggerAction, TriggerEngine, TriggerEvent, TriggerSet,
+};
 use crate::serial_tty::SerialTty;
+use crate::transfer::zmodem::{ZmodemDetector, ZmodemOffer};
+use crate::transfer::{
+    Transfer, TransferEvent, TransferIo, TransferJob, ZmodemRequest,
+};
 
 /// Max bytes to read from the TTY before forced terminal synchronization.
 pub(crate) const READ_BUFFER_SIZE: usize = 0x10_0000;
@@ -45,6 +62,45 @@ pub enum SerialMsg {
 
     /// Instruction to resize the TTY.
     Resize(WindowSize),
+
+    /// Render dropped control bytes and invalid UTF-8 as inline tokens.
+    ControlTokens(Option<ControlTokenStyle>),
+
+    /// Newline translation of the received bytes.
+    RxNewline(RxNewline),
+
+    /// Encoding of the received bytes.
+    Encoding(Encoding),
+
+    /// Local echo of written bytes, optionally in another color.
+    Echo(Vec<u8>, Option<Color>),
+
+    /// Triggers matched against the received lines.
+    Triggers(Option<TriggerSet>),
+
+    /// Level of the DTR modem line.
+    Dtr(bool),
+
+    /// Level of the RTS modem line.
+    Rts(bool),
+
+    /// Send the received text, without escape sequences and carriage
+    /// returns, to a subscriber until it hangs up.
+    Subscribe(SubscriptionSender),
+
+    /// Hand the stream to a file transfer until it is done, cancelling the
+    /// running one.
+    Transfer(TransferJob),
+
+    /// Cancel the running file transfer.
+    CancelTransfer,
+
+    /// Watch the received bytes for a remote `sz` or `rz`, reporting it to
+    /// the sender and holding the stream until it is answered.
+    ZmodemDetection(Option<Sender<TransferEvent>>),
+
+    /// Answer once the bytes queued before were handed to the TTY.
+    Flush(Sender<()>),
 }
 
 /// The main event loop.
@@ -60,6 +116,8 @@ pub struct SerialEventLoop<U: alacritty_terminal::event::EventListener> {
     tx: Sender<SerialMsg>,
     terminal: Arc<FairMutex<Term<U>>>,
     event_proxy: U,
+    tap: SerialTap,
+    line_stamps: Arc<FairMutex<LineStamps>>,
     _drain_on_exit: bool,
     ref_test: bool,
 }
@@ -72,6 +130,8 @@ where
         terminal: Arc<FairMutex<Term<U>>>,
         event_proxy: U,
         tty: SerialTty,
+        tap: SerialTap,
+        line_stamps: Arc<FairMutex<LineStamps>>,
         _drain_on_exit: bool,
         ref_test: bool,
     ) -> std::io::Result<SerialEventLoop<U>> {
@@ -91,6 +151,8 @@ where
             tx,
             terminal,
             event_proxy,
+            tap,
+            line_stamps,
             _drain_on_exit,
             ref_test,
         })
@@ -110,10 +172,70 @@ where
     fn drain_recv_channel(&mut self, state: &mut State) -> bool {
         while let Some(msg) = self.rx.recv() {
             match msg {
-                SerialMsg::Input(input) => state.write_list.push_back(input),
+                // Typed bytes would corrupt a running file transfer.
+                SerialMsg::Input(input) => {
+                    if state.transfer.is_none() {
+                        state.write_list.push_back(input)
+                    }
+                },
+                SerialMsg::Echo(input, color) => {
+                    self.echo(state, &input, color)
+                },
                 SerialMsg::Resize(window_size) => {
                     self.tty.on_resize(window_size)
                 },
+                SerialMsg::ControlTokens(style) => {
+                    state.control_tokens = style.map(ControlTokenFilter::new)
+                },
+                SerialMsg::Encoding(encoding) => {
+                    state.rx_decoder = RxDecoder::new(encoding)
+                },
+                SerialMsg::RxNewline(policy) => {
+                    state.rx_newline = match policy {
+                        RxNewline::PassThrough => None,
+                        policy => Some(RxNewlineFilter::new(policy)),
+                    }
+                },
+                SerialMsg::Triggers(triggers) => {
+                    state.triggers = triggers.map(TriggerEngine::new)
+                },
+                SerialMsg::Dtr(level) => {
+                    if let Err(err) = self.tty.write_data_terminal_ready(level)
+                    {
+                        error!("Error setting DTR: {}", err);
+                    }
+                },
+                SerialMsg::Rts(level) => {
+                    if let Err(err) = self.tty.write_request_to_send(level) {
+                        error!("Error setting RTS: {}", err);
+                    }
+                },
+                SerialMsg::Subscribe(subscriber) => {
+                    state.subscribers.push(subscriber)
+                },
+                SerialMsg::Transfer(job) => {
+                    if !state
+                        .transfer
+                        .as_ref()
+                        .is_some_and(|running| running.transfer.is_offer())
+                    {
+                        self.transfer_step(state, |transfer, io| {
+                            transfer.cancel(io)
+                        });
+                    }
+                    state.transfer = Some(job);
+                    let now = Instant::now();
+                    self.transfer_step(state, |transfer, io| {
+                        transfer.start(now, io)
+                    });
+                },
+                SerialMsg::CancelTransfer => self
+                    .transfer_step(state, |transfer, io| transfer.cancel(io)),
+                SerialMsg::ZmodemDetection(events) => {
+                    state.zmodem =
+                        events.map(|events| (ZmodemDetector::default(), events))
+                },
+                SerialMsg::Flush(flush) => state.flushes.push(flush),
                 SerialMsg::Shutdown => return false,
             }
         }
@@ -121,6 +243,28 @@ where
         true
     }
 
+    /// Show written bytes as if they were received from the device.
+    fn echo(&mut self, state: &mut State, input: &[u8], color: Option<Color>) {
+        // Every line ending starts a new line, whatever the device expects.
+        let bytes = TxNewline::CrLf.translate(input);
+
+        let mut terminal = self.terminal.lock();
+        let mut line_stamps = self.line_stamps.lock();
+        let fg = terminal.grid().cursor.template.fg;
+        if let Some(color) = color {
+            terminal.grid_mut().cursor.template.fg = color;
+        }
+
+        for byte in bytes {
+            let before = line_stamps.snapshot(&terminal, byte);
+            state.echo_parser.advance(&mut *terminal, byte);
+            line_stamps.track(&terminal, before, byte, None);
+        }
+
+        terminal.grid_mut().cursor.template.fg = fg;
+        self.event_proxy.send_event(Event::Wakeup);
+    }
+
     #[inline]
     fn tty_read<X>(
         &mut self,
@@ -131,11 +275,21 @@ where
     where
         X: Write,
     {
+        if state.transfer.is_some() {
+            self.transfer_read(state, buf)?;
+            if state.transfer.is_some() {
+                return Ok(());
+            }
+        }
+
         let mut unprocessed = 0;
         let mut processed = 0;
+        let mut fired = vec![];
+
+        let mut zmodem_request = None;
 
         // Reserve the next terminal lock for TTY reading.
-        let _terminal_lease = Some(self.terminal.lease());
+        let terminal_lease = Some(self.terminal.lease());
         let mut terminal = None;
 
         loop {
@@ -143,7 +297,13 @@ where
             match self.tty.read(&mut buf[unprocessed..]) {
                 // This is received on Windows/macOS when no more data is readable from the TTY.
                 Ok(0) if unprocessed == 0 => break,
-                Ok(got) => unprocessed += got,
+                Ok(got) => {
+                    self.tap.record(
+                        TrafficDirection::Rx,
+                        &buf[unprocessed..unprocessed + got],
+                    );
+                    unprocessed += got;
+                },
                 Err(err) => match err.kind() {
                     ErrorKind::Interrupted | ErrorKind::WouldBlock => {
                         // Go back to mio if we're caught up on parsing and the TTY would block.
@@ -175,17 +335,33 @@ where
                 writer.write_all(&buf[..unprocessed]).unwrap();
             }
 
-            // Parse the incoming bytes.
-            for byte in &buf[..unprocessed] {
-                state.parser.advance(&mut **terminal, *byte);
+            // Bytes from the ZMODEM header on belong to the transfer.
+            let mut parsed = unprocessed;
+            if let Some((detector, _)) = &mut state.zmodem {
+                if let Some((end, request)) = detector.find(&buf[..unprocessed])
+                {
+                    parsed = end.saturating_sub(ZmodemDetector::HEADER_LEN);
+                    zmodem_request = Some((request, parsed..unprocessed));
+                }
             }
-            // state.parser.advance(&mut **terminal, &buf[..unprocessed]); // alacritty-terminal 0.25.0
+
+            // Parse the incoming bytes, or the log lines decoded from them.
+            let defmt = self.tap.defmt().lock().take_rendered();
+            let line_stamps = &mut self.line_stamps.lock();
+            fired.extend(match defmt {
+                Some(lines) => {
+                    state.parse(&mut **terminal, line_stamps, &lines)
+                },
+                None => {
+                    state.advance(&mut **terminal, line_stamps, &buf[..parsed])
+                },
+            });
 
             processed += unprocessed;
             unprocessed = 0;
 
             // Assure we're not blocking the terminal too long unnecessarily.
-            if processed >= MAX_LOCKED_READ {
+            if processed >= MAX_LOCKED_READ || zmodem_request.is_some() {
                 break;
             }
         }
@@ -195,9 +371,113 @@ where
             self.event_proxy.send_event(Event::Wakeup);
         }
 
+        drop(terminal);
+        self.run_triggers(state, fired);
+        drop(terminal_lease);
+
+        if let Some((request, rest)) = zmodem_request {
+            self.offer_zmodem(state, request);
+            // The header and what followed it in the same read.
+            let now = Instant::now();
+            self.transfer_step(state, |transfer, io| {
+                transfer.receive(&buf[rest], now, io)
+            });
+            self.transfer_read(state, buf)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read while a file transfer owns the stream, the terminal doesn't see
+    /// these bytes.
+    fn transfer_read(
+        &mut self,
+        state: &mut State,
+        buf: &mut [u8],
+    ) -> io::Result<()> {
+        while state.transfer.is_some() {
+            match self.tty.read(buf) {
+                Ok(0) => break,
+                Ok(got) => {
+                    self.tap.record_transfer(&buf[..got]);
+                    let now = Instant::now();
+                    self.transfer_step(state, |transfer, io| {
+                        transfer.receive(&buf[..got], now, io)
+                    });
+                },
+                Err(err) => match err.kind() {
+                    ErrorKind::Interrupted => {},
+                    ErrorKind::WouldBlock => break,
+                    _ => return Err(err),
+                },
+            }
+        }
+
         Ok(())
     }
 
+    /// Hold the stream for the remote `sz` or `rz` until the user answers.
+    fn offer_zmodem(&mut self, state: &mut State, request: ZmodemRequest) {
+        let Some((_, events)) = &state.zmodem else {
+            return;
+        };
+
+        let offer = ZmodemOffer::new(request);
+        state.transfer =
+            Some(TransferJob::new(Box::new(offer), events.clone()));
+        let now = Instant::now();
+        self.transfer_step(state, |transfer, io| transfer.start(now, io));
+    }
+
+    /// Run one step of the file transfer and write its answer right away.
+    fn transfer_step(
+        &mut self,
+        state: &mut State,
+        step: impl FnOnce(&mut dyn Transfer, &mut TransferIo),
+    ) {
+        let Some(job) = &mut state.transfer else {
+            return;
+        };
+
+        let output = job.step(step);
+        if job.transfer.is_done() {
+            state.transfer = None;
+        }
+        if !output.is_empty() {
+            state.write_list.push_back(output.into());
+            if let Err(err) = self.tty_write(state) {
+                error!("Error writing to TTY in file transfer: {}", err);
+            }
+        }
+        self.event_proxy.send_event(Event::Wakeup);
+    }
+
+    /// Run the actions of matched triggers, bookmarks are already set by
+    /// the parser.
+    fn run_triggers(&self, state: &mut State, fired: Vec<FiredTrigger>) {
+        for trigger in fired {
+            match trigger.action {
+                TriggerAction::Send(bytes) => {
+                    state.write_list.push_back(bytes.into())
+                },
+                TriggerAction::Event => {
+                    if let Some(engine) = &state.triggers {
+                        let _ = engine.events().send(TriggerEvent {
+                            trigger: trigger.name,
+                            line: trigger.line,
+                        });
+                    }
+                    self.event_proxy.send_event(Event::Wakeup);
+                },
+                TriggerAction::Bell => self.event_proxy.send_event(Event::Bell),
+                TriggerAction::PauseLogging => {
+                    self.tap.log().lock().set_paused(true)
+                },
+                TriggerAction::Bookmark => {},
+            }
+        }
+    }
+
     #[inline]
     fn tty_write(&mut self, state: &mut State) -> io::Result<()> {
         state.ensure_next();
@@ -211,6 +491,10 @@ where
                         break 'write_many;
                     },
                     Ok(n) => {
+                        self.tap.record(
+                            TrafficDirection::Tx,
+                            &current.remaining_bytes()[..n],
+                        );
                         current.advance(n);
                         if current.finished() {
                             state.goto_next();
@@ -267,9 +551,12 @@ where
             'event_loop: loop {
                 // Wakeup the event loop when a synchronized update timeout was reached.
                 let handler = state.parser.sync_timeout();
-                let timeout = handler
-                    .sync_timeout()
-                    .map(|st| st.saturating_duration_since(Instant::now()));
+                let timeout =
+                    [handler.sync_timeout(), state.transfer_deadline()]
+                        .into_iter()
+                        .flatten()
+                        .min()
+                        .map(|st| st.saturating_duration_since(Instant::now()));
 
                 events.clear();
 
@@ -283,10 +570,20 @@ where
                     }
                 }
 
+                // Handle file transfer timeout.
+                let now = Instant::now();
+                if state.transfer_deadline().is_some_and(|d| d <= now) {
+                    self.transfer_step(&mut state, |transfer, io| {
+                        transfer.timeout(now, io)
+                    });
+                }
+
                 // Handle synchronized update timeout.
                 if events.is_empty() && self.rx.peek().is_none() {
-                    state.parser.stop_sync(&mut *self.terminal.lock());
-                    self.event_proxy.send_event(Event::Wakeup);
+                    if state.parser.sync_timeout().sync_timeout().is_some() {
+                        state.parser.stop_sync(&mut *self.terminal.lock());
+                        self.event_proxy.send_event(Event::Wakeup);
+                    }
                     continue;
                 }
 
@@ -344,6 +641,11 @@ where
 
                 // Register write interest if necessary.
                 let needs_write = state.needs_write();
+                if !needs_write {
+                    for flush in state.flushes.drain(..) {
+                        let _ = flush.send(());
+                    }
+                }
                 if needs_write != interest.writable {
                     interest.writable = needs_write;
 
@@ -465,9 +767,99 @@ pub struct State {
     pub(crate) write_list: VecDeque<Cow<'static, [u8]>>,
     writing: Option<Writing>,
     pub(crate) parser: ansi::Processor,
+    rx_decoder: Option<RxDecoder>,
+    rx_newline: Option<RxNewlineFilter>,
+    control_tokens: Option<ControlTokenFilter>,
+    triggers: Option<TriggerEngine>,
+    subscribers: Vec<SubscriptionSender>,
+    subscriber_text: TextFilter,
+    transfer: Option<TransferJob>,
+    zmodem: Option<(ZmodemDetector, Sender<TransferEvent>)>,
+    flushes: Vec<Sender<()>>,
+    echo_parser: ansi::Processor,
 }
 
 impl State {
+    /// Run received bytes through the RX filters and into the parser.
+    ///
+    /// Returns the triggers which matched the received lines.
+    #[inline]
+    pub(crate) fn advance<U: event::EventListener>(
+        &mut self,
+        terminal: &mut Term<U>,
+        line_stamps: &mut LineStamps,
+        bytes: &[u8],
+    ) -> Vec<FiredTrigger> {
+        let mut bytes = Cow::Borrowed(bytes);
+        if let Some(decoder) = &mut self.rx_decoder {
+            let mut decoded = Vec::with_capacity(bytes.len());
+            decoder.decode(&bytes, &mut decoded);
+            bytes = Cow::Owned(decoded);
+        }
+        if let Some(filter) = &mut self.rx_newline {
+            let mut filtered = Vec::with_capacity(bytes.len());
+            filter.filter(&bytes, &mut filtered);
+            bytes = Cow::Owned(filtered);
+        }
+        if let Some(filter) = &mut self.control_tokens {
+            let mut filtered = Vec::with_capacity(bytes.len());
+            filter.filter(&bytes, &mut filtered);
+            bytes = Cow::Owned(filtered);
+        }
+
+        self.parse(terminal, line_stamps, &bytes)
+    }
+
+    /// Hand bytes which need no RX filters, like the decoded `defmt` lines,
+    /// to the subscribers, the triggers and the parser.
+    pub(crate) fn parse<U: event::EventListener>(
+        &mut self,
+        terminal: &mut Term<U>,
+        line_stamps: &mut LineStamps,
+        bytes: &[u8],
+    ) -> Vec<FiredTrigger> {
+        if !self.subscribers.is_empty() {
+            let text: Vec<u8> = bytes
+                .iter()
+                .copied()
+                .filter(|&byte| {
+                    self.subscriber_text.is_text(byte) && byte != b'\r'
+                })
+                .collect();
+            if !text.is_empty() {
+                self.subscribers.retain(|subscriber| subscriber.send(&text));
+            }
+        }
+
+        let time = SystemTime::now();
+        let mut fired = vec![];
+        for byte in bytes.iter() {
+            if let Some(triggers) = &mut self.triggers {
+                let count = fired.len();
+                triggers.push(*byte, &mut fired);
+                bookmark_fired(terminal, line_stamps, &fired[count..]);
+            }
+
+            let before = line_stamps.snapshot(terminal, *byte);
+            self.parser.advance(terminal, *byte);
+            line_stamps.track(terminal, before, *byte, Some(time));
+        }
+
+        // Prompts are usually not terminated by a newline.
+        if let Some(triggers) = &mut self.triggers {
+            let count = fired.len();
+            triggers.match_prompt(&mut fired);
+            bookmark_fired(terminal, line_stamps, &fired[count..]);
+        }
+
+        fired
+    }
+
+    #[inline]
+    fn transfer_deadline(&self) -> Option<Instant> {
+        self.transfer.as_ref()?.transfer.deadline()
+    }
+
     #[inline]
     pub(crate) fn ensure_next(&mut self) {
         if self.writing.is_none() {
@@ -496,6 +888,20 @@ impl State {
     }
 }
 
+/// Bookmark the cursor line for the fired bookmark triggers.
+fn bookmark_fired<U: event::EventListener>(
+    terminal: &Term<U>,
+    line_stamps: &mut LineStamps,
+    fired: &[FiredTrigger],
+) {
+    if fired
+        .iter()
+        .any(|trigger| trigger.action == TriggerAction::Bookmark)
+    {
+        line_stamps.bookmark(terminal.grid().cursor.point.line);
+    }
+}
+
 impl Writing {
     #[inline]
     fn new(c: Cow<'static, [u8]>) -> Writing {
@@ -552,3 +958,284 @@ impl<T> PeekableReceiver<T> {
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::test_utils::temp_path;
+    use crate::transfer::tests::test_data;
+    #[cfg(unix)]
+    use crate::transfer::xmodem::{XmodemReceiver, XmodemSender};
+    #[cfg(unix)]
+    use crate::transfer::zmodem::{ZmodemReceiver, ZmodemSender};
+    #[cfg(unix)]
+    use crate::transfer::{SendFile, TransferEvent, TransferProtocol};
+    #[cfg(unix)]
+    use alacritty_terminal::event::VoidListener;
+    #[cfg(unix)]
+    use alacritty_terminal::grid::Dimensions;
+    #[cfg(unix)]
+    use alacritty_terminal::index::{Column, Line};
+    #[cfg(unix)]
+    use alacritty_terminal::term::test::TermSize;
+    #[cfg(unix)]
+    use alacritty_terminal::term::Config;
+    #[cfg(unix)]
+    use mio_serial::SerialStream;
+    #[cfg(unix)]
+    use std::time::Duration;
+
+    /// Sender without event loop, its messages are queued on the receiver.
+    pub(crate) fn fake_sender() -> (SerialEventLoopSender, Receiver<SerialMsg>)
+    {
+        let poll = mio::Poll::new().unwrap();
+        let registry = Arc::new(poll.registry().try_clone().unwrap());
+        let waker = Arc::new(Waker::new(&registry, SERIAL_TOKEN).unwrap());
+        let (sender, receiver) = mpsc::channel();
+        (
+            SerialEventLoopSender::new(sender, registry, waker),
+            receiver,
+        )
+    }
+
+    /// Sender whose messages are handled by `handle` on another thread.
+    pub(crate) fn fake_event_loop(
+        mut handle: impl FnMut(SerialMsg) + Send + 'static,
+    ) -> SerialEventLoopSender {
+        let (sender, receiver) = fake_sender();
+        std::thread::spawn(move || {
+            for msg in receiver {
+                handle(msg);
+            }
+        });
+        sender
+    }
+
+    /// Both ends of a PTY pair, the first for the session and the second
+    /// playing the device.
+    #[cfg(unix)]
+    pub(crate) fn tty_pair() -> (SerialTty, SerialStream) {
+        let (master, remote) = SerialStream::pair().unwrap();
+        (SerialTty { stream: master }, remote)
+    }
+
+    #[cfg(unix)]
+    type Loopback = (
+        SerialEventLoopSender,
+        SerialStream,
+        Arc<FairMutex<Term<VoidListener>>>,
+        JoinHandle<(SerialEventLoop<VoidListener>, State)>,
+    );
+
+    /// Event loop on one end of a PTY pair, the other end plays the device.
+    #[cfg(unix)]
+    fn loopback() -> Loopback {
+        let (tty, remote) = tty_pair();
+        let terminal = Arc::new(FairMutex::new(Term::new(
+            Config::default(),
+            &TermSize::new(80, 24),
+            VoidListener,
+        )));
+        let event_loop = SerialEventLoop::new(
+            terminal.clone(),
+            VoidListener,
+            tty,
+            SerialTap::new(1024),
+            Arc::new(FairMutex::new(LineStamps::new(100))),
+            false,
+            false,
+        )
+        .unwrap();
+        let sender = event_loop.channel();
+        (sender, remote, terminal, event_loop.spawn())
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn write_remote(remote: &mut SerialStream, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            match remote.write(bytes) {
+                Ok(written) => bytes = &bytes[written..],
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(1))
+                },
+                Err(err) => panic!("{}", err),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    /// Run a started transfer on the device end until it is done.
+    fn run_remote(
+        transfer: &mut dyn Transfer,
+        remote: &mut SerialStream,
+        mut io: TransferIo,
+    ) -> Vec<TransferEvent> {
+        let mut buf = [0; 4096];
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !transfer.is_done() && Instant::now() < deadline {
+            write_remote(remote, &std::mem::take(&mut io.output));
+            match remote.read(&mut buf) {
+                Ok(got) => {
+                    transfer.receive(&buf[..got], Instant::now(), &mut io)
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(1));
+                    let now = Instant::now();
+                    if transfer.deadline().is_some_and(|d| d <= now) {
+                        transfer.timeout(now, &mut io);
+                    }
+                },
+                Err(err) => panic!("{}", err),
+            }
+        }
+        write_remote(remote, &io.output);
+        io.events
+    }
+
+    #[cfg(unix)]
+    /// Wait for the events of a transfer of the event loop up to the last.
+    fn wait_events(events: &Receiver<TransferEvent>) -> Vec<TransferEvent> {
+        let mut received = vec![];
+        while let Ok(event) = events.recv_timeout(Duration::from_secs(5)) {
+            let is_last = matches!(
+                event,
+                TransferEvent::Finished | TransferEvent::Failed(_)
+            );
+            received.push(event);
+            if is_last {
+                break;
+            }
+        }
+        received
+    }
+
+    #[cfg(unix)]
+    fn line_text(terminal: &Term<VoidListener>, line: i32) -> String {
+        let row = &terminal.grid()[Line(line)];
+        let text: String = (0..terminal.grid().columns())
+            .map(|column| row[Column(column)].c)
+            .collect();
+        text.trim_end().to_owned()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn transfer_takes_over_the_stream() {
+        let (sender, mut remote, _terminal, thread) = loopback();
+
+        let (events, received_events) = mpsc::channel();
+        let file = SendFile {
+            name: "image.bin".into(),
+            data: test_data(5000),
+            modified: None,
+        };
+        let transfer =
+            XmodemSender::new(TransferProtocol::Xmodem1k, vec![file]);
+        sender
+            .send(SerialMsg::Transfer(TransferJob::new(
+                Box::new(transfer),
+                events,
+            )))
+            .unwrap();
+        // Dropped while the transfer owns the stream.
+        sender
+            .send(SerialMsg::Input(Cow::Borrowed(b"typed")))
+            .unwrap();
+
+        let path = temp_path("loopback");
+        let mut receiver =
+            XmodemReceiver::new(TransferProtocol::Xmodem1k, path.clone());
+        let mut io = TransferIo::default();
+        receiver.start(Instant::now(), &mut io);
+        let remote_events = run_remote(&mut receiver, &mut remote, io);
+
+        assert_eq!(remote_events.last(), Some(&TransferEvent::Finished));
+        assert_eq!(std::fs::read(&path).unwrap(), test_data(5000));
+        assert_eq!(
+            wait_events(&received_events).last(),
+            Some(&TransferEvent::Finished)
+        );
+
+        sender.send(SerialMsg::Shutdown).unwrap();
+        thread.join().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn remote_sz_is_detected() {
+        let (sender, mut remote, terminal, thread) = loopback();
+        let (events, received_events) = mpsc::channel();
+        sender
+            .send(SerialMsg::ZmodemDetection(Some(events.clone())))
+            .unwrap();
+
+        let mut sz = ZmodemSender::new(vec![SendFile {
+            name: "log.txt".into(),
+            data: test_data(50_000),
+            modified: None,
+        }]);
+        let mut io = TransferIo::default();
+        sz.start(Instant::now(), &mut io);
+        let mut output = b"$ sz log.txt\r\n".to_vec();
+        output.append(&mut io.output);
+        write_remote(&mut remote, &output);
+        let remote =
+            std::thread::spawn(move || run_remote(&mut sz, &mut remote, io));
+
+        let request = received_events.recv_timeout(Duration::from_secs(5));
+        assert_eq!(
+            request,
+            Ok(TransferEvent::ZmodemRequest(ZmodemRequest::Receive))
+        );
+        let dir = temp_path("sz");
+        std::fs::create_dir(&dir).unwrap();
+        let receiver = ZmodemReceiver::new(dir.clone());
+        sender
+            .send(SerialMsg::Transfer(TransferJob::new(
+                Box::new(receiver),
+                events,
+            )))
+            .unwrap();
+
+        assert_eq!(
+            remote.join().unwrap().last(),
+            Some(&TransferEvent::Finished)
+        );
+        let received = wait_events(&received_events);
+        assert_eq!(received.last(), Some(&TransferEvent::Finished));
+        assert!(received
+            .contains(&TransferEvent::FileReceived(dir.join("log.txt"))));
+        assert_eq!(
+            std::fs::read(dir.join("log.txt")).unwrap(),
+            test_data(50_000)
+        );
+        // The header doesn't reach the terminal.
+        let terminal = terminal.lock();
+        assert_eq!(line_text(&terminal, 0), "$ sz log.txt");
+        assert_eq!(line_text(&terminal, 1), "rz");
+        drop(terminal);
+
+        sender.send(SerialMsg::Shutdown).unwrap();
+        thread.join().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn echoed_lines_do_not_overwrite_each_other() {
+        let (sender, _remote, terminal, thread) = loopback();
+        sender
+            .send(SerialMsg::Echo(b"one\rtwo\nthree\r\n".to_vec(), None))
+            .unwrap();
+        sender.send(SerialMsg::Shutdown).unwrap();
+        thread.join().unwrap();
+
+        let terminal = terminal.lock();
+        assert_eq!(line_text(&terminal, 0), "one");
+        assert_eq!(line_text(&terminal, 1), "two");
+        assert_eq!(line_text(&terminal, 2), "three");
+        assert_eq!(terminal.grid().cursor.point.line, Line(3));
+    }
+}
@@ -0,0 +1,695 @@
+//! Packet framing of the received bytes.
+//!
+//! A `Framer` splits the RX stream into frames as the tap records it. The
+//! frames are kept with their time of arrival and the result of their check,
+//! for the frame list panel and for applications reading them.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::serial_tty::schema::{DecodedPacket, PacketSchema, SchemaDecoder};
+use crate::transfer::crc16;
+use crate::transfer::zmodem::crc32;
+
+/// Frames kept in memory, the oldest are dropped beyond it.
+pub const DEFAULT_FRAME_CAPACITY: usize = 10_000;
+
+/// Frames longer than this are dropped.
+const MAX_FRAME_LEN: usize = 64 * 1024;
+
+const SLIP_END: u8 = 0xc0;
+const SLIP_ESC: u8 = 0xdb;
+const SLIP_ESC_END: u8 = 0xdc;
+const SLIP_ESC_ESC: u8 = 0xdd;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// A SLIP escape which isn't followed by an escaped byte.
+    InvalidEscape,
+    /// A COBS code pointing past the end of the frame.
+    InvalidEncoding,
+    /// More than 64 KiB, or a length field beyond it.
+    TooLong,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FrameError::InvalidEscape => "invalid escape",
+            FrameError::InvalidEncoding => "invalid encoding",
+            FrameError::TooLong => "too long",
+        })
+    }
+}
+
+/// Splits the received bytes into frames.
+pub trait Framer: Send {
+    /// Feed received bytes, pushing the frames they complete.
+    fn push(
+        &mut self,
+        bytes: &[u8],
+        frames: &mut Vec<Result<Vec<u8>, FrameError>>,
+    );
+}
+
+/// Frame layout of a length prefixed protocol.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct LengthPrefix {
+    /// Bytes before the length field, like a sync byte or an address.
+    pub offset: usize,
+    /// Size of the length field, 1, 2 or 4 bytes.
+    pub size: usize,
+    pub big_endian: bool,
+    /// Added to the length to get the bytes following the length field,
+    /// for lengths which count the header or leave out the CRC.
+    pub adjust: isize,
+}
+
+impl Default for LengthPrefix {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            size: 1,
+            big_endian: false,
+            adjust: 0,
+        }
+    }
+}
+
+/// The built-in framers.
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum Framing {
+    /// RFC 1055 SLIP, frames end with 0xC0.
+    Slip,
+    /// Consistent overhead byte stuffing, frames end with 0x00.
+    Cobs,
+    /// Frames end with the delimiter, which isn't part of them.
+    Delimiter(Vec<u8>),
+    LengthPrefix(LengthPrefix),
+}
+
+impl Framing {
+    pub fn framer(&self) -> Box<dyn Framer> {
+        match self {
+            Framing::Slip => Box::new(SlipFramer::default()),
+            Framing::Cobs => Box::new(DelimiterFramer {
+                delimiter: vec![0],
+                cobs: true,
+                ..DelimiterFramer::default()
+            }),
+            Framing::Delimiter(delimiter) => Box::new(DelimiterFramer {
+                delimiter: delimiter.clone(),
+                ..DelimiterFramer::default()
+            }),
+            Framing::LengthPrefix(layout) => Box::new(LengthPrefixFramer {
+                layout: *layout,
+                buffer: vec![],
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SlipFramer {
+    buffer: Vec<u8>,
+    escaped: bool,
+    error: Option<FrameError>,
+}
+
+impl Framer for SlipFramer {
+    fn push(
+        &mut self,
+        bytes: &[u8],
+        frames: &mut Vec<Result<Vec<u8>, FrameError>>,
+    ) {
+        for &byte in bytes {
+            if byte == SLIP_END {
+                // Empty frames only flush line noise.
+                if !self.buffer.is_empty() || self.error.is_some() {
+                    let frame = std::mem::take(&mut self.buffer);
+                    frames.push(match self.error.take() {
+                        Some(error) => Err(error),
+                        None => Ok(frame),
+                    });
+                }
+                self.escaped = false;
+                continue;
+            }
+
+            let byte = if std::mem::take(&mut self.escaped) {
+                match byte {
+                    SLIP_ESC_END => SLIP_END,
+                    SLIP_ESC_ESC => SLIP_ESC,
+                    _ => {
+                        self.error.get_or_insert(FrameError::InvalidEscape);
+                        byte
+                    },
+                }
+            } else if byte == SLIP_ESC {
+                self.escaped = true;
+                continue;
+            } else {
+                byte
+            };
+            if self.buffer.len() < MAX_FRAME_LEN {
+                self.buffer.push(byte);
+            } else {
+                self.error.get_or_insert(FrameError::TooLong);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DelimiterFramer {
+    delimiter: Vec<u8>,
+    /// Whether the frames are COBS encoded.
+    cobs: bool,
+    buffer: Vec<u8>,
+    too_long: bool,
+}
+
+impl Framer for DelimiterFramer {
+    fn push(
+        &mut self,
+        bytes: &[u8],
+        frames: &mut Vec<Result<Vec<u8>, FrameError>>,
+    ) {
+        if self.delimiter.is_empty() {
+            return;
+        }
+
+        for &byte in bytes {
+            self.buffer.push(byte);
+            if !self.buffer.ends_with(&self.delimiter) {
+                if self.buffer.len() > MAX_FRAME_LEN + self.delimiter.len() {
+                    self.too_long = true;
+                    self.buffer.drain(..MAX_FRAME_LEN);
+                }
+                continue;
+            }
+
+            self.buffer
+                .truncate(self.buffer.len() - self.delimiter.len());
+            let frame = std::mem::take(&mut self.buffer);
+            if std::mem::take(&mut self.too_long) {
+                frames.push(Err(FrameError::TooLong));
+            } else if self.cobs && !frame.is_empty() {
+                frames.push(cobs_decode(&frame));
+            } else if !frame.is_empty() {
+                frames.push(Ok(frame));
+            }
+        }
+    }
+}
+
+/// Decode a COBS frame, without its zero delimiter.
+fn cobs_decode(encoded: &[u8]) -> Result<Vec<u8>, FrameError> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let code = encoded[i] as usize;
+        let end = i + code;
+        if code == 0 || end > encoded.len() {
+            return Err(FrameError::InvalidEncoding);
+        }
+        decoded.extend_from_slice(&encoded[i + 1..end]);
+        i = end;
+        if code < 0xff && i < encoded.len() {
+            decoded.push(0);
+        }
+    }
+    Ok(decoded)
+}
+
+#[derive(Debug)]
+struct LengthPrefixFramer {
+    layout: LengthPrefix,
+    buffer: Vec<u8>,
+}
+
+impl LengthPrefixFramer {
+    /// Length of the frame at the start of the buffer, once its header is
+    /// complete.
+    fn frame_len(&self) -> Option<Result<usize, FrameError>> {
+        let LengthPrefix {
+            offset,
+            size,
+            big_endian,
+            adjust,
+        } = self.layout;
+        let field = self.buffer.get(offset..offset + size)?;
+        let value = if big_endian {
+            field.iter().fold(0, |value, &b| value << 8 | b as usize)
+        } else {
+            field
+                .iter()
+                .rev()
+                .fold(0, |value, &b| value << 8 | b as usize)
+        };
+        let header = offset + size;
+        Some(
+            value
+                .checked_add_signed(adjust)
+                .map(|body| header + body)
+                .filter(|&len| len <= MAX_FRAME_LEN)
+                .ok_or(FrameError::TooLong),
+        )
+    }
+}
+
+impl Framer for LengthPrefixFramer {
+    fn push(
+        &mut self,
+        bytes: &[u8],
+        frames: &mut Vec<Result<Vec<u8>, FrameError>>,
+    ) {
+        if !(1..=4).contains(&self.layout.size) {
+            return;
+        }
+
+        self.buffer.extend_from_slice(bytes);
+        while let Some(len) = self.frame_len() {
+            match len {
+                Ok(len) if len <= self.buffer.len() => {
+                    frames.push(Ok(self.buffer.drain(..len).collect()));
+                },
+                Ok(_) => break,
+                // The stream can't be followed anymore, start over with the
+                // next bytes.
+                Err(error) => {
+                    frames.push(Err(error));
+                    self.buffer.clear();
+                },
+            }
+        }
+    }
+}
+
+/// Check at the end of every frame.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum FrameCheck {
+    #[default]
+    None,
+    /// CRC-8, polynomial 0x07.
+    Crc8,
+    /// CRC-16/MODBUS, little endian.
+    Crc16Modbus,
+    /// CRC-16/XMODEM, big endian.
+    Crc16Xmodem,
+    /// CRC-16/CCITT-FALSE, big endian.
+    Crc16CcittFalse,
+    /// CRC-32 of Ethernet and zlib, little endian.
+    Crc32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameCheckResult {
+    /// No check is configured, or the frame couldn't be decoded.
+    Unchecked,
+    Valid,
+    Invalid {
+        received: u32,
+        computed: u32,
+    },
+    /// The frame is shorter than its check.
+    Missing,
+}
+
+impl FrameCheck {
+    fn len(self) -> usize {
+        match self {
+            FrameCheck::None => 0,
+            FrameCheck::Crc8 => 1,
+            FrameCheck::Crc16Modbus
+            | FrameCheck::Crc16Xmodem
+            | FrameCheck::Crc16CcittFalse => 2,
+            FrameCheck::Crc32 => 4,
+        }
+    }
+
+    pub fn verify(self, frame: &[u8]) -> FrameCheckResult {
+        let len = self.len();
+        if len == 0 {
+            return FrameCheckResult::Unchecked;
+        }
+        if frame.len() < len {
+            return FrameCheckResult::Missing;
+        }
+
+        let (data, check) = frame.split_at(frame.len() - len);
+        let big_endian = |bytes: &[u8]| {
+            bytes.iter().fold(0, |value, &b| value << 8 | b as u32)
+        };
+        let little_endian = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |value, &b| value << 8 | b as u32)
+        };
+        let (received, computed) = match self {
+            FrameCheck::None => unreachable!(),
+            FrameCheck::Crc8 => (check[0] as u32, crc8(data) as u32),
+            FrameCheck::Crc16Modbus => {
+                (little_endian(check), crc16_modbus(data) as u32)
+            },
+            FrameCheck::Crc16Xmodem => (big_endian(check), crc16(data) as u32),
+            FrameCheck::Crc16CcittFalse => {
+                (big_endian(check), crc16_ccitt_false(data) as u32)
+            },
+            FrameCheck::Crc32 => (little_endian(check), crc32(data)),
+        };
+        if received == computed {
+            FrameCheckResult::Valid
+        } else {
+            FrameCheckResult::Invalid { received, computed }
+        }
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// CRC-16/MODBUS, reflected polynomial 0xA001 starting at 0xFFFF.
+pub(crate) fn crc16_modbus(data: &[u8]) -> u16 {
+    data.iter()
+        .fold(0xffff, |crc, &byte| crc16_modbus_update(crc, byte))
+}
+
+#[inline]
+pub(crate) fn crc16_modbus_update(crc: u16, byte: u8) -> u16 {
+    (0..8).fold(crc ^ byte as u16, |crc, _| {
+        if crc & 1 != 0 {
+            (crc >> 1) ^ 0xa001
+        } else {
+            crc >> 1
+        }
+    })
+}
+
+/// CRC-16/CCITT-FALSE, the XMODEM polynomial starting at 0xFFFF.
+fn crc16_ccitt_false(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedFrame {
+    /// Increments with every frame since the session was opened.
+    pub index: u64,
+    pub time: SystemTime,
+    /// Decoded bytes, including the check. Empty when `error` is set.
+    pub bytes: Vec<u8>,
+    pub check: FrameCheckResult,
+    pub error: Option<FrameError>,
+}
+
+/// Frames decoded from the received bytes.
+pub struct FrameLog {
+    framer: Option<Box<dyn Framer>>,
+    check: FrameCheck,
+    frames: VecDeque<DecodedFrame>,
+    next_index: u64,
+    capacity: usize,
+    schema: Option<SchemaDecoder>,
+}
+
+impl Default for FrameLog {
+    fn default() -> Self {
+        Self {
+            framer: None,
+            check: FrameCheck::None,
+            frames: VecDeque::new(),
+            next_index: 0,
+            capacity: DEFAULT_FRAME_CAPACITY,
+            schema: None,
+        }
+    }
+}
+
+impl FrameLog {
+    pub fn set_framer(
+        &mut self,
+        framer: Option<Box<dyn Framer>>,
+        check: FrameCheck,
+    ) {
+        self.framer = framer;
+        self.check = check;
+    }
+
+    #[inline]
+    pub fn is_framing(&self) -> bool {
+        self.framer.is_some()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        let Some(framer) = &mut self.framer else {
+            return;
+        };
+
+        let mut decoded = vec![];
+        framer.push(bytes, &mut decoded);
+        let time = SystemTime::now();
+        for result in decoded {
+            let frame = match result {
+                Ok(bytes) => DecodedFrame {
+                    index: self.next_index,
+                    time,
+                    check: self.check.verify(&bytes),
+                    bytes,
+                    error: None,
+                },
+                Err(error) => DecodedFrame {
+                    index: self.next_index,
+                    time,
+                    bytes: vec![],
+                    check: FrameCheckResult::Unchecked,
+                    error: Some(error),
+                },
+            };
+            self.next_index += 1;
+            if let Some(schema) = &mut self.schema {
+                let checked = matches!(
+                    frame.check,
+                    FrameCheckResult::Unchecked | FrameCheckResult::Valid
+                );
+                if frame.error.is_none() && checked {
+                    schema.push(&frame);
+                }
+            }
+            self.frames.push_back(frame);
+        }
+        while self.frames.len() > self.capacity {
+            self.frames.pop_front();
+        }
+    }
+
+    #[inline]
+    pub fn frames(&self) -> &VecDeque<DecodedFrame> {
+        &self.frames
+    }
+
+    /// Frames with an index of `index` or above.
+    pub fn since(&self, index: u64) -> impl Iterator<Item = &DecodedFrame> {
+        let start = self.frames.partition_point(|frame| frame.index < index);
+        self.frames.range(start..)
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Decode the frames which pass their check with `schema`.
+    pub(crate) fn set_schema(&mut self, schema: Option<PacketSchema>) {
+        self.schema = schema.map(SchemaDecoder::new);
+    }
+
+    #[inline]
+    pub(crate) fn schema_decoder(&mut self) -> Option<&mut SchemaDecoder> {
+        self.schema.as_mut()
+    }
+
+    /// Last packet of every layout of the schema.
+    pub(crate) fn latest_packets(&self) -> Vec<DecodedPacket> {
+        self.schema
+            .iter()
+            .flat_map(|schema| schema.latest())
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(
+        framing: Framing,
+        chunks: &[&[u8]],
+    ) -> Vec<Result<Vec<u8>, FrameError>> {
+        let mut framer = framing.framer();
+        let mut frames = vec![];
+        for chunk in chunks {
+            framer.push(chunk, &mut frames);
+        }
+        frames
+    }
+
+    #[test]
+    fn slip_unescapes_across_chunks() {
+        let frames = split(
+            Framing::Slip,
+            &[
+                b"\xc0\x01\xdb",
+                b"\xdc\x02\xdb\xdd\xc0",
+                b"\x03\xdb\x04\xc0",
+            ],
+        );
+        assert_eq!(
+            frames,
+            [
+                Ok(vec![0x01, 0xc0, 0x02, 0xdb]),
+                Err(FrameError::InvalidEscape)
+            ]
+        );
+    }
+
+    #[test]
+    fn cobs_decodes_zeros() {
+        let frames = split(
+            Framing::Cobs,
+            &[b"\x01\x01\x00", b"\x03\x11\x22\x02\x33\x00\x05\x11\x00"],
+        );
+        assert_eq!(
+            frames,
+            [
+                Ok(vec![0x00]),
+                Ok(vec![0x11, 0x22, 0x00, 0x33]),
+                Err(FrameError::InvalidEncoding)
+            ]
+        );
+
+        let mut long = vec![0xff];
+        long.extend(1..=254);
+        long.push(0);
+        assert_eq!(
+            split(Framing::Cobs, &[&long]),
+            [Ok((1..=254).collect::<Vec<u8>>())]
+        );
+    }
+
+    #[test]
+    fn delimiter_spans_chunks() {
+        let frames = split(
+            Framing::Delimiter(b"\r\n".to_vec()),
+            &[b"$GPGGA\r", b"\n\r\nOK\r\n"],
+        );
+        assert_eq!(frames, [Ok(b"$GPGGA".to_vec()), Ok(b"OK".to_vec())]);
+    }
+
+    #[test]
+    fn length_prefix_with_sync_and_crc() {
+        // Sync byte, big endian length of the payload, then a CRC-16.
+        let layout = LengthPrefix {
+            offset: 1,
+            size: 2,
+            big_endian: true,
+            adjust: 2,
+        };
+        let mut frame = vec![0xaa, 0x00, 0x03, 0x01, 0x02, 0x03];
+        frame.extend(crc16(&frame).to_be_bytes());
+        let mut stream = frame.clone();
+        stream.extend(&frame[..4]);
+
+        let frames = split(Framing::LengthPrefix(layout), &[&stream]);
+        assert_eq!(frames, [Ok(frame.clone())]);
+        assert_eq!(
+            FrameCheck::Crc16Xmodem.verify(&frame),
+            FrameCheckResult::Valid
+        );
+    }
+
+    #[test]
+    fn check_values() {
+        let mut frame = b"123456789".to_vec();
+        assert_eq!(crc8(&frame), 0xf4);
+        assert_eq!(crc16_modbus(&frame), 0x4b37);
+        assert_eq!(crc16_ccitt_false(&frame), 0x29b1);
+
+        frame.extend(0x4b37u16.to_le_bytes());
+        assert_eq!(
+            FrameCheck::Crc16Modbus.verify(&frame),
+            FrameCheckResult::Valid
+        );
+        assert_eq!(
+            FrameCheck::Crc32.verify(&frame[..3]),
+            FrameCheckResult::Missing
+        );
+        assert!(matches!(
+            FrameCheck::Crc16CcittFalse.verify(&frame),
+            FrameCheckResult::Invalid {
+                received: 0x374b,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn log_numbers_and_checks_frames() {
+        let mut log = FrameLog::default();
+        log.push(b"ignored\n");
+        log.set_framer(
+            Some(Framing::Delimiter(b"\n".to_vec()).framer()),
+            FrameCheck::Crc8,
+        );
+        log.push(b"a\xf4\n");
+        log.push(b"b\x00\n");
+
+        let frames: Vec<_> = log.since(1).collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].index, 1);
+        assert!(matches!(
+            frames[0].check,
+            FrameCheckResult::Invalid { received: 0, .. }
+        ));
+        assert_eq!(log.frames()[0].bytes, b"a\xf4");
+    }
+}
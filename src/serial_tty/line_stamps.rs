@@ -0,0 +1,704 @@
+//! Arrival time of the first received byte of every grid line.
+//!
+//! Lines are tracked by an absolute index which keeps growing while content
+//! scrolls into the history, so the stamps stay attached to their lines.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::grid::{Dimensions, Grid};
+use alacritty_terminal::index::{Column, Line};
+use alacritty_terminal::term::cell::Cell;
+use alacritty_terminal::term::{Term, TermMode};
+use log::error;
+
+use crate::serial_tty::archive::{row_text, ScrollbackArchive};
+use crate::serial_tty::escape::{Escape, TextFilter};
+
+/// Bookmarks kept before the oldest ones are dropped.
+const MAX_BOOKMARKS: usize = 1024;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub enum TimestampFormat {
+    /// Wall clock time, `HH:MM:SS.mmm`.
+    Absolute,
+    /// Time elapsed since the previous stamped line, `+S.mmm`.
+    Delta,
+}
+
+impl TimestampFormat {
+    /// Width of the formatted stamp in cells.
+    pub fn width(self) -> usize {
+        match self {
+            TimestampFormat::Absolute => 12,
+            TimestampFormat::Delta => 10,
+        }
+    }
+
+    pub fn format(
+        self,
+        stamp: SystemTime,
+        previous: Option<SystemTime>,
+    ) -> String {
+        match self {
+            TimestampFormat::Absolute => {
+                chrono::DateTime::<chrono::Local>::from(stamp)
+                    .format("%H:%M:%S%.3f")
+                    .to_string()
+            },
+            TimestampFormat::Delta => {
+                let delta = previous
+                    .and_then(|previous| stamp.duration_since(previous).ok())
+                    .unwrap_or(Duration::ZERO);
+                format!("+{}.{:03}", delta.as_secs(), delta.subsec_millis())
+            },
+        }
+    }
+}
+
+/// Cursor state right before a byte was parsed.
+#[derive(Debug, Clone)]
+pub(crate) struct CursorSnapshot {
+    line: Line,
+    history_size: usize,
+    input_needs_wrap: bool,
+    /// Tag of the top screen line, followed to find how far the grid
+    /// scrolled.
+    top: usize,
+    /// Tag of the newest history line.
+    newest: Option<usize>,
+    /// Text of the top screen lines, when the byte may scroll them straight
+    /// out of a history too short to hold them.
+    screen: Vec<String>,
+}
+
+impl CursorSnapshot {
+    #[inline]
+    pub(crate) fn new<U: EventListener>(terminal: &Term<U>) -> Self {
+        let grid = terminal.grid();
+        let history_size = grid.history_size();
+        Self {
+            line: grid.cursor.point.line,
+            history_size,
+            input_needs_wrap: grid.cursor.input_needs_wrap,
+            top: row_tag(grid, Line(0)),
+            newest: (history_size > 0).then(|| row_tag(grid, Line(-1))),
+            screen: vec![],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LineStamps {
+    /// Lines scrolled into the history since the session was opened.
+    scrolled: u64,
+    /// Absolute index of `stamps[0]`.
+    first: u64,
+    stamps: VecDeque<Option<SystemTime>>,
+    max_history: usize,
+    archive: Option<ScrollbackArchive>,
+    /// Absolute indexes of the bookmarked lines, in ascending order.
+    bookmarks: VecDeque<u64>,
+    escape: TextFilter,
+    /// Tags and texts of the oldest history lines, the next ones a scroll
+    /// evicts from a full history.
+    oldest: VecDeque<(usize, String)>,
+}
+
+impl LineStamps {
+    pub fn new(max_history: usize) -> Self {
+        Self {
+            scrolled: 0,
+            first: 0,
+            stamps: VecDeque::new(),
+            max_history,
+            archive: None,
+            bookmarks: VecDeque::new(),
+            escape: TextFilter::default(),
+            oldest: VecDeque::new(),
+        }
+    }
+
+    /// Store the lines evicted from the history from now on.
+    pub fn set_archive(&mut self, archive: Option<ScrollbackArchive>) {
+        self.archive = archive;
+    }
+
+    #[inline]
+    pub fn archive(&mut self) -> Option<&mut ScrollbackArchive> {
+        self.archive.as_mut()
+    }
+
+    pub fn set_max_history(&mut self, max_history: usize) {
+        self.max_history = max_history;
+        self.trim();
+    }
+
+    /// Absolute index of a grid line.
+    #[inline]
+    pub fn absolute(&self, line: Line) -> u64 {
+        (self.scrolled as i64 + line.0 as i64).max(0) as u64
+    }
+
+    /// Grid line of an absolute index, the inverse of `absolute`.
+    #[inline]
+    pub fn line(&self, absolute: u64) -> Line {
+        Line((absolute as i64 - self.scrolled as i64) as i32)
+    }
+
+    pub fn get(&self, line: Line) -> Option<SystemTime> {
+        self.get_absolute(self.absolute(line))
+    }
+
+    pub fn get_absolute(&self, absolute: u64) -> Option<SystemTime> {
+        let index = absolute.checked_sub(self.first)?;
+        self.stamps.get(index as usize).copied().flatten()
+    }
+
+    /// Latest stamp above `line`, used as base of delta timestamps.
+    pub fn previous(&self, line: Line) -> Option<SystemTime> {
+        let absolute = self.absolute(line);
+        (self.first..absolute)
+            .rev()
+            .find_map(|i| self.get_absolute(i))
+    }
+
+    pub fn bookmark(&mut self, line: Line) {
+        let absolute = self.absolute(line);
+        let index = self.bookmarks.partition_point(|&b| b < absolute);
+        if self.bookmarks.get(index) == Some(&absolute) {
+            return;
+        }
+
+        self.bookmarks.insert(index, absolute);
+        if self.bookmarks.len() > MAX_BOOKMARKS {
+            self.bookmarks.pop_front();
+        }
+    }
+
+    /// Absolute indexes of the bookmarked lines still in the grid.
+    #[inline]
+    pub fn bookmarks(&self) -> &VecDeque<u64> {
+        &self.bookmarks
+    }
+
+    pub fn clear_bookmarks(&mut self) {
+        self.bookmarks.clear();
+    }
+
+    /// Cursor state before parsing `byte`, which also keeps the text of the
+    /// lines the byte might scroll out of the history, for the archive.
+    pub(crate) fn snapshot<U: EventListener>(
+        &mut self,
+        terminal: &Term<U>,
+        byte: u8,
+    ) -> CursorSnapshot {
+        let mut snapshot = CursorSnapshot::new(terminal);
+        if self.archive.is_none()
+            || terminal.mode().contains(TermMode::ALT_SCREEN)
+        {
+            return snapshot;
+        }
+
+        let grid = terminal.grid();
+        self.cache_oldest(grid, snapshot.history_size);
+
+        // Printable text only scrolls when it wraps, anything else may
+        // scroll the whole screen.
+        let screen_lines = grid.screen_lines();
+        let may_scroll = self.escape.escape() != Escape::Ground
+            || !matches!(byte, 0x20..=0x7e | 0x80..)
+            || snapshot.input_needs_wrap;
+        if self.max_history < screen_lines && may_scroll {
+            snapshot.screen = (0..screen_lines - self.max_history)
+                .map(|line| {
+                    let line = Line(line as i32);
+                    row_text(grid, line, Column(0), grid.last_column())
+                })
+                .collect();
+        }
+
+        snapshot
+    }
+
+    /// Account for a byte which was just parsed by the terminal.
+    ///
+    /// Bytes without `time` only move the lines, like the local echo.
+    pub(crate) fn track<U: EventListener>(
+        &mut self,
+        terminal: &Term<U>,
+        before: CursorSnapshot,
+        byte: u8,
+        time: Option<SystemTime>,
+    ) {
+        let is_text = self.escape.is_text(byte);
+        if terminal.mode().contains(TermMode::ALT_SCREEN) {
+            return;
+        }
+
+        let is_linefeed = matches!(byte, 0x0a..=0x0c);
+        if let (Some(time), false) = (time, is_linefeed) {
+            self.stamp(before.line, time);
+        }
+
+        let grid = terminal.grid();
+        let scrolled = scrolled_lines(grid, &before);
+        if scrolled > 0 {
+            let evicted = (before.history_size + scrolled)
+                .saturating_sub(grid.history_size());
+            self.archive_evicted(&before, evicted);
+            self.scroll(scrolled);
+        }
+
+        if let (Some(time), true, 0x20..=0x7e | 0x80..) = (time, is_text, byte)
+        {
+            self.stamp(grid.cursor.point.line, time);
+        }
+    }
+
+    /// Shift lines after history size changed outside of the parser, for
+    /// example due to a resize.
+    ///
+    /// The grid may hold more history than `max_history` for now, those
+    /// lines are archived before their stamps are dropped.
+    pub(crate) fn history_changed<U: EventListener>(
+        &mut self,
+        terminal: &Term<U>,
+        before: usize,
+    ) {
+        let after = terminal.grid().history_size();
+        if after > before {
+            self.scrolled += (after - before) as u64;
+        } else {
+            self.scrolled =
+                self.scrolled.saturating_sub((before - after) as u64);
+        }
+
+        self.oldest.clear();
+        self.archive_history(terminal, self.max_history);
+        self.trim();
+    }
+
+    /// Archive the oldest history lines beyond `max_history`, which are
+    /// about to be dropped.
+    pub(crate) fn archive_history<U: EventListener>(
+        &mut self,
+        terminal: &Term<U>,
+        max_history: usize,
+    ) {
+        if self.archive.is_none()
+            || terminal.mode().contains(TermMode::ALT_SCREEN)
+        {
+            return;
+        }
+
+        let grid = terminal.grid();
+        for line in (max_history + 1..=grid.history_size()).rev() {
+            let line = Line(-(line as i32));
+            let text = row_text(grid, line, Column(0), grid.last_column());
+            self.archive_line(line, &text);
+        }
+    }
+
+    /// Keep the text of the oldest history lines, up to a screen of them,
+    /// since a single byte never scrolls further.
+    fn cache_oldest(&mut self, grid: &Grid<Cell>, history_size: usize) {
+        let oldest = -(history_size as i32);
+        let count = history_size.min(grid.screen_lines());
+        if self.oldest.front().is_some_and(|&(tag, _)| {
+            count == 0 || tag != row_tag(grid, Line(oldest))
+        }) {
+            self.oldest.clear();
+        }
+
+        self.oldest.truncate(count);
+        while self.oldest.len() < count {
+            let line = Line(oldest + self.oldest.len() as i32);
+            let text = row_text(grid, line, Column(0), grid.last_column());
+            self.oldest.push_back((row_tag(grid, line), text));
+        }
+    }
+
+    /// Archive the `evicted` oldest lines, as they were before the byte.
+    fn archive_evicted(&mut self, before: &CursorSnapshot, evicted: usize) {
+        if self.archive.is_none() {
+            return;
+        }
+
+        let history_size = before.history_size;
+        for index in 0..evicted {
+            let text = if index < history_size {
+                self.oldest.pop_front().map(|(_, text)| text)
+            } else {
+                before.screen.get(index - history_size).cloned()
+            };
+            if let Some(text) = text {
+                let line = Line(index as i32 - history_size as i32);
+                self.archive_line(line, &text);
+            }
+        }
+    }
+
+    /// Move a history line, which is about to be dropped, to the archive.
+    pub(crate) fn archive_line(&mut self, line: Line, text: &str) {
+        let stamp = self.get(line);
+        let Some(archive) = &mut self.archive else {
+            return;
+        };
+
+        if let Err(err) = archive.push(stamp, text) {
+            error!("Error writing scrollback archive: {}", err);
+            self.archive = None;
+        }
+    }
+
+    fn stamp(&mut self, line: Line, time: SystemTime) {
+        let absolute = self.absolute(line);
+        if absolute < self.first {
+            return;
+        }
+
+        let index = (absolute - self.first) as usize;
+        if index >= self.stamps.len() {
+            self.stamps.resize(index + 1, None);
+        }
+
+        self.stamps[index].get_or_insert(time);
+    }
+
+    fn scroll(&mut self, count: usize) {
+        self.scrolled += count as u64;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let oldest = self.scrolled.saturating_sub(self.max_history as u64);
+        while self.bookmarks.front().is_some_and(|&b| b < oldest) {
+            self.bookmarks.pop_front();
+        }
+        while self.first < oldest {
+            if self.stamps.pop_front().is_none() {
+                self.first = oldest;
+                break;
+            }
+            self.first += 1;
+        }
+    }
+}
+
+/// Identity of the row at `line`: the address of its cells, which stays
+/// put while the rows rotate through the grid.
+fn row_tag(grid: &Grid<Cell>, line: Line) -> usize {
+    &grid[line][Column(0)] as *const Cell as usize
+}
+
+/// Lines the grid scrolled into the history since `before`, found by
+/// following the row which was at the top of the screen.
+fn scrolled_lines(grid: &Grid<Cell>, before: &CursorSnapshot) -> usize {
+    let tag = |line: i32| row_tag(grid, Line(line));
+    let history_size = grid.history_size();
+    if tag(0) == before.top
+        || (history_size > 0 && Some(tag(-1)) == before.newest)
+    {
+        return 0;
+    }
+
+    if let Some(lines) =
+        (1..=history_size).find(|&lines| tag(-(lines as i32)) == before.top)
+    {
+        return lines;
+    }
+
+    // Either the lines moved down the screen, or they scrolled through the
+    // whole history and the top row wrapped around to the bottom, where the
+    // terminal cleared it. Without any history before, only the clearing
+    // tells them apart.
+    let screen_lines = grid.screen_lines();
+    match (1..screen_lines).find(|&line| tag(line as i32) == before.top) {
+        Some(line)
+            if before.newest.is_some()
+                || grid[Line(line as i32)].is_clear() =>
+        {
+            history_size + screen_lines - line
+        },
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_path;
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::term::test::TermSize;
+    use alacritty_terminal::term::Config;
+    use alacritty_terminal::vte::ansi::Processor;
+    use std::ops::Range;
+    use std::path::PathBuf;
+
+    fn terminal(history: usize, lines: usize) -> Term<VoidListener> {
+        let config = Config {
+            scrolling_history: history,
+            ..Config::default()
+        };
+        Term::new(config, &TermSize::new(10, lines), VoidListener)
+    }
+
+    fn archive(name: &str) -> (PathBuf, ScrollbackArchive) {
+        let path = temp_path(name);
+        let archive = ScrollbackArchive::create(&path).unwrap();
+        (path, archive)
+    }
+
+    fn stamp(line: usize) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(line as u64)
+    }
+
+    /// Feed `line N` lines, each stamped `N` seconds after the epoch.
+    fn feed_lines(
+        terminal: &mut Term<VoidListener>,
+        line_stamps: &mut LineStamps,
+        lines: Range<usize>,
+    ) {
+        feed_lines_with(terminal, line_stamps, lines, b"\r\n");
+    }
+
+    /// Like `feed_lines`, with another sequence than CR LF after each line.
+    fn feed_lines_with(
+        terminal: &mut Term<VoidListener>,
+        line_stamps: &mut LineStamps,
+        lines: Range<usize>,
+        newline: &[u8],
+    ) {
+        let mut parser: Processor = Processor::new();
+        for line in lines {
+            let mut input = format!("line {}", line).into_bytes();
+            input.extend_from_slice(newline);
+            for byte in input {
+                let before = line_stamps.snapshot(terminal, byte);
+                parser.advance(terminal, byte);
+                line_stamps.track(terminal, before, byte, Some(stamp(line)));
+            }
+        }
+    }
+
+    fn feed(
+        terminal: &mut Term<VoidListener>,
+        line_stamps: &mut LineStamps,
+        input: &[u8],
+    ) {
+        let mut parser: Processor = Processor::new();
+        for &byte in input {
+            let before = line_stamps.snapshot(terminal, byte);
+            parser.advance(terminal, byte);
+            line_stamps.track(terminal, before, byte, None);
+        }
+    }
+
+    /// Check the stamp of every `line N` line in the grid.
+    fn assert_stamps(terminal: &Term<VoidListener>, line_stamps: &LineStamps) {
+        let grid = terminal.grid();
+        for line in grid.topmost_line().0..=grid.bottommost_line().0 {
+            let line = Line(line);
+            let text = row_text(grid, line, Column(0), grid.last_column());
+            let expected = text
+                .strip_prefix("line ")
+                .map(|number| stamp(number.parse().unwrap()));
+            assert_eq!(line_stamps.get(line), expected, "{:?}", text);
+        }
+    }
+
+    /// Numbers of the archived lines, checking their stamps.
+    fn archived(line_stamps: &mut LineStamps) -> Vec<usize> {
+        let archive = line_stamps.archive().unwrap();
+        let lines = archive.reader().unwrap().lines(0, 100).unwrap();
+        lines
+            .iter()
+            .map(|line| {
+                let number = line.text["line ".len()..].parse().unwrap();
+                assert_eq!(line.stamp, Some(stamp(number)));
+                number
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stamps_follow_lines_into_a_full_history() {
+        let mut terminal = terminal(2, 3);
+        let mut line_stamps = LineStamps::new(2);
+        feed_lines(&mut terminal, &mut line_stamps, 0..2);
+        assert_stamps(&terminal, &line_stamps);
+        feed_lines(&mut terminal, &mut line_stamps, 2..10);
+        assert_eq!(terminal.grid().history_size(), 2);
+        assert_stamps(&terminal, &line_stamps);
+        assert_eq!(line_stamps.get(Line(-2)), Some(stamp(6)));
+    }
+
+    #[test]
+    fn index_and_next_line_with_a_full_history() {
+        let mut terminal = terminal(2, 3);
+        let mut line_stamps = LineStamps::new(2);
+        feed_lines_with(&mut terminal, &mut line_stamps, 0..6, b"\r\x1bD");
+        assert_eq!(terminal.grid().history_size(), 2);
+        assert_stamps(&terminal, &line_stamps);
+        feed_lines_with(&mut terminal, &mut line_stamps, 6..12, b"\x1bE");
+        assert_stamps(&terminal, &line_stamps);
+        assert_eq!(line_stamps.get(Line(-2)), Some(stamp(8)));
+    }
+
+    #[test]
+    fn scroll_up_with_a_full_history() {
+        let mut terminal = terminal(2, 3);
+        let (path, archive) = archive("scroll-up");
+        let mut line_stamps = LineStamps::new(2);
+        line_stamps.set_archive(Some(archive));
+        feed_lines(&mut terminal, &mut line_stamps, 0..5);
+        assert_eq!(archived(&mut line_stamps), [0]);
+
+        feed(&mut terminal, &mut line_stamps, b"\x1b[2S");
+        assert_stamps(&terminal, &line_stamps);
+        assert_eq!(archived(&mut line_stamps), [0, 1, 2]);
+
+        feed_lines_with(&mut terminal, &mut line_stamps, 5..7, b"\r\x1b[S");
+        assert_stamps(&terminal, &line_stamps);
+        assert_eq!(archived(&mut line_stamps), [0, 1, 2, 3, 4]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn scroll_region_with_a_full_history() {
+        let mut terminal = terminal(2, 4);
+        let (path, archive) = archive("scroll-region");
+        let mut line_stamps = LineStamps::new(2);
+        line_stamps.set_archive(Some(archive));
+        // The bottom line stays out of the region, like a status line.
+        feed(&mut terminal, &mut line_stamps, b"\x1b[1;3r");
+        feed_lines(&mut terminal, &mut line_stamps, 0..10);
+        assert_eq!(terminal.grid().history_size(), 2);
+        assert_stamps(&terminal, &line_stamps);
+        assert_eq!(archived(&mut line_stamps), (0..6).collect::<Vec<_>>());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn scrolling_without_history() {
+        let mut terminal = terminal(0, 3);
+        let (path, archive) = archive("no-history");
+        let mut line_stamps = LineStamps::new(0);
+        line_stamps.set_archive(Some(archive));
+        feed_lines(&mut terminal, &mut line_stamps, 0..5);
+        assert_stamps(&terminal, &line_stamps);
+        feed_lines_with(&mut terminal, &mut line_stamps, 5..8, b"\r\x1bD");
+        assert_stamps(&terminal, &line_stamps);
+        assert_eq!(archived(&mut line_stamps), (0..6).collect::<Vec<_>>());
+
+        // Reverse index moves the lines down without scrolling any out.
+        let top = line_stamps.absolute(Line(0));
+        feed(&mut terminal, &mut line_stamps, b"\x1b[H\x1bM");
+        assert_eq!(line_stamps.absolute(Line(0)), top);
+        assert_eq!(archived(&mut line_stamps), (0..6).collect::<Vec<_>>());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn history_changed_by_a_resize() {
+        let mut terminal = terminal(5, 6);
+        let mut line_stamps = LineStamps::new(5);
+        feed_lines(&mut terminal, &mut line_stamps, 0..5);
+        assert_eq!(terminal.grid().history_size(), 0);
+
+        terminal.resize(TermSize::new(10, 3));
+        line_stamps.history_changed(&terminal, 0);
+        assert_eq!(terminal.grid().history_size(), 3);
+        assert_stamps(&terminal, &line_stamps);
+
+        terminal.resize(TermSize::new(10, 5));
+        line_stamps.history_changed(&terminal, 3);
+        assert_eq!(terminal.grid().history_size(), 1);
+        assert_stamps(&terminal, &line_stamps);
+
+        feed_lines(&mut terminal, &mut line_stamps, 5..7);
+        assert_stamps(&terminal, &line_stamps);
+    }
+
+    #[test]
+    fn clear_screen_with_a_full_history() {
+        let mut terminal = terminal(2, 3);
+        let (path, archive) = archive("clear");
+        let mut line_stamps = LineStamps::new(2);
+        line_stamps.set_archive(Some(archive));
+        feed_lines(&mut terminal, &mut line_stamps, 0..10);
+        assert_eq!(archived(&mut line_stamps), (0..6).collect::<Vec<_>>());
+
+        feed(&mut terminal, &mut line_stamps, b"\x1b[2J");
+        assert_eq!(
+            row_text(terminal.grid(), Line(-1), Column(0), Column(9)),
+            "line 9"
+        );
+        assert_stamps(&terminal, &line_stamps);
+        assert_eq!(archived(&mut line_stamps), (0..8).collect::<Vec<_>>());
+
+        feed_lines(&mut terminal, &mut line_stamps, 10..12);
+        assert_stamps(&terminal, &line_stamps);
+        assert_eq!(archived(&mut line_stamps), (0..10).collect::<Vec<_>>());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn clear_screen_with_a_partial_history() {
+        let mut terminal = terminal(4, 3);
+        let mut line_stamps = LineStamps::new(4);
+        feed_lines(&mut terminal, &mut line_stamps, 0..5);
+        assert_eq!(terminal.grid().history_size(), 3);
+
+        feed(&mut terminal, &mut line_stamps, b"\x1b[2J");
+        assert_eq!(terminal.grid().history_size(), 4);
+        assert_stamps(&terminal, &line_stamps);
+        feed_lines(&mut terminal, &mut line_stamps, 5..7);
+        assert_stamps(&terminal, &line_stamps);
+    }
+
+    #[test]
+    fn evicted_lines_are_archived() {
+        let config = Config {
+            scrolling_history: 2,
+            ..Config::default()
+        };
+        let mut terminal =
+            Term::new(config, &TermSize::new(10, 3), VoidListener);
+        let mut parser: Processor = Processor::new();
+        let path = temp_path("evicted");
+        let mut line_stamps = LineStamps::new(2);
+        line_stamps
+            .set_archive(Some(ScrollbackArchive::create(&path).unwrap()));
+
+        let time = SystemTime::now();
+        // Ten short lines and a long one wrapping twice.
+        let mut input = (0..10)
+            .map(|line| format!("line {}\r\n", line))
+            .collect::<String>();
+        input.push_str("0123456789abcdefghijABCDEFGHIJ");
+        for byte in input.bytes() {
+            let before = line_stamps.snapshot(&terminal, byte);
+            parser.advance(&mut terminal, byte);
+            line_stamps.track(&terminal, before, byte, Some(time));
+        }
+
+        let archive = line_stamps.archive().unwrap();
+        let lines = archive.reader().unwrap().lines(0, 20).unwrap();
+        let texts: Vec<_> =
+            lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "line 0", "line 1", "line 2", "line 3", "line 4", "line 5",
+                "line 6", "line 7"
+            ]
+        );
+        assert!(lines.iter().all(|line| line.stamp.is_some()));
+        std::fs::remove_file(path).unwrap();
+    }
+}
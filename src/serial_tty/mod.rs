@@ -32,7 +32,21 @@ const DEFAULT_BAUDRATE: u32 = 115200;
 #[cfg(any(target_os = "macos", all(test, target_os = "macos")))]
 mod prolific_apple_patch;
 
+pub mod archive;
+pub mod control_tokens;
+pub mod defmt;
+pub mod encoding;
+pub(crate) mod escape;
 pub mod event_loop;
+pub mod framing;
+pub mod line_stamps;
+pub mod newline;
+pub mod nmea;
+pub mod plot;
+pub mod schema;
+pub(crate) mod subscription;
+pub mod tap;
+pub mod triggers;
 
 #[cfg(unix)]
 pub(crate) mod unix;
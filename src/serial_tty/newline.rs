@@ -0,0 +1,158 @@
+//! Newline translation of the received and sent byte streams.
+
+use std::fmt::{self, Display, Formatter};
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum RxNewline {
+    /// Bytes reach the terminal as they were received.
+    #[default]
+    PassThrough,
+    /// Every LF also returns the carriage, like `LINE_FEED_NEW_LINE` mode.
+    ImplicitCr,
+    /// Every CR also feeds a line. A LF right after a CR is dropped, so
+    /// CRLF devices don't produce empty lines.
+    ImplicitLf,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RxNewlineFilter {
+    policy: RxNewline,
+    last_was_cr: bool,
+}
+
+impl RxNewlineFilter {
+    pub fn new(policy: RxNewline) -> Self {
+        Self {
+            policy,
+            last_was_cr: false,
+        }
+    }
+
+    pub fn filter(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        for &byte in input {
+            match (self.policy, byte) {
+                (RxNewline::ImplicitCr, b'\n') => {
+                    output.extend_from_slice(b"\r\n")
+                },
+                (RxNewline::ImplicitLf, b'\r') => {
+                    output.extend_from_slice(b"\r\n")
+                },
+                (RxNewline::ImplicitLf, b'\n') if self.last_was_cr => {},
+                _ => output.push(byte),
+            }
+
+            self.last_was_cr = byte == b'\r';
+        }
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum TxNewline {
+    /// Bytes are written as they are, Enter sends `\r`.
+    #[default]
+    PassThrough,
+    /// `\r`
+    Cr,
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
+
+impl TxNewline {
+    #[inline]
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            TxNewline::PassThrough | TxNewline::Cr => b"\r",
+            TxNewline::Lf => b"\n",
+            TxNewline::CrLf => b"\r\n",
+        }
+    }
+
+    /// Replace every CR, LF or CRLF line ending of `input`.
+    pub fn translate(self, input: &[u8]) -> Vec<u8> {
+        if self == TxNewline::PassThrough {
+            return input.to_vec();
+        }
+
+        let mut output = Vec::with_capacity(input.len());
+        let mut bytes = input.iter().peekable();
+        while let Some(&byte) = bytes.next() {
+            match byte {
+                b'\r' => {
+                    bytes.next_if_eq(&&b'\n');
+                    output.extend_from_slice(self.as_bytes());
+                },
+                b'\n' => output.extend_from_slice(self.as_bytes()),
+                _ => output.push(byte),
+            }
+        }
+
+        output
+    }
+}
+
+impl Display for TxNewline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TxNewline::PassThrough => write!(f, "As is"),
+            TxNewline::Cr => write!(f, "CR"),
+            TxNewline::Lf => write!(f, "LF"),
+            TxNewline::CrLf => write!(f, "CRLF"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rx(policy: RxNewline, chunks: &[&[u8]]) -> Vec<u8> {
+        let mut filter = RxNewlineFilter::new(policy);
+        let mut output = vec![];
+        for chunk in chunks {
+            filter.filter(chunk, &mut output);
+        }
+        output
+    }
+
+    #[test]
+    fn rx_implicit_cr() {
+        assert_eq!(rx(RxNewline::ImplicitCr, &[b"a\nb\r\n"]), b"a\r\nb\r\r\n");
+    }
+
+    #[test]
+    fn rx_implicit_lf_across_chunks() {
+        assert_eq!(
+            rx(RxNewline::ImplicitLf, &[b"a\r", b"\nb\rc"]),
+            b"a\r\nb\r\nc"
+        );
+    }
+
+    #[test]
+    fn tx_translate() {
+        let input = b"a\rb\nc\r\nd";
+        assert_eq!(TxNewline::default().translate(input), input);
+        assert_eq!(TxNewline::Cr.translate(input), b"a\rb\rc\rd");
+        assert_eq!(TxNewline::Lf.translate(input), b"a\nb\nc\nd");
+        assert_eq!(TxNewline::CrLf.translate(input), b"a\r\nb\r\nc\r\nd");
+    }
+}
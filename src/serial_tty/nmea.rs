@@ -0,0 +1,557 @@
+//! NMEA 0183 sentences of GNSS receivers.
+//!
+//! `NmeaDecoder` reads the sentences from the received bytes and keeps the
+//! fix, position and satellites they report as a `GnssState`. Only GGA, RMC,
+//! GSV and GSA sentences are interpreted, the others are just counted.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::time::SystemTime;
+
+use chrono::{NaiveDate, NaiveTime};
+
+/// Longest line searched for a sentence, the standard allows 82 bytes.
+const MAX_LINE_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NmeaError {
+    /// Not a `$` sentence, or a field which can't be read.
+    Malformed,
+    MissingChecksum,
+    Checksum {
+        received: u8,
+        computed: u8,
+    },
+}
+
+impl fmt::Display for NmeaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NmeaError::Malformed => f.write_str("malformed sentence"),
+            NmeaError::MissingChecksum => f.write_str("missing checksum"),
+            NmeaError::Checksum { received, computed } => write!(
+                f,
+                "bad checksum {:02X}, expected {:02X}",
+                received, computed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NmeaError {}
+
+/// XOR of the bytes between `$` and `*`.
+pub fn nmea_checksum(body: &[u8]) -> u8 {
+    body.iter().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+/// A sentence with a valid checksum, split into its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NmeaSentence {
+    /// `GP`, `GL`, `GA`, `GB`, `GN`... or `P` for proprietary sentences.
+    pub talker: String,
+    /// `GGA`, `RMC`... or the rest of the address of proprietary sentences.
+    pub kind: String,
+    pub fields: Vec<String>,
+}
+
+impl NmeaSentence {
+    /// Parse a sentence starting with `$`, the line ending is optional.
+    pub fn parse(line: &str) -> Result<Self, NmeaError> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let body = line.strip_prefix('$').ok_or(NmeaError::Malformed)?;
+        let (body, checksum) =
+            body.split_once('*').ok_or(NmeaError::MissingChecksum)?;
+        let received = u8::from_str_radix(checksum, 16)
+            .ok()
+            .filter(|_| checksum.len() == 2)
+            .ok_or(NmeaError::Malformed)?;
+        let computed = nmea_checksum(body.as_bytes());
+        if received != computed {
+            return Err(NmeaError::Checksum { received, computed });
+        }
+
+        let mut fields = body.split(',');
+        let address = fields.next().unwrap_or_default();
+        if !address.is_ascii() || address.len() < 2 {
+            return Err(NmeaError::Malformed);
+        }
+        let (talker, kind) = if address.starts_with('P') {
+            address.split_at(1)
+        } else {
+            address.split_at(2)
+        };
+        Ok(Self {
+            talker: talker.to_owned(),
+            kind: kind.to_owned(),
+            fields: fields.map(str::to_owned).collect(),
+        })
+    }
+
+    fn field<T: std::str::FromStr>(&self, index: usize) -> Option<T> {
+        self.fields.get(index).and_then(|field| field.parse().ok())
+    }
+
+    fn text(&self, index: usize) -> &str {
+        self.fields.get(index).map_or("", String::as_str)
+    }
+
+    /// Degrees from `ddmm.mmmm` and its hemisphere, south and west are
+    /// negative.
+    fn coordinate(&self, index: usize) -> Option<f64> {
+        let value: f64 = self.field(index)?;
+        let degrees = (value / 100.0).trunc();
+        let degrees = degrees + (value - degrees * 100.0) / 60.0;
+        match self.text(index + 1) {
+            "N" | "E" => Some(degrees),
+            "S" | "W" => Some(-degrees),
+            _ => None,
+        }
+    }
+
+    fn time(&self, index: usize) -> Option<NaiveTime> {
+        NaiveTime::parse_from_str(self.text(index), "%H%M%S%.f").ok()
+    }
+}
+
+/// Byte ranges of the sentences of `text` whose checksum is wrong.
+pub(crate) fn bad_checksums(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut ranges = vec![];
+    let mut start = 0;
+    while let Some(found) = text[start..].find('$') {
+        let begin = start + found;
+        start = begin + 1;
+        let Some(star) = bytes[begin + 1..]
+            .iter()
+            .take(MAX_LINE_LEN)
+            .position(|&byte| byte == b'*' || byte == b'$')
+            .map(|position| begin + 1 + position)
+            .filter(|&star| bytes[star] == b'*')
+        else {
+            continue;
+        };
+        let Some(received) = text
+            .get(star + 1..star + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        else {
+            continue;
+        };
+
+        if received != nmea_checksum(&bytes[begin + 1..star]) {
+            ranges.push(begin..star + 3);
+        }
+        start = star + 3;
+    }
+    ranges
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FixQuality {
+    #[default]
+    Invalid,
+    Gps,
+    Dgps,
+    Pps,
+    Rtk,
+    FloatRtk,
+    Estimated,
+    Manual,
+    Simulation,
+    Other(u8),
+}
+
+impl From<u8> for FixQuality {
+    fn from(quality: u8) -> Self {
+        match quality {
+            0 => FixQuality::Invalid,
+            1 => FixQuality::Gps,
+            2 => FixQuality::Dgps,
+            3 => FixQuality::Pps,
+            4 => FixQuality::Rtk,
+            5 => FixQuality::FloatRtk,
+            6 => FixQuality::Estimated,
+            7 => FixQuality::Manual,
+            8 => FixQuality::Simulation,
+            other => FixQuality::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for FixQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixQuality::Invalid => f.write_str("no fix"),
+            FixQuality::Gps => f.write_str("GPS"),
+            FixQuality::Dgps => f.write_str("DGPS"),
+            FixQuality::Pps => f.write_str("PPS"),
+            FixQuality::Rtk => f.write_str("RTK fixed"),
+            FixQuality::FloatRtk => f.write_str("RTK float"),
+            FixQuality::Estimated => f.write_str("dead reckoning"),
+            FixQuality::Manual => f.write_str("manual"),
+            FixQuality::Simulation => f.write_str("simulation"),
+            FixQuality::Other(quality) => write!(f, "quality {}", quality),
+        }
+    }
+}
+
+/// Fix dimension reported by GSA.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FixMode {
+    #[default]
+    NoFix,
+    Fix2d,
+    Fix3d,
+}
+
+impl fmt::Display for FixMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FixMode::NoFix => "no fix",
+            FixMode::Fix2d => "2D",
+            FixMode::Fix3d => "3D",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Satellite {
+    /// Talker of the GSV sentence, `GP` for GPS, `GL` for GLONASS...
+    pub system: String,
+    pub prn: u16,
+    /// Degrees above the horizon.
+    pub elevation: Option<i16>,
+    /// Degrees from true north.
+    pub azimuth: Option<u16>,
+    /// Signal to noise ratio in dB-Hz, `None` while not tracked.
+    pub snr: Option<u8>,
+    /// Whether the fix uses the satellite, as reported by GSA.
+    pub used: bool,
+}
+
+/// Receiver state, as reported by the last sentences of each kind.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GnssState {
+    /// UTC time of the last fix.
+    pub time: Option<NaiveTime>,
+    pub date: Option<NaiveDate>,
+    /// Degrees, south is negative.
+    pub latitude: Option<f64>,
+    /// Degrees, west is negative.
+    pub longitude: Option<f64>,
+    /// Meters above mean sea level.
+    pub altitude: Option<f64>,
+    pub quality: FixQuality,
+    pub mode: FixMode,
+    /// Status of the last RMC sentence.
+    pub valid: bool,
+    /// Satellites used by the fix, as reported by GGA.
+    pub satellites_used: Option<u8>,
+    pub speed_knots: Option<f64>,
+    /// Degrees from true north.
+    pub course: Option<f64>,
+    pub pdop: Option<f64>,
+    pub hdop: Option<f64>,
+    pub vdop: Option<f64>,
+    /// Satellites in view, sorted by system and PRN.
+    pub satellites: Vec<Satellite>,
+    /// Sentences received with a valid checksum.
+    pub sentences: u64,
+    pub bad_checksums: u64,
+    pub last_update: Option<SystemTime>,
+}
+
+/// Reads sentences from the received bytes, while enabled.
+#[derive(Default)]
+pub(crate) struct NmeaDecoder {
+    enabled: bool,
+    line: Vec<u8>,
+    state: GnssState,
+    /// Satellites of the last complete GSV cycle of each talker.
+    in_view: BTreeMap<String, Vec<Satellite>>,
+    /// GSV cycle being received, with its talker.
+    cycle: Option<(String, Vec<Satellite>)>,
+    /// Satellites used by the fix, with their system when GSA tells it.
+    used: Vec<(Option<String>, u16)>,
+    last_was_gsa: bool,
+}
+
+impl NmeaDecoder {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.line.clear();
+    }
+
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        if !self.enabled {
+            return;
+        }
+
+        for &byte in bytes {
+            if byte != b'\n' {
+                // Overlong lines are cut, their sentence then is malformed.
+                if self.line.len() < MAX_LINE_LEN {
+                    self.line.push(byte);
+                }
+                continue;
+            }
+
+            let line = std::mem::take(&mut self.line);
+            let Some(start) = line.iter().position(|&byte| byte == b'$') else {
+                continue;
+            };
+            let sentence = std::str::from_utf8(&line[start..])
+                .map_err(|_| NmeaError::Malformed)
+                .and_then(NmeaSentence::parse);
+            match sentence {
+                Ok(sentence) => self.apply(&sentence),
+                Err(NmeaError::Checksum { .. }) => {
+                    self.state.bad_checksums += 1
+                },
+                Err(_) => {},
+            }
+        }
+    }
+
+    fn apply(&mut self, sentence: &NmeaSentence) {
+        self.state.sentences += 1;
+        self.state.last_update = Some(SystemTime::now());
+        let is_gsa = sentence.kind == "GSA";
+        match sentence.kind.as_str() {
+            "GGA" => {
+                let state = &mut self.state;
+                state.time = sentence.time(0).or(state.time);
+                state.latitude = sentence.coordinate(1);
+                state.longitude = sentence.coordinate(3);
+                state.quality =
+                    sentence.field::<u8>(5).unwrap_or_default().into();
+                state.satellites_used = sentence.field(6);
+                state.hdop = sentence.field(7);
+                state.altitude = sentence.field(8);
+            },
+            "RMC" => {
+                let state = &mut self.state;
+                state.time = sentence.time(0).or(state.time);
+                state.valid = sentence.text(1) == "A";
+                state.latitude = sentence.coordinate(2);
+                state.longitude = sentence.coordinate(4);
+                state.speed_knots = sentence.field(6);
+                state.course = sentence.field(7);
+                state.date =
+                    NaiveDate::parse_from_str(sentence.text(8), "%d%m%y")
+                        .ok()
+                        .or(state.date);
+            },
+            "GSA" => self.apply_gsa(sentence),
+            "GSV" => self.apply_gsv(sentence),
+            _ => {},
+        }
+        self.last_was_gsa = is_gsa;
+    }
+
+    /// A receiver tracking several systems sends one GSA per system.
+    fn apply_gsa(&mut self, sentence: &NmeaSentence) {
+        if !self.last_was_gsa {
+            self.used.clear();
+        }
+
+        let system = match (sentence.talker.as_str(), sentence.text(17)) {
+            ("GN", "1") => Some("GP"),
+            ("GN", "2") => Some("GL"),
+            ("GN", "3") => Some("GA"),
+            ("GN", "4") => Some("GB"),
+            ("GN", "5") => Some("GQ"),
+            ("GN", "6") => Some("GI"),
+            ("GN", _) => None,
+            (talker, _) => Some(talker),
+        };
+        self.used.extend(
+            (2..14)
+                .filter_map(|index| sentence.field::<u16>(index))
+                .map(|prn| (system.map(str::to_owned), prn)),
+        );
+
+        let state = &mut self.state;
+        state.mode = match sentence.text(1) {
+            "2" => FixMode::Fix2d,
+            "3" => FixMode::Fix3d,
+            _ => FixMode::NoFix,
+        };
+        state.pdop = sentence.field(14);
+        state.hdop = sentence.field(15).or(state.hdop);
+        state.vdop = sentence.field(16);
+    }
+
+    fn apply_gsv(&mut self, sentence: &NmeaSentence) {
+        let (Some(total), Some(number)) =
+            (sentence.field::<u8>(0), sentence.field::<u8>(1))
+        else {
+            return;
+        };
+        if number == 1 {
+            self.cycle = Some((sentence.talker.clone(), vec![]));
+        }
+        let Some((talker, satellites)) = &mut self.cycle else {
+            return;
+        };
+        if *talker != sentence.talker {
+            self.cycle = None;
+            return;
+        }
+
+        // NMEA 4.10 adds a signal ID after the satellites.
+        let count = sentence.fields.len().saturating_sub(3) / 4;
+        for group in 0..count {
+            let index = 3 + 4 * group;
+            let Some(prn) = sentence.field(index) else {
+                continue;
+            };
+            // Satellites are listed once per signal.
+            if satellites.iter().any(|satellite| satellite.prn == prn) {
+                continue;
+            }
+            satellites.push(Satellite {
+                system: talker.clone(),
+                prn,
+                elevation: sentence.field(index + 1),
+                azimuth: sentence.field(index + 2),
+                snr: sentence.field(index + 3),
+                used: false,
+            });
+        }
+
+        if number >= total {
+            if let Some((talker, satellites)) = self.cycle.take() {
+                self.in_view.insert(talker, satellites);
+            }
+        }
+    }
+
+    pub(crate) fn state(&self) -> GnssState {
+        let mut state = self.state.clone();
+        state.satellites = self
+            .in_view
+            .values()
+            .flatten()
+            .map(|satellite| Satellite {
+                used: self.used.iter().any(|(system, prn)| {
+                    *prn == satellite.prn
+                        && system
+                            .as_ref()
+                            .is_none_or(|system| *system == satellite.system)
+                }),
+                ..satellite.clone()
+            })
+            .collect();
+        state
+            .satellites
+            .sort_by(|a, b| (&a.system, a.prn).cmp(&(&b.system, b.prn)));
+        state
+    }
+
+    /// Forget the state, decoding goes on if enabled.
+    pub(crate) fn clear(&mut self) {
+        *self = Self {
+            enabled: self.enabled,
+            ..Self::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_checks_the_checksum() {
+        let sentence = NmeaSentence::parse(
+            "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n",
+        )
+        .unwrap();
+        assert_eq!(sentence.talker, "GP");
+        assert_eq!(sentence.kind, "GGA");
+        assert_eq!(sentence.fields.len(), 14);
+
+        assert_eq!(
+            NmeaSentence::parse("$GPGGA,123519,4807.038,N*00"),
+            Err(NmeaError::Checksum {
+                received: 0,
+                computed: 0x27
+            })
+        );
+        assert_eq!(
+            NmeaSentence::parse("$GPGGA,123519"),
+            Err(NmeaError::MissingChecksum)
+        );
+        assert_eq!(NmeaSentence::parse("$PUBX,00*33").unwrap().kind, "UBX");
+    }
+
+    #[test]
+    fn decoder_tracks_fix_and_satellites() {
+        let mut decoder = NmeaDecoder::default();
+        decoder.push(b"$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n");
+        assert_eq!(decoder.state().sentences, 0);
+
+        decoder.set_enabled(true);
+        decoder.push(b"noise $GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n");
+        decoder.push(b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n");
+        decoder.push(b"$GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39\r\n");
+        decoder.push(b"$GPGSV,2,1,08,01,40,083,46,02,17,308,41,12,07,344,39,14,22,228,45*75\r\n");
+        decoder.push(b"$GPGSV,2,2,08,04,40,083,,05,17,308,41,09,07,344,39,24,22,228,45*7F\r\n");
+        decoder.push(b"$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*48\r\n");
+
+        let state = decoder.state();
+        assert_eq!(state.sentences, 5);
+        assert_eq!(state.bad_checksums, 1);
+        assert!(state.valid);
+        assert_eq!(state.date, NaiveDate::from_ymd_opt(1994, 3, 23));
+        assert_eq!(state.time, NaiveTime::from_hms_opt(12, 35, 19));
+        assert!((state.latitude.unwrap() - 48.1173).abs() < 1e-6);
+        assert!((state.longitude.unwrap() - 11.516_666).abs() < 1e-6);
+        assert_eq!(state.altitude, Some(545.4));
+        assert_eq!(state.quality, FixQuality::Gps);
+        assert_eq!(state.mode, FixMode::Fix3d);
+        assert_eq!(state.pdop, Some(2.5));
+        assert_eq!(state.satellites.len(), 8);
+        assert_eq!(
+            state
+                .satellites
+                .iter()
+                .filter(|satellite| satellite.used)
+                .map(|satellite| satellite.prn)
+                .collect::<Vec<_>>(),
+            [4, 5, 9, 12, 24]
+        );
+        assert_eq!(state.satellites[2].prn, 4);
+        assert_eq!(state.satellites[2].snr, None);
+        decoder.push(b"$GLGSV,1,1,01,65,10,100,30*54\r\n");
+        let systems: Vec<(String, u16)> = decoder
+            .state()
+            .satellites
+            .iter()
+            .map(|satellite| (satellite.system.clone(), satellite.prn))
+            .take(2)
+            .collect();
+        assert_eq!(systems, [("GL".into(), 65), ("GP".into(), 1)]);
+
+        decoder.clear();
+        assert_eq!(decoder.state(), GnssState::default());
+        assert!(decoder.is_enabled());
+    }
+
+    #[test]
+    fn bad_checksums_are_located() {
+        let text = "> $GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*38 $GPZDA*00 $GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39";
+        let ranges = bad_checksums(text);
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(&text[ranges[0].clone()][..8], "$GPGSA,A");
+        assert!(text[ranges[0].clone()].ends_with("*38"));
+        assert_eq!(&text[ranges[1].clone()], "$GPZDA*00");
+    }
+}
@@ -0,0 +1,367 @@
+//! Numeric time series extracted from the received lines.
+//!
+//! Every complete line is matched against the `PlotPattern`s, each number
+//! they find is appended to the series of its name, stamped with the time
+//! the line ended, like the line stamps of the terminal.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+use regex::Regex;
+
+use crate::serial_tty::escape::TextFilter;
+
+/// Default amount of samples kept per series.
+pub const DEFAULT_PLOT_CAPACITY: usize = 10_000;
+
+/// Longest line matched, longer lines are matched in pieces.
+const MAX_LINE_LENGTH: usize = 1024;
+
+const NUMBER: &str = r"[-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?";
+
+/// How the values of a line are found.
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub enum PlotPattern {
+    /// `name=value` or `name: value` pairs, `temp=23.4 hum=51` plots the
+    /// series `temp` and `hum`.
+    #[default]
+    KeyValue,
+    /// Every number of the line, in series named by their position from
+    /// `1`, like the Arduino Serial Plotter.
+    Positional,
+    /// Capture groups of a regex, in series named by the group names, or
+    /// their index for unnamed groups.
+    Regex(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlotSample {
+    pub time: SystemTime,
+    /// Number of the received line the value was found in, values of the
+    /// same line share it.
+    pub line: u64,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlotSeries {
+    pub name: String,
+    /// Oldest first.
+    pub samples: VecDeque<PlotSample>,
+}
+
+#[derive(Debug)]
+enum CompiledPattern {
+    KeyValue(Regex),
+    Positional(Regex),
+    Regex(Regex),
+}
+
+impl CompiledPattern {
+    fn new(pattern: &PlotPattern) -> io::Result<Self> {
+        let compile = |pattern: &str| {
+            Regex::new(pattern)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+        };
+        Ok(match pattern {
+            PlotPattern::KeyValue => CompiledPattern::KeyValue(compile(
+                &format!(r"([A-Za-z_][\w.]*)\s*[=:]\s*({})", NUMBER),
+            )?),
+            PlotPattern::Positional => {
+                CompiledPattern::Positional(compile(NUMBER)?)
+            },
+            PlotPattern::Regex(pattern) => {
+                CompiledPattern::Regex(compile(pattern)?)
+            },
+        })
+    }
+
+    /// Push the named values found in `line`.
+    fn extract(&self, line: &str, values: &mut Vec<(String, f64)>) {
+        let mut push = |name: String, value: &str| {
+            if let Ok(value) = value.parse() {
+                values.push((name, value));
+            }
+        };
+        match self {
+            CompiledPattern::KeyValue(regex) => {
+                for captures in regex.captures_iter(line) {
+                    push(captures[1].to_owned(), &captures[2]);
+                }
+            },
+            CompiledPattern::Positional(regex) => {
+                for (index, found) in regex.find_iter(line).enumerate() {
+                    push((index + 1).to_string(), found.as_str());
+                }
+            },
+            CompiledPattern::Regex(regex) => {
+                let Some(captures) = regex.captures(line) else {
+                    return;
+                };
+                for (index, name) in regex.capture_names().enumerate().skip(1) {
+                    if let Some(found) = captures.get(index) {
+                        let name =
+                            name.map_or(index.to_string(), str::to_owned);
+                        push(name, found.as_str().trim());
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Assembles the received lines and extracts their values, while it has
+/// patterns.
+#[derive(Debug)]
+pub(crate) struct Plotter {
+    patterns: Vec<CompiledPattern>,
+    text: TextFilter,
+    line: Vec<u8>,
+    /// Lines ended so far.
+    lines: u64,
+    series: Vec<PlotSeries>,
+    capacity: usize,
+}
+
+impl Default for Plotter {
+    fn default() -> Self {
+        Self {
+            patterns: vec![],
+            text: TextFilter::default(),
+            line: vec![],
+            lines: 0,
+            series: vec![],
+            capacity: DEFAULT_PLOT_CAPACITY,
+        }
+    }
+}
+
+impl Plotter {
+    /// Replace the patterns, none stops plotting. The series are kept.
+    pub(crate) fn set_patterns(
+        &mut self,
+        patterns: &[PlotPattern],
+    ) -> io::Result<()> {
+        self.patterns = patterns
+            .iter()
+            .map(CompiledPattern::new)
+            .collect::<io::Result<_>>()?;
+        self.line.clear();
+        Ok(())
+    }
+
+    #[inline]
+    pub(crate) fn is_plotting(&self) -> bool {
+        !self.patterns.is_empty()
+    }
+
+    /// Samples kept per series, the oldest are dropped first.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        for series in &mut self.series {
+            let excess = series.samples.len().saturating_sub(self.capacity);
+            series.samples.drain(..excess);
+        }
+    }
+
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        if self.patterns.is_empty() {
+            return;
+        }
+
+        for &byte in bytes {
+            if !self.text.is_text(byte) {
+                continue;
+            }
+            match byte {
+                b'\n' => self.end_line(),
+                0x00..=0x1f | 0x7f => {},
+                _ => {
+                    if self.line.len() >= MAX_LINE_LENGTH {
+                        self.end_line();
+                    }
+                    self.line.push(byte);
+                },
+            }
+        }
+    }
+
+    fn end_line(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        let line = String::from_utf8_lossy(&line);
+        let mut values = vec![];
+        for pattern in &self.patterns {
+            pattern.extract(&line, &mut values);
+        }
+        let time = SystemTime::now();
+        let line = self.lines;
+        self.lines += 1;
+        for (name, value) in values {
+            self.record(name, PlotSample { time, line, value });
+        }
+    }
+
+    fn record(&mut self, name: String, sample: PlotSample) {
+        let index = match self.series.iter().position(|s| s.name == name) {
+            Some(index) => index,
+            None => {
+                self.series.push(PlotSeries {
+                    name,
+                    samples: VecDeque::new(),
+                });
+                self.series.len() - 1
+            },
+        };
+
+        let samples = &mut self.series[index].samples;
+        if samples.len() >= self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    /// Series in the order they first appeared.
+    #[inline]
+    pub(crate) fn series(&self) -> &[PlotSeries] {
+        &self.series
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.series.clear();
+    }
+}
+
+/// Write `series` as CSV, a row per line with a column per series.
+///
+/// The time is in seconds since the Unix epoch, values a line doesn't
+/// have are left empty.
+pub(crate) fn write_csv(
+    series: &[PlotSeries],
+    mut writer: impl Write,
+) -> io::Result<()> {
+    write!(writer, "time")?;
+    for series in series {
+        write!(writer, ",{}", csv_field(&series.name))?;
+    }
+    writeln!(writer)?;
+
+    let mut samples: Vec<(usize, &PlotSample)> = series
+        .iter()
+        .enumerate()
+        .flat_map(|(index, series)| {
+            series.samples.iter().map(move |sample| (index, sample))
+        })
+        .collect();
+    samples.sort_by_key(|&(index, sample)| (sample.line, index));
+
+    let mut row = vec![None; series.len()];
+    let mut samples = samples.into_iter().peekable();
+    while let Some((index, sample)) = samples.next() {
+        row[index] = Some(sample.value);
+        if samples
+            .peek()
+            .is_some_and(|next| next.1.line == sample.line)
+        {
+            continue;
+        }
+
+        let seconds = sample
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        write!(writer, "{:.6}", seconds)?;
+        for value in row.iter_mut() {
+            match value.take() {
+                Some(value) => write!(writer, ",{}", value)?,
+                None => write!(writer, ",")?,
+            }
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_patterns(patterns: &[PlotPattern]) -> Plotter {
+        let mut plotter = Plotter::default();
+        plotter.set_patterns(patterns).unwrap();
+        plotter
+    }
+
+    fn values(plotter: &Plotter, name: &str) -> Vec<f64> {
+        plotter
+            .series()
+            .iter()
+            .find(|series| series.name == name)
+            .map(|series| series.samples.iter().map(|s| s.value).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn patterns_extract_values() {
+        let mut plotter = with_patterns(&[PlotPattern::KeyValue]);
+        plotter.push(b"temp=23.4 hum: 51\r\n\x1b[32mtemp=-1e1\x1b[0m");
+        plotter.push(b" state=idle\n");
+        assert_eq!(values(&plotter, "temp"), [23.4, -10.0]);
+        assert_eq!(values(&plotter, "hum"), [51.0]);
+        assert_eq!(plotter.series().len(), 2);
+
+        let mut plotter = with_patterns(&[
+            PlotPattern::Regex(r"^(\S+),(\S+),(\S+)$".into()),
+            PlotPattern::Regex(r"ADC ch0 (?P<ch0>\d+) ch1 (?P<ch1>\d+)".into()),
+        ]);
+        plotter.push(b"1,2.5,-3\nADC ch0 512 ch1 100\n");
+        assert_eq!(values(&plotter, "1"), [1.0]);
+        assert_eq!(values(&plotter, "3"), [-3.0]);
+        assert_eq!(values(&plotter, "ch0"), [512.0]);
+        assert_eq!(values(&plotter, "ch1"), [100.0]);
+    }
+
+    #[test]
+    fn capacity_and_errors() {
+        let mut plotter = with_patterns(&[PlotPattern::Positional]);
+        plotter.set_capacity(2);
+        plotter.push(b"1 10\n2 20\n3\n");
+        assert_eq!(values(&plotter, "1"), [2.0, 3.0]);
+        assert_eq!(values(&plotter, "2"), [10.0, 20.0]);
+
+        assert_eq!(
+            plotter
+                .set_patterns(&[PlotPattern::Regex("(".into())])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn csv_has_a_row_per_line() {
+        let mut plotter = with_patterns(&[PlotPattern::KeyValue]);
+        plotter.push(b"a=1 b=2\nb=3\na=4\n");
+
+        let mut csv = vec![];
+        write_csv(plotter.series(), &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows: Vec<Vec<&str>> = csv
+            .lines()
+            .map(|row| row.split(',').skip(1).collect())
+            .collect();
+        assert!(csv.starts_with("time,a,b\n"));
+        assert_eq!(rows[1..], [vec!["1", "2"], vec!["", "3"], vec!["4", ""]]);
+    }
+}
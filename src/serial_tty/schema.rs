@@ -0,0 +1,804 @@
+//! Binary packets decoded by a schema, the layouts of their C structs.
+//!
+//! Schemas are serde types, so they can be kept with the settings of an
+//! application, or loaded from a schema file at runtime:
+//!
+//! ```text
+//! # Comments start with #. Fields are little endian, unless `endian big`
+//! # comes first or their type ends with `be`.
+//! endian little
+//! # Offset and type of the field telling the packets apart, if any.
+//! tag 0 u8
+//!
+//! packet telemetry 0x01
+//! u8 id
+//! u16 sequence
+//! i16 temperature scale 0.01 unit °C
+//! f32be pressure unit hPa
+//! pad 2
+//! i16[3] accel scale 0.001 unit g
+//!
+//! packet status 0x02
+//! u8 id
+//! u32 flags
+//! ```
+//!
+//! Fields are read from the start of the frame, trailing bytes such as the
+//! check of the frame are ignored. Values are `raw * scale + offset`, 64 bit
+//! integers above 2^53 lose precision.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::serial_tty::framing::DecodedFrame;
+use crate::serial_tty::plot::csv_field;
+
+/// Longest time packets stay in the buffer of the log before being written.
+const LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum FieldType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+    /// Skipped bytes.
+    Padding,
+}
+
+impl FieldType {
+    pub fn size(self) -> usize {
+        match self {
+            FieldType::U8 | FieldType::I8 | FieldType::Padding => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => 4,
+            FieldType::U64 | FieldType::I64 | FieldType::F64 => 8,
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "u8" => FieldType::U8,
+            "i8" => FieldType::I8,
+            "u16" => FieldType::U16,
+            "i16" => FieldType::I16,
+            "u32" => FieldType::U32,
+            "i32" => FieldType::I32,
+            "u64" => FieldType::U64,
+            "i64" => FieldType::I64,
+            "f32" => FieldType::F32,
+            "f64" => FieldType::F64,
+            _ => return None,
+        })
+    }
+
+    /// Read the value at the start of `bytes`, which is long enough.
+    fn read(self, bytes: &[u8], big_endian: bool) -> f64 {
+        let mut buf = [0; 8];
+        let size = self.size();
+        buf[..size].copy_from_slice(&bytes[..size]);
+        if big_endian {
+            buf[..size].reverse();
+        }
+        match self {
+            FieldType::U8 | FieldType::Padding => buf[0] as f64,
+            FieldType::I8 => buf[0] as i8 as f64,
+            FieldType::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            FieldType::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            FieldType::U32 => {
+                u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64
+            },
+            FieldType::I32 => {
+                i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64
+            },
+            FieldType::F32 => {
+                f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64
+            },
+            FieldType::U64 => u64::from_le_bytes(buf) as f64,
+            FieldType::I64 => i64::from_le_bytes(buf) as f64,
+            FieldType::F64 => f64::from_le_bytes(buf),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SchemaField {
+    pub name: String,
+    pub kind: FieldType,
+    /// Elements of an array, named `name[0]`, `name[1]`... or bytes of
+    /// padding.
+    pub count: usize,
+    pub big_endian: bool,
+    pub scale: f64,
+    pub offset: f64,
+    pub unit: String,
+}
+
+impl SchemaField {
+    pub fn new(name: impl Into<String>, kind: FieldType) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            count: 1,
+            big_endian: false,
+            scale: 1.0,
+            offset: 0.0,
+            unit: String::new(),
+        }
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.kind.size().saturating_mul(self.count)
+    }
+
+    /// Names of the values of the field, none for padding.
+    fn value_names(&self) -> Vec<String> {
+        match (self.kind, self.count) {
+            (FieldType::Padding, _) => vec![],
+            (_, 1) => vec![self.name.clone()],
+            (_, count) => (0..count)
+                .map(|index| format!("{}[{}]", self.name, index))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PacketLayout {
+    pub name: String,
+    /// Value of the tag field selecting this layout, `None` matches the
+    /// packets no other layout matches.
+    pub tag: Option<u64>,
+    pub fields: Vec<SchemaField>,
+}
+
+impl PacketLayout {
+    pub fn size(&self) -> usize {
+        self.checked_size().unwrap_or(usize::MAX)
+    }
+
+    fn checked_size(&self) -> Option<usize> {
+        self.fields.iter().try_fold(0usize, |size, field| {
+            size.checked_add(field.kind.size().checked_mul(field.count)?)
+        })
+    }
+}
+
+/// Field telling the packets of a schema apart.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub struct SchemaTag {
+    pub offset: usize,
+    pub kind: FieldType,
+    pub big_endian: bool,
+}
+
+#[derive(
+    Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+pub struct PacketSchema {
+    pub tag: Option<SchemaTag>,
+    pub packets: Vec<PacketLayout>,
+}
+
+impl PacketSchema {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse a schema file, as described by the module documentation.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut schema = Self::default();
+        let mut big_endian = false;
+        // Line of the first packet with a tag, which needs a tag field.
+        let mut tagged_line = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some(&keyword) = words.first() else {
+                continue;
+            };
+            let error = |message: &str| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("line {}: {}", number + 1, message),
+                )
+            };
+
+            match (keyword, &words[1..]) {
+                ("endian", ["little"]) => big_endian = false,
+                ("endian", ["big"]) => big_endian = true,
+                ("tag", [offset, kind]) => {
+                    let (kind, _, tag_big_endian) =
+                        parse_type(kind, big_endian)
+                            .filter(|&(_, count, _)| count == 1)
+                            .ok_or_else(|| error("invalid tag type"))?;
+                    schema.tag = Some(SchemaTag {
+                        offset: offset
+                            .parse()
+                            .map_err(|_| error("invalid tag offset"))?,
+                        kind,
+                        big_endian: tag_big_endian,
+                    });
+                },
+                ("packet", [name, tag @ ..]) if tag.len() <= 1 => {
+                    let tag = tag
+                        .first()
+                        .map(|tag| {
+                            parse_integer(tag)
+                                .ok_or_else(|| error("invalid packet tag"))
+                        })
+                        .transpose()?;
+                    if tag.is_some() {
+                        tagged_line.get_or_insert(number + 1);
+                    }
+                    schema.packets.push(PacketLayout {
+                        name: name.to_string(),
+                        tag,
+                        fields: vec![],
+                    });
+                },
+                ("pad", [count]) => {
+                    let field = SchemaField {
+                        count: count
+                            .parse()
+                            .map_err(|_| error("invalid padding"))?,
+                        ..SchemaField::new("", FieldType::Padding)
+                    };
+                    let packet = schema
+                        .packets
+                        .last_mut()
+                        .ok_or_else(|| error("padding outside a packet"))?;
+                    packet.fields.push(field);
+                    if packet.checked_size().is_none() {
+                        return Err(error("packet too large"));
+                    }
+                },
+                (kind, [name, options @ ..]) => {
+                    let (kind, count, field_big_endian) =
+                        parse_type(kind, big_endian)
+                            .ok_or_else(|| error("unknown keyword or type"))?;
+                    let mut field = SchemaField {
+                        count,
+                        big_endian: field_big_endian,
+                        ..SchemaField::new(*name, kind)
+                    };
+                    for option in options.chunks(2) {
+                        match option {
+                            ["scale", scale] => {
+                                field.scale = scale
+                                    .parse()
+                                    .map_err(|_| error("invalid scale"))?
+                            },
+                            ["offset", offset] => {
+                                field.offset = offset
+                                    .parse()
+                                    .map_err(|_| error("invalid offset"))?
+                            },
+                            ["unit", unit] => field.unit = unit.to_string(),
+                            _ => return Err(error("invalid field option")),
+                        }
+                    }
+                    let packet = schema
+                        .packets
+                        .last_mut()
+                        .ok_or_else(|| error("field outside a packet"))?;
+                    packet.fields.push(field);
+                    if packet.checked_size().is_none() {
+                        return Err(error("packet too large"));
+                    }
+                },
+                _ => return Err(error("unknown keyword or type")),
+            }
+        }
+
+        if schema.packets.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "No packets"));
+        }
+        if let (Some(line), None) = (tagged_line, schema.tag) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("line {}: packet tag without a tag field", line),
+            ));
+        }
+        Ok(schema)
+    }
+
+    /// Names of the values of every packet, in order.
+    fn value_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        for packet in &self.packets {
+            for name in packet.fields.iter().flat_map(SchemaField::value_names)
+            {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    /// Layout matching the tag of `bytes`.
+    fn layout(&self, bytes: &[u8]) -> Option<&PacketLayout> {
+        let tag = self.tag.and_then(|tag| {
+            let bytes = bytes.get(tag.offset..)?;
+            (bytes.len() >= tag.kind.size())
+                .then(|| tag.kind.read(bytes, tag.big_endian) as u64)
+        });
+        self.packets
+            .iter()
+            .find(|packet| packet.tag.is_some() && packet.tag == tag)
+            .or_else(|| self.packets.iter().find(|packet| packet.tag.is_none()))
+    }
+
+    pub fn decode(&self, frame: &DecodedFrame) -> Result<DecodedPacket> {
+        let layout = self.layout(&frame.bytes).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, "No packet matches the tag")
+        })?;
+        if frame.bytes.len() < layout.size() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} bytes, {} needs {}",
+                    frame.bytes.len(),
+                    layout.name,
+                    layout.size()
+                ),
+            ));
+        }
+
+        let mut fields = vec![];
+        let mut position = 0;
+        for field in &layout.fields {
+            let names = field.value_names();
+            for index in 0..field.count {
+                let start = position + index * field.kind.size();
+                if let Some(name) = names.get(index) {
+                    let raw = field
+                        .kind
+                        .read(&frame.bytes[start..], field.big_endian);
+                    fields.push(DecodedField {
+                        name: name.clone(),
+                        value: raw * field.scale + field.offset,
+                        unit: field.unit.clone(),
+                    });
+                }
+            }
+            position += field.size();
+        }
+
+        Ok(DecodedPacket {
+            packet: layout.name.clone(),
+            frame: frame.index,
+            time: frame.time,
+            fields,
+        })
+    }
+}
+
+/// `u16`, `i16be`, `f32le[4]`... with its element count and byte order.
+fn parse_type(
+    name: &str,
+    big_endian: bool,
+) -> Option<(FieldType, usize, bool)> {
+    let (name, count) = match name.split_once('[') {
+        Some((name, count)) => {
+            let count = count.strip_suffix(']')?.parse().ok()?;
+            (name, count)
+        },
+        None => (name, 1),
+    };
+    let (name, big_endian) = if let Some(name) = name.strip_suffix("be") {
+        (name, true)
+    } else if let Some(name) = name.strip_suffix("le") {
+        (name, false)
+    } else {
+        (name, big_endian)
+    };
+    Some((FieldType::parse(name)?, count, big_endian)).filter(|_| count > 0)
+}
+
+fn parse_integer(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedField {
+    pub name: String,
+    /// Scaled value.
+    pub value: f64,
+    pub unit: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedPacket {
+    /// Name of the packet layout.
+    pub packet: String,
+    /// Index of the frame it was decoded from.
+    pub frame: u64,
+    pub time: SystemTime,
+    pub fields: Vec<DecodedField>,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum SchemaLogFormat {
+    /// A column per value of any packet, empty when the packet of the row
+    /// doesn't have it.
+    #[default]
+    Csv,
+    /// A JSON object per line.
+    JsonLines,
+}
+
+/// Decodes the frames of a `FrameLog` with a schema, keeping the last
+/// packet of every layout and logging them all.
+pub(crate) struct SchemaDecoder {
+    schema: PacketSchema,
+    /// Columns of the CSV log.
+    names: Vec<String>,
+    /// Last packet of every layout, in the order of the schema.
+    latest: Vec<Option<DecodedPacket>>,
+    log: Option<SchemaLog>,
+}
+
+struct SchemaLog {
+    writer: BufWriter<File>,
+    format: SchemaLogFormat,
+    flushed_at: Instant,
+}
+
+impl SchemaDecoder {
+    pub(crate) fn new(schema: PacketSchema) -> Self {
+        Self {
+            latest: vec![None; schema.packets.len()],
+            names: schema.value_names(),
+            schema,
+            log: None,
+        }
+    }
+
+    /// Log the packets decoded from now on to `path`, replacing it.
+    pub(crate) fn start_log(
+        &mut self,
+        path: &Path,
+        format: SchemaLogFormat,
+    ) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        if format == SchemaLogFormat::Csv {
+            write!(writer, "time,frame,packet")?;
+            for name in &self.names {
+                write!(writer, ",{}", csv_field(name))?;
+            }
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+        self.log = Some(SchemaLog {
+            writer,
+            format,
+            flushed_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    pub(crate) fn stop_log(&mut self) {
+        if let Some(mut log) = self.log.take() {
+            if let Err(err) = log.writer.flush() {
+                log::error!("Failed to write the packet log: {}", err);
+            }
+        }
+    }
+
+    pub(crate) fn push(&mut self, frame: &DecodedFrame) {
+        let packet = match self.schema.decode(frame) {
+            Ok(packet) => packet,
+            Err(err) => {
+                log::debug!("Frame {} not decoded: {}", frame.index, err);
+                return;
+            },
+        };
+
+        if let Some(log) = &mut self.log {
+            if let Err(err) = log.write(&packet, &self.names) {
+                log::error!("Failed to write the packet log: {}", err);
+                self.log = None;
+            }
+        }
+
+        if let Some(index) = self
+            .schema
+            .packets
+            .iter()
+            .position(|layout| layout.name == packet.packet)
+        {
+            self.latest[index] = Some(packet);
+        }
+    }
+
+    /// Last packet of every layout received so far.
+    pub(crate) fn latest(&self) -> impl Iterator<Item = &DecodedPacket> {
+        self.latest.iter().flatten()
+    }
+}
+
+impl SchemaLog {
+    /// Flushed now and then rather than for every packet, as packets are
+    /// logged while the frames are locked.
+    fn write(
+        &mut self,
+        packet: &DecodedPacket,
+        names: &[String],
+    ) -> Result<()> {
+        let line = match self.format {
+            SchemaLogFormat::Csv => csv_row(packet, names),
+            SchemaLogFormat::JsonLines => json_line(packet),
+        };
+        self.writer.write_all(line.as_bytes())?;
+        if self.flushed_at.elapsed() >= LOG_FLUSH_INTERVAL {
+            self.writer.flush()?;
+            self.flushed_at = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SchemaDecoder {
+    fn drop(&mut self) {
+        self.stop_log();
+    }
+}
+
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+fn csv_row(packet: &DecodedPacket, names: &[String]) -> String {
+    let mut row = format!(
+        "{:.6},{},{}",
+        seconds(packet.time),
+        packet.frame,
+        csv_field(&packet.packet)
+    );
+    for name in names {
+        row.push(',');
+        if let Some(field) = packet.fields.iter().find(|f| &f.name == name) {
+            let _ = write!(row, "{}", field.value);
+        }
+    }
+    row.push('\n');
+    row
+}
+
+fn json_line(packet: &DecodedPacket) -> String {
+    let mut line = format!(
+        "{{\"time\":{:.6},\"frame\":{},\"packet\":{},\"fields\":{{",
+        seconds(packet.time),
+        packet.frame,
+        json_string(&packet.packet)
+    );
+    for (index, field) in packet.fields.iter().enumerate() {
+        if index > 0 {
+            line.push(',');
+        }
+        line.push_str(&json_string(&field.name));
+        line.push(':');
+        if field.value.is_finite() {
+            let _ = write!(line, "{}", field.value);
+        } else {
+            line.push_str("null");
+        }
+    }
+    line.push_str("}}\n");
+    line
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            },
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serial_tty::framing::FrameCheckResult;
+    use crate::test_utils::temp_path;
+
+    const SCHEMA: &str = "
+        # Test packets
+        tag 0 u8
+
+        packet telemetry 0x01
+        u8 id
+        u16 sequence
+        i16 temperature scale 0.01 unit °C
+        f32be pressure unit hPa   # sensor order
+        pad 1
+        i8[2] tilt
+
+        packet other
+        u8 id
+        u16be word
+    ";
+
+    fn frame(index: u64, bytes: &[u8]) -> DecodedFrame {
+        DecodedFrame {
+            index,
+            time: SystemTime::UNIX_EPOCH,
+            bytes: bytes.to_vec(),
+            check: FrameCheckResult::Unchecked,
+            error: None,
+        }
+    }
+
+    fn telemetry() -> Vec<u8> {
+        let mut bytes = vec![0x01, 0x34, 0x12];
+        bytes.extend((-1234i16).to_le_bytes());
+        bytes.extend(1013.25f32.to_be_bytes());
+        bytes.extend([0xee, 0xff, 0x02, 0xaa, 0xbb]);
+        bytes
+    }
+
+    #[test]
+    fn parse_and_decode() {
+        let schema = PacketSchema::parse(SCHEMA).unwrap();
+        assert_eq!(schema.packets.len(), 2);
+        assert_eq!(schema.packets[0].size(), 12);
+        assert_eq!(schema.packets[1].tag, None);
+
+        let packet = schema.decode(&frame(7, &telemetry())).unwrap();
+        assert_eq!(packet.packet, "telemetry");
+        assert_eq!(packet.frame, 7);
+        let values: Vec<(&str, f64)> = packet
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.value))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("id", 1.0),
+                ("sequence", 0x1234 as f64),
+                ("temperature", -12.34),
+                ("pressure", 1013.25),
+                ("tilt[0]", -1.0),
+                ("tilt[1]", 2.0),
+            ]
+        );
+        assert_eq!(packet.fields[2].unit, "°C");
+
+        let packet = schema.decode(&frame(8, &[0x09, 0x12, 0x34])).unwrap();
+        assert_eq!(packet.packet, "other");
+        assert_eq!(packet.fields[1].value, 0x1234 as f64);
+        assert!(schema.decode(&frame(9, &telemetry()[..11])).is_err());
+    }
+
+    #[test]
+    fn parse_errors_have_line_numbers() {
+        let err = PacketSchema::parse("packet a\nu24 x\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "line 2: unknown keyword or type");
+        assert!(PacketSchema::parse("u8 x\n").is_err());
+        assert!(PacketSchema::parse("packet a\nu8 x scale\n").is_err());
+        assert!(PacketSchema::parse("# nothing\n").is_err());
+
+        let err = PacketSchema::parse("packet a 1\nu8 x\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: packet tag without a tag field");
+        let huge = format!("packet a\nu32[{}] x\n", usize::MAX / 2);
+        let err = PacketSchema::parse(&huge).unwrap_err();
+        assert_eq!(err.to_string(), "line 2: packet too large");
+        let huge = format!("packet a\npad {}\npad {}\n", usize::MAX, 1);
+        let err = PacketSchema::parse(&huge).unwrap_err();
+        assert_eq!(err.to_string(), "line 3: packet too large");
+    }
+
+    #[test]
+    fn decoder_keeps_latest_and_logs() {
+        let schema = PacketSchema::parse(SCHEMA).unwrap();
+        let csv = temp_path("schema.csv");
+        let json = temp_path("schema.jsonl");
+
+        let mut decoder = SchemaDecoder::new(schema.clone());
+        decoder.start_log(&csv, SchemaLogFormat::Csv).unwrap();
+        let mut json_decoder = SchemaDecoder::new(schema);
+        json_decoder
+            .start_log(&json, SchemaLogFormat::JsonLines)
+            .unwrap();
+        for decoder in [&mut decoder, &mut json_decoder] {
+            decoder.push(&frame(1, &[0x02, 0, 1]));
+            decoder.push(&frame(2, &telemetry()));
+            decoder.push(&frame(3, &[0x01]));
+        }
+        assert_eq!(
+            decoder
+                .latest()
+                .map(|packet| packet.frame)
+                .collect::<Vec<_>>(),
+            [2, 1]
+        );
+        drop(decoder);
+        drop(json_decoder);
+
+        let csv_text = std::fs::read_to_string(&csv).unwrap();
+        let rows: Vec<&str> = csv_text.lines().collect();
+        assert_eq!(
+            rows,
+            [
+                "time,frame,packet,id,sequence,temperature,pressure,tilt[0],tilt[1],word",
+                "0.000000,1,other,2,,,,,,1",
+                "0.000000,2,telemetry,1,4660,-12.34,1013.25,-1,2,",
+            ]
+        );
+        let json_text = std::fs::read_to_string(&json).unwrap();
+        assert_eq!(
+            json_text.lines().next(),
+            Some(
+                "{\"time\":0.000000,\"frame\":1,\"packet\":\"other\",\"fields\":{\"id\":2,\"word\":1}}"
+            )
+        );
+        std::fs::remove_file(csv).unwrap();
+        std::fs::remove_file(json).unwrap();
+    }
+
+    #[test]
+    fn csv_log_names_are_quoted() {
+        let schema = PacketSchema::parse("packet a,b\nu8 x\"y\n").unwrap();
+        let csv = temp_path("schema-quoted.csv");
+
+        let mut decoder = SchemaDecoder::new(schema);
+        decoder.start_log(&csv, SchemaLogFormat::Csv).unwrap();
+        decoder.push(&frame(1, &[5]));
+        decoder.stop_log();
+
+        let csv_text = std::fs::read_to_string(&csv).unwrap();
+        let rows: Vec<&str> = csv_text.lines().collect();
+        assert_eq!(
+            rows,
+            ["time,frame,packet,\"x\"\"y\"", "0.000000,1,\"a,b\",5"]
+        );
+        std::fs::remove_file(csv).unwrap();
+    }
+}
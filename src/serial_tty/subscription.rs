@@ -0,0 +1,160 @@
+//! Copy of the received text for a consumer on another thread.
+//!
+//! Unlike a channel, the queue is bounded: when the consumer doesn't keep
+//! up, the oldest bytes are dropped, so a forgotten subscription can't make
+//! the memory grow for the whole session.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+struct Queue {
+    bytes: VecDeque<u8>,
+    /// Whether the sender is gone.
+    closed: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Queue keeping up to `capacity` bytes.
+pub(crate) fn subscription(
+    capacity: usize,
+) -> (SubscriptionSender, SubscriptionReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue::default()),
+        ready: Condvar::new(),
+        capacity,
+    });
+    (
+        SubscriptionSender(shared.clone()),
+        SubscriptionReceiver(shared),
+    )
+}
+
+#[derive(Debug)]
+pub(crate) struct SubscriptionSender(Arc<Shared>);
+
+impl SubscriptionSender {
+    /// Queue `bytes`, returns `false` once the receiver hung up.
+    pub(crate) fn send(&self, bytes: &[u8]) -> bool {
+        if Arc::strong_count(&self.0) == 1 {
+            return false;
+        }
+
+        let mut queue = self.0.lock();
+        queue.bytes.extend(bytes);
+        let overflow = queue.bytes.len().saturating_sub(self.0.capacity);
+        queue.bytes.drain(..overflow);
+        self.0.ready.notify_one();
+        true
+    }
+}
+
+impl Drop for SubscriptionSender {
+    fn drop(&mut self) {
+        self.0.lock().closed = true;
+        self.0.ready.notify_one();
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct SubscriptionReceiver(Arc<Shared>);
+
+impl SubscriptionReceiver {
+    /// All the queued bytes, waiting up to `timeout` for some.
+    pub(crate) fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.0.lock();
+        loop {
+            if !queue.bytes.is_empty() {
+                return Ok(queue.bytes.drain(..).collect());
+            }
+            if queue.closed {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            queue = self
+                .0
+                .ready
+                .wait_timeout(queue, deadline - now)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+    }
+
+    /// All the queued bytes, without waiting.
+    pub(crate) fn try_recv(&self) -> Result<Vec<u8>, TryRecvError> {
+        let mut queue = self.0.lock();
+        match (queue.bytes.is_empty(), queue.closed) {
+            (false, _) => Ok(queue.bytes.drain(..).collect()),
+            (true, false) => Err(TryRecvError::Empty),
+            (true, true) => Err(TryRecvError::Disconnected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_bytes_are_dropped() {
+        let (sender, receiver) = subscription(4);
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        assert!(sender.send(b"abc"));
+        assert!(sender.send(b"def"));
+        assert_eq!(
+            receiver.recv_timeout(Duration::ZERO).as_deref(),
+            Ok(&b"cdef"[..])
+        );
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+
+        drop(sender);
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(1)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn sender_sees_the_receiver_hang_up() {
+        let (sender, receiver) = subscription(4);
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            assert!(sender.send(b"a"));
+        });
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)).as_deref(),
+            Ok(&b"a"[..])
+        );
+
+        let (sender, receiver) = subscription(4);
+        drop(receiver);
+        assert!(!sender.send(b"a"));
+    }
+}
@@ -0,0 +1,302 @@
+//! Raw traffic tap shared between the event loop and the backend.
+//!
+//! Every byte read from or written to the serial TTY is recorded here before
+//! the terminal parser sees it, so alternative views (hex dump, decoders) can
+//! work on the exact wire data.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+use alacritty_terminal::sync::FairMutex;
+
+use crate::serial_tty::defmt::DefmtDecoder;
+use crate::serial_tty::framing::FrameLog;
+use crate::serial_tty::nmea::NmeaDecoder;
+use crate::serial_tty::plot::Plotter;
+
+/// Default amount of traffic bytes kept in memory.
+pub const DEFAULT_TRAFFIC_CAPACITY: usize = 4 * 1024 * 1024;
+
+/// Chunks are merged until they reach this size.
+const MAX_CHUNK_SIZE: usize = 4096;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum TrafficDirection {
+    /// Bytes received from the device.
+    Rx,
+    /// Bytes sent to the device.
+    Tx,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrafficChunk {
+    pub direction: TrafficDirection,
+    /// Offset of the first byte since the session was opened.
+    pub offset: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl TrafficChunk {
+    #[inline]
+    pub fn end(&self) -> u64 {
+        self.offset + self.bytes.len() as u64
+    }
+}
+
+/// Bounded log of the raw serial traffic.
+#[derive(Debug)]
+pub struct TrafficLog {
+    chunks: VecDeque<TrafficChunk>,
+    start: u64,
+    end: u64,
+    capacity: usize,
+    /// Nothing is recorded while paused.
+    paused: bool,
+}
+
+impl Default for TrafficLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_TRAFFIC_CAPACITY)
+    }
+}
+
+impl TrafficLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            start: 0,
+            end: 0,
+            capacity: capacity.max(1),
+            paused: false,
+        }
+    }
+
+    /// Offset of the oldest byte still kept in the log.
+    #[inline]
+    pub fn start_offset(&self) -> u64 {
+        self.start
+    }
+
+    /// Offset right after the newest byte.
+    #[inline]
+    pub fn end_offset(&self) -> u64 {
+        self.end
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.evict();
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn push(&mut self, direction: TrafficDirection, bytes: &[u8]) {
+        if bytes.is_empty() || self.paused {
+            return;
+        }
+
+        match self.chunks.back_mut() {
+            Some(last)
+                if last.direction == direction
+                    && last.bytes.len() < MAX_CHUNK_SIZE =>
+            {
+                last.bytes.extend_from_slice(bytes);
+            },
+            _ => self.chunks.push_back(TrafficChunk {
+                direction,
+                offset: self.end,
+                bytes: bytes.to_vec(),
+            }),
+        }
+
+        self.end += bytes.len() as u64;
+        self.evict();
+    }
+
+    /// Copy out the bytes in `[start, end)` that are still kept in the log.
+    pub fn range(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> Vec<(u64, u8, TrafficDirection)> {
+        let start = start.max(self.start);
+        let end = end.min(self.end);
+        let mut result = Vec::with_capacity(end.saturating_sub(start) as usize);
+        if start >= end {
+            return result;
+        }
+
+        for chunk in self.chunks.range(self.chunk_index(start)..) {
+            if chunk.offset >= end {
+                break;
+            }
+
+            let from = start.max(chunk.offset);
+            let to = end.min(chunk.end());
+            for offset in from..to {
+                let byte = chunk.bytes[(offset - chunk.offset) as usize];
+                result.push((offset, byte, chunk.direction));
+            }
+        }
+
+        result
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.start = self.end;
+    }
+
+    fn chunk_index(&self, offset: u64) -> usize {
+        self.chunks
+            .partition_point(|chunk| chunk.end() <= offset)
+            .min(self.chunks.len().saturating_sub(1))
+    }
+
+    fn evict(&mut self) {
+        while (self.end - self.start) as usize > self.capacity {
+            let overflow = (self.end - self.start) as usize - self.capacity;
+            let Some(first) = self.chunks.front_mut() else {
+                break;
+            };
+
+            if first.bytes.len() <= overflow {
+                self.start = first.end();
+                self.chunks.pop_front();
+            } else {
+                first.bytes.drain(..overflow);
+                first.offset += overflow as u64;
+                self.start = first.offset;
+            }
+        }
+    }
+}
+
+/// Cheap to clone handle to the traffic log and the decoders of the received
+/// bytes of one session.
+#[derive(Clone)]
+pub struct SerialTap {
+    log: Arc<FairMutex<TrafficLog>>,
+    frames: Arc<FairMutex<FrameLog>>,
+    defmt: Arc<FairMutex<DefmtDecoder>>,
+    nmea: Arc<FairMutex<NmeaDecoder>>,
+    plot: Arc<FairMutex<Plotter>>,
+    /// Receivers of the raw RX bytes, until they hang up.
+    subscribers: Arc<FairMutex<Vec<Sender<Vec<u8>>>>>,
+}
+
+impl SerialTap {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            log: Arc::new(FairMutex::new(TrafficLog::new(capacity))),
+            frames: Arc::new(FairMutex::new(FrameLog::default())),
+            defmt: Arc::new(FairMutex::new(DefmtDecoder::default())),
+            nmea: Arc::new(FairMutex::new(NmeaDecoder::default())),
+            plot: Arc::new(FairMutex::new(Plotter::default())),
+            subscribers: Arc::new(FairMutex::new(vec![])),
+        }
+    }
+
+    #[inline]
+    pub fn record(&self, direction: TrafficDirection, bytes: &[u8]) {
+        self.log.lock().push(direction, bytes);
+        if direction == TrafficDirection::Rx {
+            self.defmt.lock().push(bytes);
+            self.decode(bytes);
+        }
+    }
+
+    /// Like `record` for received bytes which belong to a file transfer,
+    /// so they aren't mistaken for `defmt` frames.
+    pub(crate) fn record_transfer(&self, bytes: &[u8]) {
+        self.log.lock().push(TrafficDirection::Rx, bytes);
+        self.decode(bytes);
+    }
+
+    fn decode(&self, bytes: &[u8]) {
+        self.frames.lock().push(bytes);
+        self.nmea.lock().push(bytes);
+        self.plot.lock().push(bytes);
+        self.subscribers
+            .lock()
+            .retain(|subscriber| subscriber.send(bytes.to_vec()).is_ok());
+    }
+
+    /// Get a copy of the raw RX bytes, even while the log is paused.
+    pub(crate) fn subscribe(&self) -> Receiver<Vec<u8>> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().push(sender);
+        receiver
+    }
+
+    pub fn log(&self) -> &Arc<FairMutex<TrafficLog>> {
+        &self.log
+    }
+
+    pub fn frames(&self) -> &Arc<FairMutex<FrameLog>> {
+        &self.frames
+    }
+
+    pub(crate) fn defmt(&self) -> &Arc<FairMutex<DefmtDecoder>> {
+        &self.defmt
+    }
+
+    pub(crate) fn nmea(&self) -> &Arc<FairMutex<NmeaDecoder>> {
+        &self.nmea
+    }
+
+    pub(crate) fn plot(&self) -> &Arc<FairMutex<Plotter>> {
+        &self.plot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_and_split_by_direction() {
+        let mut log = TrafficLog::new(1024);
+        log.push(TrafficDirection::Rx, b"abc");
+        log.push(TrafficDirection::Rx, b"de");
+        log.push(TrafficDirection::Tx, b"\r");
+        assert_eq!(log.chunks.len(), 2);
+        assert_eq!(log.end_offset(), 6);
+        assert_eq!(
+            log.range(4, 7),
+            vec![
+                (4, b'e', TrafficDirection::Rx),
+                (5, b'\r', TrafficDirection::Tx)
+            ]
+        );
+    }
+
+    #[test]
+    fn evict_keeps_offsets() {
+        let mut log = TrafficLog::new(4);
+        log.push(TrafficDirection::Rx, b"012");
+        log.push(TrafficDirection::Tx, b"345");
+        assert_eq!(log.start_offset(), 2);
+        let bytes: Vec<u8> =
+            log.range(0, 10).into_iter().map(|(_, b, _)| b).collect();
+        assert_eq!(bytes, b"2345");
+        assert_eq!(log.range(3, 5)[0], (3, b'3', TrafficDirection::Tx));
+    }
+}
@@ -0,0 +1,259 @@
+//! Actions fired by received lines matching a pattern.
+//!
+//! Triggers run on the event loop thread, right where the received bytes
+//! are parsed, so they react without waiting for the UI to be repainted.
+
+use std::io;
+use std::sync::mpsc::Sender;
+
+use regex::Regex;
+
+use crate::serial_tty::escape::TextFilter;
+
+/// Longest line kept for matching, longer lines are matched in pieces.
+const MAX_LINE_LENGTH: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TriggerAction {
+    /// Write the bytes to the device, as they are.
+    Send(Vec<u8>),
+    /// Report a `TriggerEvent` to the application.
+    Event,
+    /// Ring the terminal bell, like a received BEL.
+    Bell,
+    /// Stop recording the traffic log until it's resumed.
+    PauseLogging,
+    /// Bookmark the matching line.
+    Bookmark,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Trigger {
+    pub name: String,
+    pub pattern: String,
+    pub action: TriggerAction,
+    /// Also match the line while it's received, for prompts which don't end
+    /// with a newline. Other triggers only match completed lines.
+    #[serde(default)]
+    pub prompt: bool,
+}
+
+impl Trigger {
+    pub fn new(
+        name: impl Into<String>,
+        pattern: impl Into<String>,
+        action: TriggerAction,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            pattern: pattern.into(),
+            action,
+            prompt: false,
+        }
+    }
+
+    #[inline]
+    pub fn set_prompt(mut self, prompt: bool) -> Self {
+        self.prompt = prompt;
+        self
+    }
+}
+
+/// Reported to the application by `TriggerAction::Event`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggerEvent {
+    pub trigger: String,
+    pub line: String,
+}
+
+/// A trigger which matched, to be run by the event loop.
+#[derive(Debug, Clone)]
+pub(crate) struct FiredTrigger {
+    pub(crate) name: String,
+    pub(crate) action: TriggerAction,
+    pub(crate) line: String,
+}
+
+#[derive(Debug)]
+struct CompiledTrigger {
+    name: String,
+    regex: Regex,
+    action: TriggerAction,
+    prompt: bool,
+}
+
+/// Triggers of a session, sent to the event loop.
+#[derive(Debug)]
+pub struct TriggerSet {
+    triggers: Vec<CompiledTrigger>,
+    pub(crate) events: Sender<TriggerEvent>,
+}
+
+impl TriggerSet {
+    pub(crate) fn new(
+        triggers: &[Trigger],
+        events: Sender<TriggerEvent>,
+    ) -> io::Result<Self> {
+        let triggers = triggers
+            .iter()
+            .map(|trigger| {
+                Ok(CompiledTrigger {
+                    name: trigger.name.clone(),
+                    regex: Regex::new(&trigger.pattern).map_err(|err| {
+                        io::Error::new(io::ErrorKind::InvalidInput, err)
+                    })?,
+                    action: trigger.action.clone(),
+                    prompt: trigger.prompt,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self { triggers, events })
+    }
+}
+
+/// Assembles the printable text of received lines and matches it.
+#[derive(Debug)]
+pub(crate) struct TriggerEngine {
+    set: TriggerSet,
+    line: Vec<u8>,
+    text: TextFilter,
+    /// Triggers which already fired on the current line.
+    fired: Vec<bool>,
+}
+
+impl TriggerEngine {
+    pub(crate) fn new(set: TriggerSet) -> Self {
+        let fired = vec![false; set.triggers.len()];
+        Self {
+            set,
+            line: vec![],
+            text: TextFilter::default(),
+            fired,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn events(&self) -> &Sender<TriggerEvent> {
+        &self.set.events
+    }
+
+    /// Feed a received byte, a newline matches the completed line.
+    pub(crate) fn push(&mut self, byte: u8, fired: &mut Vec<FiredTrigger>) {
+        if !self.text.is_text(byte) {
+            return;
+        }
+
+        match byte {
+            b'\n' => {
+                self.match_line(fired, false);
+                self.line.clear();
+                self.fired.fill(false);
+            },
+            0x08 => {
+                self.line.pop();
+            },
+            0x00..=0x1f | 0x7f => {},
+            _ => {
+                if self.line.len() >= MAX_LINE_LENGTH {
+                    self.match_line(fired, false);
+                    self.line.clear();
+                }
+                self.line.push(byte);
+            },
+        }
+    }
+
+    /// Match the line received so far against the prompt triggers, as
+    /// prompts don't end with a newline.
+    #[inline]
+    pub(crate) fn match_prompt(&mut self, fired: &mut Vec<FiredTrigger>) {
+        self.match_line(fired, true);
+    }
+
+    /// Every trigger fires at most once per line.
+    fn match_line(&mut self, fired: &mut Vec<FiredTrigger>, prompts: bool) {
+        let line = String::from_utf8_lossy(&self.line);
+        for (trigger, has_fired) in
+            self.set.triggers.iter().zip(self.fired.iter_mut())
+        {
+            if *has_fired || (prompts && !trigger.prompt) {
+                continue;
+            }
+            if trigger.regex.is_match(&line) {
+                *has_fired = true;
+                fired.push(FiredTrigger {
+                    name: trigger.name.clone(),
+                    action: trigger.action.clone(),
+                    line: line.to_string(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine(triggers: &[Trigger]) -> TriggerEngine {
+        let (events, _) = std::sync::mpsc::channel();
+        TriggerEngine::new(TriggerSet::new(triggers, events).unwrap())
+    }
+
+    fn feed(engine: &mut TriggerEngine, input: &[u8]) -> Vec<FiredTrigger> {
+        let mut fired = vec![];
+        for &byte in input {
+            engine.push(byte, &mut fired);
+        }
+        engine.match_prompt(&mut fired);
+        fired
+    }
+
+    #[test]
+    fn partial_line_fires_once() {
+        let mut engine = engine(&[Trigger::new(
+            "autoboot",
+            "^Hit any key to stop autoboot",
+            TriggerAction::Send(b" ".to_vec()),
+        )
+        .set_prompt(true)]);
+
+        let fired = feed(&mut engine, b"\x1b[1mHit any key\x1b[0m to stop");
+        assert!(fired.is_empty());
+        let fired = feed(&mut engine, b" autoboot:  3");
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].line, "Hit any key to stop autoboot:  3");
+        assert!(feed(&mut engine, b"\x08\x08\x08 2").is_empty());
+        let fired = feed(&mut engine, b"\r\nHit any key to stop autoboot");
+        assert_eq!(fired.len(), 1);
+    }
+
+    #[test]
+    fn lines_are_matched_once_completed() {
+        let mut engine = engine(&[
+            Trigger::new("ok", "^OK$", TriggerAction::Event),
+            Trigger::new("temp", r"temp=\d+", TriggerAction::Event),
+        ]);
+
+        assert!(feed(&mut engine, b"OK").is_empty());
+        assert!(feed(&mut engine, b"AY\r\ntemp=2").is_empty());
+        let fired = feed(&mut engine, b"5\r\nOK\r\n");
+        assert_eq!(fired.len(), 2);
+        assert_eq!(fired[0].name, "temp");
+        assert_eq!(fired[0].line, "temp=25");
+        assert_eq!(fired[1].name, "ok");
+        assert_eq!(fired[1].line, "OK");
+    }
+
+    #[test]
+    fn strings_and_controls_are_skipped() {
+        let mut engine =
+            engine(&[Trigger::new("panic", "panic!", TriggerAction::Bell)]);
+        let fired = feed(&mut engine, b"\x1b]0;panic!\x07ok\r\n");
+        assert!(fired.is_empty());
+        let fired = feed(&mut engine, b"pa\x1b]8;;\x1b\\nic!\r\n");
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].line, "panic!");
+    }
+}
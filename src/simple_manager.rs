@@ -1,4 +1,7 @@
-use crate::{SerialMonitorBackend, SerialTtyOptions, TtyEvent};
+use crate::{
+    BackendSettings, DisplayMode, InputMode, SerialMonitorBackend,
+    SerialTtyOptions, TtyEvent, TxNewline,
+};
 use egui::{Color32, RichText};
 use mio_serial::{DataBits, FlowControl, Parity, StopBits};
 use std::sync::mpsc::Sender;
@@ -18,6 +21,8 @@ macro_rules! selectable_add {
 pub struct SimpleSerialMonitorManager {
     tty_list: Vec<String>,
     tty_conn: SerialTtyOptions,
+    tx_newline: TxNewline,
+    backend_settings: BackendSettings,
     last_failed: Option<std::time::Instant>,
 }
 
@@ -38,10 +43,17 @@ impl SimpleSerialMonitorManager {
         Self {
             tty_list,
             tty_conn,
+            tx_newline: TxNewline::default(),
+            backend_settings: BackendSettings::default(),
             last_failed: None,
         }
     }
 
+    /// Terminal settings of the backends opened from now on.
+    pub fn set_backend_settings(&mut self, settings: BackendSettings) {
+        self.backend_settings = settings;
+    }
+
     /// Add bar style UI to open connection or close.
     pub fn add_bar_style(
         &mut self,
@@ -153,6 +165,31 @@ impl SimpleSerialMonitorManager {
                 );
             });
 
+        ui.add_space(5.0);
+
+        ui.label("TX Newline");
+        egui::ComboBox::from_id_salt("txnewline")
+            .selected_text(self.tx_newline.to_string())
+            .width(30.0)
+            .show_ui(ui, |ui| {
+                selectable_add!(
+                    ui,
+                    self.tx_newline,
+                    [
+                        TxNewline::PassThrough,
+                        TxNewline::Cr,
+                        TxNewline::Lf,
+                        TxNewline::CrLf
+                    ]
+                );
+            });
+
+        if let Some(backend) = serial_monitor_backend {
+            if backend.tx_newline() != self.tx_newline {
+                backend.set_tx_newline(self.tx_newline);
+            }
+        }
+
         ui.add_space(15.0);
 
         if ui.button("Refresh List").clicked() {
@@ -178,18 +215,45 @@ impl SimpleSerialMonitorManager {
                     backend.write(&[0x03]);
                 }
             }
+
+            if let Some(backend) = serial_monitor_backend {
+                let mut is_hex = backend.display_mode() == DisplayMode::Hex;
+                if ui.checkbox(&mut is_hex, "HEX").changed() {
+                    backend.set_display_mode(if is_hex {
+                        DisplayMode::Hex
+                    } else {
+                        DisplayMode::Text
+                    });
+                }
+
+                let mut local_echo = backend.local_echo();
+                if ui.checkbox(&mut local_echo, "Echo").changed() {
+                    backend.set_local_echo(local_echo);
+                }
+
+                let mut is_line = backend.input_mode() == InputMode::Line;
+                if ui.checkbox(&mut is_line, "Line").changed() {
+                    backend.set_input_mode(if is_line {
+                        InputMode::Line
+                    } else {
+                        InputMode::Raw
+                    });
+                }
+            }
         } else if ui
             .button(RichText::new("OPEN ").color(Color32::GREEN))
             .clicked()
         {
-            let new_backend = SerialMonitorBackend::new(
+            let new_backend = SerialMonitorBackend::with_settings(
                 0,
                 ctx.clone(),
                 tty_proxy_sender.clone(),
                 self.tty_conn.clone(),
+                self.backend_settings.clone(),
             );
 
-            if let Ok(backend) = new_backend {
+            if let Ok(mut backend) = new_backend {
+                backend.set_tx_newline(self.tx_newline);
                 *serial_monitor_backend = Some(backend);
                 self.last_failed = None;
             } else {
@@ -0,0 +1,16 @@
+//! Helpers shared by the tests of several modules.
+
+use std::path::PathBuf;
+
+/// Path in the temporary directory, unique to the test process, with
+/// whatever an earlier run left there removed.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "egui-serial-term-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&path);
+    let _ = std::fs::remove_file(&path);
+    path
+}
@@ -205,7 +205,7 @@ impl TerminalTheme {
     }
 }
 
-fn hex_to_color(hex: &str) -> anyhow::Result<Color32> {
+pub(crate) fn hex_to_color(hex: &str) -> anyhow::Result<Color32> {
     if hex.len() != 7 {
         return Err(anyhow::format_err!("input string is in non valid format"));
     }
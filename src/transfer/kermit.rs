@@ -0,0 +1,1218 @@
+//! Kermit file transfers, with long packets, sliding windows and the file
+//! size attribute when the peer supports them.
+//!
+//! Packets are printable text between a SOH mark and a CR, so they survive
+//! lines which aren't 8-bit clean. Control bytes are prefixed with `#`, and
+//! the high bit with `&` when the peer asks for it.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use super::{
+    create_received, SendFile, Transfer, TransferEvent, TransferIo,
+    TransferProgress,
+};
+
+const MARK: u8 = 0x01;
+const EOL: u8 = b'\r';
+const QCTL: u8 = b'#';
+const REPT: u8 = b'~';
+
+/// Capabilities of the Send-Init parameters.
+const LONG_PACKETS: u8 = 2;
+const SLIDING_WINDOWS: u8 = 4;
+const ATTRIBUTES: u8 = 8;
+
+/// Longest packet of the basic protocol, from LEN to the check.
+const MAX_SHORT_LEN: usize = 94;
+/// Longest long packet accepted.
+const MAX_LONG_LEN: usize = 4096;
+/// Packets sent before waiting for an acknowledgment.
+const WINDOW_SIZE: usize = 8;
+/// How long the sender waits for an acknowledgment.
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the receiver waits for the next packet.
+const PACKET_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_RETRIES: u32 = 10;
+
+#[inline]
+fn tochar(value: usize) -> u8 {
+    (value as u8).wrapping_add(32)
+}
+
+#[inline]
+fn unchar(char: u8) -> usize {
+    char.wrapping_sub(32) as usize
+}
+
+#[inline]
+fn next_seq(seq: u8) -> u8 {
+    (seq + 1) & 63
+}
+
+/// CRC-16 of Kermit, reflected CCITT polynomial with a zero initial value.
+pub(crate) fn crc16_kermit(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u16, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Block check of `bytes` with the check type 1, 2 or 3.
+fn block_check(bytes: &[u8], check: u8) -> Vec<u8> {
+    let sum = bytes.iter().map(|&b| b as usize).sum::<usize>();
+    match check {
+        3 => {
+            let crc = crc16_kermit(bytes) as usize;
+            vec![
+                tochar((crc >> 12) & 0x0f),
+                tochar((crc >> 6) & 0x3f),
+                tochar(crc & 0x3f),
+            ]
+        },
+        2 => vec![tochar((sum >> 6) & 0x3f), tochar(sum & 0x3f)],
+        _ => vec![tochar((sum + ((sum & 0xc0) >> 6)) & 0x3f)],
+    }
+}
+
+/// Packet `kind` with already encoded data, long when it doesn't fit a
+/// basic one.
+fn packet(seq: u8, kind: u8, data: &[u8], check: u8) -> Vec<u8> {
+    // Send-Init and its acknowledgment always use the basic check.
+    let check = if kind == b'S' { 1 } else { check };
+    let check_len = check as usize;
+    let mut packet = vec![MARK];
+    let len = 2 + data.len() + check_len;
+    if len <= MAX_SHORT_LEN {
+        packet.extend([tochar(len), tochar(seq as usize), kind]);
+    } else {
+        let extended = data.len() + check_len;
+        packet.extend([
+            tochar(0),
+            tochar(seq as usize),
+            kind,
+            tochar(extended / 95),
+            tochar(extended % 95),
+        ]);
+        let header_check = block_check(&packet[1..], 1);
+        packet.extend(header_check);
+    }
+    packet.extend_from_slice(data);
+    let check = block_check(&packet[1..], check);
+    packet.extend(check);
+    packet.push(EOL);
+    packet
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Parsed {
+    Packet { seq: u8, kind: u8, data: Vec<u8> },
+    Invalid,
+}
+
+/// Take the next packet from the received bytes, `None` until one is
+/// complete.
+fn next_packet(buffer: &mut Vec<u8>, check: u8) -> Option<Parsed> {
+    let Some(start) = buffer.iter().position(|&b| b == MARK) else {
+        buffer.clear();
+        return None;
+    };
+    buffer.drain(..start);
+    if buffer.len() < 4 {
+        return None;
+    }
+
+    let kind = buffer[3];
+    let check_len = if kind == b'S' { 1 } else { check as usize };
+    let len = unchar(buffer[1]);
+    let (data_start, total) = if len == 0 {
+        if buffer.len() < 7 {
+            return None;
+        }
+        if block_check(&buffer[1..6], 1)[0] != buffer[6] {
+            buffer.remove(0);
+            return Some(Parsed::Invalid);
+        }
+        (7, 7 + unchar(buffer[4]) * 95 + unchar(buffer[5]))
+    } else {
+        (4, 2 + len)
+    };
+    if total < data_start + check_len || len > MAX_SHORT_LEN {
+        buffer.remove(0);
+        return Some(Parsed::Invalid);
+    }
+    if buffer.len() < total {
+        return None;
+    }
+
+    // A bad packet may hide the mark of the next one, so only its mark is
+    // dropped.
+    let check_start = total - check_len;
+    if block_check(&buffer[1..check_start], check_len as u8)
+        != buffer[check_start..total]
+    {
+        buffer.remove(0);
+        return Some(Parsed::Invalid);
+    }
+    let parsed = Parsed::Packet {
+        seq: unchar(buffer[2]) as u8 & 63,
+        kind,
+        data: buffer[data_start..check_start].to_vec(),
+    };
+    buffer.drain(..total);
+    Some(parsed)
+}
+
+/// Send-Init parameters, what one side can receive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Params {
+    max_len: usize,
+    check: u8,
+    /// Prefix of bytes with the high bit, or `Y` when it agrees to one.
+    qbin: u8,
+    rept: u8,
+    capabilities: u8,
+    window: usize,
+    max_long_len: usize,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            max_len: MAX_SHORT_LEN,
+            check: 3,
+            qbin: b'Y',
+            rept: REPT,
+            capabilities: LONG_PACKETS | SLIDING_WINDOWS | ATTRIBUTES,
+            window: WINDOW_SIZE,
+            max_long_len: MAX_LONG_LEN,
+        }
+    }
+}
+
+impl Params {
+    fn encode(&self) -> Vec<u8> {
+        vec![
+            tochar(self.max_len),
+            tochar(PACKET_TIMEOUT.as_secs() as usize),
+            tochar(0),
+            b'@',
+            tochar(EOL as usize),
+            QCTL,
+            self.qbin,
+            b'0' + self.check,
+            self.rept,
+            tochar(self.capabilities as usize),
+            tochar(self.window),
+            tochar(self.max_long_len / 95),
+            tochar(self.max_long_len % 95),
+        ]
+    }
+
+    /// Parameters of the peer, with the defaults for the missing ones.
+    fn decode(data: &[u8]) -> Self {
+        let field = |index: usize| data.get(index).copied();
+        let capabilities = field(9).map_or(0, |c| unchar(c) as u8);
+        // The window and long packet fields follow the capabilities, which
+        // may continue over several bytes.
+        let extra = data
+            .iter()
+            .skip(9)
+            .position(|&c| unchar(c) & 1 == 0)
+            .map_or(data.len(), |index| 10 + index);
+        let max_long_len = match (field(extra + 1), field(extra + 2)) {
+            (Some(high), Some(low)) => unchar(high) * 95 + unchar(low),
+            _ => 500,
+        };
+        Self {
+            max_len: field(0).map_or(80, unchar).min(MAX_SHORT_LEN),
+            check: field(7)
+                .filter(|check| (b'1'..=b'3').contains(check))
+                .map_or(1, |check| check - b'0'),
+            qbin: field(6).unwrap_or(b'N'),
+            rept: field(8).unwrap_or(b' '),
+            capabilities,
+            window: field(extra).map_or(1, unchar).clamp(1, 31),
+            max_long_len,
+        }
+    }
+}
+
+/// Parameters both sides agreed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Session {
+    check: u8,
+    qbin: Option<u8>,
+    rept: Option<u8>,
+    window: usize,
+    /// Longest data field of the packets sent to the peer.
+    max_data: usize,
+    attributes: bool,
+}
+
+impl Session {
+    /// Before the Send-Init exchange.
+    fn initial() -> Self {
+        Self {
+            check: 1,
+            qbin: None,
+            rept: None,
+            window: 1,
+            max_data: MAX_SHORT_LEN - 3,
+            attributes: false,
+        }
+    }
+
+    fn negotiate(ours: &Params, theirs: &Params) -> Self {
+        let both = ours.capabilities & theirs.capabilities;
+        let is_prefix =
+            |c: u8| (33..=62).contains(&c) || (96..=126).contains(&c);
+        let qbin = match (ours.qbin, theirs.qbin) {
+            (b'Y', theirs) if is_prefix(theirs) => Some(theirs),
+            (ours, b'Y') if is_prefix(ours) => Some(ours),
+            (ours, theirs) if ours == theirs && is_prefix(ours) => Some(ours),
+            _ => None,
+        };
+        let check = if ours.check == theirs.check {
+            ours.check
+        } else {
+            1
+        };
+        let max_len = if both & LONG_PACKETS != 0 {
+            theirs.max_long_len.min(MAX_LONG_LEN).saturating_sub(10)
+        } else {
+            theirs.max_len.saturating_sub(2 + check as usize)
+        };
+        Self {
+            check,
+            qbin,
+            rept: (ours.rept == theirs.rept && is_prefix(ours.rept))
+                .then_some(ours.rept),
+            window: if both & SLIDING_WINDOWS != 0 {
+                ours.window.min(theirs.window)
+            } else {
+                1
+            },
+            max_data: max_len.max(10),
+            attributes: both & ATTRIBUTES != 0,
+        }
+    }
+
+    fn encode_byte(&self, byte: u8, output: &mut Vec<u8>) {
+        let mut byte = byte;
+        if let Some(qbin) = self.qbin {
+            if byte & 0x80 != 0 {
+                output.push(qbin);
+                byte &= 0x7f;
+            }
+        }
+        let low = byte & 0x7f;
+        if low < 32 || low == 127 {
+            output.extend([QCTL, byte ^ 64]);
+        } else if low == QCTL
+            || Some(low) == self.qbin
+            || Some(low) == self.rept
+        {
+            output.extend([QCTL, byte]);
+        } else {
+            output.push(byte);
+        }
+    }
+
+    /// Encode the start of `data` into at most `max_data` bytes, returns the
+    /// encoded bytes and how many bytes of `data` they hold.
+    fn encode(&self, data: &[u8]) -> (Vec<u8>, usize) {
+        let mut output = vec![];
+        let mut consumed = 0;
+        let mut encoded = vec![];
+        while consumed < data.len() {
+            let byte = data[consumed];
+            let run = data[consumed..]
+                .iter()
+                .take(94)
+                .take_while(|&&b| b == byte)
+                .count();
+            encoded.clear();
+            self.encode_byte(byte, &mut encoded);
+            let count = match self.rept {
+                Some(rept) if run > 2 => {
+                    encoded.splice(0..0, [rept, tochar(run)]);
+                    run
+                },
+                _ => 1,
+            };
+            if output.len() + encoded.len() > self.max_data {
+                break;
+            }
+            output.extend_from_slice(&encoded);
+            consumed += count;
+        }
+        (output, consumed)
+    }
+
+    fn decode(&self, data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(data.len());
+        let mut bytes = data.iter().copied();
+        while let Some(mut byte) = bytes.next() {
+            let mut count = 1;
+            if Some(byte) == self.rept {
+                count = bytes.next().map_or(0, unchar);
+                byte = bytes.next().unwrap_or(0);
+            }
+            let mut high = 0;
+            if Some(byte) == self.qbin {
+                high = 0x80;
+                byte = bytes.next().unwrap_or(0);
+            }
+            if byte == QCTL {
+                byte = bytes.next().unwrap_or(0);
+                if (63..=95).contains(&(byte & 0x7f)) {
+                    byte ^= 64;
+                }
+            }
+            output.extend(std::iter::repeat_n(byte | high, count));
+        }
+        output
+    }
+}
+
+#[derive(Debug)]
+struct InFlight {
+    seq: u8,
+    packet: Vec<u8>,
+    /// Bytes of the file the packet holds.
+    size: usize,
+    acked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SendState {
+    WaitInit,
+    WaitFile,
+    WaitAttributes,
+    Sending,
+    WaitEof,
+    WaitBreak,
+    Done,
+}
+
+#[derive(Debug)]
+pub(crate) struct KermitSender {
+    params: Params,
+    session: Session,
+    files: VecDeque<SendFile>,
+    file: Option<SendFile>,
+    state: SendState,
+    buffer: Vec<u8>,
+    seq: u8,
+    /// Packets waiting for their acknowledgment, oldest first.
+    window: VecDeque<InFlight>,
+    /// End of the file data put into packets.
+    offset: usize,
+    retries: u32,
+    total_retries: u32,
+    deadline: Option<Instant>,
+}
+
+impl KermitSender {
+    pub(crate) fn new(files: Vec<SendFile>) -> Self {
+        Self::with_params(files, Params::default())
+    }
+
+    fn with_params(files: Vec<SendFile>, params: Params) -> Self {
+        Self {
+            params,
+            session: Session::initial(),
+            files: files.into(),
+            file: None,
+            state: SendState::WaitInit,
+            buffer: vec![],
+            seq: 0,
+            window: VecDeque::new(),
+            offset: 0,
+            retries: 0,
+            total_retries: 0,
+            deadline: None,
+        }
+    }
+
+    fn send(
+        &mut self,
+        kind: u8,
+        data: &[u8],
+        size: usize,
+        now: Instant,
+        io: &mut TransferIo,
+    ) {
+        let packet = packet(self.seq, kind, data, self.session.check);
+        io.output.extend_from_slice(&packet);
+        self.window.push_back(InFlight {
+            seq: self.seq,
+            packet,
+            size,
+            acked: false,
+        });
+        self.seq = next_seq(self.seq);
+        self.deadline = Some(now + ACK_TIMEOUT);
+    }
+
+    /// Send the next packets once the window allows it. Only data packets
+    /// share the window, the others wait for everything before them.
+    fn next(&mut self, now: Instant, io: &mut TransferIo) {
+        if self.state == SendState::Sending {
+            while self.window.len() < self.session.window {
+                let Some(file) = &self.file else {
+                    break;
+                };
+                if self.offset >= file.data.len() {
+                    break;
+                }
+
+                let (data, size) =
+                    self.session.encode(&file.data[self.offset..]);
+                self.offset += size;
+                self.send(b'D', &data, size, now, io);
+            }
+            let sent = self.file.as_ref().map_or(0, |file| file.data.len());
+            if self.offset < sent {
+                return;
+            }
+        }
+        if !self.window.is_empty() {
+            return;
+        }
+
+        match self.state {
+            SendState::WaitInit | SendState::WaitEof => self.next_file(now, io),
+            SendState::WaitFile if self.session.attributes => {
+                let size = self
+                    .file
+                    .as_ref()
+                    .map_or(0, |file| file.data.len())
+                    .to_string();
+                let mut data = vec![b'1', tochar(size.len())];
+                data.extend(size.bytes());
+                self.state = SendState::WaitAttributes;
+                self.send(b'A', &data, 0, now, io);
+            },
+            SendState::WaitFile | SendState::WaitAttributes => {
+                self.state = SendState::Sending;
+                self.next(now, io);
+            },
+            SendState::Sending => {
+                self.state = SendState::WaitEof;
+                self.send(b'Z', &[], 0, now, io);
+            },
+            SendState::WaitBreak => self.finish(io),
+            SendState::Done => {},
+        }
+    }
+
+    fn next_file(&mut self, now: Instant, io: &mut TransferIo) {
+        self.file = self.files.pop_front();
+        self.offset = 0;
+        let Some(file) = &self.file else {
+            self.state = SendState::WaitBreak;
+            self.send(b'B', &[], 0, now, io);
+            return;
+        };
+
+        let (name, _) = self.session.encode(file.name.as_bytes());
+        self.state = SendState::WaitFile;
+        self.progress(io);
+        self.send(b'F', &name, 0, now, io);
+    }
+
+    fn ack(&mut self, seq: u8, data: &[u8], now: Instant, io: &mut TransferIo) {
+        let Some(in_flight) = self.window.iter_mut().find(|p| p.seq == seq)
+        else {
+            return;
+        };
+
+        in_flight.acked = true;
+        self.retries = 0;
+        if self.state == SendState::WaitInit {
+            self.session =
+                Session::negotiate(&self.params, &Params::decode(data));
+        }
+        while self.window.front().is_some_and(|p| p.acked) {
+            self.window.pop_front();
+        }
+        if self.window.is_empty() {
+            self.deadline = None;
+        }
+        if self.state == SendState::Sending {
+            self.progress(io);
+        }
+        self.next(now, io);
+    }
+
+    fn nak(&mut self, seq: u8, now: Instant, io: &mut TransferIo) {
+        if self.window.iter().any(|p| p.seq == seq && !p.acked) {
+            self.resend(seq, now, io);
+        } else if seq == self.seq && self.state != SendState::WaitInit {
+            // Asking for the packet not sent yet acknowledges the last one.
+            let last = self.seq.wrapping_sub(1) & 63;
+            self.ack(last, &[], now, io);
+        }
+    }
+
+    fn resend(&mut self, seq: u8, now: Instant, io: &mut TransferIo) {
+        self.retries += 1;
+        self.total_retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.error("Too many retries", io);
+            return;
+        }
+
+        if let Some(in_flight) = self.window.iter().find(|p| p.seq == seq) {
+            io.output.extend_from_slice(&in_flight.packet);
+        }
+        self.deadline = Some(now + ACK_TIMEOUT);
+        self.progress(io);
+    }
+
+    fn progress(&self, io: &mut TransferIo) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        let in_flight = self.window.iter().map(|p| p.size).sum::<usize>();
+        io.events.push(TransferEvent::Progress(TransferProgress {
+            file: file.name.clone(),
+            transferred: (self.offset - in_flight) as u64,
+            total: Some(file.data.len() as u64),
+            retries: self.total_retries,
+        }));
+    }
+
+    fn finish(&mut self, io: &mut TransferIo) {
+        self.state = SendState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Finished);
+    }
+
+    fn fail(&mut self, reason: &str, io: &mut TransferIo) {
+        self.state = SendState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Failed(reason.to_owned()));
+    }
+
+    /// Fail, telling the receiver why.
+    fn error(&mut self, reason: &str, io: &mut TransferIo) {
+        let (message, _) = self.session.encode(reason.as_bytes());
+        io.output
+            .extend(packet(self.seq, b'E', &message, self.session.check));
+        self.fail(reason, io);
+    }
+}
+
+impl Transfer for KermitSender {
+    fn start(&mut self, now: Instant, io: &mut TransferIo) {
+        let params = self.params.encode();
+        self.send(b'S', &params, 0, now, io);
+    }
+
+    fn receive(&mut self, bytes: &[u8], now: Instant, io: &mut TransferIo) {
+        self.buffer.extend_from_slice(bytes);
+        while self.state != SendState::Done {
+            let Some(parsed) =
+                next_packet(&mut self.buffer, self.session.check)
+            else {
+                break;
+            };
+
+            match parsed {
+                Parsed::Packet {
+                    seq,
+                    kind: b'Y',
+                    data,
+                } => self.ack(seq, &data, now, io),
+                Parsed::Packet {
+                    seq, kind: b'N', ..
+                } => self.nak(seq, now, io),
+                Parsed::Packet {
+                    kind: b'E', data, ..
+                } => {
+                    let message = self.session.decode(&data);
+                    self.fail(
+                        &format!(
+                            "Error from the receiver: {}",
+                            String::from_utf8_lossy(&message)
+                        ),
+                        io,
+                    );
+                },
+                Parsed::Packet { .. } | Parsed::Invalid => {},
+            }
+        }
+    }
+
+    fn timeout(&mut self, now: Instant, io: &mut TransferIo) {
+        if let Some(seq) = self.window.iter().find(|p| !p.acked).map(|p| p.seq)
+        {
+            self.resend(seq, now, io);
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn cancel(&mut self, io: &mut TransferIo) {
+        if self.state != SendState::Done {
+            self.error("Cancelled", io);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.state == SendState::Done
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReceiveState {
+    WaitInit,
+    Receiving,
+    Done,
+}
+
+#[derive(Debug)]
+struct ReceivedFile {
+    name: String,
+    path: PathBuf,
+    file: File,
+    size: Option<u64>,
+}
+
+#[derive(Debug)]
+pub(crate) struct KermitReceiver {
+    /// Directory of the received files.
+    destination: PathBuf,
+    params: Params,
+    session: Session,
+    state: ReceiveState,
+    buffer: Vec<u8>,
+    /// Sequence number of the next packet to handle.
+    expected: u8,
+    /// Packets received ahead of `expected`, indexed from it.
+    slots: VecDeque<Option<(u8, Vec<u8>)>>,
+    /// Acknowledgment of the Send-Init, sent again when it was lost.
+    init_ack: Vec<u8>,
+    file: Option<ReceivedFile>,
+    offset: u64,
+    retries: u32,
+    total_retries: u32,
+    deadline: Option<Instant>,
+}
+
+impl KermitReceiver {
+    pub(crate) fn new(destination: PathBuf) -> Self {
+        Self::with_params(destination, Params::default())
+    }
+
+    fn with_params(destination: PathBuf, params: Params) -> Self {
+        Self {
+            destination,
+            params,
+            session: Session::initial(),
+            state: ReceiveState::WaitInit,
+            buffer: vec![],
+            expected: 0,
+            slots: VecDeque::new(),
+            init_ack: vec![],
+            file: None,
+            offset: 0,
+            retries: 0,
+            total_retries: 0,
+            deadline: None,
+        }
+    }
+
+    fn reply(&self, seq: u8, kind: u8, io: &mut TransferIo) {
+        io.output.extend(packet(seq, kind, &[], self.session.check));
+    }
+
+    fn init(&mut self, seq: u8, data: &[u8], io: &mut TransferIo) {
+        let theirs = Params::decode(data);
+        self.init_ack = packet(seq, b'Y', &self.params.encode(), 1);
+        io.output.extend_from_slice(&self.init_ack);
+        self.session = Session::negotiate(&self.params, &theirs);
+        self.expected = next_seq(seq);
+        self.slots = vec![None; self.session.window].into();
+        self.state = ReceiveState::Receiving;
+    }
+
+    fn packet(
+        &mut self,
+        seq: u8,
+        kind: u8,
+        data: Vec<u8>,
+        io: &mut TransferIo,
+    ) {
+        let window = self.slots.len();
+        let distance = (seq.wrapping_sub(self.expected) & 63) as usize;
+        if distance >= window {
+            // A packet already handled, whose acknowledgment was lost.
+            if distance >= 64 - window {
+                if kind == b'S' {
+                    io.output.extend_from_slice(&self.init_ack);
+                } else {
+                    self.reply(seq, b'Y', io);
+                }
+            }
+            return;
+        }
+
+        self.reply(seq, b'Y', io);
+        if self.slots[distance].is_some() {
+            return;
+        }
+        // Ask again for the packets lost since the newest one.
+        let newest = self.slots.iter().rposition(Option::is_some);
+        for missing in newest.map_or(0, |i| i + 1)..distance {
+            let missing_seq = (self.expected + missing as u8) & 63;
+            self.reply(missing_seq, b'N', io);
+        }
+        self.slots[distance] = Some((kind, data));
+
+        while let Some(Some((kind, data))) = self.slots.front().cloned() {
+            self.slots.pop_front();
+            self.slots.push_back(None);
+            self.expected = next_seq(self.expected);
+            self.handle(kind, &data, io);
+            if self.state == ReceiveState::Done {
+                break;
+            }
+        }
+    }
+
+    /// Handle the packets in order.
+    fn handle(&mut self, kind: u8, data: &[u8], io: &mut TransferIo) {
+        match kind {
+            b'F' => {
+                let name = self.session.decode(data);
+                let name = String::from_utf8_lossy(&name).into_owned();
+                match create_received(&self.destination, &name) {
+                    Ok((path, file)) => {
+                        self.file = Some(ReceivedFile {
+                            name,
+                            path,
+                            file,
+                            size: None,
+                        });
+                        self.offset = 0;
+                        self.progress(io);
+                    },
+                    Err(err) => self.fail_io(err, io),
+                }
+            },
+            b'A' => {
+                let size = attribute_size(&self.session.decode(data));
+                if let Some(file) = &mut self.file {
+                    file.size = size.or(file.size);
+                }
+            },
+            b'D' => {
+                let data = self.session.decode(data);
+                let Some(file) = &mut self.file else {
+                    return;
+                };
+                if let Err(err) = file.file.write_all(&data) {
+                    return self.fail_io(err, io);
+                }
+                self.offset += data.len() as u64;
+                self.progress(io);
+            },
+            b'Z' => {
+                let Some(file) = self.file.take() else {
+                    return;
+                };
+                // The sender may discard the file it was sending.
+                if data.first() == Some(&b'D') {
+                    drop(file.file);
+                    let _ = std::fs::remove_file(&file.path);
+                    return;
+                }
+                if let Err(err) = file.file.sync_all() {
+                    return self.fail_io(err, io);
+                }
+                io.events.push(TransferEvent::FileReceived(file.path));
+            },
+            b'B' => self.finish(io),
+            _ => {},
+        }
+    }
+
+    fn progress(&self, io: &mut TransferIo) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        io.events.push(TransferEvent::Progress(TransferProgress {
+            file: file.name.clone(),
+            transferred: self.offset,
+            total: file.size,
+            retries: self.total_retries,
+        }));
+    }
+
+    fn finish(&mut self, io: &mut TransferIo) {
+        self.state = ReceiveState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Finished);
+    }
+
+    fn fail(&mut self, reason: &str, io: &mut TransferIo) {
+        self.state = ReceiveState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Failed(reason.to_owned()));
+    }
+
+    /// Fail, telling the sender why.
+    fn error(&mut self, reason: &str, io: &mut TransferIo) {
+        let (message, _) = self.session.encode(reason.as_bytes());
+        io.output.extend(packet(
+            self.expected,
+            b'E',
+            &message,
+            self.session.check,
+        ));
+        self.fail(reason, io);
+    }
+
+    fn fail_io(&mut self, err: std::io::Error, io: &mut TransferIo) {
+        self.error(&format!("Error writing the file: {}", err), io);
+    }
+}
+
+/// File size from the data of an attribute packet.
+fn attribute_size(data: &[u8]) -> Option<u64> {
+    let mut rest = data;
+    let mut size = None;
+    while let [tag, len, tail @ ..] = rest {
+        let len = unchar(*len).min(tail.len());
+        let value = std::str::from_utf8(&tail[..len]).ok();
+        match tag {
+            b'1' => size = value.and_then(|v| v.parse().ok()),
+            // Size in kilobytes, when the exact one is missing.
+            b'!' if size.is_none() => {
+                size = value
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .and_then(|k| k.checked_mul(1024))
+            },
+            _ => {},
+        }
+        rest = &tail[len..];
+    }
+    size
+}
+
+impl Transfer for KermitReceiver {
+    fn start(&mut self, now: Instant, _io: &mut TransferIo) {
+        self.deadline = Some(now + PACKET_TIMEOUT);
+    }
+
+    fn receive(&mut self, bytes: &[u8], now: Instant, io: &mut TransferIo) {
+        self.buffer.extend_from_slice(bytes);
+        while self.state != ReceiveState::Done {
+            let Some(parsed) =
+                next_packet(&mut self.buffer, self.session.check)
+            else {
+                break;
+            };
+
+            match (self.state, parsed) {
+                (
+                    _,
+                    Parsed::Packet {
+                        kind: b'E', data, ..
+                    },
+                ) => {
+                    let message = self.session.decode(&data);
+                    self.fail(
+                        &format!(
+                            "Error from the sender: {}",
+                            String::from_utf8_lossy(&message)
+                        ),
+                        io,
+                    );
+                },
+                (
+                    ReceiveState::WaitInit,
+                    Parsed::Packet {
+                        seq,
+                        kind: b'S',
+                        data,
+                    },
+                ) => {
+                    self.retries = 0;
+                    self.init(seq, &data, io);
+                    self.deadline = Some(now + PACKET_TIMEOUT);
+                },
+                (
+                    ReceiveState::Receiving,
+                    Parsed::Packet { seq, kind, data },
+                ) => {
+                    self.retries = 0;
+                    self.packet(seq, kind, data, io);
+                    self.deadline = Some(now + PACKET_TIMEOUT);
+                },
+                (ReceiveState::Receiving, Parsed::Invalid) => {
+                    self.total_retries += 1;
+                    self.reply(self.expected, b'N', io);
+                    self.progress(io);
+                },
+                _ => {},
+            }
+        }
+        if self.state == ReceiveState::Done {
+            self.deadline = None;
+        }
+    }
+
+    fn timeout(&mut self, now: Instant, io: &mut TransferIo) {
+        self.retries += 1;
+        self.total_retries += 1;
+        if self.retries > MAX_RETRIES {
+            self.error("No response from the sender", io);
+            return;
+        }
+
+        self.reply(self.expected, b'N', io);
+        self.deadline = Some(now + PACKET_TIMEOUT);
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn cancel(&mut self, io: &mut TransferIo) {
+        if self.state != ReceiveState::Done {
+            self.error("Cancelled", io);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.state == ReceiveState::Done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_path;
+    use crate::transfer::tests::{run, test_data};
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc16_kermit(b"123456789"), 0x2189);
+    }
+
+    #[test]
+    fn attribute_sizes() {
+        let attribute = |tag: u8, value: &str| {
+            let mut data = vec![tag, tochar(value.len())];
+            data.extend(value.as_bytes());
+            data
+        };
+        assert_eq!(attribute_size(&attribute(b'1', "1500")), Some(1500));
+        assert_eq!(attribute_size(&attribute(b'!', "2")), Some(2048));
+        assert_eq!(
+            attribute_size(&attribute(b'!', &(u64::MAX / 1000).to_string())),
+            None
+        );
+        let mut both = attribute(b'!', "2");
+        both.extend(attribute(b'1', "1500"));
+        assert_eq!(attribute_size(&both), Some(1500));
+    }
+
+    #[test]
+    fn prefixed_data_round_trips() {
+        let session = Session {
+            check: 3,
+            qbin: Some(b'&'),
+            rept: Some(REPT),
+            window: 1,
+            max_data: 4000,
+            attributes: false,
+        };
+        let mut data: Vec<u8> = (0..=255).collect();
+        data.extend([0; 200]);
+        data.extend(b"#&~~~");
+
+        let (encoded, consumed) = session.encode(&data);
+        assert_eq!(consumed, data.len());
+        assert!(encoded.iter().all(|&b| (32..127).contains(&b)));
+        assert_eq!(session.decode(&encoded), data);
+
+        let mut buffer = packet(5, b'D', &encoded, 3);
+        buffer.extend(packet(6, b'Z', b"", 3));
+        assert_eq!(
+            next_packet(&mut buffer, 3),
+            Some(Parsed::Packet {
+                seq: 5,
+                kind: b'D',
+                data: encoded,
+            })
+        );
+        assert_eq!(
+            next_packet(&mut buffer, 3),
+            Some(Parsed::Packet {
+                seq: 6,
+                kind: b'Z',
+                data: vec![],
+            })
+        );
+        assert_eq!(next_packet(&mut buffer, 3), None);
+    }
+
+    #[test]
+    fn windowed_batch_with_corrupted_packet() {
+        let dir = temp_path("kermit");
+        std::fs::create_dir(&dir).unwrap();
+        let files = vec![
+            SendFile {
+                name: "config.bin".into(),
+                data: test_data(30_000),
+                modified: None,
+            },
+            SendFile {
+                name: "empty".into(),
+                data: vec![],
+                modified: None,
+            },
+        ];
+        let mut sender = KermitSender::new(files);
+        let mut receiver = KermitReceiver::new(dir.clone());
+
+        let mut corrupted = false;
+        let (sent, received) = run(&mut sender, &mut receiver, |bytes| {
+            if !corrupted && bytes.len() > 2_000 {
+                corrupted = true;
+                bytes[1_000] ^= 0x01;
+            }
+        });
+
+        assert!(corrupted);
+        assert_eq!(sender.session.window, WINDOW_SIZE);
+        assert!(sender.session.max_data > MAX_SHORT_LEN);
+        assert_eq!(sent.last(), Some(&TransferEvent::Finished));
+        assert_eq!(received.last(), Some(&TransferEvent::Finished));
+        assert!(received.iter().any(|event| matches!(
+            event,
+            TransferEvent::Progress(progress)
+                if progress.total == Some(30_000) && progress.retries == 1
+        )));
+        assert_eq!(
+            std::fs::read(dir.join("config.bin")).unwrap(),
+            test_data(30_000)
+        );
+        assert!(std::fs::read(dir.join("empty")).unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn basic_peer_without_extensions() {
+        let dir = temp_path("kermit-basic");
+        std::fs::create_dir(&dir).unwrap();
+        let basic = Params {
+            max_len: 80,
+            check: 1,
+            qbin: b'&',
+            rept: b' ',
+            capabilities: 0,
+            window: 1,
+            max_long_len: 500,
+        };
+        let mut sender = KermitSender::new(vec![SendFile {
+            name: "fw.hex".into(),
+            data: test_data(3_000),
+            modified: None,
+        }]);
+        let mut receiver = KermitReceiver::with_params(dir.clone(), basic);
+
+        let mut dropped = false;
+        let (sent, received) = run(&mut sender, &mut receiver, |bytes| {
+            if !dropped && bytes.len() > 50 && bytes[3] == b'D' {
+                dropped = true;
+                bytes.clear();
+            }
+        });
+
+        assert!(dropped);
+        assert_eq!(
+            sender.session,
+            Session {
+                check: 1,
+                qbin: Some(b'&'),
+                rept: None,
+                window: 1,
+                max_data: 77,
+                attributes: false,
+            }
+        );
+        assert_eq!(sent.last(), Some(&TransferEvent::Finished));
+        assert_eq!(received.last(), Some(&TransferEvent::Finished));
+        assert_eq!(
+            std::fs::read(dir.join("fw.hex")).unwrap(),
+            test_data(3_000)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn received_file_keeps_existing_one() {
+        let dir = temp_path("kermit-existing");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("a.bin"), b"old").unwrap();
+        let mut sender = KermitSender::new(vec![SendFile {
+            name: "a.bin".into(),
+            data: test_data(100),
+            modified: None,
+        }]);
+        let mut receiver = KermitReceiver::new(dir.clone());
+
+        let (_, received) = run(&mut sender, &mut receiver, |_| {});
+        assert!(received
+            .contains(&TransferEvent::FileReceived(dir.join("a-1.bin"))));
+        assert_eq!(std::fs::read(dir.join("a.bin")).unwrap(), b"old");
+        assert_eq!(std::fs::read(dir.join("a-1.bin")).unwrap(), test_data(100));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cancel_by_the_receiver() {
+        let mut sender = KermitSender::new(vec![SendFile {
+            name: "a".into(),
+            data: test_data(100),
+            modified: None,
+        }]);
+        let mut receiver = KermitReceiver::new(temp_path("kermit-cancel"));
+        let mut sender_io = TransferIo::default();
+        let mut receiver_io = TransferIo::default();
+        let now = Instant::now();
+
+        sender.start(now, &mut sender_io);
+        receiver.start(now, &mut receiver_io);
+        receiver.receive(&sender_io.output, now, &mut receiver_io);
+        receiver.cancel(&mut receiver_io);
+        sender.receive(&receiver_io.output, now, &mut sender_io);
+
+        assert!(sender.is_done());
+        assert_eq!(
+            sender_io.events.last(),
+            Some(&TransferEvent::Failed(
+                "Error from the receiver: Cancelled".into()
+            ))
+        );
+    }
+}
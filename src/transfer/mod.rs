@@ -0,0 +1,306 @@
+//! File transfer protocols which take over the serial byte stream.
+//!
+//! While a transfer runs, the event loop hands the received bytes to it
+//! instead of the terminal parser, and writes whatever it answers. Once the
+//! transfer is done the stream goes back to the terminal.
+//!
+//! The protocols don't do any I/O themselves, they are driven by received
+//! bytes and timeouts, so they can be tested against each other.
+
+pub(crate) mod kermit;
+pub(crate) mod xmodem;
+pub(crate) mod zmodem;
+
+use std::fmt::Debug;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum TransferProtocol {
+    /// 128 byte blocks, with CRC-16 or the checksum the receiver asks for.
+    #[default]
+    Xmodem,
+    /// 1024 byte blocks with CRC-16.
+    Xmodem1k,
+    /// Batch of files, with their names and sizes.
+    Ymodem,
+    /// Streaming batch of files, started by `sz` or `rz` on the remote.
+    Zmodem,
+    /// Batch of files in printable packets, for equipment which only speaks
+    /// Kermit.
+    Kermit,
+}
+
+/// What the remote asks for when it starts ZMODEM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ZmodemRequest {
+    /// The remote runs `sz` and sends files.
+    Receive,
+    /// The remote runs `rz` and waits for files.
+    Send,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferProgress {
+    /// Name of the file being transferred, empty when the protocol doesn't
+    /// tell it.
+    pub file: String,
+    pub transferred: u64,
+    /// Size of the file, when the protocol tells it.
+    pub total: Option<u64>,
+    /// Blocks sent or asked again so far.
+    pub retries: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferEvent {
+    Progress(TransferProgress),
+    /// A received file was written.
+    FileReceived(PathBuf),
+    /// The remote started ZMODEM and holds the stream until it is answered
+    /// with `SerialMonitorBackend::receive_files` or `send_files`, or
+    /// refused with `cancel_transfer`.
+    ZmodemRequest(ZmodemRequest),
+    /// The transfer completed, the terminal has the stream back.
+    Finished,
+    /// The transfer was cancelled or failed, the terminal has the stream
+    /// back.
+    Failed(String),
+}
+
+/// Bytes to write and events to report, produced by a transfer.
+#[derive(Debug, Default)]
+pub(crate) struct TransferIo {
+    pub(crate) output: Vec<u8>,
+    pub(crate) events: Vec<TransferEvent>,
+}
+
+/// A protocol running on the serial stream.
+pub(crate) trait Transfer: Send + Debug {
+    fn start(&mut self, now: Instant, io: &mut TransferIo);
+
+    fn receive(&mut self, bytes: &[u8], now: Instant, io: &mut TransferIo);
+
+    /// Called once `deadline` has passed.
+    fn timeout(&mut self, now: Instant, io: &mut TransferIo);
+
+    fn deadline(&self) -> Option<Instant>;
+
+    /// Abort the transfer, telling the peer when the protocol allows it.
+    fn cancel(&mut self, io: &mut TransferIo);
+
+    /// Whether the stream can be handed back to the terminal.
+    fn is_done(&self) -> bool;
+
+    /// Whether the transfer only holds the stream until the user answers,
+    /// so it is replaced without telling the peer.
+    fn is_offer(&self) -> bool {
+        false
+    }
+}
+
+/// A file to send, read up front.
+#[derive(Debug, Clone)]
+pub(crate) struct SendFile {
+    pub(crate) name: String,
+    pub(crate) data: Vec<u8>,
+    pub(crate) modified: Option<SystemTime>,
+}
+
+impl SendFile {
+    pub(crate) fn read(path: &Path) -> io::Result<Self> {
+        let data = std::fs::read(path)?;
+        Ok(Self {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            data,
+            modified: std::fs::metadata(path)?.modified().ok(),
+        })
+    }
+}
+
+/// Where a received file named `name` by the sender is written.
+///
+/// Only the name is used, the sender can't pick the directory.
+pub(crate) fn received_path(directory: &Path, name: &str) -> PathBuf {
+    let name = Path::new(name)
+        .file_name()
+        .map(|name| name.to_owned())
+        .unwrap_or_else(|| "received.bin".into());
+    directory.join(name)
+}
+
+/// Create a new file for `name` in `directory`, adding a number to the name
+/// instead of overwriting an existing file.
+pub(crate) fn create_received(
+    directory: &Path,
+    name: &str,
+) -> io::Result<(PathBuf, File)> {
+    let path = received_path(directory, name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()));
+    for number in 0..1000 {
+        let path = match number {
+            0 => path.clone(),
+            _ => directory.join(format!(
+                "{}-{}{}",
+                stem,
+                number,
+                extension.as_deref().unwrap_or_default()
+            )),
+        };
+        match File::create_new(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {},
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("Too many files named {}", path.display()),
+    ))
+}
+
+/// A transfer sent to the event loop, with the channel of its events.
+#[derive(Debug)]
+pub struct TransferJob {
+    pub(crate) transfer: Box<dyn Transfer>,
+    pub(crate) events: Sender<TransferEvent>,
+    pub(crate) active: Arc<AtomicBool>,
+}
+
+impl TransferJob {
+    pub(crate) fn new(
+        transfer: Box<dyn Transfer>,
+        events: Sender<TransferEvent>,
+    ) -> Self {
+        Self {
+            transfer,
+            events,
+            active: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Run one step of the transfer, returns the bytes to write.
+    pub(crate) fn step(
+        &mut self,
+        step: impl FnOnce(&mut dyn Transfer, &mut TransferIo),
+    ) -> Vec<u8> {
+        let mut io = TransferIo::default();
+        step(&mut *self.transfer, &mut io);
+        for event in io.events {
+            let _ = self.events.send(event);
+        }
+        if self.transfer.is_done() {
+            self.active.store(false, Ordering::Relaxed);
+        }
+        io.output
+    }
+}
+
+/// CRC-16/XMODEM, polynomial 0x1021 with a zero initial value.
+pub(crate) fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ (byte as u16) << 8, |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test_utils::temp_path;
+
+    /// Connect two transfers, letting time pass whenever nothing is in
+    /// flight, and return the events of both.
+    pub(crate) fn run(
+        sender: &mut dyn Transfer,
+        receiver: &mut dyn Transfer,
+        mut corrupt: impl FnMut(&mut Vec<u8>),
+    ) -> (Vec<TransferEvent>, Vec<TransferEvent>) {
+        let mut now = Instant::now();
+        let mut sender_io = TransferIo::default();
+        let mut receiver_io = TransferIo::default();
+        sender.start(now, &mut sender_io);
+        receiver.start(now, &mut receiver_io);
+
+        for _ in 0..100_000 {
+            if sender.is_done() && receiver.is_done() {
+                break;
+            }
+
+            let mut to_receiver = std::mem::take(&mut sender_io.output);
+            let to_sender = std::mem::take(&mut receiver_io.output);
+            if to_receiver.is_empty() && to_sender.is_empty() {
+                now = [sender.deadline(), receiver.deadline()]
+                    .into_iter()
+                    .flatten()
+                    .min()
+                    .expect("both transfers are stuck");
+                if sender.deadline().is_some_and(|d| d <= now) {
+                    sender.timeout(now, &mut sender_io);
+                }
+                if receiver.deadline().is_some_and(|d| d <= now) {
+                    receiver.timeout(now, &mut receiver_io);
+                }
+                continue;
+            }
+
+            corrupt(&mut to_receiver);
+            receiver.receive(&to_receiver, now, &mut receiver_io);
+            sender.receive(&to_sender, now, &mut sender_io);
+        }
+
+        (sender_io.events, receiver_io.events)
+    }
+
+    pub(crate) fn test_data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+    }
+
+    #[test]
+    fn received_files_are_not_overwritten() {
+        let dir = temp_path("received");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("log.txt"), b"old").unwrap();
+
+        let (path, _) = create_received(&dir, "../log.txt").unwrap();
+        assert_eq!(path, dir.join("log-1.txt"));
+        let (path, _) = create_received(&dir, "log.txt").unwrap();
+        assert_eq!(path, dir.join("log-2.txt"));
+        let (path, _) = create_received(&dir, "firmware").unwrap();
+        assert_eq!(path, dir.join("firmware"));
+        assert_eq!(std::fs::read(dir.join("log.txt")).unwrap(), b"old");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+    }
+}
@@ -0,0 +1,746 @@
+//! XMODEM, XMODEM-1K and YMODEM batch transfers.
+//!
+//! The receiver drives these protocols: it asks for CRC-16 with `C` or for
+//! checksums with NAK, and acknowledges every block. YMODEM adds a block 0
+//! with the name and size of every file, and an empty one to end the batch.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use super::{
+    crc16, create_received, SendFile, Transfer, TransferEvent, TransferIo,
+    TransferProgress, TransferProtocol,
+};
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+const CRC: u8 = b'C';
+
+/// How long the sender waits for the receiver to start.
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the sender waits for a block to be acknowledged.
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Interval of the receiver's start requests.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(3);
+/// How long the receiver waits for the next block.
+const BLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest gap inside a block before the receiver drops it.
+const CHAR_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_RETRIES: u32 = 10;
+/// Start requests asking for CRC-16 before XMODEM falls back to checksums.
+const CRC_REQUESTS: u32 = 3;
+
+#[inline]
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Block `block` holding `data`, padded to `size` bytes.
+fn packet(
+    block: u8,
+    data: &[u8],
+    size: usize,
+    padding: u8,
+    crc: bool,
+) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(size + 5);
+    packet.push(if size == 1024 { STX } else { SOH });
+    packet.extend([block, !block]);
+    packet.extend_from_slice(data);
+    packet.resize(3 + size, padding);
+    if crc {
+        packet.extend(crc16(&packet[3..]).to_be_bytes());
+    } else {
+        packet.push(checksum(&packet[3..]));
+    }
+    packet
+}
+
+fn cancel_sequence(io: &mut TransferIo) {
+    io.output.extend_from_slice(&[CAN; 8]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SendState {
+    /// Waiting for the receiver to ask for a file.
+    WaitStart,
+    WaitHeaderAck,
+    /// Waiting for the receiver to ask for the data after a YMODEM header.
+    WaitDataStart,
+    WaitAck,
+    WaitEotAck,
+    /// Waiting for the receiver to acknowledge the end of the batch.
+    WaitEndAck,
+    Done,
+}
+
+#[derive(Debug)]
+pub(crate) struct XmodemSender {
+    protocol: TransferProtocol,
+    files: VecDeque<SendFile>,
+    file: Option<SendFile>,
+    state: SendState,
+    crc: bool,
+    block: u8,
+    offset: usize,
+    /// Last packet, sent again on NAK or timeout.
+    packet: Vec<u8>,
+    /// Data bytes in `packet`.
+    packet_len: usize,
+    retries: u32,
+    total_retries: u32,
+    cancels: u8,
+    deadline: Option<Instant>,
+}
+
+impl XmodemSender {
+    /// XMODEM only sends the first of `files`.
+    pub(crate) fn new(
+        protocol: TransferProtocol,
+        files: Vec<SendFile>,
+    ) -> Self {
+        Self {
+            protocol,
+            files: files.into(),
+            file: None,
+            state: SendState::WaitStart,
+            crc: true,
+            block: 1,
+            offset: 0,
+            packet: vec![],
+            packet_len: 0,
+            retries: 0,
+            total_retries: 0,
+            cancels: 0,
+            deadline: None,
+        }
+    }
+
+    fn send_packet(
+        &mut self,
+        packet: Vec<u8>,
+        state: SendState,
+        now: Instant,
+        io: &mut TransferIo,
+    ) {
+        io.output.extend_from_slice(&packet);
+        self.packet = packet;
+        self.state = state;
+        self.retries = 0;
+        self.deadline = Some(now + ACK_TIMEOUT);
+    }
+
+    fn resend(&mut self, now: Instant, io: &mut TransferIo) {
+        self.retries += 1;
+        self.total_retries += 1;
+        if self.retries > MAX_RETRIES {
+            cancel_sequence(io);
+            self.fail("Too many retries", io);
+            return;
+        }
+
+        io.output.extend_from_slice(&self.packet);
+        self.deadline = Some(now + ACK_TIMEOUT);
+    }
+
+    /// Send the block at `offset`, or EOT after the last one.
+    fn send_data(&mut self, now: Instant, io: &mut TransferIo) {
+        let data = self.file.as_ref().map_or(&[][..], |file| &file.data);
+        let remaining = data.len().saturating_sub(self.offset);
+        if remaining == 0 {
+            self.packet_len = 0;
+            self.send_packet(vec![EOT], SendState::WaitEotAck, now, io);
+            return;
+        }
+
+        let long = self.protocol != TransferProtocol::Xmodem && self.crc;
+        let size = if long && remaining > 128 { 1024 } else { 128 };
+        let len = remaining.min(size);
+        let packet = packet(
+            self.block,
+            &data[self.offset..self.offset + len],
+            size,
+            SUB,
+            self.crc,
+        );
+        self.packet_len = len;
+        self.send_packet(packet, SendState::WaitAck, now, io);
+    }
+
+    /// Send the YMODEM header of the next file, or the end of the batch.
+    fn send_header(&mut self, now: Instant, io: &mut TransferIo) {
+        self.file = self.files.pop_front();
+        self.offset = 0;
+        self.block = 1;
+        let Some(file) = &self.file else {
+            let packet = packet(0, &[], 128, 0, true);
+            self.send_packet(packet, SendState::WaitEndAck, now, io);
+            return;
+        };
+
+        let mut header = file.name.as_bytes().to_vec();
+        header.push(0);
+        header.extend(file.data.len().to_string().bytes());
+        if let Some(modified) = file
+            .modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        {
+            header.extend(format!(" {:o}", modified.as_secs()).bytes());
+        }
+        let size = if header.len() > 128 { 1024 } else { 128 };
+        let packet = packet(0, &header, size, 0, true);
+        self.progress(io);
+        self.send_packet(packet, SendState::WaitHeaderAck, now, io);
+    }
+
+    fn progress(&self, io: &mut TransferIo) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        io.events.push(TransferEvent::Progress(TransferProgress {
+            file: file.name.clone(),
+            transferred: self.offset.min(file.data.len()) as u64,
+            total: Some(file.data.len() as u64),
+            retries: self.total_retries,
+        }));
+    }
+
+    fn finish(&mut self, io: &mut TransferIo) {
+        self.state = SendState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Finished);
+    }
+
+    fn fail(&mut self, reason: &str, io: &mut TransferIo) {
+        self.state = SendState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Failed(reason.to_owned()));
+    }
+
+    fn handle(&mut self, byte: u8, now: Instant, io: &mut TransferIo) {
+        if byte == CAN {
+            self.cancels += 1;
+            if self.cancels >= 2 {
+                self.fail("Cancelled by the receiver", io);
+            }
+            return;
+        }
+        self.cancels = 0;
+
+        let is_ymodem = self.protocol == TransferProtocol::Ymodem;
+        match (self.state, byte) {
+            (SendState::WaitStart, CRC) if is_ymodem => {
+                self.send_header(now, io)
+            },
+            (SendState::WaitStart, CRC | NAK) if !is_ymodem => {
+                self.crc = byte == CRC;
+                self.file = self.files.pop_front();
+                self.progress(io);
+                self.send_data(now, io);
+            },
+            (SendState::WaitHeaderAck, ACK) => {
+                self.state = SendState::WaitDataStart;
+                self.deadline = Some(now + ACK_TIMEOUT);
+            },
+            (SendState::WaitDataStart, CRC) => self.send_data(now, io),
+            (SendState::WaitAck, ACK) => {
+                self.offset += self.packet_len;
+                self.block = self.block.wrapping_add(1);
+                self.progress(io);
+                self.send_data(now, io);
+            },
+            (SendState::WaitEotAck, ACK) if is_ymodem => {
+                self.state = SendState::WaitStart;
+                self.deadline = Some(now + ACK_TIMEOUT);
+            },
+            (SendState::WaitEotAck, ACK) | (SendState::WaitEndAck, ACK) => {
+                self.finish(io)
+            },
+            (
+                SendState::WaitHeaderAck
+                | SendState::WaitAck
+                | SendState::WaitEotAck
+                | SendState::WaitEndAck,
+                NAK,
+            ) => self.resend(now, io),
+            // Repeated start requests and line noise.
+            _ => {},
+        }
+    }
+}
+
+impl Transfer for XmodemSender {
+    fn start(&mut self, now: Instant, _io: &mut TransferIo) {
+        self.deadline = Some(now + START_TIMEOUT);
+    }
+
+    fn receive(&mut self, bytes: &[u8], now: Instant, io: &mut TransferIo) {
+        for &byte in bytes {
+            if self.state == SendState::Done {
+                break;
+            }
+            self.handle(byte, now, io);
+        }
+    }
+
+    fn timeout(&mut self, now: Instant, io: &mut TransferIo) {
+        match self.state {
+            SendState::WaitStart | SendState::WaitDataStart => {
+                cancel_sequence(io);
+                self.fail("No response from the receiver", io);
+            },
+            SendState::Done => {},
+            _ => self.resend(now, io),
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn cancel(&mut self, io: &mut TransferIo) {
+        if self.state != SendState::Done {
+            cancel_sequence(io);
+            self.fail("Cancelled", io);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.state == SendState::Done
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReceiveState {
+    /// Sending start requests until the first block arrives.
+    Start,
+    Data,
+    Done,
+}
+
+#[derive(Debug)]
+pub(crate) struct XmodemReceiver {
+    protocol: TransferProtocol,
+    /// The file for XMODEM, the directory of the files for YMODEM.
+    destination: PathBuf,
+    state: ReceiveState,
+    crc: bool,
+    /// Waiting for a YMODEM block 0.
+    header: bool,
+    buffer: Vec<u8>,
+    expected: u8,
+    /// Blocks received of the current file.
+    blocks: u64,
+    file: String,
+    size: Option<u64>,
+    data: Vec<u8>,
+    requests: u32,
+    retries: u32,
+    total_retries: u32,
+    eots: u8,
+    cancels: u8,
+    deadline: Option<Instant>,
+}
+
+impl XmodemReceiver {
+    pub(crate) fn new(
+        protocol: TransferProtocol,
+        destination: PathBuf,
+    ) -> Self {
+        Self {
+            protocol,
+            destination,
+            state: ReceiveState::Start,
+            crc: true,
+            header: protocol == TransferProtocol::Ymodem,
+            buffer: vec![],
+            expected: 1,
+            blocks: 0,
+            file: String::new(),
+            size: None,
+            data: vec![],
+            requests: 0,
+            retries: 0,
+            total_retries: 0,
+            eots: 0,
+            cancels: 0,
+            deadline: None,
+        }
+    }
+
+    /// Ask the sender to start, a file or the data of a YMODEM file.
+    fn request(&mut self, now: Instant, io: &mut TransferIo) {
+        if self.requests >= MAX_RETRIES {
+            cancel_sequence(io);
+            self.fail("No response from the sender", io);
+            return;
+        }
+
+        self.requests += 1;
+        io.output.push(if self.crc { CRC } else { NAK });
+        self.deadline = Some(now + REQUEST_INTERVAL);
+    }
+
+    fn nak(&mut self, now: Instant, io: &mut TransferIo) {
+        self.buffer.clear();
+        self.retries += 1;
+        self.total_retries += 1;
+        if self.retries > MAX_RETRIES {
+            cancel_sequence(io);
+            self.fail("Too many retries", io);
+            return;
+        }
+
+        io.output.push(NAK);
+        self.deadline = Some(now + BLOCK_TIMEOUT);
+    }
+
+    fn ack(&mut self, now: Instant, io: &mut TransferIo) {
+        io.output.push(ACK);
+        self.retries = 0;
+        self.deadline = Some(now + BLOCK_TIMEOUT);
+    }
+
+    fn progress(&self, io: &mut TransferIo) {
+        io.events.push(TransferEvent::Progress(TransferProgress {
+            file: self.file.clone(),
+            transferred: self.data.len() as u64,
+            total: self.size,
+            retries: self.total_retries,
+        }));
+    }
+
+    fn finish(&mut self, io: &mut TransferIo) {
+        self.state = ReceiveState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Finished);
+    }
+
+    fn fail(&mut self, reason: &str, io: &mut TransferIo) {
+        self.state = ReceiveState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Failed(reason.to_owned()));
+    }
+
+    fn packet(
+        &mut self,
+        packet: &[u8],
+        size: usize,
+        now: Instant,
+        io: &mut TransferIo,
+    ) {
+        let (block, inverse) = (packet[1], packet[2]);
+        let data = &packet[3..3 + size];
+        let is_valid = if self.crc {
+            let crc = u16::from_be_bytes([packet[3 + size], packet[4 + size]]);
+            crc16(data) == crc
+        } else {
+            checksum(data) == packet[3 + size]
+        };
+        if block != !inverse || !is_valid {
+            self.nak(now, io);
+            return;
+        }
+
+        if self.header {
+            if block == 0 {
+                self.file_header(data, now, io);
+            } else {
+                self.nak(now, io);
+            }
+            return;
+        }
+
+        if block == self.expected {
+            let data = match self.size {
+                Some(size) => {
+                    let left = size.saturating_sub(self.data.len() as u64);
+                    &data[..data.len().min(left as usize)]
+                },
+                None => data,
+            };
+            self.data.extend_from_slice(data);
+            self.state = ReceiveState::Data;
+            self.expected = self.expected.wrapping_add(1);
+            self.blocks += 1;
+            self.ack(now, io);
+            self.progress(io);
+        } else if block == self.expected.wrapping_sub(1) {
+            // The sender missed the acknowledgment.
+            self.ack(now, io);
+        } else {
+            cancel_sequence(io);
+            self.fail("Lost block synchronization", io);
+        }
+    }
+
+    /// Block 0 of YMODEM: the name and size of the next file, or nothing at
+    /// the end of the batch.
+    fn file_header(&mut self, data: &[u8], now: Instant, io: &mut TransferIo) {
+        let name_end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        if name_end == 0 {
+            io.output.push(ACK);
+            self.finish(io);
+            return;
+        }
+
+        let info = &data[(name_end + 1).min(data.len())..];
+        let size_end = info
+            .iter()
+            .position(|&b| b == b' ' || b == 0)
+            .unwrap_or(info.len());
+        self.file = String::from_utf8_lossy(&data[..name_end]).into_owned();
+        self.size = std::str::from_utf8(&info[..size_end])
+            .ok()
+            .and_then(|size| size.parse().ok());
+        self.data.clear();
+        self.header = false;
+        self.expected = 1;
+        self.blocks = 0;
+        self.requests = 0;
+        self.state = ReceiveState::Data;
+        self.ack(now, io);
+        self.progress(io);
+        self.request(now, io);
+    }
+
+    fn end_of_file(&mut self, now: Instant, io: &mut TransferIo) {
+        if self.protocol == TransferProtocol::Ymodem {
+            if self.header {
+                // The sender missed the acknowledgment of the last EOT.
+                io.output.push(ACK);
+                return;
+            }
+
+            // The first EOT is refused, so a corrupted byte can't end the
+            // file early.
+            if self.eots == 0 {
+                self.eots = 1;
+                io.output.push(NAK);
+                self.deadline = Some(now + BLOCK_TIMEOUT);
+                return;
+            }
+        }
+
+        self.eots = 0;
+        io.output.push(ACK);
+        if self.size.is_none() {
+            while self.data.last() == Some(&SUB) {
+                self.data.pop();
+            }
+        }
+        if let Err(err) = self.write_file(io) {
+            cancel_sequence(io);
+            self.fail(&format!("Error writing the file: {}", err), io);
+            return;
+        }
+
+        if self.protocol == TransferProtocol::Ymodem {
+            self.header = true;
+            self.state = ReceiveState::Start;
+            self.requests = 0;
+            self.request(now, io);
+        } else {
+            self.finish(io);
+        }
+    }
+
+    fn write_file(&mut self, io: &mut TransferIo) -> io::Result<()> {
+        let data = std::mem::take(&mut self.data);
+        let path = if self.protocol == TransferProtocol::Ymodem {
+            let (path, mut file) =
+                create_received(&self.destination, &self.file)?;
+            file.write_all(&data)?;
+            path
+        } else {
+            std::fs::write(&self.destination, data)?;
+            self.destination.clone()
+        };
+        io.events.push(TransferEvent::FileReceived(path));
+        Ok(())
+    }
+}
+
+impl Transfer for XmodemReceiver {
+    fn start(&mut self, now: Instant, io: &mut TransferIo) {
+        self.request(now, io);
+    }
+
+    fn receive(&mut self, bytes: &[u8], now: Instant, io: &mut TransferIo) {
+        self.buffer.extend_from_slice(bytes);
+        while self.state != ReceiveState::Done {
+            let Some(&first) = self.buffer.first() else {
+                break;
+            };
+
+            match first {
+                SOH | STX => {
+                    let size = if first == SOH { 128 } else { 1024 };
+                    let len = 3 + size + if self.crc { 2 } else { 1 };
+                    if self.buffer.len() < len {
+                        self.deadline = Some(now + CHAR_TIMEOUT);
+                        break;
+                    }
+
+                    let packet: Vec<u8> = self.buffer.drain(..len).collect();
+                    self.cancels = 0;
+                    self.packet(&packet, size, now, io);
+                },
+                EOT => {
+                    self.buffer.remove(0);
+                    self.cancels = 0;
+                    self.end_of_file(now, io);
+                },
+                CAN => {
+                    self.buffer.remove(0);
+                    self.cancels += 1;
+                    if self.cancels >= 2 {
+                        self.fail("Cancelled by the sender", io);
+                    }
+                },
+                // Line noise between blocks.
+                _ => {
+                    self.buffer.remove(0);
+                },
+            }
+        }
+    }
+
+    fn timeout(&mut self, now: Instant, io: &mut TransferIo) {
+        match self.state {
+            ReceiveState::Start => {
+                let is_xmodem = self.protocol == TransferProtocol::Xmodem;
+                if is_xmodem && self.requests == CRC_REQUESTS {
+                    self.crc = false;
+                }
+                self.request(now, io);
+            },
+            ReceiveState::Data if self.blocks == 0 => {
+                self.buffer.clear();
+                self.request(now, io);
+            },
+            ReceiveState::Data => self.nak(now, io),
+            ReceiveState::Done => {},
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn cancel(&mut self, io: &mut TransferIo) {
+        if self.state != ReceiveState::Done {
+            cancel_sequence(io);
+            self.fail("Cancelled", io);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.state == ReceiveState::Done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_path;
+    use crate::transfer::tests::{run, test_data};
+
+    fn file(name: &str, len: usize) -> SendFile {
+        SendFile {
+            name: name.to_owned(),
+            data: test_data(len),
+            modified: None,
+        }
+    }
+
+    #[test]
+    fn xmodem_retries_corrupted_block() {
+        let path = temp_path("xmodem");
+        let mut sender =
+            XmodemSender::new(TransferProtocol::Xmodem, vec![file("a", 1000)]);
+        let mut receiver =
+            XmodemReceiver::new(TransferProtocol::Xmodem, path.clone());
+
+        let mut packets = 0;
+        let (sent, received) = run(&mut sender, &mut receiver, |bytes| {
+            if bytes.first() == Some(&SOH) {
+                packets += 1;
+                if packets == 3 {
+                    bytes[40] ^= 0xff;
+                }
+            }
+        });
+
+        assert_eq!(sent.last(), Some(&TransferEvent::Finished));
+        assert_eq!(received.last(), Some(&TransferEvent::Finished));
+        assert!(matches!(
+            &sent[sent.len() - 2],
+            TransferEvent::Progress(progress) if progress.retries == 1
+        ));
+        // The padding of the last block is removed.
+        assert_eq!(std::fs::read(&path).unwrap(), test_data(1000));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ymodem_batch_keeps_names_and_sizes() {
+        let dir = temp_path("ymodem");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("env.txt"), "kept").unwrap();
+        let files = vec![file("u-boot.bin", 3000), file("../env.txt", 26)];
+        let mut sender = XmodemSender::new(TransferProtocol::Ymodem, files);
+        let mut receiver =
+            XmodemReceiver::new(TransferProtocol::Ymodem, dir.clone());
+
+        let (sent, received) = run(&mut sender, &mut receiver, |_| {});
+        assert_eq!(sent.last(), Some(&TransferEvent::Finished));
+        assert_eq!(received.last(), Some(&TransferEvent::Finished));
+        assert_eq!(
+            std::fs::read(dir.join("u-boot.bin")).unwrap(),
+            test_data(3000)
+        );
+        assert_eq!(std::fs::read(dir.join("env.txt")).unwrap(), b"kept");
+        assert_eq!(
+            std::fs::read(dir.join("env-1.txt")).unwrap(),
+            test_data(26)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cancel_by_the_receiver() {
+        let path = temp_path("xmodem-cancel");
+        let mut sender = XmodemSender::new(
+            TransferProtocol::Xmodem1k,
+            vec![file("a", 100_000)],
+        );
+        let mut receiver =
+            XmodemReceiver::new(TransferProtocol::Xmodem1k, path.clone());
+
+        let mut sender_io = TransferIo::default();
+        let mut receiver_io = TransferIo::default();
+        let now = Instant::now();
+        sender.start(now, &mut sender_io);
+        receiver.start(now, &mut receiver_io);
+        sender.receive(&receiver_io.output, now, &mut sender_io);
+        assert_eq!(sender_io.output[0], STX);
+
+        receiver_io.output.clear();
+        receiver.cancel(&mut receiver_io);
+        sender.receive(&receiver_io.output, now, &mut sender_io);
+        assert!(sender.is_done() && receiver.is_done());
+        assert_eq!(
+            sender_io.events.last(),
+            Some(&TransferEvent::Failed("Cancelled by the receiver".into()))
+        );
+    }
+}
@@ -0,0 +1,1249 @@
+//! ZMODEM transfers, compatible with `sz` and `rz` of lrzsz.
+//!
+//! Frames start with a header: hex encoded for the short control frames,
+//! binary with CRC-16 or CRC-32 for the ones followed by data subpackets.
+//! The data is escaped with ZDLE so flow control characters never show up
+//! on the line.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use super::{
+    crc16, create_received, SendFile, Transfer, TransferEvent, TransferIo,
+    TransferProgress, ZmodemRequest,
+};
+
+const ZPAD: u8 = b'*';
+const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZSKIP: u8 = 5;
+const ZNAK: u8 = 6;
+const ZABORT: u8 = 7;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZFERR: u8 = 12;
+const ZCOMMAND: u8 = 18;
+
+/// Frame ends of data subpackets.
+const ZCRCE: u8 = b'h';
+const ZCRCG: u8 = b'i';
+const ZCRCQ: u8 = b'j';
+const ZCRCW: u8 = b'k';
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+/// ZRINIT capabilities, in ZF0.
+const CANFDX: u8 = 0x01;
+const CANOVIO: u8 = 0x02;
+const CANFC32: u8 = 0x20;
+const ESCCTL: u8 = 0x40;
+/// ZFILE conversion option for binary files, in ZF0.
+const ZCBIN: u8 = 1;
+
+/// Header prefix of ZRQINIT, sent by `sz`, and ZRINIT, sent by `rz`, without
+/// the last digit of the frame type.
+const DETECT_PATTERN: &[u8] = b"**\x18B0";
+
+/// Cancel sequence of lrzsz, the backspaces erase it from a shell prompt.
+const CANCEL_SEQUENCE: &[u8] = b"\x18\x18\x18\x18\x18\x18\x18\x18\x18\x18\
+    \x08\x08\x08\x08\x08\x08\x08\x08\x08\x08";
+
+/// How long the user has to answer a request of the remote.
+const OFFER_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long to wait for an answer before sending the last frame again.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the receiver waits for the final "OO" of the sender.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_RETRIES: u32 = 10;
+/// Data of one subpacket when sending.
+const SUBPACKET_SIZE: usize = 1024;
+/// Largest subpacket accepted, ZMODEM-8k uses 8 KiB ones.
+const MAX_SUBPACKET_SIZE: usize = 8192;
+/// Data sent before waiting for an acknowledgment, when the receiver
+/// doesn't ask for less.
+const WINDOW_SIZE: usize = 16 * 1024;
+
+/// CRC-32 of ZMODEM, the same as the one of zip and Ethernet.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc: u32, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    kind: u8,
+    /// ZP0 to ZP3, which are ZF3 to ZF0 for the frames carrying flags.
+    data: [u8; 4],
+}
+
+impl Header {
+    #[inline]
+    fn new(kind: u8, data: [u8; 4]) -> Self {
+        Self { kind, data }
+    }
+
+    #[inline]
+    fn at(kind: u8, position: u64) -> Self {
+        Self::new(kind, (position as u32).to_le_bytes())
+    }
+
+    #[inline]
+    fn position(&self) -> u64 {
+        u32::from_le_bytes(self.data) as u64
+    }
+
+    #[inline]
+    fn flags(&self) -> u8 {
+        self.data[3]
+    }
+
+    fn bytes(&self) -> [u8; 5] {
+        let [p0, p1, p2, p3] = self.data;
+        [self.kind, p0, p1, p2, p3]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Frame {
+    Header(Header),
+    /// A data subpacket and its frame end.
+    Data(Vec<u8>, u8),
+    /// A header or subpacket with a bad CRC or encoding.
+    Invalid,
+    /// The peer sent the cancel sequence.
+    Cancel,
+}
+
+#[derive(Debug, Default)]
+enum DecodeState {
+    #[default]
+    Idle,
+    Pad,
+    Format,
+    Hex(Vec<u8>),
+    Binary {
+        crc32: bool,
+        bytes: Vec<u8>,
+    },
+    Data {
+        crc32: bool,
+        bytes: Vec<u8>,
+        end: Option<u8>,
+        crc: Vec<u8>,
+    },
+}
+
+enum Unescaped {
+    Pending,
+    Byte(u8),
+    FrameEnd(u8),
+    Invalid,
+}
+
+/// Splits the received bytes into frames.
+#[derive(Debug, Default)]
+struct Decoder {
+    state: DecodeState,
+    escape: bool,
+    cans: usize,
+}
+
+impl Decoder {
+    fn push(&mut self, byte: u8, frames: &mut Vec<Frame>) {
+        if byte == ZDLE {
+            self.cans += 1;
+            if self.cans >= 5 {
+                self.cans = 0;
+                self.escape = false;
+                self.state = DecodeState::Idle;
+                frames.push(Frame::Cancel);
+                return;
+            }
+        } else {
+            self.cans = 0;
+        }
+        // Flow control characters are never part of a frame.
+        if byte & 0x7f == XON || byte & 0x7f == XOFF {
+            return;
+        }
+
+        let state = std::mem::take(&mut self.state);
+        self.state = match state {
+            DecodeState::Idle if byte == ZPAD => DecodeState::Pad,
+            DecodeState::Idle => DecodeState::Idle,
+            DecodeState::Pad => match byte {
+                ZPAD => DecodeState::Pad,
+                ZDLE => DecodeState::Format,
+                _ => DecodeState::Idle,
+            },
+            DecodeState::Format => match byte {
+                ZBIN | ZBIN32 => DecodeState::Binary {
+                    crc32: byte == ZBIN32,
+                    bytes: vec![],
+                },
+                ZHEX => DecodeState::Hex(vec![]),
+                _ => DecodeState::Idle,
+            },
+            DecodeState::Hex(digits) => self.hex(digits, byte, frames),
+            DecodeState::Binary { crc32, bytes } => {
+                self.binary(crc32, bytes, byte, frames)
+            },
+            DecodeState::Data {
+                crc32,
+                bytes,
+                end,
+                crc,
+            } => self.data(crc32, bytes, end, crc, byte, frames),
+        };
+    }
+
+    fn unescape(&mut self, byte: u8) -> Unescaped {
+        if !self.escape {
+            if byte == ZDLE {
+                self.escape = true;
+                return Unescaped::Pending;
+            }
+            return Unescaped::Byte(byte);
+        }
+
+        match byte {
+            // Part of a cancel sequence, counted by `push`.
+            ZDLE => Unescaped::Pending,
+            ZCRCE | ZCRCG | ZCRCQ | ZCRCW => {
+                self.escape = false;
+                Unescaped::FrameEnd(byte)
+            },
+            ZRUB0 => {
+                self.escape = false;
+                Unescaped::Byte(0x7f)
+            },
+            ZRUB1 => {
+                self.escape = false;
+                Unescaped::Byte(0xff)
+            },
+            byte if byte & 0x60 == 0x40 => {
+                self.escape = false;
+                Unescaped::Byte(byte ^ 0x40)
+            },
+            _ => {
+                self.escape = false;
+                Unescaped::Invalid
+            },
+        }
+    }
+
+    fn hex(
+        &mut self,
+        mut digits: Vec<u8>,
+        byte: u8,
+        frames: &mut Vec<Frame>,
+    ) -> DecodeState {
+        let Some(digit) = (byte as char).to_digit(16) else {
+            frames.push(Frame::Invalid);
+            return DecodeState::Idle;
+        };
+        digits.push(digit as u8);
+        if digits.len() < 14 {
+            return DecodeState::Hex(digits);
+        }
+
+        let bytes: Vec<u8> = digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect();
+        let crc = u16::from_be_bytes([bytes[5], bytes[6]]);
+        if crc16(&bytes[..5]) == crc {
+            let data = [bytes[1], bytes[2], bytes[3], bytes[4]];
+            frames.push(Frame::Header(Header::new(bytes[0], data)));
+        } else {
+            frames.push(Frame::Invalid);
+        }
+        DecodeState::Idle
+    }
+
+    fn binary(
+        &mut self,
+        crc32: bool,
+        mut bytes: Vec<u8>,
+        byte: u8,
+        frames: &mut Vec<Frame>,
+    ) -> DecodeState {
+        match self.unescape(byte) {
+            Unescaped::Pending => return DecodeState::Binary { crc32, bytes },
+            Unescaped::Byte(byte) => bytes.push(byte),
+            Unescaped::FrameEnd(_) | Unescaped::Invalid => {
+                frames.push(Frame::Invalid);
+                return DecodeState::Idle;
+            },
+        }
+        if bytes.len() < if crc32 { 9 } else { 7 } {
+            return DecodeState::Binary { crc32, bytes };
+        }
+
+        let is_valid = if crc32 {
+            let crc = [bytes[5], bytes[6], bytes[7], bytes[8]];
+            self::crc32(&bytes[..5]) == u32::from_le_bytes(crc)
+        } else {
+            crc16(&bytes[..5]) == u16::from_be_bytes([bytes[5], bytes[6]])
+        };
+        if !is_valid {
+            frames.push(Frame::Invalid);
+            return DecodeState::Idle;
+        }
+
+        let header =
+            Header::new(bytes[0], [bytes[1], bytes[2], bytes[3], bytes[4]]);
+        frames.push(Frame::Header(header));
+        match header.kind {
+            ZSINIT | ZFILE | ZDATA | ZCOMMAND => DecodeState::Data {
+                crc32,
+                bytes: vec![],
+                end: None,
+                crc: vec![],
+            },
+            _ => DecodeState::Idle,
+        }
+    }
+
+    fn data(
+        &mut self,
+        crc32: bool,
+        mut bytes: Vec<u8>,
+        mut end: Option<u8>,
+        mut crc: Vec<u8>,
+        byte: u8,
+        frames: &mut Vec<Frame>,
+    ) -> DecodeState {
+        match (self.unescape(byte), end) {
+            (Unescaped::Pending, _) => {},
+            (Unescaped::Byte(byte), None) => bytes.push(byte),
+            (Unescaped::Byte(byte), Some(_)) => crc.push(byte),
+            (Unescaped::FrameEnd(frame_end), None) => end = Some(frame_end),
+            (Unescaped::FrameEnd(_), Some(_)) | (Unescaped::Invalid, _) => {
+                frames.push(Frame::Invalid);
+                return DecodeState::Idle;
+            },
+        }
+        if bytes.len() > MAX_SUBPACKET_SIZE {
+            frames.push(Frame::Invalid);
+            return DecodeState::Idle;
+        }
+        let Some(frame_end) = end else {
+            return DecodeState::Data {
+                crc32,
+                bytes,
+                end,
+                crc,
+            };
+        };
+        if crc.len() < if crc32 { 4 } else { 2 } {
+            return DecodeState::Data {
+                crc32,
+                bytes,
+                end,
+                crc,
+            };
+        }
+
+        bytes.push(frame_end);
+        let is_valid = if crc32 {
+            self::crc32(&bytes)
+                == u32::from_le_bytes([crc[0], crc[1], crc[2], crc[3]])
+        } else {
+            crc16(&bytes) == u16::from_be_bytes([crc[0], crc[1]])
+        };
+        bytes.pop();
+        if !is_valid {
+            frames.push(Frame::Invalid);
+            return DecodeState::Idle;
+        }
+
+        frames.push(Frame::Data(bytes, frame_end));
+        match frame_end {
+            ZCRCG | ZCRCQ => DecodeState::Data {
+                crc32,
+                bytes: vec![],
+                end: None,
+                crc: vec![],
+            },
+            _ => DecodeState::Idle,
+        }
+    }
+
+    fn decode(&mut self, bytes: &[u8]) -> Vec<Frame> {
+        let mut frames = vec![];
+        for &byte in bytes {
+            self.push(byte, &mut frames);
+        }
+        frames
+    }
+}
+
+/// Writes frames, with the options the receiver asked for.
+#[derive(Debug, Default)]
+struct Encoder {
+    crc32: bool,
+    escape_controls: bool,
+}
+
+impl Encoder {
+    fn hex_header(header: Header, output: &mut Vec<u8>) {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+        let bytes = header.bytes();
+        output.extend_from_slice(&[ZPAD, ZPAD, ZDLE, ZHEX]);
+        for byte in bytes.iter().chain(&crc16(&bytes).to_be_bytes()) {
+            output.push(DIGITS[(byte >> 4) as usize]);
+            output.push(DIGITS[(byte & 0xf) as usize]);
+        }
+        output.extend_from_slice(b"\r\x8a");
+        if header.kind != ZACK && header.kind != ZFIN {
+            output.push(XON);
+        }
+    }
+
+    fn binary_header(&self, header: Header, output: &mut Vec<u8>) {
+        let bytes = header.bytes();
+        output.extend_from_slice(&[
+            ZPAD,
+            ZDLE,
+            if self.crc32 { ZBIN32 } else { ZBIN },
+        ]);
+        self.escape(&bytes, output);
+        if self.crc32 {
+            self.escape(&crc32(&bytes).to_le_bytes(), output);
+        } else {
+            self.escape(&crc16(&bytes).to_be_bytes(), output);
+        }
+    }
+
+    fn data(&self, data: &[u8], frame_end: u8, output: &mut Vec<u8>) {
+        self.escape(data, output);
+        output.extend_from_slice(&[ZDLE, frame_end]);
+        let mut covered = data.to_vec();
+        covered.push(frame_end);
+        if self.crc32 {
+            self.escape(&crc32(&covered).to_le_bytes(), output);
+        } else {
+            self.escape(&crc16(&covered).to_be_bytes(), output);
+        }
+    }
+
+    fn escape(&self, bytes: &[u8], output: &mut Vec<u8>) {
+        for &byte in bytes {
+            let needs_escape = match byte & 0x7f {
+                ZDLE | 0x10 | XON | XOFF => true,
+                low => self.escape_controls && low < 0x20,
+            };
+            if needs_escape {
+                output.extend_from_slice(&[ZDLE, byte ^ 0x40]);
+            } else {
+                output.push(byte);
+            }
+        }
+    }
+}
+
+/// Finds the header the remote `sz` or `rz` starts with.
+#[derive(Debug, Default)]
+pub(crate) struct ZmodemDetector {
+    matched: usize,
+}
+
+impl ZmodemDetector {
+    /// Length of the detected part of the header.
+    pub(crate) const HEADER_LEN: usize = DETECT_PATTERN.len() + 1;
+
+    /// Returns the end of the header and what the remote asks for.
+    pub(crate) fn find(
+        &mut self,
+        bytes: &[u8],
+    ) -> Option<(usize, ZmodemRequest)> {
+        for (index, &byte) in bytes.iter().enumerate() {
+            if self.matched == DETECT_PATTERN.len() {
+                self.matched = 0;
+                match byte {
+                    b'0' => return Some((index + 1, ZmodemRequest::Receive)),
+                    b'1' => return Some((index + 1, ZmodemRequest::Send)),
+                    _ => {},
+                }
+            }
+
+            if byte == DETECT_PATTERN[self.matched] {
+                self.matched += 1;
+            } else if byte == ZPAD {
+                // "***" still ends with the two pads.
+                self.matched = if self.matched == 2 { 2 } else { 1 };
+            } else {
+                self.matched = 0;
+            }
+        }
+        None
+    }
+}
+
+/// Holds the stream after the remote started `sz` or `rz`, until the user
+/// answers with a transfer or cancels.
+#[derive(Debug)]
+pub(crate) struct ZmodemOffer {
+    request: ZmodemRequest,
+    decoder: Decoder,
+    deadline: Option<Instant>,
+    is_done: bool,
+}
+
+impl ZmodemOffer {
+    pub(crate) fn new(request: ZmodemRequest) -> Self {
+        Self {
+            request,
+            decoder: Decoder::default(),
+            deadline: None,
+            is_done: false,
+        }
+    }
+
+    fn fail(&mut self, reason: &str, io: &mut TransferIo) {
+        self.is_done = true;
+        self.deadline = None;
+        io.events.push(TransferEvent::Failed(reason.to_owned()));
+    }
+}
+
+impl Transfer for ZmodemOffer {
+    fn start(&mut self, now: Instant, io: &mut TransferIo) {
+        self.deadline = Some(now + OFFER_TIMEOUT);
+        io.events.push(TransferEvent::ZmodemRequest(self.request));
+    }
+
+    fn receive(&mut self, bytes: &[u8], _now: Instant, io: &mut TransferIo) {
+        // The remote repeats its header meanwhile, only a cancel matters.
+        if self.decoder.decode(bytes).contains(&Frame::Cancel) {
+            self.fail("Cancelled by the remote", io);
+        }
+    }
+
+    fn timeout(&mut self, _now: Instant, io: &mut TransferIo) {
+        io.output.extend_from_slice(CANCEL_SEQUENCE);
+        self.fail("The ZMODEM request was not answered", io);
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn cancel(&mut self, io: &mut TransferIo) {
+        if !self.is_done {
+            io.output.extend_from_slice(CANCEL_SEQUENCE);
+            self.fail("Cancelled", io);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.is_done
+    }
+
+    fn is_offer(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SendState {
+    WaitInit,
+    WaitFilePosition,
+    WaitAck,
+    WaitEofAck,
+    WaitFin,
+    Done,
+}
+
+#[derive(Debug)]
+pub(crate) struct ZmodemSender {
+    files: VecDeque<SendFile>,
+    file: Option<SendFile>,
+    state: SendState,
+    decoder: Decoder,
+    encoder: Encoder,
+    window: usize,
+    offset: u64,
+    /// End of the data sent since the last acknowledgment.
+    window_end: u64,
+    /// Last frames, sent again on timeout.
+    last: Vec<u8>,
+    retries: u32,
+    total_retries: u32,
+    deadline: Option<Instant>,
+}
+
+impl ZmodemSender {
+    pub(crate) fn new(files: Vec<SendFile>) -> Self {
+        Self {
+            files: files.into(),
+            file: None,
+            state: SendState::WaitInit,
+            decoder: Decoder::default(),
+            encoder: Encoder::default(),
+            window: WINDOW_SIZE,
+            offset: 0,
+            window_end: 0,
+            last: vec![],
+            retries: 0,
+            total_retries: 0,
+            deadline: None,
+        }
+    }
+
+    fn send(
+        &mut self,
+        output: Vec<u8>,
+        state: SendState,
+        now: Instant,
+        io: &mut TransferIo,
+    ) {
+        io.output.extend_from_slice(&output);
+        self.last = output;
+        self.state = state;
+        self.deadline = Some(now + FRAME_TIMEOUT);
+    }
+
+    fn resend(&mut self, now: Instant, io: &mut TransferIo) {
+        self.retries += 1;
+        self.total_retries += 1;
+        if self.retries > MAX_RETRIES {
+            io.output.extend_from_slice(CANCEL_SEQUENCE);
+            self.fail("Too many retries", io);
+            return;
+        }
+
+        io.output.extend_from_slice(&self.last);
+        self.deadline = Some(now + FRAME_TIMEOUT);
+    }
+
+    /// Offer the next file, or end the session.
+    fn next_file(&mut self, now: Instant, io: &mut TransferIo) {
+        self.file = self.files.pop_front();
+        self.offset = 0;
+        let mut output = vec![];
+        let Some(file) = &self.file else {
+            Encoder::hex_header(Header::at(ZFIN, 0), &mut output);
+            self.send(output, SendState::WaitFin, now, io);
+            return;
+        };
+
+        let mut info = file.name.as_bytes().to_vec();
+        info.push(0);
+        info.extend(file.data.len().to_string().bytes());
+        if let Some(modified) = file
+            .modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        {
+            info.extend(format!(" {:o}", modified.as_secs()).bytes());
+        }
+        info.push(0);
+        self.encoder
+            .binary_header(Header::new(ZFILE, [0, 0, 0, ZCBIN]), &mut output);
+        self.encoder.data(&info, ZCRCW, &mut output);
+        self.progress(io);
+        self.send(output, SendState::WaitFilePosition, now, io);
+    }
+
+    /// Send the data from `offset` up to the window, or ZEOF at the end.
+    fn send_data(&mut self, now: Instant, io: &mut TransferIo) {
+        let data = self.file.as_ref().map_or(&[][..], |file| &file.data);
+        let mut output = vec![];
+        let offset = (self.offset as usize).min(data.len());
+        if offset == data.len() {
+            self.encoder
+                .binary_header(Header::at(ZEOF, offset as u64), &mut output);
+            self.send(output, SendState::WaitEofAck, now, io);
+            return;
+        }
+
+        let end = (offset + self.window).min(data.len());
+        self.encoder
+            .binary_header(Header::at(ZDATA, offset as u64), &mut output);
+        let mut chunks = data[offset..end].chunks(SUBPACKET_SIZE).peekable();
+        while let Some(chunk) = chunks.next() {
+            let frame_end = if chunks.peek().is_some() {
+                ZCRCG
+            } else {
+                ZCRCW
+            };
+            self.encoder.data(chunk, frame_end, &mut output);
+        }
+        self.window_end = end as u64;
+        self.send(output, SendState::WaitAck, now, io);
+    }
+
+    fn progress(&self, io: &mut TransferIo) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        io.events.push(TransferEvent::Progress(TransferProgress {
+            file: file.name.clone(),
+            transferred: self.offset.min(file.data.len() as u64),
+            total: Some(file.data.len() as u64),
+            retries: self.total_retries,
+        }));
+    }
+
+    fn finish(&mut self, io: &mut TransferIo) {
+        self.state = SendState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Finished);
+    }
+
+    fn fail(&mut self, reason: &str, io: &mut TransferIo) {
+        self.state = SendState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Failed(reason.to_owned()));
+    }
+
+    fn header(&mut self, header: Header, now: Instant, io: &mut TransferIo) {
+        match (self.state, header.kind) {
+            (SendState::WaitInit | SendState::WaitEofAck, ZRINIT) => {
+                let flags = header.flags();
+                self.encoder.crc32 = flags & CANFC32 != 0;
+                self.encoder.escape_controls = flags & ESCCTL != 0;
+                let buffer =
+                    u16::from_le_bytes([header.data[0], header.data[1]]);
+                self.window = match buffer as usize {
+                    0 => WINDOW_SIZE,
+                    buffer => buffer.max(SUBPACKET_SIZE),
+                };
+                self.retries = 0;
+                self.next_file(now, io);
+            },
+            (
+                SendState::WaitFilePosition
+                | SendState::WaitAck
+                | SendState::WaitEofAck,
+                ZRPOS,
+            ) => {
+                if self.state != SendState::WaitFilePosition {
+                    self.total_retries += 1;
+                }
+                self.offset = header.position();
+                self.retries = 0;
+                self.send_data(now, io);
+            },
+            (SendState::WaitFilePosition, ZSKIP) => self.next_file(now, io),
+            (SendState::WaitAck, ZACK)
+                if header.position() == self.window_end =>
+            {
+                self.offset = self.window_end;
+                self.retries = 0;
+                self.progress(io);
+                self.send_data(now, io);
+            },
+            (SendState::WaitFin, ZFIN) => {
+                io.output.extend_from_slice(b"OO");
+                self.finish(io);
+            },
+            (_, ZNAK) => self.resend(now, io),
+            (_, ZABORT | ZFERR) => self.fail("Aborted by the receiver", io),
+            _ => {},
+        }
+    }
+}
+
+impl Transfer for ZmodemSender {
+    fn start(&mut self, now: Instant, io: &mut TransferIo) {
+        // Starts the remote `rz` unless it is already running.
+        let mut output = b"rz\r".to_vec();
+        Encoder::hex_header(Header::at(ZRQINIT, 0), &mut output);
+        self.send(output, SendState::WaitInit, now, io);
+    }
+
+    fn receive(&mut self, bytes: &[u8], now: Instant, io: &mut TransferIo) {
+        for frame in self.decoder.decode(bytes) {
+            if self.state == SendState::Done {
+                break;
+            }
+
+            match frame {
+                Frame::Header(header) => self.header(header, now, io),
+                Frame::Cancel => self.fail("Cancelled by the receiver", io),
+                Frame::Data(..) | Frame::Invalid => {},
+            }
+        }
+    }
+
+    fn timeout(&mut self, now: Instant, io: &mut TransferIo) {
+        if self.state != SendState::Done {
+            self.resend(now, io);
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn cancel(&mut self, io: &mut TransferIo) {
+        if self.state != SendState::Done {
+            io.output.extend_from_slice(CANCEL_SEQUENCE);
+            self.fail("Cancelled", io);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.state == SendState::Done
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReceiveState {
+    /// Waiting for a file or the end of the session.
+    Init,
+    Receiving,
+    /// Waiting for the "OO" ending the session.
+    Closing,
+    Done,
+}
+
+#[derive(Debug)]
+struct ReceivedFile {
+    name: String,
+    path: PathBuf,
+    file: File,
+    size: Option<u64>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ZmodemReceiver {
+    /// Directory of the received files.
+    destination: PathBuf,
+    state: ReceiveState,
+    decoder: Decoder,
+    /// Header whose data subpacket comes next.
+    pending: Option<u8>,
+    file: Option<ReceivedFile>,
+    offset: u64,
+    /// Whether the data subpackets continue at `offset`.
+    accepting: bool,
+    last: Vec<u8>,
+    overs: u8,
+    retries: u32,
+    total_retries: u32,
+    deadline: Option<Instant>,
+}
+
+impl ZmodemReceiver {
+    pub(crate) fn new(destination: PathBuf) -> Self {
+        Self {
+            destination,
+            state: ReceiveState::Init,
+            decoder: Decoder::default(),
+            pending: None,
+            file: None,
+            offset: 0,
+            accepting: false,
+            last: vec![],
+            overs: 0,
+            retries: 0,
+            total_retries: 0,
+            deadline: None,
+        }
+    }
+
+    fn send(&mut self, header: Header, now: Instant, io: &mut TransferIo) {
+        let mut output = vec![];
+        Encoder::hex_header(header, &mut output);
+        io.output.extend_from_slice(&output);
+        self.last = output;
+        self.deadline = Some(now + FRAME_TIMEOUT);
+    }
+
+    fn send_init(&mut self, now: Instant, io: &mut TransferIo) {
+        let flags = CANFDX | CANOVIO | CANFC32;
+        self.send(Header::new(ZRINIT, [0, 0, 0, flags]), now, io);
+    }
+
+    /// Ask the sender to go on from `offset` after an error.
+    fn reposition(&mut self, now: Instant, io: &mut TransferIo) {
+        self.accepting = false;
+        self.retries += 1;
+        self.total_retries += 1;
+        if self.retries > MAX_RETRIES {
+            io.output.extend_from_slice(CANCEL_SEQUENCE);
+            self.fail("Too many retries", io);
+            return;
+        }
+
+        self.send(Header::at(ZRPOS, self.offset), now, io);
+    }
+
+    fn progress(&self, io: &mut TransferIo) {
+        let Some(file) = &self.file else {
+            return;
+        };
+
+        io.events.push(TransferEvent::Progress(TransferProgress {
+            file: file.name.clone(),
+            transferred: self.offset,
+            total: file.size,
+            retries: self.total_retries,
+        }));
+    }
+
+    fn finish(&mut self, io: &mut TransferIo) {
+        self.state = ReceiveState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Finished);
+    }
+
+    fn fail(&mut self, reason: &str, io: &mut TransferIo) {
+        self.state = ReceiveState::Done;
+        self.deadline = None;
+        io.events.push(TransferEvent::Failed(reason.to_owned()));
+    }
+
+    fn fail_io(&mut self, err: std::io::Error, io: &mut TransferIo) {
+        io.output.extend_from_slice(CANCEL_SEQUENCE);
+        self.fail(&format!("Error writing the file: {}", err), io);
+    }
+
+    /// Open the file described by the data of ZFILE.
+    fn open_file(&mut self, info: &[u8], now: Instant, io: &mut TransferIo) {
+        let name_end = info.iter().position(|&b| b == 0).unwrap_or(info.len());
+        let name = String::from_utf8_lossy(&info[..name_end]).into_owned();
+        let size = info
+            .get(name_end + 1..)
+            .and_then(|rest| rest.split(|&b| b == b' ' || b == 0).next())
+            .and_then(|size| std::str::from_utf8(size).ok()?.parse().ok());
+        match create_received(&self.destination, &name) {
+            Ok((path, file)) => {
+                self.file = Some(ReceivedFile {
+                    name,
+                    path,
+                    file,
+                    size,
+                });
+                self.offset = 0;
+                self.accepting = false;
+                self.retries = 0;
+                self.state = ReceiveState::Receiving;
+                self.progress(io);
+                self.send(Header::at(ZRPOS, 0), now, io);
+            },
+            Err(err) => self.fail_io(err, io),
+        }
+    }
+
+    fn data(
+        &mut self,
+        data: &[u8],
+        frame_end: u8,
+        now: Instant,
+        io: &mut TransferIo,
+    ) {
+        match self.pending.take() {
+            Some(ZFILE) => return self.open_file(data, now, io),
+            Some(ZSINIT) => return self.send(Header::at(ZACK, 0), now, io),
+            _ => {},
+        }
+        if !self.accepting {
+            return;
+        }
+        let Some(file) = &mut self.file else {
+            return;
+        };
+
+        if let Err(err) = file.file.write_all(data) {
+            return self.fail_io(err, io);
+        }
+        self.offset += data.len() as u64;
+        self.retries = 0;
+        self.progress(io);
+        if frame_end == ZCRCW || frame_end == ZCRCQ {
+            self.send(Header::at(ZACK, self.offset), now, io);
+        }
+        if frame_end == ZCRCW || frame_end == ZCRCE {
+            self.accepting = false;
+        }
+        self.deadline = Some(now + FRAME_TIMEOUT);
+    }
+
+    fn header(&mut self, header: Header, now: Instant, io: &mut TransferIo) {
+        self.pending = None;
+        match (self.state, header.kind) {
+            (ReceiveState::Init, ZRQINIT) => self.send_init(now, io),
+            (ReceiveState::Init, ZSINIT | ZFILE) => {
+                self.pending = Some(header.kind)
+            },
+            (ReceiveState::Receiving, ZDATA) => {
+                if header.position() == self.offset {
+                    self.accepting = true;
+                } else {
+                    self.reposition(now, io);
+                }
+            },
+            (ReceiveState::Receiving, ZEOF)
+                if header.position() == self.offset =>
+            {
+                let Some(file) = self.file.take() else {
+                    return;
+                };
+                if let Err(err) = file.file.sync_all() {
+                    return self.fail_io(err, io);
+                }
+                io.events.push(TransferEvent::FileReceived(file.path));
+                self.state = ReceiveState::Init;
+                self.retries = 0;
+                self.send_init(now, io);
+            },
+            (ReceiveState::Init | ReceiveState::Closing, ZFIN) => {
+                self.state = ReceiveState::Closing;
+                self.send(Header::at(ZFIN, 0), now, io);
+                self.deadline = Some(now + CLOSE_TIMEOUT);
+            },
+            (_, ZCOMMAND) => {
+                io.output.extend_from_slice(CANCEL_SEQUENCE);
+                self.fail("Remote commands are not supported", io);
+            },
+            _ => {},
+        }
+    }
+}
+
+impl Transfer for ZmodemReceiver {
+    fn start(&mut self, now: Instant, io: &mut TransferIo) {
+        self.send_init(now, io);
+    }
+
+    fn receive(&mut self, bytes: &[u8], now: Instant, io: &mut TransferIo) {
+        if self.state == ReceiveState::Closing {
+            let overs = bytes.iter().filter(|&&b| b == b'O').count();
+            self.overs = self
+                .overs
+                .saturating_add(u8::try_from(overs).unwrap_or(u8::MAX));
+            if self.overs >= 2 {
+                self.finish(io);
+                return;
+            }
+        }
+
+        for frame in self.decoder.decode(bytes) {
+            if self.state == ReceiveState::Done {
+                break;
+            }
+
+            match frame {
+                Frame::Header(header) => self.header(header, now, io),
+                Frame::Data(data, frame_end) => {
+                    self.data(&data, frame_end, now, io)
+                },
+                // Whatever follows a bad subpacket is ignored until the
+                // sender goes back to `offset`.
+                Frame::Invalid if self.accepting => self.reposition(now, io),
+                Frame::Invalid => {},
+                Frame::Cancel => self.fail("Cancelled by the sender", io),
+            }
+        }
+    }
+
+    fn timeout(&mut self, now: Instant, io: &mut TransferIo) {
+        match self.state {
+            ReceiveState::Init => {
+                self.retries += 1;
+                if self.retries > MAX_RETRIES {
+                    io.output.extend_from_slice(CANCEL_SEQUENCE);
+                    self.fail("No response from the sender", io);
+                    return;
+                }
+                io.output.extend_from_slice(&self.last);
+                self.deadline = Some(now + FRAME_TIMEOUT);
+            },
+            ReceiveState::Receiving => self.reposition(now, io),
+            // The "OO" is optional.
+            ReceiveState::Closing => self.finish(io),
+            ReceiveState::Done => {},
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn cancel(&mut self, io: &mut TransferIo) {
+        if self.state != ReceiveState::Done {
+            io.output.extend_from_slice(CANCEL_SEQUENCE);
+            self.fail("Cancelled", io);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.state == ReceiveState::Done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_path;
+    use crate::transfer::tests::{run, test_data};
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn detects_sz_and_rz() {
+        let mut detector = ZmodemDetector::default();
+        assert_eq!(detector.find(b"$ sz log.txt\r\nrz\r**\x18B0"), None);
+        assert_eq!(
+            detector.find(b"0000000000000"),
+            Some((1, ZmodemRequest::Receive))
+        );
+        assert_eq!(
+            detector.find(b"$ rz\r\n***\x18B0100000023be50\r\x8a\x11"),
+            Some((13, ZmodemRequest::Send))
+        );
+        assert_eq!(detector.find(b"**\x18B02"), None);
+    }
+
+    #[test]
+    fn escaped_frames_round_trip() {
+        let encoder = Encoder {
+            crc32: true,
+            escape_controls: false,
+        };
+        let data: Vec<u8> = (0..=255).collect();
+        let mut output = vec![];
+        encoder.binary_header(Header::at(ZDATA, 0x1118), &mut output);
+        encoder.data(&data, ZCRCG, &mut output);
+        encoder.data(b"*\x18", ZCRCE, &mut output);
+        assert!(!output.iter().any(|&b| b & 0x7f == XON || b & 0x7f == XOFF));
+        Encoder::hex_header(Header::at(ZEOF, 258), &mut output);
+
+        let frames = Decoder::default().decode(&output);
+        assert_eq!(
+            frames,
+            [
+                Frame::Header(Header::at(ZDATA, 0x1118)),
+                Frame::Data(data, ZCRCG),
+                Frame::Data(b"*\x18".to_vec(), ZCRCE),
+                Frame::Header(Header::at(ZEOF, 258)),
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_with_corrupted_subpacket() {
+        let dir = temp_path("zmodem");
+        std::fs::create_dir(&dir).unwrap();
+        let files = vec![
+            SendFile {
+                name: "rootfs.img".into(),
+                data: test_data(40_000),
+                modified: None,
+            },
+            SendFile {
+                name: "empty".into(),
+                data: vec![],
+                modified: None,
+            },
+        ];
+        let mut sender = ZmodemSender::new(files);
+        let mut receiver = ZmodemReceiver::new(dir.clone());
+
+        let mut corrupted = false;
+        let (sent, received) = run(&mut sender, &mut receiver, |bytes| {
+            if !corrupted && bytes.len() > 10_000 {
+                corrupted = true;
+                bytes[5_000] ^= 0x01;
+            }
+        });
+
+        assert!(corrupted);
+        assert_eq!(sent.last(), Some(&TransferEvent::Finished));
+        assert_eq!(received.last(), Some(&TransferEvent::Finished));
+        assert!(received.iter().any(|event| matches!(
+            event,
+            TransferEvent::Progress(progress) if progress.retries == 1
+        )));
+        assert_eq!(
+            std::fs::read(dir.join("rootfs.img")).unwrap(),
+            test_data(40_000)
+        );
+        assert!(std::fs::read(dir.join("empty")).unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn received_file_keeps_existing_one() {
+        let dir = temp_path("zmodem-existing");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("a.bin"), b"old").unwrap();
+        let mut sender = ZmodemSender::new(vec![SendFile {
+            name: "a.bin".into(),
+            data: test_data(100),
+            modified: None,
+        }]);
+        let mut receiver = ZmodemReceiver::new(dir.clone());
+
+        let (_, received) = run(&mut sender, &mut receiver, |_| {});
+        assert!(received
+            .contains(&TransferEvent::FileReceived(dir.join("a-1.bin"))));
+        assert_eq!(std::fs::read(dir.join("a.bin")).unwrap(), b"old");
+        assert_eq!(std::fs::read(dir.join("a-1.bin")).unwrap(), test_data(100));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn many_overs_while_closing() {
+        let mut receiver = ZmodemReceiver::new(temp_path("zmodem-overs"));
+        let mut io = TransferIo::default();
+        let now = Instant::now();
+        receiver.state = ReceiveState::Closing;
+        receiver.receive(&[b'O'; 300], now, &mut io);
+        assert!(receiver.is_done());
+        assert_eq!(io.events, [TransferEvent::Finished]);
+    }
+
+    #[test]
+    fn receiver_cancel_stops_sender() {
+        let mut sender = ZmodemSender::new(vec![SendFile {
+            name: "a".into(),
+            data: test_data(100),
+            modified: None,
+        }]);
+        let mut receiver = ZmodemReceiver::new(temp_path("zmodem-cancel"));
+
+        let mut sender_io = TransferIo::default();
+        let mut receiver_io = TransferIo::default();
+        let now = Instant::now();
+        sender.start(now, &mut sender_io);
+        receiver.cancel(&mut receiver_io);
+        sender.receive(&receiver_io.output, now, &mut sender_io);
+        assert!(sender.is_done());
+        assert_eq!(
+            sender_io.events.last(),
+            Some(&TransferEvent::Failed("Cancelled by the receiver".into()))
+        );
+    }
+}
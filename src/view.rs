@@ -7,16 +7,23 @@ use egui::Modifiers;
 use egui::MouseWheelUnit;
 use egui::Shape;
 use egui::Widget;
-use egui::{Align2, Painter, Pos2, Rect, Response, Stroke, Vec2};
+use egui::{Align2, Color32, Painter, Pos2, Rect, Response, Stroke, Vec2};
 use egui::{CornerRadius, Key};
 use egui::{Id, PointerButton};
 
+use crate::backend::hex_dump::{DisplayMode, HexDumpRow};
 use crate::backend::BackendCommand;
-use crate::backend::SerialMonitorBackend;
 use crate::backend::{LinkAction, MouseButton, SelectionType};
+use crate::backend::{RenderableContent, SerialMonitorBackend};
 use crate::bindings::Binding;
 use crate::bindings::{BindingAction, BindingsLayout, InputKind};
 use crate::font::TerminalFont;
+use crate::highlight::HighlightRule;
+use crate::line_input::{show_line_input, InputMode, LineInputState};
+use crate::search_bar::{show_search_bar, SearchBarState};
+use crate::serial_tty::control_tokens::{CONTROL_TOKEN_URI, INVALID_TOKEN_URI};
+use crate::serial_tty::line_stamps::TimestampFormat;
+use crate::serial_tty::tap::TrafficDirection;
 use crate::theme::TerminalTheme;
 use crate::types::Size;
 
@@ -26,6 +33,7 @@ const EGUI_TERM_WIDGET_ID_PREFIX: &str = "egui_term::instance::";
 enum InputAction {
     BackendCall(BackendCommand),
     WriteToClipboard(String),
+    OpenSearch,
     Ignore,
 }
 
@@ -34,6 +42,8 @@ pub struct SerialMonitorViewState {
     is_dragged: bool,
     scroll_pixels: f32,
     current_mouse_position_on_grid: TerminalGridPoint,
+    line_input: LineInputState,
+    search_bar: SearchBarState,
 }
 
 pub struct SerialMonitorView<'a> {
@@ -44,12 +54,20 @@ pub struct SerialMonitorView<'a> {
     font: TerminalFont,
     theme: TerminalTheme,
     bindings_layout: BindingsLayout,
+    timestamp_format: Option<TimestampFormat>,
+    highlight_rules: Option<&'a [HighlightRule]>,
 }
 
 impl Widget for SerialMonitorView<'_> {
     fn ui(self, ui: &mut egui::Ui) -> Response {
-        let (layout, painter) =
-            ui.allocate_painter(self.size, egui::Sense::click());
+        let input_mode = self.backend.input_mode();
+        let mut size = self.size;
+        if input_mode == InputMode::Line {
+            size.y -=
+                ui.spacing().interact_size.y + ui.spacing().item_spacing.y;
+        }
+
+        let (layout, painter) = ui.allocate_painter(size, egui::Sense::click());
 
         let widget_id = self.widget_id;
         let mut state = ui.memory(|m| {
@@ -58,7 +76,26 @@ impl Widget for SerialMonitorView<'_> {
                 .unwrap_or_default()
         });
 
-        self.focus(&layout)
+        if input_mode == InputMode::Line {
+            show_line_input(
+                ui,
+                self.backend,
+                &mut state.line_input,
+                self.has_focus && !state.search_bar.is_open(),
+            );
+        }
+
+        if state.search_bar.is_open() {
+            show_search_bar(
+                ui,
+                widget_id,
+                layout.rect,
+                self.backend,
+                &mut state.search_bar,
+            );
+        }
+
+        self.focus(&layout, &state)
             .resize(&layout)
             .process_input(&layout, &mut state)
             .show(&mut state, &layout, &painter);
@@ -86,6 +123,8 @@ impl<'a> SerialMonitorView<'a> {
             font: TerminalFont::default(),
             theme: TerminalTheme::default(),
             bindings_layout: BindingsLayout::new(),
+            timestamp_format: None,
+            highlight_rules: None,
         }
     }
 
@@ -113,6 +152,20 @@ impl<'a> SerialMonitorView<'a> {
         self
     }
 
+    /// Colorize the text matching the rules, earlier rules first.
+    #[inline]
+    pub fn set_highlight_rules(mut self, rules: &'a [HighlightRule]) -> Self {
+        self.highlight_rules = Some(rules);
+        self
+    }
+
+    /// Draw a gutter with the RX timestamp of every line.
+    #[inline]
+    pub fn set_timestamps(mut self, format: Option<TimestampFormat>) -> Self {
+        self.timestamp_format = format;
+        self
+    }
+
     #[inline]
     pub fn add_bindings(
         mut self,
@@ -122,8 +175,13 @@ impl<'a> SerialMonitorView<'a> {
         self
     }
 
-    fn focus(self, layout: &Response) -> Self {
-        if self.has_focus {
+    fn focus(self, layout: &Response, state: &SerialMonitorViewState) -> Self {
+        // Keyboard belongs to the line editor in line mode, and to the
+        // search bar while it's open.
+        if self.has_focus
+            && self.backend.input_mode() == InputMode::Raw
+            && !state.search_bar.is_open()
+        {
             layout.request_focus();
         } else {
             layout.surrender_focus();
@@ -132,39 +190,93 @@ impl<'a> SerialMonitorView<'a> {
         self
     }
 
+    /// Width of the timestamp gutter in points.
+    fn gutter_width(&self, ctx: &egui::Context) -> f32 {
+        self.timestamp_format.map_or(0.0, |format| {
+            (format.width() + 1) as f32
+                * self.font.font_measure(ctx).width.floor()
+        })
+    }
+
     fn resize(self, layout: &Response) -> Self {
+        let mut size = layout.rect.size();
+        size.x -= self.gutter_width(&layout.ctx);
         self.backend.process_command(BackendCommand::Resize(
-            Size::from(layout.rect.size()),
+            Size::from(size),
             self.font.font_measure(&layout.ctx),
         ));
 
         self
     }
 
+    /// Whether the search binding was pressed, looked up on its own while
+    /// the line editor takes the other keys.
+    fn search_key_pressed(&self, layout: &Response) -> bool {
+        let terminal_mode = self.backend.last_content().terminal_mode;
+        layout.ctx.input(|i| {
+            i.events.iter().any(|event| match event {
+                egui::Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => {
+                    self.bindings_layout.get_action(
+                        InputKind::KeyCode(*key),
+                        *modifiers,
+                        terminal_mode,
+                    ) == BindingAction::Search
+                },
+                _ => false,
+            })
+        })
+    }
+
     fn process_input(
         self,
         layout: &Response,
         state: &mut SerialMonitorViewState,
     ) -> Self {
-        if !layout.has_focus() || !layout.contains_pointer() {
+        let is_raw_input = self.backend.input_mode() == InputMode::Raw
+            && !state.search_bar.is_open();
+        if self.backend.input_mode() == InputMode::Line
+            && state.line_input.has_focus()
+            && self.search_key_pressed(layout)
+        {
+            state.search_bar.open();
+            layout.ctx.request_repaint();
+        }
+        if (is_raw_input && !layout.has_focus()) || !layout.contains_pointer() {
             return self;
         }
 
         let modifiers = layout.ctx.input(|i| i.modifiers);
         let events = layout.ctx.input(|i| i.events.clone());
+        let gutter_offset = Vec2::new(self.gutter_width(&layout.ctx), 0.0);
         for event in events {
             let mut input_actions = vec![];
 
             match event {
+                egui::Event::Copy | egui::Event::Paste(_) if !is_raw_input => {
+                    input_actions.push(process_clipboard_event(
+                        event,
+                        state,
+                        self.backend,
+                        self.timestamp_format,
+                    ))
+                },
                 egui::Event::Text(_)
                 | egui::Event::Key { .. }
                 | egui::Event::Copy
-                | egui::Event::Paste(_) => {
+                | egui::Event::Paste(_)
+                    if is_raw_input =>
+                {
                     input_actions.push(process_keyboard_event(
                         event,
                         self.backend,
                         &self.bindings_layout,
                         modifiers,
+                        self.timestamp_format,
                     ))
                 },
                 egui::Event::MouseWheel { unit, delta, .. } => input_actions
@@ -186,7 +298,7 @@ impl<'a> SerialMonitorView<'a> {
                     self.backend,
                     &self.bindings_layout,
                     button,
-                    pos,
+                    pos - gutter_offset,
                     &modifiers,
                     pressed,
                 )),
@@ -195,7 +307,7 @@ impl<'a> SerialMonitorView<'a> {
                         state,
                         layout,
                         self.backend,
-                        pos,
+                        pos - gutter_offset,
                         &modifiers,
                     )
                 },
@@ -210,6 +322,10 @@ impl<'a> SerialMonitorView<'a> {
                     InputAction::WriteToClipboard(data) => {
                         layout.ctx.copy_text(data);
                     },
+                    InputAction::OpenSearch => {
+                        state.search_bar.open();
+                        layout.ctx.request_repaint();
+                    },
                     InputAction::Ignore => {},
                 }
             }
@@ -224,6 +340,11 @@ impl<'a> SerialMonitorView<'a> {
         layout: &Response,
         painter: &Painter,
     ) {
+        let gutter_width = self.gutter_width(&layout.ctx);
+        // Rules set on the backend directly stay while the view has none.
+        if let Some(rules) = self.highlight_rules {
+            self.backend.set_highlight_rules(rules);
+        }
         let content = self.backend.sync();
         let layout_min = layout.rect.min;
         let layout_max = layout.rect.max;
@@ -238,6 +359,44 @@ impl<'a> SerialMonitorView<'a> {
             global_bg,
         ))];
 
+        if content.display_mode == DisplayMode::Hex {
+            shapes.extend(hex_dump_shapes(
+                content,
+                &self.theme,
+                &self.font,
+                painter,
+                layout_min,
+            ));
+            painter.extend(shapes);
+            return;
+        }
+
+        if !content.archive_rows.is_empty() {
+            shapes.extend(archive_shapes(
+                content,
+                self.timestamp_format,
+                &self.theme,
+                &self.font,
+                painter,
+                layout_min,
+                gutter_width,
+            ));
+            painter.extend(shapes);
+            return;
+        }
+
+        if let Some(format) = self.timestamp_format {
+            shapes.extend(timestamp_gutter_shapes(
+                content,
+                format,
+                &self.theme,
+                &self.font,
+                painter,
+                layout_min,
+            ));
+        }
+
+        let layout_min = layout_min + Vec2::new(gutter_width, 0.0);
         for indexed in content.grid.display_iter() {
             let flags = indexed.cell.flags;
             let is_wide_char_spacer =
@@ -278,6 +437,48 @@ impl<'a> SerialMonitorView<'a> {
                 fg = fg.linear_multiply(0.7);
             }
 
+            let highlight = content
+                .highlights
+                .get(line_num as usize)
+                .and_then(|spans| {
+                    spans.iter().find(|span| {
+                        span.columns.contains(&indexed.point.column.0)
+                    })
+                })
+                .map(|span| span.style)
+                .unwrap_or_default();
+            fg = highlight.fg.unwrap_or(fg);
+            bg = highlight.bg.unwrap_or(bg);
+
+            // Tokens of bytes which the parser would have dropped
+            if let Some(hyperlink) = indexed.cell.hyperlink() {
+                let token_color = match hyperlink.uri() {
+                    CONTROL_TOKEN_URI => Some(NamedColor::Magenta),
+                    INVALID_TOKEN_URI => Some(NamedColor::Red),
+                    _ => None,
+                };
+
+                if let Some(token_color) = token_color {
+                    fg = self.theme.get_color(Color::Named(token_color));
+                    bg = self
+                        .theme
+                        .get_color(Color::Named(NamedColor::DimBlack));
+                }
+            }
+
+            let is_focused_match = content
+                .focused_search_match
+                .as_ref()
+                .is_some_and(|r| r.contains(&indexed.point));
+            if is_focused_match
+                || content
+                    .search_matches
+                    .iter()
+                    .any(|r| r.contains(&indexed.point))
+            {
+                (fg, bg) = search_match_colors(&self.theme, is_focused_match);
+            }
+
             if is_inverse || is_selected {
                 std::mem::swap(&mut fg, &mut bg);
             }
@@ -295,7 +496,7 @@ impl<'a> SerialMonitorView<'a> {
             }
 
             // Handle hovered hyperlink underline
-            if is_hovered_hyperling {
+            if is_hovered_hyperling || highlight.underline {
                 let underline_height = y + cell_height;
                 shapes.push(Shape::LineSegment {
                     points: [
@@ -327,30 +528,270 @@ impl<'a> SerialMonitorView<'a> {
                     std::mem::swap(&mut fg, &mut bg);
                 }
 
-                shapes.push(Shape::text(
-                    &painter.fonts(|c| c.clone()),
-                    Pos2 {
-                        x: x + (cell_width / 2.0),
-                        y,
-                    },
-                    Align2::CENTER_TOP,
-                    indexed.c,
-                    self.font.font_type(),
-                    fg,
-                ));
+                // The font has no bold face, so bold text is drawn twice
+                // a pixel apart.
+                let offsets: &[f32] =
+                    if highlight.bold { &[0.0, 1.0] } else { &[0.0] };
+                for offset in offsets {
+                    shapes.push(Shape::text(
+                        &painter.fonts(|c| c.clone()),
+                        Pos2 {
+                            x: x + (cell_width / 2.0) + offset,
+                            y,
+                        },
+                        Align2::CENTER_TOP,
+                        indexed.c,
+                        self.font.font_type(),
+                        fg,
+                    ));
+                }
             }
         }
 
+        // Bookmarks are marked by a bar on the left edge of their row.
+        let bookmark_color =
+            self.theme.get_color(Color::Named(NamedColor::Blue));
+        for row in &content.bookmarked_rows {
+            let y = layout_min.y + cell_height * *row as f32;
+            shapes.push(Shape::Rect(RectShape::filled(
+                Rect::from_min_size(
+                    Pos2::new(layout.rect.min.x, y),
+                    Vec2::new(3.0, cell_height),
+                ),
+                CornerRadius::ZERO,
+                bookmark_color,
+            )));
+        }
+
         painter.extend(shapes);
     }
 }
 
+fn timestamp_gutter_shapes(
+    content: &RenderableContent,
+    format: TimestampFormat,
+    theme: &TerminalTheme,
+    font: &TerminalFont,
+    painter: &Painter,
+    layout_min: Pos2,
+) -> Vec<Shape> {
+    let cell_height = content.terminal_size.cell_height as f32;
+    let color = theme.get_color(Color::Named(NamedColor::BrightBlack));
+    let fonts = painter.fonts(|c| c.clone());
+
+    content
+        .line_stamps
+        .iter()
+        .enumerate()
+        .filter_map(|(line, line_stamp)| {
+            let stamp = line_stamp.stamp?;
+            Some(Shape::text(
+                &fonts,
+                Pos2::new(
+                    layout_min.x,
+                    layout_min.y + cell_height * line as f32,
+                ),
+                Align2::LEFT_TOP,
+                format.format(stamp, line_stamp.previous),
+                font.font_type(),
+                color,
+            ))
+        })
+        .collect()
+}
+
+/// Render the plain text rows of the scrollback archive, with their RX
+/// timestamps in the gutter.
+fn archive_shapes(
+    content: &RenderableContent,
+    timestamp_format: Option<TimestampFormat>,
+    theme: &TerminalTheme,
+    font: &TerminalFont,
+    painter: &Painter,
+    layout_min: Pos2,
+    gutter_width: f32,
+) -> Vec<Shape> {
+    let cell_height = content.terminal_size.cell_height as f32;
+    let cell_width = content.terminal_size.cell_width as f32;
+    let stamp_color = theme.get_color(Color::Named(NamedColor::BrightBlack));
+    let text_color = theme.get_color(Color::Named(NamedColor::Foreground));
+    let fonts = painter.fonts(|c| c.clone());
+    let mut shapes = vec![];
+    let mut previous = None;
+
+    for (row, columns) in &content.archive_search_matches {
+        let is_focused = content.focused_archive_row == Some(*row);
+        let (_, bg) = search_match_colors(theme, is_focused);
+        shapes.push(Shape::Rect(RectShape::filled(
+            Rect::from_min_size(
+                Pos2::new(
+                    layout_min.x
+                        + gutter_width
+                        + cell_width * columns.start as f32,
+                    layout_min.y + cell_height * *row as f32,
+                ),
+                Vec2::new(cell_width * columns.len() as f32, cell_height),
+            ),
+            CornerRadius::ZERO,
+            bg,
+        )));
+    }
+
+    for (line, row) in content.archive_rows.iter().enumerate() {
+        let y = layout_min.y + cell_height * line as f32;
+        if let (Some(format), Some(stamp)) = (timestamp_format, row.stamp) {
+            shapes.push(Shape::text(
+                &fonts,
+                Pos2::new(layout_min.x, y),
+                Align2::LEFT_TOP,
+                format.format(stamp, previous),
+                font.font_type(),
+                stamp_color,
+            ));
+        }
+        previous = row.stamp.or(previous);
+
+        shapes.push(Shape::text(
+            &fonts,
+            Pos2::new(layout_min.x + gutter_width, y),
+            Align2::LEFT_TOP,
+            &row.text,
+            font.font_type(),
+            text_color,
+        ));
+    }
+
+    shapes
+}
+
+/// Foreground and background of the search matches.
+fn search_match_colors(
+    theme: &TerminalTheme,
+    is_focused: bool,
+) -> (Color32, Color32) {
+    let bg = if is_focused {
+        NamedColor::Cyan
+    } else {
+        NamedColor::Yellow
+    };
+
+    (
+        theme.get_color(Color::Named(NamedColor::Black)),
+        theme.get_color(Color::Named(bg)),
+    )
+}
+
+/// Render received and sent bytes as `offset  hex bytes  |ascii|` rows.
+fn hex_dump_shapes(
+    content: &RenderableContent,
+    theme: &TerminalTheme,
+    font: &TerminalFont,
+    painter: &Painter,
+    layout_min: Pos2,
+) -> Vec<Shape> {
+    let cell_height = content.terminal_size.cell_height as f32;
+    let cell_width = content.terminal_size.cell_width as f32;
+    let bytes_per_row = content.hex_dump_bytes_per_row;
+    let offset_color = theme.get_color(Color::Named(NamedColor::BrightBlack));
+    let direction_color = |direction: TrafficDirection| match direction {
+        TrafficDirection::Rx => {
+            theme.get_color(Color::Named(NamedColor::Foreground))
+        },
+        TrafficDirection::Tx => {
+            theme.get_color(Color::Named(NamedColor::Yellow))
+        },
+    };
+    // `00000000  ` then 3 columns per byte with a gap every 8 bytes.
+    let hex_column = |index: usize| 10 + index * 3 + index / 8;
+    let ascii_column = hex_column(bytes_per_row) + 1;
+
+    let fonts = painter.fonts(|c| c.clone());
+    let mut shapes = vec![];
+    let mut text = |column: usize, line: usize, text: String, color| {
+        shapes.push(Shape::text(
+            &fonts,
+            Pos2::new(
+                layout_min.x + cell_width * column as f32,
+                layout_min.y + cell_height * line as f32,
+            ),
+            Align2::LEFT_TOP,
+            text,
+            font.font_type(),
+            color,
+        ));
+    };
+
+    for (line, row) in content.hex_dump.iter().enumerate() {
+        text(0, line, format!("{:08X}", row.offset), offset_color);
+        text(ascii_column - 1, line, "|".to_string(), offset_color);
+        text(
+            ascii_column + bytes_per_row,
+            line,
+            "|".to_string(),
+            offset_color,
+        );
+
+        for (index, byte) in row.bytes.iter().enumerate() {
+            if let Some((byte, direction)) = byte {
+                let color = direction_color(*direction);
+                text(hex_column(index), line, format!("{:02X}", byte), color);
+                text(
+                    ascii_column + index,
+                    line,
+                    HexDumpRow::ascii(*byte).to_string(),
+                    color,
+                );
+            }
+        }
+    }
+
+    shapes
+}
+
+fn selected_text(
+    backend: &SerialMonitorBackend,
+    timestamp_format: Option<TimestampFormat>,
+) -> String {
+    match timestamp_format {
+        Some(format) => backend.selectable_content_with_timestamps(format),
+        None => backend.selectable_content(),
+    }
+}
+
+/// Copy and paste while the keys go to the line editor or the search bar.
+fn process_clipboard_event(
+    event: egui::Event,
+    state: &mut SerialMonitorViewState,
+    backend: &SerialMonitorBackend,
+    timestamp_format: Option<TimestampFormat>,
+) -> InputAction {
+    match event {
+        egui::Event::Copy => InputAction::WriteToClipboard(selected_text(
+            backend,
+            timestamp_format,
+        )),
+        // A focused editor already received the text.
+        egui::Event::Paste(text)
+            if backend.input_mode() == InputMode::Line
+                && !state.search_bar.is_open()
+                && !state.line_input.has_focus() =>
+        {
+            state.line_input.insert(&text);
+            InputAction::Ignore
+        },
+        _ => InputAction::Ignore,
+    }
+}
+
 fn process_keyboard_event(
     event: egui::Event,
     backend: &SerialMonitorBackend,
     bindings_layout: &BindingsLayout,
     modifiers: Modifiers,
+    timestamp_format: Option<TimestampFormat>,
 ) -> InputAction {
+    let selectable_content = || selected_text(backend, timestamp_format);
+
     match event {
         egui::Event::Text(text) => {
             process_text_event(&text, modifiers, backend, bindings_layout)
@@ -371,16 +812,14 @@ fn process_keyboard_event(
         egui::Event::Copy => {
             #[cfg(not(any(target_os = "ios", target_os = "macos")))]
             if modifiers.contains(Modifiers::COMMAND | Modifiers::SHIFT) {
-                let content = backend.selectable_content();
-                InputAction::WriteToClipboard(content)
+                InputAction::WriteToClipboard(selectable_content())
             } else {
                 // Hotfix - Send ^C when there's not selection on view.
                 InputAction::BackendCall(BackendCommand::Write([0x3].to_vec()))
             }
             #[cfg(any(target_os = "ios", target_os = "macos"))]
             {
-                let content = backend.selectable_content();
-                InputAction::WriteToClipboard(content)
+                InputAction::WriteToClipboard(selectable_content())
             }
         },
         egui::Event::Key {
@@ -454,6 +893,13 @@ fn process_keyboard_key(
         BindingAction::Esc(seq) => InputAction::BackendCall(
             BackendCommand::Write(seq.as_bytes().to_vec()),
         ),
+        BindingAction::ToggleLocalEcho => {
+            InputAction::BackendCall(BackendCommand::ToggleLocalEcho)
+        },
+        BindingAction::Search => InputAction::OpenSearch,
+        BindingAction::RunScript(name) => {
+            InputAction::BackendCall(BackendCommand::RunScript(name))
+        },
         _ => InputAction::Ignore,
     }
 }